@@ -0,0 +1,26 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+///
+/// @brief stamps the git commit and build time into the binary, see version.rs
+///
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let dirty = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output()
+        .ok()
+        .is_some_and(|out| out.status.success() && !out.stdout.is_empty());
+    let built = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+
+    println!("cargo:rustc-env=BONSAIBOT_GIT_COMMIT={}{}", commit, if dirty { "-dirty" } else { "" });
+    println!("cargo:rustc-env=BONSAIBOT_BUILD_EPOCH={}", built);
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+}
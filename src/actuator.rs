@@ -0,0 +1,341 @@
+use crate::config::PinSpec;
+use crate::hal::{self, OutputLine, Platform, PwmLine};
+use crate::logging;
+use tokio::time::{sleep, sleep_until, Duration, Instant};
+
+//
+// @brief  number of duty cycle steps in a soft start/stop ramp
+//
+const RAMP_STEPS: u32 = 20;
+
+///
+/// @brief anything which can be switched on and off by the daemon (relays, mostly)
+///
+pub trait Actuator: Send {
+    fn set_on(&mut self);
+    fn set_off(&mut self);
+    fn is_on(&self) -> bool;
+
+    ///
+    /// @brief drives the output at a fraction of full power; plain relays snap on above zero
+    ///
+    fn set_level(&mut self, level: f64) {
+        if level > 0.0 { self.set_on() } else { self.set_off() }
+    }
+
+    ///
+    /// @brief how long to take going between off and full power, None to switch instantly
+    ///
+    fn ramp(&self) -> Option<Duration> {
+        None
+    }
+}
+
+///
+/// @return how long to hold an actuator for a run of `seconds`, to the millisecond
+///
+/// @note run lengths are fractional seconds throughout, a dosing pump wants 1.5 s shots
+///
+pub fn run_time(seconds: f64) -> Duration {
+    Duration::from_millis((seconds * 1000.0).round().max(0.0) as u64)
+}
+
+///
+/// @brief switches an actuator off, ramping down first if it has a soft stop profile
+///
+pub async fn switch_off(actuator: &mut dyn Actuator) {
+    if let Some(ramp) = actuator.ramp() {
+        ramp_level(actuator, 1.0, 0.0, ramp).await;
+    }
+    actuator.set_off();
+}
+
+async fn ramp_level(actuator: &mut dyn Actuator, from: f64, to: f64, ramp: Duration) {
+    for step in 1..=RAMP_STEPS {
+        actuator.set_level(from + (to - from) * step as f64 / RAMP_STEPS as f64);
+        sleep(ramp / RAMP_STEPS).await;
+    }
+}
+
+///
+/// @brief stands in for an output in shadow mode: logs what it's told to do and remembers it,
+///        without touching any hardware
+///
+pub struct Shadow {
+    name: String,
+    on: bool,
+}
+
+impl Shadow {
+    pub fn boxed(name: impl Into<String>) -> Box<dyn Actuator> {
+        Box::new(Shadow { name: name.into(), on: false })
+    }
+
+    fn switch(&mut self, on: bool) {
+        if on != self.on {
+            let state = if on { "on" } else { "off" };
+            logging::send(6, &format!("Shadow mode: would switch {} {}", self.name, state),
+                &[("SERVICE", &"shadow"), ("EVENT", &"would_switch"), ("ACTUATOR", &self.name), ("STATE", &state)]);
+        }
+        self.on = on;
+    }
+}
+
+impl Actuator for Shadow {
+    fn set_on(&mut self) {
+        self.switch(true);
+    }
+
+    fn set_off(&mut self) {
+        self.switch(false);
+    }
+
+    fn is_on(&self) -> bool {
+        self.on
+    }
+}
+
+///
+/// @brief a relay driven directly from a board gpio, honoring the board polarity
+///
+pub struct GpioRelay {
+    line: Box<dyn OutputLine>,
+    pin: u8,
+    active_low: bool,
+    on: bool,
+}
+
+impl GpioRelay {
+
+    ///
+    /// @brief claims the pin and drives it to the de-energized level straight away,
+    ///        so active-low boards don't click every relay on at startup
+    ///
+    pub fn new(platform: &dyn Platform, spec: &PinSpec) -> hal::Result<GpioRelay> {
+        let line = platform.output(spec.pin, spec.active_low)?;
+        Ok(GpioRelay { line, pin: spec.pin, active_low: spec.active_low, on: false })
+    }
+
+    fn drive(&mut self, on: bool) {
+        let result = if on != self.active_low { self.line.set_high() } else { self.line.set_low() };
+        match result {
+            Ok(_) => self.on = on,
+            Err(e) => {
+                logging::print(3, &format!("GPIO write error on pin {}: {}", self.pin, e));
+            },
+        }
+    }
+}
+
+impl Actuator for GpioRelay {
+    fn set_on(&mut self) {
+        self.drive(true);
+    }
+
+    fn set_off(&mut self) {
+        self.drive(false);
+    }
+
+    fn is_on(&self) -> bool {
+        self.on
+    }
+}
+
+///
+/// @brief a motor on a pwm capable driver (a MOSFET board, not a relay) which soft starts and
+///        stops over `ramp` to spare the plumbing from water hammer
+///
+pub struct PwmMotor {
+    line: Box<dyn PwmLine>,
+    pin: u8,
+    active_low: bool,
+    ramp: Duration,
+    level: f64,
+}
+
+impl PwmMotor {
+    pub fn new(platform: &dyn Platform, spec: &PinSpec, frequency: f64, ramp: Duration) -> hal::Result<PwmMotor> {
+        let mut motor = PwmMotor {
+            line: platform.pwm(spec.pin, frequency)?,
+            pin: spec.pin,
+            active_low: spec.active_low,
+            ramp,
+            level: 1.0,
+        };
+        motor.set_level(0.0);
+        Ok(motor)
+    }
+}
+
+impl Actuator for PwmMotor {
+    fn set_on(&mut self) {
+        self.set_level(1.0);
+    }
+
+    fn set_off(&mut self) {
+        self.set_level(0.0);
+    }
+
+    fn is_on(&self) -> bool {
+        self.level > 0.0
+    }
+
+    fn set_level(&mut self, level: f64) {
+        let level = level.clamp(0.0, 1.0);
+        let duty = if self.active_low { 1.0 - level } else { level };
+        match self.line.set_duty(duty) {
+            Ok(_) => self.level = level,
+            Err(e) => {
+                logging::print(3, &format!("PWM write error on pin {}: {}", self.pin, e));
+            },
+        }
+    }
+
+    fn ramp(&self) -> Option<Duration> {
+        Some(self.ramp)
+    }
+}
+
+///
+/// @brief spaces out relay activations so loads don't all draw their inrush current at once
+///        and brown out the supply
+///
+/// @note only switching on is staggered, switching off is always immediate
+///
+pub struct Stagger {
+    gap: Duration,
+    last_on: Option<Instant>,
+}
+
+impl Stagger {
+    pub fn new(gap: Duration) -> Stagger {
+        Stagger { gap, last_on: None }
+    }
+
+    ///
+    /// @brief switches the actuator on, first waiting out whatever is left of the gap since
+    ///        the previous activation; already-on actuators are left alone
+    ///
+    pub async fn switch_on(&mut self, actuator: &mut dyn Actuator) {
+        if actuator.is_on() {
+            return;
+        }
+        if let Some(last) = self.last_on {
+            sleep_until(last + self.gap).await;
+        }
+        self.last_on = Some(Instant::now());
+        match actuator.ramp() {
+            Some(ramp) => ramp_level(actuator, 0.0, 1.0, ramp).await,
+            None => actuator.set_on(),
+        }
+    }
+}
+
+///
+/// @brief holds a thermostat-style actuator in each state for at least `min_on`/`min_off`,
+///        so a reading hovering at a threshold can't chatter the relay
+///
+/// @note only the controller's own switching asks it; safety cut-offs and the door switch
+///       the actuator off regardless, which it notices on the next `observe`
+///
+pub struct MinCycle {
+    min_on: Duration,
+    min_off: Duration,
+    on: bool,
+    since: Option<Instant>,
+}
+
+impl MinCycle {
+    pub fn new(min_on: Duration, min_off: Duration) -> MinCycle {
+        MinCycle { min_on, min_off, on: false, since: None }
+    }
+
+    ///
+    /// @brief notes the actuator's state, starting the clock when it changed
+    ///
+    pub fn observe(&mut self, on: bool, now: Instant) {
+        if on != self.on {
+            self.on = on;
+            self.since = Some(now);
+        }
+    }
+
+    ///
+    /// @brief whether switching to `on` now keeps to the minimum times; a state the actuator
+    ///        hasn't been seen changing from is always allowed
+    ///
+    pub fn allows(&self, on: bool, now: Instant) -> bool {
+        let hold = if self.on { self.min_on } else { self.min_off };
+        on == self.on || self.since.is_none_or(|since| now - since >= hold)
+    }
+
+    ///
+    /// @brief how much longer the current state is held, zero once it may switch
+    ///
+    pub fn remaining(&self, now: Instant) -> Duration {
+        let hold = if self.on { self.min_on } else { self.min_off };
+        self.since.map_or(Duration::ZERO, |since| hold.saturating_sub(now - since))
+    }
+}
+
+///
+/// @brief an actuator that only remembers its state, for the tests of whatever drives one
+///
+#[cfg(test)]
+#[derive(Default)]
+pub struct Fake {
+    on: bool,
+}
+
+#[cfg(test)]
+impl Actuator for Fake {
+    fn set_on(&mut self) {
+        self.on = true;
+    }
+
+    fn set_off(&mut self) {
+        self.on = false;
+    }
+
+    fn is_on(&self) -> bool {
+        self.on
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_stagger() {
+        let mut stagger = Stagger::new(Duration::from_millis(200));
+        let (mut a, mut b) = (Fake::default(), Fake::default());
+
+        let start = Instant::now();
+        stagger.switch_on(&mut a).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        stagger.switch_on(&mut a).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        stagger.switch_on(&mut b).await;
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert!(a.is_on() && b.is_on());
+    }
+
+    #[test]
+    pub fn test_min_cycle() {
+        let mut cycle = MinCycle::new(Duration::from_secs(120), Duration::from_secs(60));
+        let t0 = Instant::now();
+        assert!(cycle.allows(true, t0));
+
+        cycle.observe(true, t0);
+        assert!(!cycle.allows(false, t0 + Duration::from_secs(119)));
+        assert!(cycle.allows(true, t0 + Duration::from_secs(119)));
+        assert!(cycle.allows(false, t0 + Duration::from_secs(120)));
+
+        cycle.observe(false, t0 + Duration::from_secs(130));
+        assert!(!cycle.allows(true, t0 + Duration::from_secs(189)));
+        assert_eq!(cycle.remaining(t0 + Duration::from_secs(150)), Duration::from_secs(40));
+        assert!(cycle.allows(true, t0 + Duration::from_secs(190)));
+        assert_eq!(cycle.remaining(t0 + Duration::from_secs(200)), Duration::ZERO);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::config::AdcChannelSpec;
+use crate::hal::{HalError, I2cDevice, Platform};
+use crate::reading::Reading;
+use std::{error, fmt};
+use tokio::time::{sleep, Duration};
+
+const CONVERSION_REG: u8             = 0x00;
+const CONFIG_REG: u8                 = 0x01;
+
+const OS_SINGLE: u16                 = 1 << 15;  // write: start conversion, read: 1 when idle
+const MODE_SINGLE_SHOT: u16          = 1 << 8;
+const COMP_QUE_DISABLE: u16          = 0b11;
+const MUX_SINGLE_ENDED_AIN0: u16     = 0b100;    // AINx vs GND is 0b100 + x
+
+const FULL_SCALE_VOLTS: [f32; 6]     = [6.144, 4.096, 2.048, 1.024, 0.512, 0.256];
+const DATA_RATES_SPS: [u16; 8]       = [8, 16, 32, 64, 128, 250, 475, 860];
+const CONVERSION_POLL_LIMIT: u32     = 10;
+
+pub type Result<T> = std::result::Result<T, AdsError>;
+
+#[derive(Debug)]
+pub enum AdsError {
+    InvalidChannel(u8),
+    InvalidGain(f32),
+    InvalidDataRate(u16),
+    ConversionTimeout,
+    I2c(HalError),
+}
+
+impl fmt::Display for AdsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AdsError::InvalidChannel(c) =>
+                write!(f, "Channel {} does not exist (0..=3)", c),
+            AdsError::InvalidGain(g) =>
+                write!(f, "Full scale {} V is not a PGA setting, expected one of {:?}", g, FULL_SCALE_VOLTS),
+            AdsError::InvalidDataRate(r) =>
+                write!(f, "Data rate {} SPS is not supported, expected one of {:?}", r, DATA_RATES_SPS),
+            AdsError::ConversionTimeout =>
+                write!(f, "Conversion did not complete"),
+            AdsError::I2c(..) =>
+                write!(f, "I2C error"),
+        }
+    }
+}
+
+impl error::Error for AdsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            AdsError::I2c(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+///
+/// @brief TI ADS1115 16-bit I2C ADC, used single-shot and single-ended
+///
+pub struct ADS1115 {
+    i2c: Box<dyn I2cDevice>,
+    sensor_id: String,
+}
+
+impl ADS1115 {
+
+    pub fn new(platform: &dyn Platform, bus: u8, address: u16) -> Result<ADS1115> {
+        let i2c = platform.i2c(bus, address).map_err(AdsError::I2c)?;
+        Ok(ADS1115 { i2c, sensor_id: format!("ads1115@i2c-{}:{:#04x}", bus, address) })
+    }
+
+    ///
+    /// @brief runs a single conversion on the channel and returns the input in volts
+    ///
+    pub async fn read_voltage(&mut self, spec: &AdcChannelSpec) -> Result<Reading<f32>> {
+        let config = Self::config_word(spec)?;
+        let [hi, lo] = config.to_be_bytes();
+        self.i2c.write(&[CONFIG_REG, hi, lo]).map_err(AdsError::I2c)?;
+
+        // one sample period plus a little slack for the internal oscillator
+        let period_us = 1_000_000 / spec.data_rate as u64;
+        sleep(Duration::from_micros(period_us + 100)).await;
+
+        let mut polls = 0;
+        while self.read_register(CONFIG_REG)? & OS_SINGLE == 0 {
+            polls += 1;
+            if polls > CONVERSION_POLL_LIMIT {
+                return Err(AdsError::ConversionTimeout);
+            }
+            sleep(Duration::from_micros(period_us / 4 + 50)).await;
+        }
+
+        let raw = self.read_register(CONVERSION_REG)? as i16;
+        Ok(Reading::now(raw as f32 * spec.full_scale / 32768.0, &self.sensor_id))
+    }
+
+    fn read_register(&mut self, reg: u8) -> Result<u16> {
+        let mut buf = [0u8; 2];
+        self.i2c.write_read(&[reg], &mut buf).map_err(AdsError::I2c)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    ///
+    /// @brief builds the config register for a single-shot conversion (datasheet sec. 9.6.3)
+    ///
+    pub fn config_word(spec: &AdcChannelSpec) -> Result<u16> {
+        if spec.channel > 3 {
+            return Err(AdsError::InvalidChannel(spec.channel));
+        }
+        let pga = FULL_SCALE_VOLTS.iter().position(|v| (v - spec.full_scale).abs() < 1e-4)
+            .ok_or(AdsError::InvalidGain(spec.full_scale))? as u16;
+        let dr = DATA_RATES_SPS.iter().position(|r| *r == spec.data_rate)
+            .ok_or(AdsError::InvalidDataRate(spec.data_rate))? as u16;
+        let mux = MUX_SINGLE_ENDED_AIN0 + spec.channel as u16;
+
+        Ok(OS_SINGLE | mux << 12 | pga << 9 | MODE_SINGLE_SHOT | dr << 5 | COMP_QUE_DISABLE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_config_word() {
+        let spec = AdcChannelSpec { name: "soil".to_string(), channel: 1, full_scale: 4.096, data_rate: 128, interval_secs: None };
+        // OS=1, MUX=101, PGA=001, MODE=1, DR=100, COMP_QUE=11
+        assert_eq!(ADS1115::config_word(&spec).unwrap(), 0b1101_0011_1000_0011);
+
+        let bad = AdcChannelSpec { full_scale: 3.3, ..spec };
+        assert!(matches!(ADS1115::config_word(&bad), Err(AdsError::InvalidGain(_))));
+    }
+}
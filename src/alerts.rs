@@ -0,0 +1,136 @@
+use crate::config::TempRateSpec;
+use crate::events::{Bus, Event};
+use crate::logging;
+use chrono::{DateTime, Utc};
+
+///
+/// @brief raises an alert: something needs a human to look at the enclosure or the Pi
+///
+/// @note logged at warning priority with EVENT=alert and ALERT=`kind`, and published on the
+///       event bus so /recent and anything else subscribed sees it too
+///
+pub fn raise(bus: &Bus, kind: &'static str, message: &str) {
+    logging::send(4, message, &[("EVENT", &"alert"), ("ALERT", &kind)]);
+
+    // no subscribers is fine
+    let _ = bus.send(Event::Alert { at: Utc::now(), kind, message: message.to_string() });
+}
+
+///
+/// @brief latches while a value is outside a band so it alerts once per excursion
+///
+#[derive(Debug, Default)]
+pub struct Band {
+    outside: bool,
+}
+
+impl Band {
+    ///
+    /// @return Some(true) when `value` just left `low`..`high`, Some(false) when it came back
+    ///
+    pub fn check(&mut self, value: f64, low: Option<f64>, high: Option<f64>) -> Option<bool> {
+        let outside = low.is_some_and(|low| value < low) || high.is_some_and(|high| value > high);
+        if outside == self.outside {
+            return None;
+        }
+        self.outside = outside;
+        Some(outside)
+    }
+}
+
+///
+/// @return how far the last of `readings` moved over them: up from the lowest when `rising`,
+///         down from the highest (negative) otherwise
+///
+pub fn change(readings: &[(DateTime<Utc>, f64)], rising: bool) -> Option<f64> {
+    let (_, last) = readings.last()?;
+    let values = readings.iter().map(|(_, v)| *v);
+    Some(match rising {
+        true => last - values.fold(f64::INFINITY, f64::min),
+        false => last - values.fold(f64::NEG_INFINITY, f64::max),
+    })
+}
+
+///
+/// @brief the [[alerts.temp_rate]] rules, each latched like a Band so a fast rise alerts
+///        once rather than on every reading it lasts
+///
+pub struct Rates {
+    rules: Vec<(TempRateSpec, Band)>,
+}
+
+impl Rates {
+    pub fn new(rules: &[TempRateSpec]) -> Rates {
+        Rates { rules: rules.iter().map(|rule| (rule.clone(), Band::default())).collect() }
+    }
+
+    ///
+    /// @brief the longest window a rule looks back over
+    ///
+    pub fn window_mins(&self) -> u64 {
+        self.rules.iter().map(|(rule, _)| rule.within_mins).max().unwrap_or(0)
+    }
+
+    ///
+    /// @param readings  temperatures oldest first, covering at least `window_mins`
+    ///
+    /// @return a message for each rule `readings` just broke
+    ///
+    pub fn check(&mut self, readings: &[(DateTime<Utc>, f64)], now: DateTime<Utc>) -> Vec<String> {
+        let mut broken = Vec::new();
+        for (rule, band) in &mut self.rules {
+            let since = now - chrono::Duration::minutes(rule.within_mins as i64);
+            let window: Vec<_> = readings.iter().filter(|(at, _)| *at >= since).copied().collect();
+            let Some(change) = change(&window, rule.change_c > 0.0) else {
+                continue;
+            };
+            let (low, high) = if rule.change_c > 0.0 { (None, Some(rule.change_c)) } else { (Some(rule.change_c), None) };
+            if band.check(change, low, high) == Some(true) {
+                broken.push(format!("Enclosure temperature {} {:.1} C within {} min, faster than {:.1} C allowed",
+                    if change > 0.0 { "rose" } else { "fell" }, change.abs(), rule.within_mins, rule.change_c.abs()));
+            }
+        }
+        broken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_raise() {
+        let bus = crate::events::bus();
+        let mut rx = bus.subscribe();
+        raise(&bus, "crash_loop", "Restarted 5 times in the last hour");
+        match rx.try_recv() {
+            Ok(Event::Alert { kind, message, .. }) => {
+                assert_eq!(kind, "crash_loop");
+                assert_eq!(message, "Restarted 5 times in the last hour");
+            },
+            other => panic!("expected an alert, got {:?}", other),
+        }
+
+        let mut band = Band::default();
+        assert_eq!(band.check(12.0, Some(5.0), Some(30.0)), None);
+        assert_eq!(band.check(3.0, Some(5.0), Some(30.0)), Some(true));
+        assert_eq!(band.check(2.0, Some(5.0), Some(30.0)), None);
+        assert_eq!(band.check(2.0, None, Some(30.0)), Some(false));
+
+        let t0 = Utc::now();
+        let at = |mins, temp| (t0 + chrono::Duration::minutes(mins), temp);
+        let mut rates = Rates::new(&[TempRateSpec { change_c: 5.0, within_mins: 15 }, TempRateSpec { change_c: -4.0, within_mins: 30 }]);
+        assert_eq!(rates.window_mins(), 30);
+        // the 14 C reading 25 min back is outside the rising rule's window
+        let mut readings = vec![at(0, 14.0), at(10, 20.0), at(20, 22.0), at(25, 24.0)];
+        assert!(rates.check(&readings, t0 + chrono::Duration::minutes(25)).is_empty());
+        readings.push(at(30, 28.0));
+        assert_eq!(rates.check(&readings, t0 + chrono::Duration::minutes(30)),
+            vec!["Enclosure temperature rose 6.0 C within 15 min, faster than 5.0 C allowed"]);
+        readings.push(at(31, 28.5));
+        assert!(rates.check(&readings, t0 + chrono::Duration::minutes(31)).is_empty());
+        readings.push(at(40, 21.0));
+        assert_eq!(rates.check(&readings, t0 + chrono::Duration::minutes(40)),
+            vec!["Enclosure temperature fell 7.5 C within 30 min, faster than 4.0 C allowed"]);
+    }
+}
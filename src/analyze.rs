@@ -0,0 +1,257 @@
+use crate::cli::AnalyzeArgs;
+use crate::config::Config;
+use crate::db;
+use crate::output;
+use crate::tuner::MAX_GAP_MINS;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::error::Error;
+use tokio_postgres::Client;
+
+//
+// @brief  how far back `analyze` replays without --from
+//
+const DEFAULT_DAYS: i64 = 7;
+
+///
+/// @brief a stored RH reading and whether the humidifier was on when it was taken
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub at: DateTime<Utc>,
+    pub rh: f64,
+    pub on: bool,
+}
+
+///
+/// @brief how the humidifier did over the replayed readings with one band
+///
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct Outcome {
+    pub rh_low: f64,
+    pub rh_high: f64,
+    pub cycles: u64,
+    pub runtime_mins: f64,
+    pub in_band_pct: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Analysis {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub replayed_mins: f64,
+    pub effect_per_min: f64,
+    pub recorded: Outcome,
+    pub simulated: Outcome,
+}
+
+///
+/// @brief consecutive readings close enough together to replay, with the minutes between
+///
+fn steps(samples: &[Sample]) -> impl Iterator<Item = (&Sample, &Sample, f64)> {
+    samples.windows(2)
+        .map(|pair| (&pair[0], &pair[1], (pair[1].at - pair[0].at).num_seconds() as f64 / 60.0))
+        .filter(|(_, _, mins)| *mins > 0.0 && *mins <= MAX_GAP_MINS as f64)
+}
+
+///
+/// @return RH per minute the humidifier adds on top of what the enclosure does by itself:
+///         the slope while it was on less the slope while it was off
+///
+pub fn effect(samples: &[Sample]) -> Option<f64> {
+    let (mut on, mut off) = ((0.0, 0.0), (0.0, 0.0));
+    for (a, b, mins) in steps(samples) {
+        let slot = if a.on { &mut on } else { &mut off };
+        *slot = (slot.0 + b.rh - a.rh, slot.1 + mins);
+    }
+    if on.1 == 0.0 || off.1 == 0.0 {
+        return None;
+    }
+    Some(on.0 / on.1 - off.0 / off.1).filter(|effect| *effect > 0.0)
+}
+
+#[derive(Default)]
+struct Tally {
+    outcome: Outcome,
+    in_band_mins: f64,
+    total_mins: f64,
+}
+
+impl Tally {
+    fn add(&mut self, rh: f64, on: bool, mins: f64) {
+        self.total_mins += mins;
+        if on {
+            self.outcome.runtime_mins += mins;
+        }
+        if (self.outcome.rh_low..=self.outcome.rh_high).contains(&rh) {
+            self.in_band_mins += mins;
+        }
+    }
+
+    fn finish(mut self) -> Outcome {
+        self.outcome.in_band_pct = if self.total_mins > 0.0 { 100.0 * self.in_band_mins / self.total_mins } else { 0.0 };
+        self.outcome
+    }
+}
+
+///
+/// @brief what the readings themselves say, judged against `rh_low`..`rh_high`
+///
+pub fn recorded(samples: &[Sample], rh_low: f64, rh_high: f64) -> Outcome {
+    let mut tally = Tally { outcome: Outcome { rh_low, rh_high, ..Default::default() }, ..Default::default() };
+    for (a, b, mins) in steps(samples) {
+        tally.add(a.rh, a.on, mins);
+        if b.on && !a.on {
+            tally.outcome.cycles += 1;
+        }
+    }
+    tally.finish()
+}
+
+///
+/// @brief replays the readings with the humidifier switching on below `rh_low` and off above
+///        `rh_high`: each step RH drifts as it did, plus `effect` for every minute the
+///        simulated humidifier is on and the real one wasn't, less it for the reverse
+///
+/// @note the band holds all day, schedules, dormancy and trend anticipation aren't replayed
+///
+pub fn simulate(samples: &[Sample], rh_low: f64, rh_high: f64, effect: f64) -> Outcome {
+    let mut tally = Tally { outcome: Outcome { rh_low, rh_high, ..Default::default() }, ..Default::default() };
+    let Some(first) = samples.first() else {
+        return tally.finish();
+    };
+    let (mut rh, mut on) = (first.rh, first.on);
+    let mut last: Option<DateTime<Utc>> = None;
+    for (a, b, mins) in steps(samples) {
+        // after a gap the replay starts again from what was measured
+        if last != Some(a.at) {
+            rh = a.rh;
+        }
+        if rh < rh_low && !on {
+            on = true;
+            tally.outcome.cycles += 1;
+        } else if rh > rh_high && on {
+            on = false;
+        }
+        tally.add(rh, on, mins);
+        let correction = effect * mins * (on as u8 as f64 - a.on as u8 as f64);
+        rh = (rh + b.rh - a.rh + correction).clamp(0.0, 100.0);
+        last = Some(b.at);
+    }
+    tally.finish()
+}
+
+///
+/// @brief the RH readings from `from` to `to`, each with the humidifier state at the time
+///
+pub async fn load(client: &Client, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Sample>, tokio_postgres::Error> {
+    let readings = client.query("
+        SELECT timestamp, humidity FROM climate_data
+        WHERE NOT is_pump_start AND humidity IS NOT NULL AND timestamp >= $1 AND timestamp < $2 AND mine(device_id)
+        ORDER BY timestamp", &[&from, &to]).await?;
+    // the switch before `from` says how the humidifier started out
+    let switches = client.query("
+        SELECT timestamp, is_on FROM actuator_events
+        WHERE actuator = 'humidifier' AND timestamp < $2 AND mine(device_id)
+          AND timestamp >= COALESCE((SELECT MAX(timestamp) FROM actuator_events
+                                     WHERE actuator = 'humidifier' AND timestamp < $1 AND mine(device_id)), $1)
+        ORDER BY timestamp", &[&from, &to]).await?;
+
+    let mut switches = switches.iter().map(|row| (row.get::<_, DateTime<Utc>>(0), row.get::<_, bool>(1))).peekable();
+    let mut on = false;
+    Ok(readings.iter().map(|row| {
+        let at: DateTime<Utc> = row.get(0);
+        while let Some((_, is_on)) = switches.next_if(|(switched, _)| *switched <= at) {
+            on = is_on;
+        }
+        Sample { at, rh: row.get(1), on }
+    }).collect())
+}
+
+fn print(analysis: &Analysis) {
+    let band = |o: &Outcome| format!("{:.1}-{:.1} %", o.rh_low, o.rh_high);
+    println!("Replayed {:.1} h of readings from {} to {}, the humidifier adds {:.2} %/min",
+        analysis.replayed_mins / 60.0, analysis.from.format("%Y-%m-%d %H:%M"), analysis.to.format("%Y-%m-%d %H:%M"), analysis.effect_per_min);
+    let (recorded, simulated) = (&analysis.recorded, &analysis.simulated);
+    println!("{:<14} {:>22} {:>22}", "", format!("recorded {}", band(recorded)), format!("what-if {}", band(simulated)));
+    println!("{:<14} {:>22} {:>22}", "cycles", recorded.cycles, simulated.cycles);
+    println!("{:<14} {:>22} {:>22}", "runtime", format!("{:.1} h", recorded.runtime_mins / 60.0), format!("{:.1} h", simulated.runtime_mins / 60.0));
+    println!("{:<14} {:>22} {:>22}", "time in band", format!("{:.1} %", recorded.in_band_pct), format!("{:.1} %", simulated.in_band_pct));
+}
+
+///
+/// @brief `bonsai-bot analyze`: replays stored readings under another humidifier band and
+///        compares it with what happened under the configured one
+///
+pub async fn run(args: AnalyzeArgs, json: bool) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let rh_low = args.rh_low.unwrap_or(config.humidifier.rh_low);
+    let rh_high = args.rh_high.unwrap_or(config.humidifier.rh_high);
+    if rh_low >= rh_high {
+        return Err(format!("--rh-low {} must be below --rh-high {}", rh_low, rh_high).into());
+    }
+    let to = args.to.unwrap_or_else(Utc::now);
+    let from = args.from.unwrap_or(to - Duration::days(DEFAULT_DAYS));
+
+    let (client, connection) = db::establish_connection().await?;
+    let connection = tokio::spawn(connection);
+    let samples = load(&client, from, to).await;
+    drop(client);
+    let _ = connection.await;
+    let samples = samples?;
+
+    let effect = effect(&samples)
+        .ok_or("Not enough readings with the humidifier both on and off in that period to tell what it does")?;
+    let recorded = recorded(&samples, config.humidifier.rh_low, config.humidifier.rh_high);
+    let analysis = Analysis {
+        from,
+        to,
+        replayed_mins: steps(&samples).map(|(_, _, mins)| mins).sum(),
+        effect_per_min: effect,
+        recorded,
+        simulated: simulate(&samples, rh_low, rh_high, effect),
+    };
+    match json {
+        true => output::json(&analysis),
+        false => print(&analysis),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_simulate() {
+        // the enclosure loses 0.5 %/min, the humidifier (on below 70, off above 75) adds 1.5
+        let t0 = Utc::now();
+        let mut samples = Vec::new();
+        let (mut rh, mut on) = (72.0, false);
+        for minute in 0..120 {
+            on = (on || rh < 70.0) && rh <= 75.0;
+            samples.push(Sample { at: t0 + Duration::minutes(minute), rh, on });
+            rh += if on { 1.0 } else { -0.5 };
+        }
+        let effect = effect(&samples).unwrap();
+        assert!((effect - 1.5).abs() < 1e-9);
+
+        let actual = recorded(&samples, 70.0, 75.0);
+        assert_eq!(actual.cycles, 7);
+        assert!(actual.in_band_pct > 85.0);
+
+        // replaying the same band reproduces what happened
+        let same = simulate(&samples, 70.0, 75.0, effect);
+        assert_eq!((same.cycles, same.runtime_mins), (actual.cycles, actual.runtime_mins));
+
+        // a band twice as wide cycles about half as often, and an unreachable one never stops
+        let wide = simulate(&samples, 65.0, 75.0, effect);
+        assert!(wide.cycles < actual.cycles && wide.cycles >= 4);
+        let high = simulate(&samples, 99.5, 100.0, effect);
+        assert_eq!((high.cycles, high.runtime_mins), (1, 119.0));
+
+        // compact storage keeps a keyframe every 30 min, those gaps are still data
+        let keyframes = [Sample { at: t0, rh: 72.0, on: false }, Sample { at: t0 + Duration::minutes(MAX_GAP_MINS), rh: 72.0, on: false }];
+        assert_eq!(steps(&keyframes).count(), 1);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::db;
+use crate::fleet;
+use crate::output;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use tokio_postgres::Client;
+
+//
+// @brief  what can be marked on a chart, in the order they are fetched
+//
+pub const KINDS: [&str; 4] = ["pump", "humidifier", "alert", "journal"];
+
+///
+/// @brief one marker, as Grafana's JSON datasource wants it: epoch milliseconds, with an end
+///        for regions such as a humidifier run
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_end: Option<i64>,
+    pub title: String,
+    pub text: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Range {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AnnotationQuery {
+    #[serde(default)]
+    pub query: String,
+}
+
+///
+/// @brief body of `POST /grafana/annotations`; the annotation's query lists the kinds to
+///        show, e.g. "pump, journal", and is empty for all of them
+///
+#[derive(Debug, Deserialize)]
+pub struct AnnotationRequest {
+    pub range: Range,
+    #[serde(default)]
+    pub annotation: AnnotationQuery,
+}
+
+///
+/// @brief the kinds named in `query`, all of them if it names none
+///
+pub fn kinds(query: &str) -> Vec<&'static str> {
+    let named: Vec<&'static str> = KINDS.iter().copied()
+        .filter(|kind| query.split([',', ' ']).any(|word| word.trim().eq_ignore_ascii_case(kind)))
+        .collect();
+    if named.is_empty() { KINDS.to_vec() } else { named }
+}
+
+fn marker(kind: &str, at: DateTime<Utc>, end: Option<DateTime<Utc>>, title: &str, text: String) -> Annotation {
+    Annotation {
+        time: at.timestamp_millis(),
+        time_end: end.map(|end| end.timestamp_millis()),
+        title: title.to_string(),
+        text,
+        tags: vec![kind.to_string()],
+    }
+}
+
+///
+/// @brief the markers of `kinds` between `from` and `to`, oldest first
+///
+pub async fn fetch(client: &Client, from: DateTime<Utc>, to: DateTime<Utc>, kinds: &[&str]) -> Result<Vec<Annotation>, tokio_postgres::Error> {
+    let mut annotations = Vec::new();
+    if kinds.contains(&"pump") {
+        for row in client.query("SELECT timestamp, pump_ended_at, pump_seconds FROM climate_data
+                WHERE is_pump_start AND timestamp >= $1 AND timestamp < $2", &[&from, &to]).await? {
+            let seconds: Option<f64> = row.get(2);
+            let text = seconds.map(|s| format!("Pump ran {:.0} s", s)).unwrap_or_else(|| "Pump run".to_string());
+            annotations.push(marker("pump", row.get(0), row.get(1), "Watered", text));
+        }
+    }
+    if kinds.contains(&"humidifier") {
+        // each switch-on runs until the next event, which is normally the switch-off
+        for row in client.query("SELECT timestamp, until FROM (
+                    SELECT timestamp, is_on, lead(timestamp) OVER (ORDER BY timestamp) AS until FROM actuator_events
+                    WHERE actuator = 'humidifier' AND timestamp >= $1 AND timestamp < $2) AS runs
+                WHERE is_on", &[&from, &to]).await? {
+            annotations.push(marker("humidifier", row.get(0), row.get(1), "Humidifier", "Humidifier on".to_string()));
+        }
+    }
+    if kinds.contains(&"alert") {
+        for row in client.query("SELECT timestamp, kind, message FROM alert_log WHERE timestamp >= $1 AND timestamp < $2", &[&from, &to]).await? {
+            let kind: String = row.get(1);
+            annotations.push(marker("alert", row.get(0), None, &format!("Alert: {}", kind), row.get(2)));
+        }
+    }
+    if kinds.contains(&"journal") {
+        for row in client.query("SELECT timestamp, note FROM journal_entries WHERE timestamp >= $1 AND timestamp < $2", &[&from, &to]).await? {
+            annotations.push(marker("journal", row.get(0), None, "Journal", row.get(1)));
+        }
+    }
+    annotations.sort_by_key(|a| a.time);
+    Ok(annotations)
+}
+
+///
+/// @brief `bonsai-bot journal`: notes something done to the tree, e.g. "Repotted", so charts
+///        can mark it
+///
+pub async fn add_journal_entry(note: String, at: Option<DateTime<Utc>>, json: bool) -> Result<(), Box<dyn Error>> {
+    let (client, connection) = db::establish_connection().await?;
+    let connection = tokio::spawn(connection);
+    fleet::ensure(&client).await?;
+
+    let at = at.unwrap_or_else(Utc::now);
+    client.execute("INSERT INTO journal_entries (timestamp, note) VALUES ($1, $2)", &[&at, &note]).await?;
+    if json {
+        output::json(&serde_json::json!({ "at": at, "note": note }));
+    } else {
+        println!("Noted at {}: {}", at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"), note);
+    }
+
+    drop(client);
+    let _ = connection.await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_kinds() {
+        assert_eq!(kinds(""), KINDS.to_vec());
+        assert_eq!(kinds("journal, Pump"), vec!["pump", "journal"]);
+        assert_eq!(kinds("#deploy"), KINDS.to_vec());
+
+        let at = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let json = serde_json::to_value(marker("pump", at, None, "Watered", "Pump ran 60 s".to_string())).unwrap();
+        assert_eq!(json, serde_json::json!({ "time": 1714564800000i64, "title": "Watered", "text": "Pump ran 60 s", "tags": ["pump"] }));
+    }
+}
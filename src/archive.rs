@@ -0,0 +1,215 @@
+use crate::alerts;
+use crate::config::ArchiveConfig;
+use crate::db;
+use crate::events::Bus;
+use crate::export::{month_start, next_month};
+use crate::lease;
+use crate::logging;
+use chrono::{DateTime, Duration, Utc};
+use futures::{pin_mut, SinkExt, StreamExt};
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::time::sleep;
+use tokio_postgres::{Client, IsolationLevel};
+
+//
+// @brief  the raw tables and which of their rows may leave the database; pump starts stay
+//         since the schedule is derived from them, and climate rows only go once rolled up
+//
+const TABLES: [(&str, &str); 6] = [
+    ("climate_data", "NOT is_pump_start AND timestamp < (SELECT COALESCE(MAX(bucket), '-infinity') FROM climate_hourly)"),
+    ("analog_data", "TRUE"),
+    ("ambient_data", "TRUE"),
+    ("wind_data", "TRUE"),
+    ("leaf_wetness", "TRUE"),
+    ("weight_data", "TRUE"),
+];
+
+//
+// @brief  zstd level, 9 keeps a Pi busy for seconds a month rather than minutes
+//
+const LEVEL: i32 = 9;
+
+//
+// @brief  read size when restoring, the decompressed CSV is streamed in pieces this big
+//
+const CHUNK_BYTES: usize = 64 * 1024;
+
+///
+/// @return where a month of `table` goes; a month archived before (rows that came in late)
+///         gets a numbered file next to the first rather than overwriting it
+///
+fn archive_path(dir: &Path, table: &str, month: DateTime<Utc>) -> PathBuf {
+    let dir = dir.join(table);
+    let name = month.format("%Y-%m").to_string();
+    (1..).map(|n| match n {
+        1 => dir.join(format!("{}.csv.zst", name)),
+        n => dir.join(format!("{}+{}.csv.zst", name, n)),
+    }).find(|path| !path.exists()).unwrap()
+}
+
+///
+/// @return the months whose every reading is older than `cutoff`, oldest first
+///
+fn months(oldest: DateTime<Utc>, cutoff: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let mut months = Vec::new();
+    let mut month = month_start(oldest);
+    while next_month(month) <= cutoff {
+        months.push(month);
+        month = next_month(month);
+    }
+    months
+}
+
+///
+/// @brief writes one month of `table` to a file and deletes it from the database, both in
+///        the same snapshot so nothing is deleted that isn't in the file
+///
+/// @return the rows moved and the file, None when the month had none
+///
+async fn archive_month(client: &mut Client, dir: &Path, table: &str, filter: &str, month: DateTime<Utc>)
+        -> Result<Option<(u64, PathBuf)>, Box<dyn Error>> {
+    let predicate = format!("timestamp >= '{}' AND timestamp < '{}' AND {}",
+        month.to_rfc3339(), next_month(month).to_rfc3339(), filter);
+    let tx = client.build_transaction().isolation_level(IsolationLevel::RepeatableRead).start().await?;
+
+    let count: i64 = tx.query_one(&format!("SELECT COUNT(*) FROM {} WHERE {}", table, predicate), &[]).await?.get(0);
+    if count == 0 {
+        return Ok(None);
+    }
+
+    let path = archive_path(dir, table, month);
+    let partial = path.with_extension("partial");
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    let written: Result<(), Box<dyn Error>> = async {
+        let mut encoder = zstd::Encoder::new(std::fs::File::create(&partial)?, LEVEL)?;
+        let stream = tx.copy_out(&format!("COPY (SELECT * FROM {} WHERE {} ORDER BY timestamp) TO STDOUT WITH (FORMAT csv, HEADER true)",
+            table, predicate)).await?;
+        pin_mut!(stream);
+        while let Some(chunk) = stream.next().await {
+            encoder.write_all(&chunk?)?;
+        }
+        encoder.finish()?.sync_all()?;
+        Ok(())
+    }.await;
+    written.inspect_err(|_| { let _ = std::fs::remove_file(&partial); })?;
+    std::fs::rename(&partial, &path)?;
+
+    let deleted = tx.execute(&format!("DELETE FROM {} WHERE {}", table, predicate), &[]).await?;
+    tx.commit().await?;
+    Ok(Some((deleted, path)))
+}
+
+///
+/// @brief archives every whole month older than `after_days` in each raw table
+///
+/// @note with a `holder`, only runs while it holds the `archive` lease, one instance
+///       archiving at a time is enough when several share the database
+///
+/// @return how many rows were moved out of the database
+///
+pub async fn run_once(config: &ArchiveConfig, holder: Option<&str>) -> Result<u64, Box<dyn Error>> {
+    let (mut client, connection) = db::establish_connection().await?;
+    let connection = tokio::spawn(connection);
+    if let Some(holder) = holder {
+        if !lease::acquire(&client, "archive", holder, 2 * 60 * 60 * config.every_hours).await? {
+            drop(client);
+            let _ = connection.await;
+            return Ok(0);
+        }
+    }
+
+    let cutoff = Utc::now() - Duration::days(config.after_days as i64);
+    let mut moved = 0;
+    for (table, filter) in TABLES {
+        let oldest: Option<DateTime<Utc>> = client.query_one(&format!("SELECT MIN(timestamp) FROM {} WHERE {}", table, filter), &[])
+            .await?.get(0);
+        for month in oldest.map_or(Vec::new(), |oldest| months(oldest, cutoff)) {
+            if let Some((rows, path)) = archive_month(&mut client, Path::new(&config.dir), table, filter, month).await? {
+                logging::send(6, &format!("Archived {} rows of {} to {}", rows, table, path.display()),
+                    &[("SERVICE", &"archive"), ("EVENT", &"archive"), ("FILE", &path.display())]);
+                moved += rows;
+            }
+        }
+    }
+
+    drop(client);
+    let _ = connection.await;
+    Ok(moved)
+}
+
+///
+/// @brief archives every `every_hours`, starting right away; failures raise an
+///        `archive_failed` alert
+///
+pub fn spawn(config: ArchiveConfig, holder: String, bus: Bus) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = run_once(&config, Some(&holder)).await {
+                alerts::raise(&bus, "archive_failed", &format!("Archiving old readings failed: {}", e));
+            }
+            sleep(std::time::Duration::from_secs(config.every_hours * 60 * 60)).await;
+        }
+    });
+}
+
+///
+/// @brief `bonsai-bot db restore-archive <file>`: loads an archive into `restored_<table>`,
+///        next to the live table so the archiver leaves it alone
+///
+/// @note the table is taken from the directory the file is in, as the archiver laid it out
+///
+/// @return the table it went into and how many rows
+///
+pub async fn restore(client: &Client, file: &Path) -> Result<(String, u64), Box<dyn Error>> {
+    let table = file.parent().and_then(Path::file_name).and_then(|name| name.to_str())
+        .filter(|name| TABLES.iter().any(|(table, _)| table == name))
+        .ok_or_else(|| format!("{} isn't in a directory named after an archived table", file.display()))?;
+
+    let mut reader = BufReader::new(zstd::Decoder::new(std::fs::File::open(file)?)?);
+    let mut header = String::new();
+    reader.read_line(&mut header)?;
+    // the header names the columns, so archives from before a column was added still load
+    let columns: Vec<&str> = header.trim_end().split(',').collect();
+    if columns.iter().any(|c| c.is_empty() || !c.chars().all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_')) {
+        return Err(format!("{} doesn't start with a CSV header", file.display()).into());
+    }
+
+    client.batch_execute(&format!("CREATE TABLE IF NOT EXISTS restored_{0} (LIKE {0} INCLUDING ALL)", table)).await?;
+    let sink = client.copy_in(&format!("COPY restored_{} ({}) FROM STDIN WITH (FORMAT csv)", table, columns.join(", "))).await?;
+    pin_mut!(sink);
+    loop {
+        let mut chunk = vec![0; CHUNK_BYTES];
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        chunk.truncate(read);
+        sink.send(std::io::Cursor::new(chunk)).await?;
+    }
+    let rows = sink.as_mut().finish().await?;
+    Ok((format!("restored_{}", table), rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    pub fn test_archive() {
+        let at = |y, m, d| Utc.with_ymd_and_hms(y, m, d, 0, 0, 0).unwrap();
+        assert_eq!(months(at(2023, 11, 20), at(2024, 2, 15)), vec![at(2023, 11, 1), at(2023, 12, 1), at(2024, 1, 1)]);
+        assert_eq!(months(at(2024, 2, 3), at(2024, 3, 1)), vec![at(2024, 2, 1)]);
+        assert!(months(at(2024, 2, 3), at(2024, 2, 28)).is_empty());
+
+        let dir = std::env::temp_dir().join(format!("bonsai-archive-{}", std::process::id()));
+        let first = archive_path(&dir, "wind_data", at(2024, 5, 1));
+        assert_eq!(first, dir.join("wind_data/2024-05.csv.zst"));
+        std::fs::create_dir_all(first.parent().unwrap()).unwrap();
+        std::fs::write(&first, b"").unwrap();
+        assert_eq!(archive_path(&dir, "wind_data", at(2024, 5, 1)), dir.join("wind_data/2024-05+2.csv.zst"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use crate::ipc::Request;
+use tokio_postgres::Client;
+
+///
+/// @brief where a manual action came from
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Cli,
+    Http,
+    #[cfg(feature = "dbus")]
+    Dbus,
+    #[cfg(feature = "homekit")]
+    Homekit,
+    Config,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Cli => "cli",
+            Source::Http => "http",
+            #[cfg(feature = "dbus")]
+            Source::Dbus => "dbus",
+            #[cfg(feature = "homekit")]
+            Source::Homekit => "homekit",
+            Source::Config => "config",
+        }
+    }
+}
+
+///
+/// @brief who asked for an action: the interface plus whatever identity it could vouch for
+///        (unix uid on the control socket, token name over HTTP, unique bus name on D-Bus,
+///        file path for config)
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub source: Source,
+    pub identity: Option<String>,
+}
+
+impl Origin {
+    pub fn new(source: Source, identity: Option<String>) -> Origin {
+        Origin { source, identity }
+    }
+
+    ///
+    /// @brief e.g. "cli uid 1000", or just "http" when nothing could be vouched for
+    ///
+    pub fn describe(&self) -> String {
+        match &self.identity {
+            Some(identity) => format!("{} {}", self.source.as_str(), identity),
+            None => self.source.as_str().to_string(),
+        }
+    }
+}
+
+///
+/// @brief the action name and detail to log for a request, None for read-only requests
+///
+pub fn describe(request: &Request) -> Option<(String, String)> {
+    if matches!(request, Request::Status | Request::Metrics | Request::Maintenance | Request::Recent { .. }) {
+        return None;
+    }
+    let detail = serde_json::to_value(request).ok()?;
+    let action = detail.get("cmd")?.as_str()?.to_string();
+    Some((action, detail.to_string()))
+}
+
+///
+/// @brief appends a row to audit_log; the table rejects updates and deletes
+///
+pub async fn record(client: &Client, origin: &Origin, action: &str, detail: &str) -> Result<(), tokio_postgres::Error> {
+    client.execute("INSERT INTO audit_log (source, identity, action, detail) VALUES ($1, $2, $3, $4)",
+        &[&origin.source.as_str(), &origin.identity, &action, &detail]).await?;
+    Ok(())
+}
+
+///
+/// @brief logs a request if it changes anything, read-only requests are skipped
+///
+pub async fn record_request(client: &Client, origin: &Origin, request: &Request) -> Result<(), tokio_postgres::Error> {
+    match describe(request) {
+        Some((action, detail)) => record(client, origin, &action, &detail).await,
+        None => Ok(()),
+    }
+}
+
+///
+/// @brief logs the config the daemon started with, unless it matches the last one logged
+///
+/// @return true if the config changed since the last start
+///
+pub async fn record_config(client: &Client, path: &str, contents: &str) -> Result<bool, tokio_postgres::Error> {
+    let last = client.query_opt("SELECT detail FROM audit_log WHERE action = 'config' AND mine(device_id) ORDER BY id DESC LIMIT 1", &[]).await?;
+    if last.is_some_and(|row| row.get::<_, String>(0) == contents) {
+        return Ok(false);
+    }
+    record(client, &Origin::new(Source::Config, Some(path.to_string())), "config", contents).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_describe() {
+        assert_eq!(describe(&Request::Status), None);
+        assert_eq!(describe(&Request::RunPump { seconds: 1.5 }),
+            Some(("run_pump".to_string(), r#"{"cmd":"run_pump","seconds":1.5}"#.to_string())));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::config::{Scope, TokenSpec};
+use crate::output;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+
+//
+// @brief  random bytes in a generated token, printed as hex
+//
+const TOKEN_BYTES: usize = 32;
+
+///
+/// @brief a freshly generated API token, only ever shown once
+///
+pub fn generate() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+///
+/// @brief `bonsai-bot token`: prints a new token and the config entry which accepts it
+///
+pub fn print_new(name: String, scope: Scope, json: bool) -> Result<(), Box<dyn Error>> {
+    let token = generate();
+    let spec = TokenSpec { name, sha256: hash(&token), scope };
+    if json {
+        output::json(&serde_json::json!({ "token": token, "config": spec }));
+        return Ok(());
+    }
+    println!("Token for {}, shown only this once:\n\n    {}\n", spec.name, token);
+    println!("Add this to the config and restart the daemon:\n\n[[http.tokens]]\n{}", toml::to_string(&spec)?);
+    Ok(())
+}
+
+///
+/// @brief what the config stores instead of the token itself
+///
+pub fn hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+///
+/// @brief the configured token matching `token`, if it grants at least `needed`
+///
+/// @return Err(true) when the token is known but lacks the scope, Err(false) when unknown
+///
+pub fn check<'a>(tokens: &'a [TokenSpec], token: &str, needed: Scope) -> Result<&'a TokenSpec, bool> {
+    let hashed = hash(token);
+    match tokens.iter().find(|spec| spec.sha256.eq_ignore_ascii_case(&hashed)) {
+        Some(spec) if spec.scope >= needed => Ok(spec),
+        Some(_) => Err(true),
+        None => Err(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_check() {
+        let grafana = generate();
+        let phone = generate();
+        assert_eq!(grafana.len(), 2 * TOKEN_BYTES);
+        assert_eq!(hash("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        let tokens = vec![
+            TokenSpec { name: "grafana".to_string(), sha256: hash(&grafana), scope: Scope::Read },
+            TokenSpec { name: "phone".to_string(), sha256: hash(&phone), scope: Scope::Control },
+        ];
+        assert_eq!(check(&tokens, &grafana, Scope::Read).map(|t| t.name.as_str()), Ok("grafana"));
+        assert_eq!(check(&tokens, &grafana, Scope::Control).map(|t| t.name.as_str()), Err(true));
+        assert_eq!(check(&tokens, &phone, Scope::Read).map(|t| t.name.as_str()), Ok("phone"));
+        assert_eq!(check(&tokens, "guess", Scope::Read).map(|t| t.name.as_str()), Err(false));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::config::BlackboxConfig;
+use chrono::{DateTime, Duration, Local, Utc};
+use serde_json::{Map, Value};
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+//
+// @brief  the box is two files, new records go to CURRENT until it is half the configured
+//         size, then it becomes PREVIOUS and CURRENT starts over
+//
+const CURRENT: &str = "current.jsonl";
+const PREVIOUS: &str = "previous.jsonl";
+
+static RECORDER: OnceLock<Mutex<Recorder>> = OnceLock::new();
+
+struct Recorder {
+    dir: PathBuf,
+    half_bytes: u64,
+    file: File,
+    written: u64,
+}
+
+impl Recorder {
+    fn open(dir: &Path, max_kib: u64) -> std::io::Result<Recorder> {
+        std::fs::create_dir_all(dir)?;
+        // appends, so the records from before a crash loop survive it
+        let file = OpenOptions::new().create(true).append(true).open(dir.join(CURRENT))?;
+        let written = file.metadata()?.len();
+        Ok(Recorder { dir: dir.to_path_buf(), half_bytes: max_kib * 1024 / 2, file, written })
+    }
+
+    fn write(&mut self, line: &str) -> std::io::Result<()> {
+        if self.written > 0 && self.written + line.len() as u64 > self.half_bytes {
+            std::fs::rename(self.dir.join(CURRENT), self.dir.join(PREVIOUS))?;
+            self.file = OpenOptions::new().create(true).write(true).truncate(true).open(self.dir.join(CURRENT))?;
+            self.written = 0;
+        }
+        // a single write per record, so a crash tears at most the last line
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+fn line(at: DateTime<Utc>, priority: u8, message: &str, fields: &[(&str, &dyn Display)]) -> String {
+    let mut record = Map::new();
+    record.insert("at".to_string(), Value::from(at.to_rfc3339()));
+    record.insert("priority".to_string(), Value::from(priority));
+    record.insert("message".to_string(), Value::from(message));
+    for (name, value) in fields {
+        record.insert(name.to_string(), Value::from(value.to_string()));
+    }
+    format!("{}\n", Value::Object(record))
+}
+
+///
+/// @brief starts recording everything logged through `logging::send` under `config.dir`
+///
+pub fn open(config: &BlackboxConfig) -> std::io::Result<()> {
+    let recorder = Recorder::open(Path::new(&config.dir), config.max_kib)?;
+    let _ = RECORDER.set(Mutex::new(recorder));
+    Ok(())
+}
+
+///
+/// @brief adds a record to the box, if it's open
+///
+/// @note failing to write is only worth a line in the journal, the box is a diagnostic aid
+///
+pub fn record(priority: u8, message: &str, fields: &[(&str, &dyn Display)]) {
+    let Some(recorder) = RECORDER.get() else {
+        return;
+    };
+    let mut recorder = recorder.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = recorder.write(&line(Utc::now(), priority, message, fields)) {
+        crate::logging::print(4, &format!("Could not write to the blackbox in {}: {}", recorder.dir.display(), e));
+    }
+}
+
+///
+/// @return a record as one line: local time, priority, message and the other fields
+///
+fn format_record(record: &Map<String, Value>) -> Option<String> {
+    let at: DateTime<Utc> = record.get("at")?.as_str()?.parse().ok()?;
+    let fields: Vec<String> = record.iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "at" | "priority" | "message"))
+        .map(|(name, value)| format!("{}={}", name, value.as_str().unwrap_or_default()))
+        .collect();
+    Some(format!("{} <{}> {}  {}", at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+        record.get("priority")?, record.get("message")?.as_str()?, fields.join(" ")).trim_end().to_string())
+}
+
+///
+/// @brief `bonsai-bot blackbox dump`: prints the box oldest first, only the last `hours` if given;
+///        with `json` the records as they were stored
+///
+/// @note a line torn by a power cut is skipped
+///
+pub fn dump(config: &BlackboxConfig, hours: Option<u64>, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let since = hours.map(|hours| Utc::now() - Duration::hours(hours as i64));
+    let dir = Path::new(&config.dir);
+    for name in [PREVIOUS, CURRENT] {
+        let file = match File::open(dir.join(name)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("{}: {}", dir.join(name).display(), e).into()),
+        };
+        for line in BufReader::new(file).lines() {
+            let Ok(Value::Object(record)) = serde_json::from_str::<Value>(&line?) else {
+                continue;
+            };
+            let at = record.get("at").and_then(Value::as_str).and_then(|at| at.parse::<DateTime<Utc>>().ok());
+            if since.is_some_and(|since| at.is_none_or(|at| at < since)) {
+                continue;
+            }
+            if json {
+                crate::output::json(&record);
+            } else if let Some(line) = format_record(&record) {
+                println!("{}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_blackbox() {
+        let at = "2024-05-01T12:00:00Z".parse().unwrap();
+        let written = line(at, 6, "Queued 21.40, 72.30", &[("SERVICE", &"climate"), ("RH", &72.3)]);
+        let Ok(Value::Object(record)) = serde_json::from_str::<Value>(&written) else {
+            panic!("{} isn't a JSON object", written);
+        };
+        let expected = format!("{} <6> Queued 21.40, 72.30  RH=72.3 SERVICE=climate", at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"));
+        assert_eq!(format_record(&record), Some(expected));
+
+        let dir = std::env::temp_dir().join(format!("bonsai-blackbox-{}", std::process::id()));
+        let mut recorder = Recorder::open(&dir, 1).unwrap();
+        for _ in 0..5 {
+            recorder.write(&written).unwrap();
+        }
+        // five records are over the 512 bytes a file holds, so the first ones moved over
+        let size = |name| std::fs::metadata(dir.join(name)).map_or(0, |m| m.len());
+        assert!(5 * written.len() > 512);
+        assert!(size(PREVIOUS) > 0 && size(PREVIOUS) <= 512 && size(CURRENT) <= 512);
+        assert_eq!((size(PREVIOUS) + size(CURRENT)) % written.len() as u64, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
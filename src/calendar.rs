@@ -0,0 +1,158 @@
+use crate::config::ReminderSpec;
+use crate::maintenance::ConsumableStatus;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+//
+// @brief  iCalendar's UTC date-time and date formats
+//
+const DATE_TIME: &str = "%Y%m%dT%H%M%SZ";
+const DATE: &str = "%Y%m%d";
+
+///
+/// @brief when an entry happens: a timed run, or a whole day
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum When {
+    At(DateTime<Utc>, Duration),
+    Day(NaiveDate),
+}
+
+///
+/// @brief one VEVENT; `uid` stays the same across fetches so calendars update rather than
+///        duplicate it
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub uid: String,
+    pub summary: String,
+    pub when: When,
+    pub every_days: Option<u32>,
+}
+
+///
+/// @brief the pump runs from `next` on, every `every` until `until`
+///
+pub fn pump_runs(next: DateTime<Utc>, every: Duration, run: Duration, until: DateTime<Utc>) -> Vec<Entry> {
+    std::iter::successors(Some(next), |at| Some(*at + every))
+        .take_while(|at| *at < until)
+        .map(|at| Entry {
+            uid: format!("pump-{}@bonsai-bot", at.format(DATE_TIME)),
+            summary: "Bonsai pump run".to_string(),
+            when: When::At(at, run),
+            every_days: None,
+        })
+        .collect()
+}
+
+///
+/// @brief the day each calendar-interval consumable falls due, today for overdue ones
+///
+/// @note runtime intervals depend on how much the actuator runs, so they can't be put on a
+///       calendar
+///
+pub fn maintenance_due(consumables: &[ConsumableStatus], today: NaiveDate) -> Vec<Entry> {
+    consumables.iter().filter_map(|c| {
+        let due = (c.serviced_at + Duration::days(c.every_days? as i64)).date_naive().max(today);
+        Some(Entry {
+            uid: format!("maintenance-{}-{}@bonsai-bot", c.name.replace(' ', "-"), c.serviced_at.format(DATE_TIME)),
+            summary: format!("Service the bonsai {}", c.name),
+            when: When::Day(due),
+            every_days: None,
+        })
+    }).collect()
+}
+
+pub fn reminders(specs: &[ReminderSpec]) -> Vec<Entry> {
+    specs.iter().enumerate().map(|(i, spec)| Entry {
+        uid: format!("reminder-{}-{}@bonsai-bot", i, spec.start.format(DATE)),
+        summary: spec.summary.clone(),
+        when: When::Day(spec.start),
+        every_days: Some(spec.every_days),
+    }).collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+///
+/// @brief ends a content line, folding it at 75 octets as RFC 5545 asks
+///
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+///
+/// @brief the whole feed as an iCalendar document
+///
+pub fn render(entries: &[Entry], now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//bonsai-bot//watering and maintenance//EN");
+    push_line(&mut out, "X-WR-CALNAME:Bonsai");
+    for entry in entries {
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(&mut out, &format!("UID:{}", entry.uid));
+        push_line(&mut out, &format!("DTSTAMP:{}", now.format(DATE_TIME)));
+        match entry.when {
+            When::At(at, run) => {
+                push_line(&mut out, &format!("DTSTART:{}", at.format(DATE_TIME)));
+                push_line(&mut out, &format!("DTEND:{}", (at + run).format(DATE_TIME)));
+            },
+            When::Day(day) => {
+                push_line(&mut out, &format!("DTSTART;VALUE=DATE:{}", day.format(DATE)));
+            },
+        }
+        if let Some(days) = entry.every_days {
+            push_line(&mut out, &format!("RRULE:FREQ=DAILY;INTERVAL={}", days));
+        }
+        push_line(&mut out, &format!("SUMMARY:{}", escape(&entry.summary)));
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    pub fn test_calendar() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let runs = pump_runs(now + Duration::hours(6), Duration::hours(24), Duration::seconds(30), now + Duration::days(3));
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[2].when, When::At(Utc.with_ymd_and_hms(2024, 5, 3, 18, 0, 0).unwrap(), Duration::seconds(30)));
+
+        let wick = |serviced_at, every_days| ConsumableStatus {
+            name: "humidifier wick".to_string(), serviced_at, days: 0.0, every_days,
+            runtime_hours: None, every_runtime_hours: Some(500.0), due: false,
+        };
+        let due = maintenance_due(&[wick(now - Duration::days(10), Some(30)), wick(now - Duration::days(40), Some(30)), wick(now, None)], now.date_naive());
+        assert_eq!(due.iter().map(|e| e.when.clone()).collect::<Vec<_>>(),
+            vec![When::Day(NaiveDate::from_ymd_opt(2024, 5, 21).unwrap()), When::Day(now.date_naive())]);
+
+        let feed = render(&[runs[0].clone(), Entry {
+            uid: "reminder-0@bonsai-bot".to_string(),
+            summary: "Fertilize; half strength, then water".to_string(),
+            when: When::Day(NaiveDate::from_ymd_opt(2024, 4, 1).unwrap()),
+            every_days: Some(14),
+        }], now);
+        assert!(feed.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(feed.contains("\r\nDTSTART:20240501T180000Z\r\nDTEND:20240501T180030Z\r\n"));
+        assert!(feed.contains("\r\nDTSTART;VALUE=DATE:20240401\r\nRRULE:FREQ=DAILY;INTERVAL=14\r\n"));
+        assert!(feed.contains("SUMMARY:Fertilize\\; half strength\\, then water\r\n"));
+        assert!(feed.lines().all(|line| line.len() <= 76));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::config::ChaosConfig;
+use crate::hal::{HalError, I2cDevice, InputLine, OutputLine, Platform, PwmLine, Result, SpiDevice};
+use crate::ipc::Fault;
+use crate::logging;
+use chrono::{Duration, Utc};
+use std::sync::{Mutex, OnceLock};
+use tokio_postgres::Client;
+
+static CHAOS: OnceLock<Chaos> = OnceLock::new();
+
+///
+/// @brief decides when a fault fires: once for each time it was asked for over the control
+///        socket, otherwise at random with its configured probability
+///
+struct Chaos {
+    config: ChaosConfig,
+    armed: Mutex<Vec<Fault>>,
+}
+
+impl Chaos {
+    fn probability(&self, fault: Fault) -> f64 {
+        match fault {
+            Fault::I2cNak => self.config.i2c_nak,
+            Fault::Gpio => self.config.gpio,
+            Fault::DbDisconnect => self.config.db_disconnect,
+            Fault::ClockJump => self.config.clock_jump,
+        }
+    }
+
+    fn fires(&self, fault: Fault) -> bool {
+        let mut armed = self.armed.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(i) = armed.iter().position(|f| *f == fault) {
+            armed.remove(i);
+            return true;
+        }
+        rand::random::<f64>() < self.probability(fault)
+    }
+}
+
+fn fires(fault: Fault) -> bool {
+    CHAOS.get().is_some_and(|chaos| chaos.fires(fault))
+}
+
+///
+/// @brief turns fault injection on for the rest of the run
+///
+pub fn enable(config: ChaosConfig) {
+    let _ = CHAOS.set(Chaos { config, armed: Mutex::new(Vec::new()) });
+}
+
+///
+/// @brief `bonsai-bot inject <fault>`: the next I2C transfer or GPIO switch fails, or the next
+///        climate tick drops the database connection or jumps the clock
+///
+pub fn arm(fault: Fault) -> std::result::Result<String, String> {
+    let chaos = CHAOS.get().ok_or("Fault injection is off, the config has no [chaos] section")?;
+    chaos.armed.lock().unwrap_or_else(|e| e.into_inner()).push(fault);
+    logging::send(4, &format!("Injecting {:?} on demand", fault), &[("SERVICE", &"chaos"), ("EVENT", &"armed"), ("FAULT", &format!("{:?}", fault))]);
+    Ok(format!("Armed {:?}, it fires on the next chance", fault))
+}
+
+fn injected(fault: Fault) -> HalError {
+    logging::send(4, &format!("Injected {:?}", fault), &[("SERVICE", &"chaos"), ("EVENT", &"injected"), ("FAULT", &format!("{:?}", fault))]);
+    HalError::Injected(match fault {
+        Fault::I2cNak => "i2c NAK",
+        _ => "gpio failure",
+    })
+}
+
+///
+/// @brief runs once a climate tick: may kill the database session, leaving the daemon to
+///        notice and reconnect, and may step the system clock by `clock_jump_secs`
+///
+/// @note stepping the clock needs CAP_SYS_TIME, and NTP will step it back
+///
+pub async fn tick(client: &Client) {
+    let Some(chaos) = CHAOS.get() else {
+        return;
+    };
+    if chaos.fires(Fault::DbDisconnect) {
+        logging::send(4, "Injected DbDisconnect", &[("SERVICE", &"chaos"), ("EVENT", &"injected"), ("FAULT", &"DbDisconnect")]);
+        // the server hangs up on us, the same as a Postgres restart
+        let _ = client.batch_execute("SELECT pg_terminate_backend(pg_backend_pid())").await;
+    }
+    if chaos.fires(Fault::ClockJump) {
+        let to = Utc::now() + Duration::seconds(chaos.config.clock_jump_secs);
+        logging::send(4, &format!("Injected ClockJump to {}", to.to_rfc3339()),
+            &[("SERVICE", &"chaos"), ("EVENT", &"injected"), ("FAULT", &"ClockJump")]);
+        match std::process::Command::new("date").args(["-u", "-s", &format!("@{}", to.timestamp())]).output() {
+            Ok(output) if output.status.success() => {},
+            Ok(output) => logging::send(3, &format!("Could not set the clock: {}", String::from_utf8_lossy(&output.stderr).trim()),
+                &[("SERVICE", &"chaos"), ("EVENT", &"error")]),
+            Err(e) => logging::send(3, &format!("Could not run date: {}", e), &[("SERVICE", &"chaos"), ("EVENT", &"error")]),
+        }
+    }
+}
+
+///
+/// @brief wraps a platform so its I2C devices and GPIO lines fail when their fault fires
+///
+pub fn wrap(platform: Box<dyn Platform>) -> Box<dyn Platform> {
+    Box::new(ChaosPlatform { inner: platform })
+}
+
+struct ChaosPlatform {
+    inner: Box<dyn Platform>,
+}
+
+impl Platform for ChaosPlatform {
+    fn output(&self, pin: u8, initial_high: bool) -> Result<Box<dyn OutputLine>> {
+        Ok(Box::new(ChaosOutput { inner: self.inner.output(pin, initial_high)? }))
+    }
+
+    fn i2c(&self, bus: u8, address: u16) -> Result<Box<dyn I2cDevice>> {
+        Ok(Box::new(ChaosI2c { inner: self.inner.i2c(bus, address)? }))
+    }
+
+    fn input(&self, pin: u8, pull_up: bool) -> Result<Box<dyn InputLine>> {
+        Ok(Box::new(ChaosInput { inner: self.inner.input(pin, pull_up)? }))
+    }
+
+    fn pwm(&self, pin: u8, frequency: f64) -> Result<Box<dyn PwmLine>> {
+        self.inner.pwm(pin, frequency)
+    }
+
+    fn spi(&self, bus: u8, chip_select: u8, clock_hz: u32) -> Result<Box<dyn SpiDevice>> {
+        self.inner.spi(bus, chip_select, clock_hz)
+    }
+}
+
+struct ChaosI2c {
+    inner: Box<dyn I2cDevice>,
+}
+
+impl I2cDevice for ChaosI2c {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if fires(Fault::I2cNak) {
+            return Err(injected(Fault::I2cNak));
+        }
+        self.inner.read(buf)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        if fires(Fault::I2cNak) {
+            return Err(injected(Fault::I2cNak));
+        }
+        self.inner.write(bytes)
+    }
+
+    fn write_read(&mut self, bytes: &[u8], buf: &mut [u8]) -> Result<()> {
+        if fires(Fault::I2cNak) {
+            return Err(injected(Fault::I2cNak));
+        }
+        self.inner.write_read(bytes, buf)
+    }
+}
+
+struct ChaosOutput {
+    inner: Box<dyn OutputLine>,
+}
+
+impl OutputLine for ChaosOutput {
+    fn set_high(&mut self) -> Result<()> {
+        if fires(Fault::Gpio) {
+            return Err(injected(Fault::Gpio));
+        }
+        self.inner.set_high()
+    }
+
+    fn set_low(&mut self) -> Result<()> {
+        if fires(Fault::Gpio) {
+            return Err(injected(Fault::Gpio));
+        }
+        self.inner.set_low()
+    }
+}
+
+struct ChaosInput {
+    inner: Box<dyn InputLine>,
+}
+
+impl InputLine for ChaosInput {
+    fn is_high(&mut self) -> Result<bool> {
+        if fires(Fault::Gpio) {
+            return Err(injected(Fault::Gpio));
+        }
+        self.inner.is_high()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_fires() {
+        let chaos = Chaos {
+            config: ChaosConfig { gpio: 1.0, ..Default::default() },
+            armed: Mutex::new(vec![Fault::I2cNak, Fault::I2cNak]),
+        };
+        assert!(chaos.fires(Fault::I2cNak));
+        assert!(chaos.fires(Fault::I2cNak));
+        assert!(!chaos.fires(Fault::I2cNak));
+        assert!(!chaos.fires(Fault::DbDisconnect));
+        assert!(chaos.fires(Fault::Gpio));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::config::Scope;
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+///
+/// @brief command line interface; with no subcommand the climate daemon runs
+///
+#[derive(Debug, Parser)]
+#[command(name = "bonsai-bot", version, disable_version_flag = true, about = "Climate control for bonsai trees")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Print the version; with --verbose also the commit, build time, features and config hash
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// Used with --version
+    #[arg(long, requires = "version")]
+    pub verbose: bool,
+
+    /// Print one line of JSON instead of text, for scripts; errors too, as {"error": ...}
+    #[arg(long, global = true)]
+    pub json: bool,
+}
+
+impl Command {
+    ///
+    /// @brief whether the subcommand has a `--json` form; all but the daemon, which logs to the
+    ///        journal rather than printing a result, and the TUI, which takes over the terminal
+    ///
+    pub fn has_json(&self) -> bool {
+        !matches!(self, Command::Run { .. } | Command::Tui)
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the climate control daemon (the default)
+    Run {
+        /// Run the control logic and log what it would switch, without driving any outputs
+        #[arg(long)]
+        shadow: bool,
+        /// Start without the [self_test] hardware self-test
+        #[arg(long)]
+        skip_self_test: bool,
+    },
+    /// Provision the database, config file and systemd unit on this machine
+    Install(InstallArgs),
+    /// Show the latest reading and actuator states
+    Status,
+    /// Print service timing metrics from the running daemon (Prometheus text format)
+    Metrics,
+    /// Run the pump now
+    Pump {
+        /// How long to run it for
+        #[arg(long, default_value_t = crate::PUMP_DURATION_SECS)]
+        seconds: f64,
+    },
+    /// Run the fan now
+    Fan {
+        /// How long to run it for
+        #[arg(long, default_value_t = crate::FAN_DURATION_SECS)]
+        seconds: f64,
+    },
+    /// List consumables and whether they are due for service
+    Maintenance,
+    /// Mark a consumable as serviced, restarting its intervals
+    Serviced {
+        /// The consumable's name in the config, e.g. "humidifier wick"
+        name: String,
+    },
+    /// Put the tree into dormancy or wake it, or let chill hours decide again
+    Dormancy {
+        #[arg(value_enum)]
+        mode: crate::dormancy::Mode,
+    },
+    /// Let a heater latched off by its runaway protection run again, once it's been checked
+    HeaterClear,
+    /// Acknowledge a pending alert so it stops escalating, or every pending alert
+    Ack {
+        /// The alert kind, e.g. "sensor_failed"
+        kind: Option<String>,
+    },
+    /// List the config's profiles, or switch to one; the daemon restarts to run with it
+    Profile {
+        /// The profile to switch to, e.g. "winter", or "default" for none
+        name: Option<String>,
+    },
+    /// Switch a relay on a remote MQTT node, e.g. `relay shelf humidifier on`
+    Relay {
+        /// The node's name in the [mqtt] config
+        node: String,
+        /// The ESPHome switch id
+        relay: String,
+        #[arg(value_enum)]
+        state: RelayState,
+    },
+    /// Generate an HTTP API token and print the config entry for it
+    Token {
+        /// Who the token is for, shows up in the audit log
+        #[arg(long)]
+        name: String,
+        /// `read` for dashboards, `control` to also run the pump and fan
+        #[arg(long, value_enum, default_value_t = Scope::Read)]
+        scope: Scope,
+    },
+    /// Measure how fast RH rises and decays and suggest a humidifier band, uses BONSAIBOT_DATABASE_URL
+    Tune,
+    /// Replay stored readings under another humidifier band: cycles, runtime and time in band;
+    /// uses BONSAIBOT_DATABASE_URL
+    Analyze(AnalyzeArgs),
+    /// Weekly humidity stability reports: time in band, humidifier duty cycle and the worst
+    /// excursions; uses BONSAIBOT_DATABASE_URL
+    Stability {
+        /// How many weeks back to show
+        #[arg(long, default_value_t = 8)]
+        weeks: u32,
+    },
+    /// Watch the running daemon live: readings, history, actuators and recent events
+    Tui,
+    /// Calibrate a soil moisture probe step by step (air, water, saturated soil); uses BONSAIBOT_DATABASE_URL
+    Calibrate {
+        /// The probe's [[adc.channels]] name
+        channel: String,
+    },
+    /// Note something done to the tree, e.g. "Repotted", for charts to mark; uses BONSAIBOT_DATABASE_URL
+    Journal {
+        note: String,
+        /// When it happened (RFC 3339), defaults to now
+        #[arg(long)]
+        at: Option<DateTime<Utc>>,
+    },
+    /// Print a Grafana dashboard (JSON, for Import dashboard) for the tables this config fills
+    GrafanaDashboard,
+    /// Check the config for mistakes before restarting the daemon with it
+    CheckConfig {
+        /// Config file to check, defaults to BONSAIBOT_CONFIG or /etc/bonsai-bot/config.toml
+        #[arg(long)]
+        config: Option<String>,
+    },
+    /// Install a newer release now if there is one, as configured in [update]
+    Update,
+    /// Exit non-zero unless the daemon answers and has read the climate recently, for
+    /// container health checks
+    Healthcheck,
+    /// Make the running daemon suffer a fault once; needs the chaos feature and a [chaos] section
+    Inject {
+        #[arg(value_enum)]
+        fault: crate::ipc::Fault,
+    },
+    /// Database maintenance, uses BONSAIBOT_DATABASE_URL
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Every device sharing the database, uses BONSAIBOT_DATABASE_URL
+    #[command(subcommand)]
+    Fleet(FleetCommand),
+    /// The on-disk record of recent readings, switches and errors, as configured in [blackbox]
+    #[command(subcommand)]
+    Blackbox(BlackboxCommand),
+    /// systemd integration for installs that don't go through `install`
+    #[command(subcommand)]
+    Systemd(SystemdCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SystemdCommand {
+    /// Print the unit file `install` writes, for this binary and config
+    Gen(GenArgs),
+    /// Encrypt a credential read from stdin for the unit, e.g. database-url or one the config
+    /// names as credential:NAME
+    Credential {
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BlackboxCommand {
+    /// Print the records, oldest first
+    Dump {
+        /// Only the last this many hours
+        #[arg(long)]
+        hours: Option<u64>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FleetCommand {
+    /// Each device's state, latest reading and recent alerts
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// Create any missing tables and indexes
+    Init,
+    /// Fill the database with realistic synthetic history for dashboard development
+    Seed {
+        /// How many days of history to generate, ending now
+        #[arg(long, default_value_t = 30)]
+        days: i64,
+        /// Seed even if climate_data already has rows
+        #[arg(long)]
+        force: bool,
+    },
+    /// Export climate history for offline analysis, one file per month
+    Export(ExportArgs),
+    /// Back up the whole database now, as configured in [backup]
+    Backup,
+    /// Move old raw readings to compressed files now, as configured in [archive]
+    Archive,
+    /// Load archived months back into restored_<table> tables for analysis
+    RestoreArchive {
+        /// Files written by the archiver, e.g. <dir>/climate_data/2024-05.csv.zst
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// File format; parquet needs a build with the parquet feature
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub format: ExportFormat,
+
+    /// Directory to write into, files go to <out>/climate_data/YYYY-MM.<format>
+    #[arg(long, default_value = ".")]
+    pub out: String,
+
+    /// Only export readings from this time on (RFC 3339)
+    #[arg(long)]
+    pub from: Option<DateTime<Utc>>,
+
+    /// Only export readings before this time (RFC 3339)
+    #[arg(long)]
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    /// Switch the humidifier on below this RH, defaults to [humidifier] rh_low
+    #[arg(long)]
+    pub rh_low: Option<f64>,
+
+    /// Switch it off above this RH, defaults to [humidifier] rh_high
+    #[arg(long)]
+    pub rh_high: Option<f64>,
+
+    /// Replay readings from this time on (RFC 3339), defaults to a week before --to
+    #[arg(long)]
+    pub from: Option<DateTime<Utc>>,
+
+    /// Replay readings before this time (RFC 3339), defaults to now
+    #[arg(long)]
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RelayState {
+    On,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+#[derive(Debug, Args)]
+pub struct InstallArgs {
+    /// Postgres connection string, defaults to BONSAIBOT_DATABASE_URL
+    #[arg(long, env = "BONSAIBOT_DATABASE_URL")]
+    pub database_url: String,
+
+    /// Where to write the config file
+    #[arg(long, default_value = crate::config::DEFAULT_CONFIG_PATH)]
+    pub config: String,
+
+    /// User the service runs as, needs to be in the gpio, i2c and spi groups
+    #[arg(long, default_value = "pi")]
+    pub user: String,
+
+    /// Write the unit file but don't enable or start it
+    #[arg(long)]
+    pub no_enable: bool,
+
+    /// Skip the sensor and relay access checks
+    #[arg(long)]
+    pub skip_hardware_check: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct GenArgs {
+    /// Config file the daemon runs with, read for the directories it writes to
+    #[arg(long, default_value = crate::config::DEFAULT_CONFIG_PATH)]
+    pub config: String,
+
+    /// User the service runs as, needs to be in the gpio, i2c and spi groups
+    #[arg(long, default_value = "pi")]
+    pub user: String,
+}
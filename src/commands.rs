@@ -0,0 +1,313 @@
+use crate::config::Config;
+use crate::ipc::{self, Request, Response, StatusReport};
+use crate::sht20::SHT20;
+use crate::actuator::{self, Stagger};
+use crate::audit::{self, Origin, Source};
+use crate::{db, dormancy, expander, fleet, hal, heater, irrigation, lifecycle, maintenance, output, pins, profile};
+use crate::maintenance::ConsumableStatus;
+use chrono::{DateTime, Utc};
+use std::error::Error;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Duration};
+
+///
+/// @brief handles `status`, `metrics`, `pump` and `fan`: goes through the daemon's control socket when
+///        it is running, and only touches the hardware directly when it is not
+///
+pub async fn run(request: Request, json: bool) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+
+    match ipc::request(&config.ipc.socket, &request).await {
+        Ok(Response::Status(report)) => print_status(&report, true, json),
+        Ok(Response::Metrics { text }) if json => output::json(&serde_json::json!({ "metrics": text })),
+        Ok(Response::Metrics { text }) => print!("{}", text),
+        Ok(Response::Maintenance { consumables }) => print_maintenance(&consumables, json),
+        Ok(Response::Recent { events }) => output::json(&events),
+        Ok(Response::Ok { message }) => output::message(json, &message),
+        Ok(Response::Error { message }) => return Err(message.into()),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+            run_direct(&config, request, json).await?;
+        },
+        Err(e) => return Err(format!("Control socket {}: {}", config.ipc.socket, e).into()),
+    }
+
+    Ok(())
+}
+
+//
+// @brief  a daemon whose last climate reading is this many sampling periods old is unhealthy
+//
+const HEALTHY_PERIODS: i64 = 3;
+
+///
+/// @brief whether the daemon reporting `report` is doing its job: reading the climate every
+///        `climate_secs`, or started too recently to have missed a reading
+///
+fn healthy(report: &StatusReport, climate_secs: u64, now: DateTime<Utc>) -> Result<(), String> {
+    let allowed = chrono::Duration::seconds(HEALTHY_PERIODS * climate_secs as i64);
+    match (report.measured_at, report.started_at) {
+        (Some(at), _) if now - at <= allowed => Ok(()),
+        (Some(at), _) => Err(format!("last climate reading {} ago", lifecycle::format_uptime(now - at))),
+        (None, Some(started)) if now - started <= allowed => Ok(()),
+        (None, _) => Err("no climate reading since the daemon started".to_string()),
+    }
+}
+
+///
+/// @brief `bonsai-bot healthcheck`, for a container's HEALTHCHECK: fails unless the daemon
+///        answers on the control socket and has read the climate recently
+///
+pub async fn healthcheck(json: bool) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    match ipc::request(&config.ipc.socket, &Request::Status).await {
+        Ok(Response::Status(report)) => healthy(&report, config.sampling.climate_secs, Utc::now())?,
+        Ok(_) => return Err("unexpected response to a status request".into()),
+        Err(e) => return Err(format!("Control socket {}: {}", config.ipc.socket, e).into()),
+    }
+    output::message(json, "healthy");
+    Ok(())
+}
+
+///
+/// @brief no daemon is running, so it's safe to claim the hardware ourselves
+///
+async fn run_direct(config: &Config, request: Request, json: bool) -> Result<(), Box<dyn Error>> {
+    match request {
+        Request::Metrics | Request::Recent { .. } => Err("Metrics and recent events are only available from a running daemon".into()),
+        Request::Relay { .. } => Err("Remote relays are switched through a running daemon's MQTT connection".into()),
+        Request::Inject { .. } => Err("Faults are only injected into a running daemon".into()),
+        Request::Ack { .. } => Err("Alerts are acknowledged to the running daemon that raised them".into()),
+        Request::Maintenance | Request::Serviced { .. } | Request::Dormancy { .. } | Request::HeaterClear =>
+            run_database(config, request, json).await,
+        Request::Profile { name } => {
+            let message = profile::select(&name)?;
+            output::message(json, &format!("{}, the daemon starts with it next time", message));
+            Ok(())
+        },
+        Request::Status => read_direct(config, json).await,
+        Request::RunPump { seconds } => drive_direct(config, &request, "pump", seconds, json).await,
+        Request::RunFan { seconds } => drive_direct(config, &request, "fan", seconds, json).await,
+    }
+}
+
+///
+/// @brief `status` without a daemon: one reading straight off the SHT20
+///
+async fn read_direct(config: &Config, json: bool) -> Result<(), Box<dyn Error>> {
+    pins::validate(config)?;
+    let platform = hal::open(&config.platform)?;
+    let sht20 = Arc::new(Mutex::new(SHT20::new(platform.as_ref())?));
+    let reading = SHT20::read_climate(sht20).await?;
+    let (temperature, humidity) = reading.value;
+    let report = StatusReport {
+        temperature: Some(temperature as f64),
+        humidity: Some(humidity as f64),
+        measured_at: Some(reading.taken_at),
+        ..Default::default()
+    };
+    print_status(&report, false, json);
+    Ok(())
+}
+
+///
+/// @brief `pump` and `fan` without a daemon: runs the `role` relay, or every irrigation zone
+///        for the pump, for `seconds`
+///
+async fn drive_direct(config: &Config, request: &Request, role: &str, seconds: f64, json: bool) -> Result<(), Box<dyn Error>> {
+    ipc::check_run(seconds, config.actuation.max_manual_secs)?;
+    pins::validate(config)?;
+    let platform = hal::open(&config.platform)?;
+    let expanders = expander::open_all(platform.as_ref(), &config.expanders)?;
+    let mut relay = match role {
+        "pump" => pins::claim_pump(platform.as_ref(), &expanders, config)?,
+        _ => pins::claim(platform.as_ref(), &expanders, "fan", &config.pins.fan)?,
+    };
+    output::message(json, &format!("Daemon not running, driving the {} directly for {} s", role, seconds));
+    record_direct(request).await;
+    let mut stagger = Stagger::new(Duration::from_millis(config.actuation.stagger_ms));
+    if role == "pump" && !config.zones.is_empty() {
+        let mut zones = irrigation::open_zones(platform.as_ref(), &expanders, config)?;
+        irrigation::water(relay.as_mut(), &mut zones, &mut stagger, Some(seconds), 1.0).await;
+        return Ok(());
+    }
+    stagger.switch_on(relay.as_mut()).await;
+    sleep(actuator::run_time(seconds)).await;
+    actuator::switch_off(relay.as_mut()).await;
+    Ok(())
+}
+
+///
+/// @brief `maintenance`, `serviced`, `dormancy` and `heater-clear` without a daemon only need
+///        the database
+///
+/// @note a dormancy change or cleared heater is picked up by the daemon when it next starts
+///
+async fn run_database(config: &Config, request: Request, json: bool) -> Result<(), Box<dyn Error>> {
+    let (client, connection) = db::establish_connection().await?;
+    let connection = tokio::spawn(connection);
+    fleet::ensure(&client).await?;
+
+    match &request {
+        Request::Serviced { name } => {
+            let origin = cli_origin();
+            maintenance::mark_serviced(&client, &config.consumables, name, &origin).await?;
+            audit::record_request(&client, &origin, &request).await?;
+            output::message(json, &format!("Marked the {} as serviced", name));
+        },
+        Request::Dormancy { mode } => {
+            let origin = cli_origin();
+            let mut state = dormancy::load(&client).await?;
+            output::message(json, &dormancy::set(&client, &mut state, *mode, &origin.describe()).await?);
+            audit::record_request(&client, &origin, &request).await?;
+        },
+        Request::HeaterClear => {
+            let origin = cli_origin();
+            heater::record_clear(&client, &origin.describe()).await?;
+            audit::record_request(&client, &origin, &request).await?;
+            output::message(json, "Heater cleared, it runs again when the daemon starts");
+        },
+        _ => {
+            maintenance::ensure_tracked(&client, &config.consumables).await?;
+            print_maintenance(&maintenance::status(&client, &config.consumables).await?, json);
+        },
+    }
+
+    drop(client);
+    let _ = connection.await;
+    Ok(())
+}
+
+///
+/// @brief whoever is running this command, as far as the audit log is concerned
+///
+fn cli_origin() -> Origin {
+    let uid = std::fs::metadata("/proc/self").map(|m| format!("uid {}", m.uid())).ok();
+    Origin::new(Source::Cli, uid)
+}
+
+///
+/// @brief the daemon isn't there to log it, so a direct run writes its own audit row
+///
+/// @note a missing or unreachable database only warns, the run still goes ahead
+///
+async fn record_direct(request: &Request) {
+    let origin = cli_origin();
+    let result = match db::establish_connection().await {
+        Ok((client, connection)) => {
+            let connection = tokio::spawn(connection);
+            let result = match fleet::ensure(&client).await {
+                Ok(()) => audit::record_request(&client, &origin, request).await,
+                Err(e) => Err(e),
+            }.map_err(|e| e.to_string());
+            drop(client);
+            let _ = connection.await;
+            result
+        },
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = result {
+        eprintln!("Warning: not recorded in audit log: {}", e);
+    }
+}
+
+fn print_maintenance(consumables: &[ConsumableStatus], json: bool) {
+    if json {
+        return output::json(&consumables);
+    }
+    if consumables.is_empty() {
+        println!("No consumables configured");
+    }
+    for c in consumables {
+        let mut progress = vec![match c.every_days {
+            Some(every) => format!("{:.0} of {} days", c.days, every),
+            None => format!("{:.0} days", c.days),
+        }];
+        if let Some(hours) = c.runtime_hours {
+            progress.push(match c.every_runtime_hours {
+                Some(every) => format!("{:.1} of {} h run", hours, every),
+                None => format!("{:.1} h run", hours),
+            });
+        }
+        println!("{:<20} serviced {}, {}{}", c.name, c.serviced_at.format("%Y-%m-%d"), progress.join(", "),
+            if c.due { ", DUE" } else { "" });
+    }
+}
+
+fn print_status(report: &StatusReport, from_daemon: bool, json: bool) {
+    if json {
+        // `daemon` false: read straight off the sensor, none of the other fields mean anything
+        let mut value = serde_json::to_value(report).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            fields.insert("daemon".to_string(), from_daemon.into());
+        }
+        return output::json(&value);
+    }
+    match (report.temperature, report.humidity, report.measured_at) {
+        (Some(t), Some(rh), Some(at)) =>
+            println!("Temperature {:3.2} C, humidity {:3.2} % at {}", t, rh, at.format("%Y-%m-%d %H:%M:%S %Z")),
+        _ => println!("No reading yet"),
+    }
+    if from_daemon {
+        if report.shadow {
+            println!("Shadow mode: outputs are not driven, states below are what it would do");
+        }
+        if report.dormant {
+            println!("Dormant: watering less often and holding a lower RH");
+        }
+        if let Some(variant) = &report.variant {
+            println!("Experiment variant {} today", variant);
+        }
+        println!("Humidifier {}", if report.humidifier_on { "on" } else { "off" });
+        if report.mister_on {
+            println!("Mister on");
+        }
+        if let Some(reason) = &report.heater_latched {
+            println!("Heater LATCHED OFF: {}, clear with `bonsai-bot heater-clear`", reason);
+        } else if report.heater_on {
+            println!("Heater on");
+        }
+        if report.light_on {
+            println!("Grow light on");
+        }
+        if let Some(kmh) = report.wind_kmh {
+            println!("Wind {:.1} km/h", kmh);
+        }
+        if let Some(grams) = report.tank_grams {
+            println!("Humidifier tank {:.0} g", grams);
+        }
+        if let Some(grams) = report.pot_grams {
+            println!("Pot {:.0} g", grams);
+        }
+        if let Some(next) = report.next_pump {
+            println!("Next pump run at {}", next.format("%Y-%m-%d %H:%M:%S %Z"));
+        }
+        if let Some(started) = report.started_at {
+            println!("Up {}, restarted {} time(s) in the 24 h before that",
+                lifecycle::format_uptime(Utc::now() - started), report.restarts_last_day);
+        }
+    } else {
+        println!("(daemon not running, read the sensor directly)");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_healthy() {
+        let now = Utc::now();
+        let ago = |secs| Some(now - chrono::Duration::seconds(secs));
+        let mut report = StatusReport { measured_at: ago(120), started_at: ago(3600), ..Default::default() };
+        assert_eq!(healthy(&report, 60, now), Ok(()));
+        report.measured_at = ago(181);
+        assert_eq!(healthy(&report, 60, now), Err("last climate reading 3 min ago".to_string()));
+        report.measured_at = None;
+        assert!(healthy(&report, 60, now).is_err());
+        report.started_at = ago(30);
+        assert_eq!(healthy(&report, 60, now), Ok(()));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::config::CompactConfig;
+use crate::storage::Write;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+///
+/// @brief decides which readings compact storage keeps: the first of each series, any that
+///        moved more than its deadband from the last kept one, and a keyframe every
+///        `keyframe_mins` so gaps in the data still mean the daemon wasn't running
+///
+/// @note events (actuators, doors, alerts, ...) are always kept
+/// @note a reading only becomes the one later ones are measured against once it's queued, and
+///       stops being it if the queue drops it, so the series resumes from the next reading
+///
+pub struct Compactor {
+    config: CompactConfig,
+    kept: HashMap<String, (DateTime<Utc>, Vec<f64>)>,
+}
+
+// a reading's series, when it was taken, its values and their deadbands
+struct Reading {
+    series: String,
+    at: DateTime<Utc>,
+    values: Vec<f64>,
+    bands: Vec<f64>,
+}
+
+impl Compactor {
+    pub fn new(config: CompactConfig) -> Compactor {
+        Compactor { config, kept: HashMap::new() }
+    }
+
+    ///
+    /// @return whether `write` is to be stored
+    ///
+    pub fn keep(&self, write: &Write) -> bool {
+        let Some(reading) = self.reading(write) else {
+            return true;
+        };
+        let keyframe = Duration::minutes(self.config.keyframe_mins as i64);
+        match self.kept.get(&reading.series) {
+            None => true,
+            Some((last_at, last)) => reading.at - *last_at >= keyframe
+                || reading.values.iter().zip(last).zip(&reading.bands).any(|((value, last), band)| (value - last).abs() > *band),
+        }
+    }
+
+    ///
+    /// @brief notes `write` was queued, later readings of its series are measured against it
+    ///
+    pub fn kept(&mut self, write: &Write) {
+        if let Some(reading) = self.reading(write) {
+            self.kept.insert(reading.series, (reading.at, reading.values));
+        }
+    }
+
+    ///
+    /// @brief notes `write` never made it to the database, its series keeps the next reading
+    ///
+    pub fn lost(&mut self, write: &Write) {
+        if let Some(reading) = self.reading(write) {
+            if self.kept.get(&reading.series).is_some_and(|(at, _)| *at == reading.at) {
+                self.kept.remove(&reading.series);
+            }
+        }
+    }
+
+    fn reading(&self, write: &Write) -> Option<Reading> {
+        let c = &self.config;
+        let (series, at, values, bands) = match write {
+            Write::Climate { at, temperature, humidity, variant } =>
+                (format!("climate/{}", variant.unwrap_or_default()), *at, vec![*temperature, *humidity], vec![c.temperature_c, c.humidity_pct]),
+            Write::Ambient { at, temperature, humidity, .. } =>
+                ("ambient".to_string(), *at, vec![*temperature, *humidity], vec![c.temperature_c, c.humidity_pct]),
+            Write::Analog { at, channel, voltage, .. } =>
+                (format!("analog/{}", channel), *at, vec![*voltage], vec![c.volts]),
+            _ => return None,
+        };
+        Some(Reading { series, at, values, bands })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_keep() {
+        let mut compactor = Compactor::new(CompactConfig::default());
+        let t0 = Utc::now();
+        let climate = |mins, temperature, humidity| Write::Climate { at: t0 + Duration::minutes(mins), temperature, humidity, variant: None };
+        let mut keep = |write: Write| {
+            let keep = compactor.keep(&write);
+            if keep {
+                compactor.kept(&write);
+            }
+            keep
+        };
+
+        assert!(keep(climate(0, 22.0, 70.0)));
+        assert!(!keep(climate(5, 22.1, 70.9)));
+        // measured against the last kept reading, not the last one seen, so drift still shows
+        assert!(!keep(climate(10, 22.2, 70.5)));
+        assert!(keep(climate(15, 22.25, 70.5)));
+        assert!(keep(climate(20, 22.25, 69.0)));
+        assert!(!keep(climate(49, 22.25, 69.0)));
+        assert!(keep(climate(50, 22.25, 69.0)));
+        assert!(keep(Write::Door { opened_at: t0, closed_at: t0 }));
+
+        // a kept reading the queue dropped doesn't hold back the ones after it
+        compactor.lost(&climate(50, 22.25, 69.0));
+        assert!(compactor.keep(&climate(51, 22.25, 69.0)));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::config::CondensationConfig;
+
+//
+// @brief  Magnus formula coefficients over water (Sonntag 1990), good from -45 to 60 C
+//
+const MAGNUS_A: f64 = 17.62;
+const MAGNUS_B: f64 = 243.12;
+
+//
+// @brief  how much further above the dew point the glass has to get before the risk clears,
+//         so a wall hovering at the margin doesn't flap the fan
+//
+const CLEAR_HYSTERESIS_C: f64 = 1.0;
+
+///
+/// @brief the temperature at which air at `temp_c` and `rh` percent starts condensing
+///
+pub fn dew_point(temp_c: f64, rh: f64) -> f64 {
+    let gamma = (rh.clamp(1.0, 100.0) / 100.0).ln() + MAGNUS_A * temp_c / (MAGNUS_B + temp_c);
+    MAGNUS_B * gamma / (MAGNUS_A - gamma)
+}
+
+///
+/// @brief the enclosure wall temperature: the probe if one is configured and has been read,
+///        otherwise an estimate `wall_factor` of the way from the room to the enclosure
+///
+/// @return None with neither a probe reading nor a room temperature to go on
+///
+pub fn glass_temperature(config: &CondensationConfig, probe_volts: Option<f64>, room_temp: Option<f64>, enclosure_temp: f64) -> Option<f64> {
+    match (&config.probe, probe_volts, room_temp) {
+        (Some(probe), Some(volts), _) => Some((volts - probe.volts_offset) * probe.degrees_per_volt),
+        (Some(_), None, _) => None,
+        (None, _, Some(room)) => Some(room + config.wall_factor * (enclosure_temp - room)),
+        (None, _, None) => None,
+    }
+}
+
+///
+/// @brief at risk while the glass is within `margin_c` of the dew point
+///
+pub struct Condensation {
+    margin: f64,
+    run_fan: bool,
+    at_risk: bool,
+}
+
+impl Condensation {
+    pub fn new(config: &CondensationConfig) -> Condensation {
+        Condensation { margin: config.margin_c, run_fan: config.run_fan, at_risk: false }
+    }
+
+    ///
+    /// @brief feeds the latest dew point and glass temperature
+    ///
+    /// @return Some(true) when the risk starts, Some(false) when it clears
+    ///
+    pub fn update(&mut self, dew_point: f64, glass: f64) -> Option<bool> {
+        let headroom = glass - dew_point;
+        if !self.at_risk && headroom < self.margin {
+            self.at_risk = true;
+            return Some(true);
+        }
+        if self.at_risk && headroom > self.margin + CLEAR_HYSTERESIS_C {
+            self.at_risk = false;
+            return Some(false);
+        }
+        None
+    }
+
+    pub fn is_at_risk(&self) -> bool {
+        self.at_risk
+    }
+
+    ///
+    /// @brief whether the fan should stay on regardless of its own schedule
+    ///
+    pub fn holds_fan(&self) -> bool {
+        self.at_risk && self.run_fan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GlassProbeSpec;
+
+    #[test]
+    pub fn test_condensation() {
+        assert!((dew_point(20.0, 50.0) - 9.26).abs() < 0.01);
+        assert!((dew_point(25.0, 100.0) - 25.0).abs() < 0.01);
+
+        let config = CondensationConfig::default();
+        assert_eq!(glass_temperature(&config, None, Some(16.0), 24.0), Some(20.0));
+        assert_eq!(glass_temperature(&config, Some(0.7), None, 24.0), None);
+        let probe = CondensationConfig { probe: Some(GlassProbeSpec { channel: "glass".to_string(), volts_offset: 0.5, degrees_per_volt: 100.0 }), ..config };
+        assert!((glass_temperature(&probe, Some(0.7), Some(16.0), 24.0).unwrap() - 20.0).abs() < 1e-9);
+
+        let mut condensation = Condensation::new(&probe);
+        assert_eq!(condensation.update(15.0, 20.0), None);
+        assert_eq!(condensation.update(18.5, 20.0), Some(true));
+        assert_eq!(condensation.update(17.5, 20.0), None);
+        assert!(condensation.is_at_risk());
+        assert_eq!(condensation.update(16.5, 20.0), Some(false));
+    }
+}
@@ -0,0 +1,1977 @@
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::error::{self, BonsaiError};
+use std::path::Path;
+
+//
+// @brief  default location of the configuration file, overridden by BONSAIBOT_CONFIG
+//
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/bonsai-bot/config.toml";
+pub const DEFAULT_I2C_DEVICE: &str  = "/dev/i2c-{bus}";
+
+//
+// @brief  the profile the daemon was last switched to, in its StateDirectory;
+//         BONSAIBOT_PROFILE overrides it. `default` is the config without any profile
+//
+pub const PROFILE_PATH: &str    = "/var/lib/bonsai-bot/profile";
+pub const DEFAULT_PROFILE: &str = "default";
+
+///
+/// @brief top level daemon configuration, loaded from TOML
+///
+/// @note each `[profiles.NAME]` table is laid over the rest of the file when that profile is
+///       active, after the one it `inherits` from if any; `profile` says which one is
+///
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub platform: PlatformConfig,
+    pub pins: PinConfig,
+    pub expanders: BTreeMap<String, ExpanderSpec>,
+    pub adc: Option<AdcConfig>,
+    pub ipc: IpcConfig,
+    pub actuation: ActuationConfig,
+    pub pump: PumpConfig,
+    pub zones: Vec<ZoneSpec>,
+    pub http: Option<HttpConfig>,
+    pub retention: RetentionConfig,
+    pub compact: Option<CompactConfig>,
+    pub humidifier: HumidifierConfig,
+    pub misting: Option<MistingConfig>,
+    pub heater: Option<HeaterConfig>,
+    pub light: Option<LightConfig>,
+    pub sampling: SamplingConfig,
+    pub ambient: Option<AmbientConfig>,
+    pub door: Option<DoorConfig>,
+    pub alerts: AlertsConfig,
+    pub current: Vec<CurrentSensorSpec>,
+    pub consumables: Vec<ConsumableSpec>,
+    pub experiment: Option<ExperimentConfig>,
+    pub tuner: Option<TunerConfig>,
+    pub condensation: Option<CondensationConfig>,
+    pub leaf_wetness: Option<LeafWetnessConfig>,
+    pub dormancy: DormancyConfig,
+    pub backup: Option<BackupConfig>,
+    pub archive: Option<ArchiveConfig>,
+    pub update: Option<UpdateConfig>,
+    pub blackbox: Option<BlackboxConfig>,
+    pub chaos: Option<ChaosConfig>,
+    pub telemetry: Vec<TelemetrySpec>,
+    pub plant_sensors: Vec<PlantSensorSpec>,
+    pub mqtt: Option<MqttConfig>,
+    pub lora: Option<LoraConfig>,
+    pub homekit: Option<HomekitConfig>,
+    pub webhooks: Vec<WebhookSpec>,
+    pub notify: Option<NotifyConfig>,
+    pub watchdog: Option<WatchdogConfig>,
+    pub wind: Option<WindConfig>,
+    pub tank: Option<TankConfig>,
+    pub pot: Option<PotConfig>,
+    pub watering_feedback: WateringFeedbackConfig,
+    pub until_wet: Option<UntilWetConfig>,
+    pub self_test: Option<SelfTestConfig>,
+    pub logging: LoggingConfig,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, toml::Table>,
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+///
+/// @brief which gpio/i2c backend to drive the hardware through
+///
+/// @note `rppal` only works on Raspberry Pis; `cdev` uses the kernel's gpio character device
+///       and i2c-dev, in which case pin numbers are line offsets on `gpiochip` and bus n is
+///       opened at `i2c_device` with `{bus}` replaced by n. Containers want `cdev`, which
+///       only needs those devices passed in rather than /dev/gpiomem
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlatformConfig {
+    pub backend: Backend,
+    pub gpiochip: String,
+    pub i2c_device: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Rppal,
+    Cdev,
+}
+
+impl Default for PlatformConfig {
+    fn default() -> Self {
+        PlatformConfig {
+            backend: Backend::Rppal,
+            gpiochip: "/dev/gpiochip0".to_string(),
+            i2c_device: DEFAULT_I2C_DEVICE.to_string(),
+        }
+    }
+}
+
+///
+/// @brief relay pin mapping
+///
+/// @note see pinout at link below:
+/// https://www.etechnophiles.com/wp-content/uploads/2020/12/R-PI-pinout.jpg?ezimgfmt=ng:webp/ngcb40
+///
+/// @note the control board still has one additional relay for future expansion (recommend gpio 23)
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PinConfig {
+    pub fan: PinSpec,
+    pub humidifier: PinSpec,
+    pub pump: PinSpec,
+}
+
+///
+/// @brief a single output and its polarity; a BCM gpio number, or a channel on a named expander
+///
+/// @note set `active_low` for relay boards which energize the coil when the input is pulled low
+///
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PinSpec {
+    pub pin: u8,
+    #[serde(default)]
+    pub active_low: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expander: Option<String>,
+}
+
+///
+/// @brief an I2C GPIO expander which relays can be wired to, referenced by name from a PinSpec
+///
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpanderSpec {
+    pub kind: ExpanderKind,
+    pub address: u16,
+    #[serde(default = "default_i2c_bus")]
+    pub bus: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpanderKind {
+    Pcf8574,
+    Mcp23017,
+}
+
+///
+/// @brief an ADS1115 I2C ADC and the analog sensors wired to its inputs
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdcConfig {
+    #[serde(default = "default_adc_address")]
+    pub address: u16,
+    #[serde(default = "default_i2c_bus")]
+    pub bus: u8,
+    #[serde(default)]
+    pub channels: Vec<AdcChannelSpec>,
+}
+
+///
+/// @brief one single-ended ADC input
+///
+/// @note `full_scale` picks the PGA gain in volts (6.144, 4.096, 2.048, 1.024, 0.512 or 0.256)
+///       and `data_rate` is in samples per second (8 to 860); `interval_secs` defaults to the
+///       climate interval
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdcChannelSpec {
+    pub name: String,
+    pub channel: u8,
+    #[serde(default = "default_full_scale")]
+    pub full_scale: f32,
+    #[serde(default = "default_data_rate")]
+    pub data_rate: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+}
+
+///
+/// @brief an INA219 on the supply of one actuator (`humidifier`, `pump` or `fan`), checking it
+///        draws at least `on_min_amps` while switched on and at most `off_max_amps` while off
+///
+/// @note on the pump it also profiles every run's current and compares it with the last
+///       `baseline_runs` normal ones (0 turns that off), alerting when the mean or the ripple
+///       is off by more than `max_deviation` of the usual mean
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CurrentSensorSpec {
+    pub actuator: String,
+    #[serde(default = "default_ina_address")]
+    pub address: u16,
+    #[serde(default = "default_i2c_bus")]
+    pub bus: u8,
+    #[serde(default = "default_shunt_ohms")]
+    pub shunt_ohms: f64,
+    #[serde(default = "default_on_min_amps")]
+    pub on_min_amps: f64,
+    #[serde(default = "default_off_max_amps")]
+    pub off_max_amps: f64,
+    #[serde(default = "default_baseline_runs")]
+    pub baseline_runs: usize,
+    #[serde(default = "default_max_deviation")]
+    pub max_deviation: f64,
+}
+
+///
+/// @brief a part that wears out, due for service every `every_days` or after the `actuator` has
+///        run for `every_runtime_hours` since it was last serviced, whichever comes first
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConsumableSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub every_days: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub every_runtime_hours: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actuator: Option<String>,
+}
+
+///
+/// @brief pg_dump of the whole database into `dir` every `every_hours`, keeping the newest `keep`
+///
+/// @note point `dir` at a network mount, or set `upload` to a command run on every dump with
+///       `{file}` replaced by its path, e.g. `["rclone", "copy", "{file}", "s3:bonsai-backups/"]`
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackupConfig {
+    pub dir: String,
+    #[serde(default = "default_backup_hours")]
+    pub every_hours: u64,
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upload: Vec<String>,
+}
+
+///
+/// @brief moves raw readings older than `after_days` out of the database into one
+///        zstd-compressed CSV per table and month under `dir`, needs the `archive` feature
+///
+/// @note `bonsai-bot db restore-archive <file>` loads one back for analysis
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveConfig {
+    pub dir: String,
+    #[serde(default = "default_archive_after_days")]
+    pub after_days: u32,
+    #[serde(default = "default_archive_hours")]
+    pub every_hours: u64,
+}
+
+///
+/// @brief installs new releases: checks `release_url` (a GitHub "latest release" API URL,
+///        or anything serving the same JSON) every `every_hours`, needs the `update` feature
+///
+/// @note a release needs `bonsai-bot-<arch>` and its ed25519 signature by `public_key` (hex)
+///       as `bonsai-bot-<arch>.sig`; the binary at `install_path`, by default the running
+///       one, is replaced and with `restart` the daemon stops for systemd to start it again
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateConfig {
+    pub release_url: String,
+    pub public_key: String,
+    #[serde(default = "default_update_hours")]
+    pub every_hours: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install_path: Option<String>,
+    #[serde(default)]
+    pub restart: bool,
+}
+
+///
+/// @brief keeps the last `max_kib` of everything the daemon logs with structured fields
+///        (readings and the thresholds they were held to, switches, errors, alerts) in `dir`
+///
+/// @note it outlives the journal's volatile storage and a gap in the database; read it back
+///       with `bonsai-bot blackbox dump`
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlackboxConfig {
+    pub dir: String,
+    #[serde(default = "default_blackbox_kib")]
+    pub max_kib: u64,
+}
+
+///
+/// @brief fault injection for bench runs, needs the `chaos` feature: the chance each I2C
+///        transfer or GPIO switch fails, and each climate tick drops the database connection
+///        or steps the clock by `clock_jump_secs`
+///
+/// @note all zero by default, `bonsai-bot inject <fault>` then fires them one at a time
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChaosConfig {
+    pub i2c_nak: f64,
+    pub gpio: f64,
+    pub db_disconnect: f64,
+    pub clock_jump: f64,
+    pub clock_jump_secs: i64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            i2c_nak: 0.0,
+            gpio: 0.0,
+            db_disconnect: 0.0,
+            clock_jump: 0.0,
+            clock_jump_secs: 3600,
+        }
+    }
+}
+
+///
+/// @brief a hosted IoT platform every reading is pushed to, needs the `cloud` feature
+///
+/// @note ThingSpeak takes the channel's write API key and which fields hold temperature and
+///       humidity; Adafruit IO takes the account's username, key and a feed for each
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "service", rename_all = "lowercase", deny_unknown_fields)]
+pub enum TelemetrySpec {
+    Thingspeak {
+        api_key: String,
+        #[serde(default = "default_temperature_field")]
+        temperature_field: u8,
+        #[serde(default = "default_humidity_field")]
+        humidity_field: u8,
+    },
+    Adafruit {
+        username: String,
+        api_key: String,
+        temperature_feed: String,
+        humidity_feed: String,
+    },
+}
+
+///
+/// @brief a Mi Flora style soil sensor stuck in a pot outside the enclosure, read from its
+///        Bluetooth advertisements; needs the `ble` feature
+///
+/// @note `address` is the sensor's MAC, `zone` the drip zone watering that pot if any
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlantSensorSpec {
+    pub name: String,
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zone: Option<String>,
+}
+
+///
+/// @brief the MQTT broker remote ESPHome nodes publish to, needs the `mqtt` feature
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub nodes: Vec<RemoteNodeSpec>,
+}
+
+///
+/// @brief an ESP32 running ESPHome in another enclosure, its readings stored in remote_data
+///
+/// @note `topic` is the node's ESPHome topic prefix (its name unless set); `temperature` and
+///       `humidity` are the ids of its sensors, `relays` the ids of switches the daemon may
+///       command
+///
+/// @note with `humidifier` set, the daemon also runs one of those relays off the node's own
+///       humidity readings
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteNodeSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    #[serde(default = "default_remote_temperature")]
+    pub temperature: String,
+    #[serde(default = "default_remote_humidity")]
+    pub humidity: String,
+    #[serde(default)]
+    pub relays: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub humidifier: Option<RemoteHumidifierSpec>,
+}
+
+///
+/// @brief a remote node's humidifier relay, on below `rh_low` and off above `rh_high` like the
+///        local one
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteHumidifierSpec {
+    pub relay: String,
+    pub rh_low: f64,
+    pub rh_high: f64,
+}
+
+///
+/// @brief an SX127x LoRa module on SPI receiving from battery powered nodes in the garden
+///
+/// @note frequency, spreading factor, bandwidth and sync word must match the nodes'; `nodes`
+///       limits which node names are stored, empty stores every report
+///
+/// @note `reset` is the module's reset line, optional but it must be a native gpio
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoraConfig {
+    #[serde(default)]
+    pub spi_bus: u8,
+    #[serde(default)]
+    pub chip_select: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reset: Option<PinSpec>,
+    pub frequency_mhz: f64,
+    #[serde(default = "default_lora_spreading_factor")]
+    pub spreading_factor: u8,
+    #[serde(default = "default_lora_bandwidth_khz")]
+    pub bandwidth_khz: f64,
+    #[serde(default = "default_lora_sync_word")]
+    pub sync_word: u8,
+    #[serde(default)]
+    pub nodes: Vec<String>,
+}
+
+///
+/// @brief the enclosure as a HomeKit bridge for the Home app, needs the `homekit` feature
+///
+/// @note `pin` is the eight digit setup code typed in when adding the bridge; pairings are
+///       kept in `storage_dir`, delete it to pair from scratch
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HomekitConfig {
+    pub pin: String,
+    #[serde(default = "default_homekit_name")]
+    pub name: String,
+    #[serde(default = "default_homekit_storage_dir")]
+    pub storage_dir: String,
+}
+
+///
+/// @brief the hardware watchdog the daemon keeps fed while its runtime is responsive
+///
+/// @note the Pi reboots `stall_secs` plus the device's own timeout after the daemon's tasks
+///       stop being scheduled; enable the device with `dtparam=watchdog=on`
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchdogConfig {
+    #[serde(default = "default_watchdog_device")]
+    pub device: String,
+    #[serde(default = "default_watchdog_stall_secs")]
+    pub stall_secs: u64,
+}
+
+///
+/// @brief a cup anemometer for benches outside, its reed switch pulsing once a second for
+///        every `kmh_per_hz` km/h of wind
+///
+/// @note pots dry out much faster on hot windy days: while the enclosure sensor reads at least
+///       `hot_c` and the wind has averaged `windy_kmh` over the last half hour, runs last
+///       `boost` times as long and the pump waters again once `water_every_hours` have passed
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindConfig {
+    pub sensor: PinSpec,
+    #[serde(default = "default_true")]
+    pub pull_up: bool,
+    #[serde(default = "default_kmh_per_hz")]
+    pub kmh_per_hz: f64,
+    #[serde(default = "default_wind_secs")]
+    pub log_secs: u64,
+    #[serde(default = "default_windy_kmh")]
+    pub windy_kmh: f64,
+    #[serde(default = "default_hot_c")]
+    pub hot_c: f64,
+    #[serde(default = "default_wind_boost")]
+    pub boost: f64,
+    #[serde(default = "default_windy_water_hours")]
+    pub water_every_hours: u64,
+}
+
+///
+/// @brief a load cell on an HX711 amplifier, `data` (DOUT) and `clock` (PD_SCK) on native
+///        gpios; it weighs (counts - `zero_counts`) / `counts_per_gram` grams
+///
+/// @note each weighing is the median of `samples` conversions, about a tenth of a second each
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoadCellSpec {
+    pub data: PinSpec,
+    pub clock: PinSpec,
+    pub zero_counts: i64,
+    pub counts_per_gram: f64,
+    #[serde(default = "default_load_cell_samples")]
+    pub samples: usize,
+}
+
+///
+/// @brief the humidifier's water tank standing on a load cell, weighed every `every_secs`
+///        with the empty tank as its zero; less than `low_grams` of water raises a
+///        `tank_low` alert
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TankConfig {
+    pub cell: LoadCellSpec,
+    pub low_grams: f64,
+    #[serde(default = "default_tank_secs")]
+    pub every_secs: u64,
+}
+
+///
+/// @brief the pot standing on a load cell, weighed every `every_secs`: below `dry_grams` it
+///        is watered straight away, and the daily run is skipped while it weighs more
+///
+/// @note it isn't watered again within `min_gap_hours` of the last run, which keeps a pot
+///       that stays light (a clogged line, a dry-out in a heatwave) from being pumped nonstop
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PotConfig {
+    pub cell: LoadCellSpec,
+    pub dry_grams: f64,
+    #[serde(default = "default_tank_secs")]
+    pub every_secs: u64,
+    #[serde(default = "default_pot_gap_hours")]
+    pub min_gap_hours: u64,
+}
+
+///
+/// @brief checks each pump run `settle_mins` after it: unless the pot gained `min_grams` or a
+///        calibrated soil probe rose `min_moisture` percent, a `watering_ineffective` alert
+///        is raised
+///
+/// @note needs a [pot] load cell or a calibrated probe, runs without either aren't checked
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WateringFeedbackConfig {
+    pub settle_mins: u64,
+    pub min_grams: f64,
+    pub min_moisture: f64,
+}
+
+impl Default for WateringFeedbackConfig {
+    fn default() -> Self {
+        WateringFeedbackConfig {
+            settle_mins: 30,
+            min_grams: 50.0,
+            min_moisture: 3.0,
+        }
+    }
+}
+
+///
+/// @brief waters in bursts of `burst_secs` instead of one fixed run: `soak_mins` after each
+///        burst the `channel` probe is read again, and bursts go on until it reads
+///        `target_moisture` or `max_volume_ml` has gone in
+///
+/// @note needs the pump's `flow_ml_per_min`, or every zone's flow, to tell the volume; the
+///       scheduled run goes ahead as usual while the probe has no calibrated reading
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UntilWetConfig {
+    pub channel: String,
+    pub target_moisture: f64,
+    pub max_volume_ml: f64,
+    #[serde(default = "default_burst_secs")]
+    pub burst_secs: f64,
+    #[serde(default = "default_soak_mins")]
+    pub soak_mins: u64,
+}
+
+///
+/// @brief a hardware self-test at every start: each of `relays` (actuator or zone names, every
+///        one claimed when left out) is pulsed for `pulse_ms`, the SHT20 is read, and the
+///        database clock has to be within `max_clock_skew_secs` of ours
+///
+/// @note a relay with a [[current]] sensor also has to draw current during its pulse, and none
+///       after; `bonsai-bot run --skip-self-test` skips the lot
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelfTestConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relays: Option<Vec<String>>,
+    pub pulse_ms: u64,
+    pub max_clock_skew_secs: i64,
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        SelfTestConfig {
+            relays: None,
+            pulse_ms: 500,
+            max_clock_skew_secs: 30,
+        }
+    }
+}
+
+///
+/// @brief where the daemon logs: the journal, or stdout for a container runtime to collect
+///
+/// @note readings and events keep their structured fields either way, on stdout as
+///       KEY=value after the message
+///
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub output: LogOutput,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    #[default]
+    Journal,
+    Stdout,
+}
+
+///
+/// @brief a URL the chosen events are POSTed to as JSON, needs the `cloud` feature
+///
+/// @note `events` are any of reading, actuator, pump_complete, door and alert; with a
+///       `secret` each request carries `X-Bonsai-Signature: sha256=<hex HMAC of the body>`
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookSpec {
+    pub url: String,
+    pub events: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,
+}
+
+///
+/// @brief where alerts go, needs the `cloud` feature: each route sends alerts of at least
+///        `severity` (and of `kinds`, when given) to the notifiers it names
+///
+/// @note an alert kind already sent within `dedup_mins` isn't sent again; `severity`
+///       overrides the built-in severity of an alert kind, e.g. `tank_low = "critical"`
+/// @note an alert matching an escalation stays pending until acknowledged, see EscalationSpec
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotifyConfig {
+    #[serde(default = "default_notify_dedup_mins")]
+    pub dedup_mins: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub severity: BTreeMap<String, Severity>,
+    pub notifiers: Vec<NotifierSpec>,
+    pub routes: Vec<RouteSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub escalations: Vec<EscalationSpec>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+///
+/// @brief a place alerts can be sent, named for the routes
+///
+/// @note ntfy POSTs to a topic URL (with `token` as a bearer token); Telegram sends through
+///       a bot to `chat_id`; email pipes a message to `command`, a sendmail-compatible MTA;
+///       webhook POSTs the alert event as JSON, signed like [[webhooks]]
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum NotifierSpec {
+    Ntfy {
+        name: String,
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    Telegram {
+        name: String,
+        bot_token: String,
+        chat_id: String,
+    },
+    Email {
+        name: String,
+        to: String,
+        #[serde(default = "default_email_from")]
+        from: String,
+        #[serde(default = "default_email_command")]
+        command: Vec<String>,
+    },
+    Webhook {
+        name: String,
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secret: Option<String>,
+        #[serde(default = "default_webhook_retries")]
+        retries: u32,
+    },
+}
+
+impl NotifierSpec {
+    pub fn name(&self) -> &str {
+        match self {
+            NotifierSpec::Ntfy { name, .. } | NotifierSpec::Telegram { name, .. }
+                | NotifierSpec::Email { name, .. } | NotifierSpec::Webhook { name, .. } => name,
+        }
+    }
+}
+
+///
+/// @brief alerts of at least `severity` go to each notifier in `to`, as `template` with
+///        {kind}, {severity}, {message}, {device} and {at} filled in
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteSpec {
+    pub severity: Severity,
+    pub to: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kinds: Vec<String>,
+    #[serde(default = "default_notify_template")]
+    pub template: String,
+}
+
+///
+/// @brief an alert of at least `severity` nobody acknowledged within `after_mins` is sent
+///        again to the notifiers in `to`, once
+///
+/// @note acknowledge with `bonsai-bot ack`, `POST /alerts/ack` or `/ack` to a Telegram notifier's bot
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EscalationSpec {
+    pub severity: Severity,
+    pub after_mins: u64,
+    pub to: Vec<String>,
+}
+
+///
+/// @brief a second SHT20 measuring the room outside the enclosure
+///
+/// @note the SHT20 address is fixed, so it needs its own i2c bus (e.g. the i2c-gpio overlay)
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AmbientConfig {
+    pub bus: u8,
+}
+
+///
+/// @brief a reed switch on the enclosure door
+///
+/// @note `switch` reads high while the door is open (or low with `active_low`); with the
+///       default pull-up, wire the reed switch between the pin and ground
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DoorConfig {
+    pub switch: PinSpec,
+    #[serde(default = "default_true")]
+    pub pull_up: bool,
+    #[serde(default = "default_door_alert_mins")]
+    pub alert_after_mins: u64,
+}
+
+///
+/// @brief when to raise alerts about the daemon itself
+///
+/// @note more than `max_restarts_per_hour` starts within an hour (not counting the first)
+///       means something keeps killing the daemon, see system_events
+/// @note enclosure temperatures outside `temp_low`..`temp_high` raise an alert, either bound
+///       can be left out; [dormancy] has its own pair
+/// @note each of `temp_rate` alerts when the temperature moves more than its `change_c`
+///       within `within_mins`, which absolute limits catch only once it's too late
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertsConfig {
+    pub max_restarts_per_hour: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temp_low: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temp_high: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub temp_rate: Vec<TempRateSpec>,
+}
+
+///
+/// @brief a temperature change of `change_c` within `within_mins`: positive for a rise
+///        (direct sun, a heater stuck on), negative for a fall
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TempRateSpec {
+    pub change_c: f64,
+    pub within_mins: u64,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        AlertsConfig {
+            max_restarts_per_hour: 3,
+            temp_low: None,
+            temp_high: None,
+            temp_rate: Vec::new(),
+        }
+    }
+}
+
+///
+/// @brief winter rest: the pump only runs every `water_every_days`, the humidifier holds
+///        `rh_low`..`rh_high` and the temperature alert band becomes `temp_low`..`temp_high`
+///
+/// @note `bonsai-bot dormancy on|off` switches it by hand; otherwise it starts once the
+///       enclosure spent `enter_chill_hours` below `chill_below_c` over the last week, and ends
+///       once that drops under `exit_chill_hours`, each trigger off when left out
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DormancyConfig {
+    pub chill_below_c: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enter_chill_hours: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_chill_hours: Option<f64>,
+    pub water_every_days: u32,
+    pub rh_low: f64,
+    pub rh_high: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temp_low: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temp_high: Option<f64>,
+}
+
+impl Default for DormancyConfig {
+    fn default() -> Self {
+        DormancyConfig {
+            chill_below_c: 7.0,
+            enter_chill_hours: None,
+            exit_chill_hours: None,
+            water_every_days: 7,
+            rh_low: 50.0,
+            rh_high: 60.0,
+            temp_low: None,
+            temp_high: None,
+        }
+    }
+}
+
+///
+/// @brief local control interfaces: the CLI's unix socket and, when built with the `dbus`
+///        feature, the org.bonsaibot.BonsaiBot service on the system bus
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IpcConfig {
+    pub socket: String,
+    pub dbus: bool,
+}
+
+impl Default for IpcConfig {
+    fn default() -> Self {
+        IpcConfig {
+            socket: crate::ipc::DEFAULT_SOCKET_PATH.to_string(),
+            dbus: false,
+        }
+    }
+}
+
+///
+/// @brief how relays are switched
+///
+/// @note `stagger_ms` is the minimum gap between two relays switching on, so the humidifier,
+///       fan and pump never draw their inrush current from the supply at the same moment
+///
+/// @note a manual pump or fan run is refused within `manual_cooldown_secs` of the previous
+///       manual run of the same actuator finishing, whichever interface it came from, and
+///       when it asks for longer than `max_manual_secs`
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActuationConfig {
+    pub stagger_ms: u64,
+    pub manual_cooldown_secs: u64,
+    pub max_manual_secs: u64,
+}
+
+impl Default for ActuationConfig {
+    fn default() -> Self {
+        ActuationConfig {
+            stagger_ms: 250,
+            manual_cooldown_secs: 60,
+            max_manual_secs: 600,
+        }
+    }
+}
+
+///
+/// @brief how the pump is driven
+///
+/// @note `pwm` needs the pump on a MOSFET motor driver rather than a relay; it then soft starts
+///       and stops over `ramp_ms`, using software pwm at `pwm_frequency` Hz on the pump pin
+/// @note `flow_ml_per_min`, measured at the emitters, turns pump runtime into water used
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PumpConfig {
+    pub drive: PumpDrive,
+    pub pwm_frequency: f64,
+    pub ramp_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_ml_per_min: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PumpDrive {
+    Relay,
+    Pwm,
+}
+
+impl Default for PumpConfig {
+    fn default() -> Self {
+        PumpConfig {
+            drive: PumpDrive::Relay,
+            pwm_frequency: 500.0,
+            ramp_ms: 2000,
+            flow_ml_per_min: None,
+        }
+    }
+}
+
+///
+/// @brief a drip zone behind a solenoid valve; with zones configured each pump run waters them
+///        one after another instead of running the pump on its own
+///
+/// @note `flow_ml_per_min` is the measured flow into this zone; without it the flow is
+///       modelled as `emitters` drippers of `emitter_lph` liters/hour each. Either one logs
+///       delivered volume, and lets `volume_ml` set how much a run waters instead of `seconds`
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneSpec {
+    pub name: String,
+    pub valve: PinSpec,
+    #[serde(default = "default_zone_seconds")]
+    pub seconds: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flow_ml_per_min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emitters: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emitter_lph: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_ml: Option<f64>,
+}
+
+///
+/// @brief the HTTP API for dashboards and mobile clients, off unless the section is present
+///
+/// @note with no `tokens` the read endpoints are open and the control endpoints refuse
+///       everything; with tokens, every request needs one
+///
+/// @note each token gets at most `control_per_min` control requests in any minute
+///
+/// @note `mdns` advertises the API as `_bonsaibot._tcp`, needs a build with the `mdns` feature
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    #[serde(default = "default_http_listen")]
+    pub listen: String,
+    #[serde(default = "default_control_per_min")]
+    pub control_per_min: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<TokenSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub mdns: bool,
+    #[serde(default)]
+    pub calendar: CalendarConfig,
+}
+
+///
+/// @brief what `GET /calendar.ics` lists: pump runs over the next `days_ahead`, consumables
+///        falling due, and recurring reminders such as fertilizing
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalendarConfig {
+    pub days_ahead: i64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reminders: Vec<ReminderSpec>,
+}
+
+///
+/// @brief an all-day event every `every_days` from `start`, e.g. "Fertilize" every 14 days
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReminderSpec {
+    pub summary: String,
+    pub start: NaiveDate,
+    pub every_days: u32,
+}
+
+///
+/// @brief serve the HTTP API over HTTPS instead, needs a build with the `tls` feature
+///
+/// @note with `self_signed`, a certificate for this host is generated on the first start
+///       if neither `cert` nor `key` exist yet
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: String,
+    pub key: String,
+    pub self_signed: bool,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        CalendarConfig {
+            days_ahead: 14,
+            reminders: Vec::new(),
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert: "/var/lib/bonsai-bot/tls/cert.pem".to_string(),
+            key: "/var/lib/bonsai-bot/tls/key.pem".to_string(),
+            self_signed: true,
+        }
+    }
+}
+
+///
+/// @brief an API token, stored as the hex SHA-256 of the token `bonsai-bot token` printed
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenSpec {
+    pub name: String,
+    pub sha256: String,
+    pub scope: Scope,
+}
+
+///
+/// @brief what a token may do; `control` can also read
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Control,
+}
+
+///
+/// @brief the hourly job which maintains the climate rollups and prunes raw history
+///
+/// @note `raw_days` unset keeps raw readings forever; the rollups are never pruned
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_days: Option<u32>,
+    pub interval_mins: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            raw_days: None,
+            interval_mins: 60,
+        }
+    }
+}
+
+///
+/// @brief humidifier control tweaks
+///
+/// @note the humidifier switches on below `rh_low` and off above `rh_high`
+/// @note with `predictive` on, the slope of the last `trend_readings` readings is used to start
+///       the humidifier before RH actually crosses the low threshold
+/// @note after `fallback_after_failures` failed readings in a row (0 never) the humidifier runs
+///       for `fallback_duty` of every `fallback_cycle_mins` until the sensor recovers
+/// @note RH above `emergency_rh` for `emergency_after_mins` forces the humidifier off and the
+///       fan on until RH is back under the high threshold
+/// @note with `mix_fan_after_secs` set, the fan runs for `mix_fan_secs` that long after the
+///       humidifier switches on, so the sensor sees mixed air rather than the plume
+/// @note once switched, the thresholds leave the humidifier on for at least `min_on_secs`
+///       and off for at least `min_off_secs`
+/// @note a `schedule` replaces `rh_low` and `rh_high` with bands by local time of day
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HumidifierConfig {
+    pub rh_low: f64,
+    pub rh_high: f64,
+    pub predictive: bool,
+    pub trend_readings: usize,
+    pub fallback_after_failures: u32,
+    pub fallback_duty: f64,
+    pub fallback_cycle_mins: u64,
+    pub warmup_readings: u32,
+    pub emergency_rh: f64,
+    pub emergency_after_mins: u64,
+    pub mix_fan_after_secs: Option<u64>,
+    pub mix_fan_secs: f64,
+    pub min_on_secs: u64,
+    pub min_off_secs: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<SetpointSpec>,
+}
+
+impl Default for HumidifierConfig {
+    fn default() -> Self {
+        HumidifierConfig {
+            rh_low: crate::RH_LO_THRESH,
+            rh_high: crate::RH_HI_THRESH,
+            predictive: false,
+            trend_readings: 4,
+            fallback_after_failures: 3,
+            fallback_duty: 0.2,
+            fallback_cycle_mins: 30,
+            warmup_readings: 1,
+            emergency_rh: 95.0,
+            emergency_after_mins: 15,
+            mix_fan_after_secs: None,
+            mix_fan_secs: 15.0,
+            min_on_secs: 0,
+            min_off_secs: 0,
+            schedule: Vec::new(),
+        }
+    }
+}
+
+///
+/// @brief a humidifier band in force from `from` (local time) until the next entry's
+///        `from`, the last one running on past midnight into the first
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetpointSpec {
+    pub from: NaiveTime,
+    pub rh_low: f64,
+    pub rh_high: f64,
+}
+
+///
+/// @brief two sets of humidifier settings which take turns a day at a time from `start` (variant
+///        a on even days, b on odd ones) until `end`, to compare them on the same plant
+///
+/// @note days follow the local clock; readings and switches are tagged with the active variant
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentConfig {
+    pub start: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<NaiveDate>,
+    pub a: VariantSpec,
+    pub b: VariantSpec,
+}
+
+///
+/// @brief one side of an experiment; `predictive` falls back to the [humidifier] setting
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariantSpec {
+    pub rh_low: f64,
+    pub rh_high: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub predictive: Option<bool>,
+}
+
+///
+/// @brief the hysteresis auto-tuner, which measures how fast RH rises and decays over the last
+///        `days` and picks the narrowest band that keeps the humidifier under
+///        `max_cycles_per_hour`, between `min_band` and `max_band` percent wide
+///
+/// @note the daemon only logs its suggestion unless `apply` is set, and leaves running
+///       experiments alone
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TunerConfig {
+    pub apply: bool,
+    pub days: u32,
+    pub max_cycles_per_hour: f64,
+    pub min_band: f64,
+    pub max_band: f64,
+}
+
+impl Default for TunerConfig {
+    fn default() -> Self {
+        TunerConfig {
+            apply: false,
+            days: 7,
+            max_cycles_per_hour: 4.0,
+            min_band: 4.0,
+            max_band: 16.0,
+        }
+    }
+}
+
+///
+/// @brief condensation watch: while the enclosure glass is within `margin_c` of the dew point
+///        the fan runs and both humidifier thresholds drop by `rh_reduction`
+///
+/// @note the glass temperature comes from `probe` if set, otherwise it's estimated
+///       `wall_factor` of the way from the [ambient] room temperature to the enclosure's
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CondensationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probe: Option<GlassProbeSpec>,
+    pub wall_factor: f64,
+    pub margin_c: f64,
+    pub rh_reduction: f64,
+    pub run_fan: bool,
+}
+
+impl Default for CondensationConfig {
+    fn default() -> Self {
+        CondensationConfig {
+            probe: None,
+            wall_factor: 0.5,
+            margin_c: 2.0,
+            rh_reduction: 5.0,
+            run_fan: true,
+        }
+    }
+}
+
+///
+/// @brief a linear temperature sensor on the glass read through an ADC `channel` (by name),
+///        e.g. a TMP36 is `volts_offset = 0.5`, `degrees_per_volt = 100.0`
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlassProbeSpec {
+    pub channel: String,
+    #[serde(default)]
+    pub volts_offset: f64,
+    pub degrees_per_volt: f64,
+}
+
+///
+/// @brief a misting nozzle behind a solenoid `valve`, fired in short pulses while RH is low,
+///        on its own schedule rather than the humidifier's thresholds
+///
+/// @note every `every_mins` it mists for `pulse_secs` if RH is below `below_rh`, or the
+///       humidifier's rh_low when left out; at most `max_pulses_per_hour` pulses and
+///       `max_secs_per_day` of misting keep a sensor stuck low from flooding the pots
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MistingConfig {
+    pub valve: PinSpec,
+    #[serde(default = "default_pulse_secs")]
+    pub pulse_secs: f64,
+    #[serde(default = "default_mist_every_mins")]
+    pub every_mins: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub below_rh: Option<f64>,
+    #[serde(default = "default_max_pulses_per_hour")]
+    pub max_pulses_per_hour: u32,
+    #[serde(default = "default_max_mist_secs_per_day")]
+    pub max_secs_per_day: f64,
+}
+
+///
+/// @brief a heater on `relay`, switched on below `on_below` and off from `off_above`
+///
+/// @note with it on, the enclosure has to warm `min_rise_c` every `stall_mins` and never
+///       faster than `max_rise_c_per_min`, otherwise the relay or element is taken to be
+///       stuck or the sensor to be off the enclosure: the heater latches off and a
+///       `heater_runaway` alert is raised until `bonsai-bot heater-clear`
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaterConfig {
+    pub relay: PinSpec,
+    pub on_below: f64,
+    pub off_above: f64,
+    #[serde(default = "default_heater_stall_mins")]
+    pub stall_mins: u64,
+    #[serde(default = "default_heater_min_rise_c")]
+    pub min_rise_c: f64,
+    #[serde(default = "default_heater_max_rise_c_per_min")]
+    pub max_rise_c_per_min: f64,
+}
+
+///
+/// @brief a grow light on `relay`, on from `on` until `off` (local time, through midnight
+///        when `off` comes first)
+///
+/// @note `sensor` names the [[plant_sensors]] entry under the light: `check_mins` after the
+///       light switches on it has to read `min_rise_lux` more than it did in the dark, or a
+///       `light_failure` alert is raised
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LightConfig {
+    pub relay: PinSpec,
+    pub on: NaiveTime,
+    pub off: NaiveTime,
+    pub sensor: String,
+    #[serde(default = "default_light_min_rise_lux")]
+    pub min_rise_lux: f64,
+    #[serde(default = "default_light_check_mins")]
+    pub check_mins: u64,
+}
+
+///
+/// @brief compact storage: a reading is only stored once it moved more than its deadband
+///        from the last stored one, or `keyframe_mins` after it
+///
+/// @note holding the last stored value reconstructs every reading to within the deadband;
+///       the hourly and daily rollups average the stored rows rather than every reading
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompactConfig {
+    pub temperature_c: f64,
+    pub humidity_pct: f64,
+    pub volts: f64,
+    pub keyframe_mins: u64,
+}
+
+impl Default for CompactConfig {
+    fn default() -> Self {
+        CompactConfig {
+            temperature_c: 0.2,
+            humidity_pct: 1.0,
+            volts: 0.01,
+            keyframe_mins: 30,
+        }
+    }
+}
+
+///
+/// @brief a leaf wetness grid read through an ADC `channel` (by name), reading `dry_volts`
+///        dry and `wet_volts` soaked
+///
+/// @note leaves count as wet at `wet_percent` or more; infection needs `min_wet_hours` of
+///       wetness at the optimum temperature and longer either side of it
+///
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeafWetnessConfig {
+    pub channel: String,
+    pub dry_volts: f64,
+    pub wet_volts: f64,
+    #[serde(default = "default_wet_percent")]
+    pub wet_percent: f64,
+    #[serde(default = "default_min_wet_hours")]
+    pub min_wet_hours: f64,
+}
+
+///
+/// @brief climate sampling rates
+///
+/// @note the SHT20 is read every `climate_secs`; after a pump run or humidifier switch it is
+///       read every `burst_period_secs` for `burst_window_mins`, a zero window turns that off;
+///       a sensor or database tick still waiting after `tick_budget_secs` is abandoned
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplingConfig {
+    pub climate_secs: u64,
+    pub burst_period_secs: u64,
+    pub burst_window_mins: u64,
+    pub tick_budget_secs: u64,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        SamplingConfig {
+            climate_secs: 60 * crate::CLIMATE_PERIODIC_MINS as u64,
+            burst_period_secs: 30,
+            burst_window_mins: 10,
+            tick_budget_secs: 30,
+        }
+    }
+}
+
+fn default_temperature_field() -> u8 {
+    1
+}
+
+fn default_humidity_field() -> u8 {
+    2
+}
+
+fn default_backup_hours() -> u64 {
+    24
+}
+
+fn default_backup_keep() -> usize {
+    14
+}
+
+fn default_archive_after_days() -> u32 {
+    365
+}
+
+fn default_archive_hours() -> u64 {
+    24
+}
+
+fn default_update_hours() -> u64 {
+    24
+}
+
+fn default_blackbox_kib() -> u64 {
+    2048
+}
+
+fn default_i2c_bus() -> u8 {
+    1
+}
+
+fn default_adc_address() -> u16 {
+    0x48
+}
+
+fn default_full_scale() -> f32 {
+    4.096
+}
+
+fn default_data_rate() -> u16 {
+    128
+}
+
+fn default_ina_address() -> u16 {
+    0x40
+}
+
+fn default_shunt_ohms() -> f64 {
+    0.1
+}
+
+fn default_on_min_amps() -> f64 {
+    0.05
+}
+
+fn default_off_max_amps() -> f64 {
+    0.02
+}
+
+fn default_baseline_runs() -> usize {
+    10
+}
+
+fn default_max_deviation() -> f64 {
+    0.25
+}
+
+fn default_http_listen() -> String {
+    "0.0.0.0:8080".to_string()
+}
+
+fn default_control_per_min() -> usize {
+    4
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_door_alert_mins() -> u64 {
+    10
+}
+
+fn default_burst_secs() -> f64 {
+    10.0
+}
+
+fn default_soak_mins() -> u64 {
+    5
+}
+
+fn default_zone_seconds() -> f64 {
+    crate::PUMP_DURATION_SECS
+}
+
+fn default_lora_spreading_factor() -> u8 {
+    9
+}
+
+fn default_lora_bandwidth_khz() -> f64 {
+    125.0
+}
+
+// the private network sync word; 0x34 is LoRaWAN's
+fn default_lora_sync_word() -> u8 {
+    0x12
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+fn default_notify_dedup_mins() -> u64 {
+    60
+}
+
+fn default_notify_template() -> String {
+    "{message}".to_string()
+}
+
+fn default_email_from() -> String {
+    "bonsai-bot".to_string()
+}
+
+fn default_email_command() -> Vec<String> {
+    vec!["sendmail".to_string(), "-t".to_string()]
+}
+
+fn default_homekit_name() -> String {
+    "Bonsai".to_string()
+}
+
+fn default_homekit_storage_dir() -> String {
+    "/var/lib/bonsai-bot/homekit".to_string()
+}
+
+fn default_watchdog_device() -> String {
+    "/dev/watchdog".to_string()
+}
+
+fn default_watchdog_stall_secs() -> u64 {
+    60
+}
+
+fn default_pulse_secs() -> f64 {
+    3.0
+}
+
+fn default_mist_every_mins() -> u64 {
+    20
+}
+
+fn default_max_pulses_per_hour() -> u32 {
+    4
+}
+
+fn default_max_mist_secs_per_day() -> f64 {
+    180.0
+}
+
+fn default_heater_stall_mins() -> u64 {
+    20
+}
+
+fn default_heater_min_rise_c() -> f64 {
+    1.0
+}
+
+fn default_heater_max_rise_c_per_min() -> f64 {
+    2.0
+}
+
+fn default_light_min_rise_lux() -> f64 {
+    500.0
+}
+
+fn default_light_check_mins() -> u64 {
+    30
+}
+
+fn default_wet_percent() -> f64 {
+    50.0
+}
+
+fn default_min_wet_hours() -> f64 {
+    6.0
+}
+
+fn default_kmh_per_hz() -> f64 {
+    2.4
+}
+
+fn default_wind_secs() -> u64 {
+    60
+}
+
+fn default_load_cell_samples() -> usize {
+    5
+}
+
+fn default_tank_secs() -> u64 {
+    300
+}
+
+fn default_pot_gap_hours() -> u64 {
+    4
+}
+
+fn default_windy_kmh() -> f64 {
+    15.0
+}
+
+fn default_hot_c() -> f64 {
+    28.0
+}
+
+fn default_wind_boost() -> f64 {
+    1.5
+}
+
+fn default_windy_water_hours() -> u64 {
+    6
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "bonsai-bot".to_string()
+}
+
+fn default_remote_temperature() -> String {
+    "temperature".to_string()
+}
+
+fn default_remote_humidity() -> String {
+    "humidity".to_string()
+}
+
+impl ZoneSpec {
+
+    ///
+    /// @brief flow into the zone, measured if it was, else from its emitters
+    ///
+    pub fn flow_ml_per_min(&self) -> Option<f64> {
+        self.flow_ml_per_min.or_else(|| Some(self.emitters? as f64 * self.emitter_lph? * 1000.0 / 60.0))
+    }
+
+    ///
+    /// @brief how long a scheduled run waters the zone, long enough for `volume_ml` if set,
+    ///        to the millisecond
+    ///
+    pub fn run_seconds(&self) -> f64 {
+        match (self.volume_ml, self.flow_ml_per_min()) {
+            (Some(volume), Some(flow)) => (volume / flow * 60_000.0).round().max(1.0) / 1000.0,
+            _ => self.seconds,
+        }
+    }
+}
+
+impl RemoteNodeSpec {
+    pub fn topic(&self) -> &str {
+        self.topic.as_deref().unwrap_or(&self.name)
+    }
+}
+
+impl PinSpec {
+    pub fn native(pin: u8) -> PinSpec {
+        PinSpec { pin, active_low: false, expander: None }
+    }
+}
+
+impl Default for PinConfig {
+    fn default() -> Self {
+        PinConfig {
+            fan:        PinSpec::native(22),
+            humidifier: PinSpec::native(24),
+            pump:       PinSpec::native(27),
+        }
+    }
+}
+
+impl PinConfig {
+
+    ///
+    /// @brief every configured relay pin alongside the role it plays
+    ///
+    pub fn roles(&self) -> Vec<(&'static str, &PinSpec)> {
+        vec![
+            ("fan",        &self.fan),
+            ("humidifier", &self.humidifier),
+            ("pump",       &self.pump),
+        ]
+    }
+}
+
+impl Config {
+
+    ///
+    /// @brief every pin the daemon uses: the relays, each zone valve, the misting valve, the
+    ///        door switch, the anemometer, the LoRa module's reset line and the load cells
+    ///
+    pub fn gpios(&self) -> Vec<(&'static str, &PinSpec)> {
+        let mut gpios = self.pins.roles();
+        gpios.extend(self.zones.iter().map(|zone| ("valve", &zone.valve)));
+        gpios.extend(self.misting.iter().map(|misting| ("mister", &misting.valve)));
+        gpios.extend(self.heater.iter().map(|heater| ("heater", &heater.relay)));
+        gpios.extend(self.light.iter().map(|light| ("light", &light.relay)));
+        gpios.extend(self.door.iter().map(|door| ("door", &door.switch)));
+        gpios.extend(self.wind.iter().map(|wind| ("anemometer", &wind.sensor)));
+        gpios.extend(self.lora.iter().flat_map(|lora| &lora.reset).map(|reset| ("lora_reset", reset)));
+        gpios.extend(self.tank.iter().flat_map(|tank| [("tank_data", &tank.cell.data), ("tank_clock", &tank.cell.clock)]));
+        gpios.extend(self.pot.iter().flat_map(|pot| [("pot_data", &pot.cell.data), ("pot_clock", &pot.cell.clock)]));
+        gpios
+    }
+
+    ///
+    /// @brief the config file in use, BONSAIBOT_CONFIG or the default path
+    ///
+    pub fn path() -> String {
+        std::env::var("BONSAIBOT_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+    }
+
+    ///
+    /// @brief loads the config from BONSAIBOT_CONFIG (or the default path) with the active
+    ///        profile, falling back to built-in defaults when no file exists
+    ///
+    pub fn load() -> error::Result<Config> {
+        let path = Self::path();
+        if Path::new(&path).exists() {
+            Self::from_file(&path)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    ///
+    /// @return the profile to run with: BONSAIBOT_PROFILE, else the one last switched to,
+    ///         else `default`
+    ///
+    pub fn active_profile() -> String {
+        std::env::var("BONSAIBOT_PROFILE").ok()
+            .or_else(|| std::fs::read_to_string(PROFILE_PATH).ok())
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    ///
+    /// @brief parses the config file at the given path, with the active profile
+    ///
+    pub fn from_file(path: &str) -> error::Result<Config> {
+        Self::from_file_as(path, &Self::active_profile())
+    }
+
+    pub fn from_file_as(path: &str, profile: &str) -> error::Result<Config> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| BonsaiError::Config(format!("Could not read {}: {}", path, e)))?;
+        Self::parse_as(&contents, profile)
+            .map_err(|e| BonsaiError::Config(format!("Could not parse {}: {}", path, e)))
+    }
+
+    ///
+    /// @brief parses the config file at the given path as each profile it can be switched
+    ///        to, `default` first
+    ///
+    pub fn from_file_every_profile(path: &str) -> error::Result<Vec<Config>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| BonsaiError::Config(format!("Could not read {}: {}", path, e)))?;
+        let base = Self::parse_as(&contents, DEFAULT_PROFILE)
+            .map_err(|e| BonsaiError::Config(format!("Could not parse {}: {}", path, e)))?;
+        let mut configs = vec![];
+        for name in base.profiles.keys().filter(|name| *name != DEFAULT_PROFILE) {
+            configs.push(Self::parse_as(&contents, name)
+                .map_err(|e| BonsaiError::Config(format!("Could not parse {} as profile {}: {}", path, name, e)))?);
+        }
+        configs.insert(0, base);
+        Ok(configs)
+    }
+
+    ///
+    /// @brief parses config from a TOML string, without any profile
+    ///
+    pub fn parse(contents: &str) -> std::result::Result<Config, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    ///
+    /// @brief parses config from a TOML string with `profile` laid over it
+    ///
+    pub fn parse_as(contents: &str, profile: &str) -> std::result::Result<Config, String> {
+        let mut table: toml::Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let profiles = match table.get("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles.clone(),
+            _ => toml::Table::new(),
+        };
+        for layer in profile_layers(&profiles, profile)? {
+            overlay(&mut table, layer);
+        }
+        let mut config: Config = toml::Value::Table(table).try_into().map_err(|e: toml::de::Error| e.to_string())?;
+        config.profile = Some(profile.to_string()).filter(|name| name != DEFAULT_PROFILE);
+        Ok(config)
+    }
+}
+
+///
+/// @return the tables `name` is made of, the one it inherits from first; `default` is none
+///         unless the file has a [profiles.default]
+///
+fn profile_layers<'a>(profiles: &'a toml::Table, name: &str) -> std::result::Result<Vec<&'a toml::Table>, String> {
+    let mut layers = Vec::new();
+    let mut next = Some(name);
+    while let Some(name) = next {
+        if layers.len() > profiles.len() {
+            return Err(format!("profile {} inherits from itself", name));
+        }
+        let profile = match profiles.get(name) {
+            Some(toml::Value::Table(profile)) => profile,
+            None if name == DEFAULT_PROFILE => break,
+            _ => return Err(format!("no profile {}, expected {} or one of {:?}", name, DEFAULT_PROFILE, profiles.keys().collect::<Vec<_>>())),
+        };
+        next = match profile.get("inherits") {
+            Some(toml::Value::String(parent)) => Some(parent.as_str()),
+            Some(_) => return Err(format!("profiles.{}.inherits has to be a profile name", name)),
+            None => None,
+        };
+        layers.push(profile);
+    }
+    layers.reverse();
+    Ok(layers)
+}
+
+///
+/// @brief lays `layer` over `base`: tables merge key by key, anything else is replaced
+///
+fn overlay(base: &mut toml::Table, layer: &toml::Table) {
+    for (key, value) in layer.iter().filter(|(key, _)| *key != "inherits") {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(below)), toml::Value::Table(above)) => overlay(below, above),
+            _ => { base.insert(key.clone(), value.clone()); },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_pin_config() {
+        let config = Config::parse(r#"
+            [pins]
+            pump = { pin = 17, active_low = true }
+            fan = { pin = 3, expander = "relays" }
+
+            [expanders.relays]
+            kind = "mcp23017"
+            address = 0x20
+        "#).expect("Config did not parse");
+
+        assert_eq!(config.pins.pump, PinSpec { pin: 17, active_low: true, expander: None });
+        assert_eq!(config.pins.fan.expander.as_deref(), Some("relays"));
+        assert_eq!(config.pins.humidifier, PinConfig::default().humidifier);
+        assert_eq!(config.expanders["relays"].kind, ExpanderKind::Mcp23017);
+        assert_eq!(config.expanders["relays"].bus, 1);
+    }
+
+    #[test]
+    pub fn test_zone_volume() {
+        let config = Config::parse(r#"
+            [[zones]]
+            name = "juniper"
+            valve = { pin = 5 }
+            emitters = 4
+            emitter_lph = 2.0
+            volume_ml = 200
+
+            [[zones]]
+            name = "maple"
+            valve = { pin = 6 }
+            seconds = 45
+            flow_ml_per_min = 240
+            emitters = 4
+            emitter_lph = 2.0
+        "#).expect("Config did not parse");
+
+        // four 2 l/h drippers give 133 ml/min, so 200 ml takes 90 s
+        assert_eq!(config.zones[0].run_seconds(), 90.0);
+        assert_eq!(config.zones[1].flow_ml_per_min(), Some(240.0));
+        assert_eq!(config.zones[1].run_seconds(), 45.0);
+    }
+
+    #[test]
+    pub fn test_profiles() {
+        let contents = r#"
+            [humidifier]
+            rh_low = 60.0
+            rh_high = 70.0
+            min_on_secs = 30
+
+            [pump]
+            flow_ml_per_min = 240
+
+            [profiles.winter.humidifier]
+            rh_low = 50.0
+            rh_high = 60.0
+
+            [profiles.travel]
+            inherits = "winter"
+            pump = { flow_ml_per_min = 180 }
+            humidifier = { rh_high = 65.0 }
+
+            [profiles.loop]
+            inherits = "loop"
+        "#;
+        let base = Config::parse_as(contents, DEFAULT_PROFILE).unwrap();
+        assert_eq!((base.humidifier.rh_low, base.humidifier.rh_high, base.profile), (60.0, 70.0, None));
+
+        // tables merge key by key down the chain, the nearest profile winning
+        let travel = Config::parse_as(contents, "travel").unwrap();
+        assert_eq!((travel.humidifier.rh_low, travel.humidifier.rh_high, travel.humidifier.min_on_secs), (50.0, 65.0, 30));
+        assert_eq!((travel.pump.flow_ml_per_min, travel.profile.as_deref()), (Some(180.0), Some("travel")));
+
+        assert!(Config::parse_as(contents, "loop").unwrap_err().contains("inherits from itself"));
+        assert!(Config::parse_as(contents, "summer").unwrap_err().starts_with("no profile summer"));
+    }
+}
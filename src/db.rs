@@ -0,0 +1,553 @@
+use crate::error::{self, BonsaiError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use crate::logging;
+use crate::secrets;
+use tokio_postgres::{Client, NoTls};
+
+pub type Connection = Pin<Box<dyn Future<Output = Result<(), tokio_postgres::Error>> + Send>>;
+
+///
+/// @brief a numbered schema change, applied once and recorded in schema_migrations
+///
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+//
+// @brief  every schema change in order; only ever append, never edit an applied one
+//
+// @note 1 uses IF NOT EXISTS so databases created before migrations were tracked adopt it
+//
+pub const MIGRATIONS: [Migration; 26] = [
+    Migration { version: 1, name: "initial tables", sql: "
+CREATE TABLE IF NOT EXISTS climate_data (
+    timestamp       TIMESTAMPTZ NOT NULL,
+    temperature     DOUBLE PRECISION,
+    humidity        DOUBLE PRECISION,
+    is_pump_start   BOOLEAN NOT NULL DEFAULT FALSE
+);
+CREATE INDEX IF NOT EXISTS climate_data_timestamp_idx ON climate_data (timestamp);
+CREATE INDEX IF NOT EXISTS climate_data_pump_idx ON climate_data (timestamp) WHERE is_pump_start;
+
+CREATE TABLE IF NOT EXISTS analog_data (
+    timestamp       TIMESTAMPTZ NOT NULL,
+    channel         TEXT NOT NULL,
+    voltage         DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX IF NOT EXISTS analog_data_timestamp_idx ON analog_data (channel, timestamp);
+
+CREATE TABLE IF NOT EXISTS irrigation_data (
+    timestamp       TIMESTAMPTZ NOT NULL,
+    zone            TEXT NOT NULL,
+    seconds         INTEGER NOT NULL,
+    volume_ml       DOUBLE PRECISION
+);
+CREATE INDEX IF NOT EXISTS irrigation_data_timestamp_idx ON irrigation_data (zone, timestamp);
+" },
+    Migration { version: 2, name: "climate rollups", sql: "
+CREATE TABLE climate_hourly (
+    bucket          TIMESTAMPTZ PRIMARY KEY,
+    temperature_min DOUBLE PRECISION,
+    temperature_avg DOUBLE PRECISION,
+    temperature_max DOUBLE PRECISION,
+    humidity_min    DOUBLE PRECISION,
+    humidity_avg    DOUBLE PRECISION,
+    humidity_max    DOUBLE PRECISION,
+    samples         BIGINT NOT NULL
+);
+CREATE TABLE climate_daily (LIKE climate_hourly INCLUDING ALL);
+" },
+    Migration { version: 3, name: "ambient readings", sql: "
+CREATE TABLE ambient_data (
+    timestamp           TIMESTAMPTZ NOT NULL,
+    temperature         DOUBLE PRECISION NOT NULL,
+    humidity            DOUBLE PRECISION NOT NULL,
+    temperature_delta   DOUBLE PRECISION NOT NULL,
+    humidity_delta      DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX ambient_data_timestamp_idx ON ambient_data (timestamp);
+" },
+    Migration { version: 4, name: "door log", sql: "
+CREATE TABLE door_log (
+    opened_at       TIMESTAMPTZ NOT NULL,
+    closed_at       TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX door_log_opened_idx ON door_log (opened_at);
+" },
+    Migration { version: 5, name: "audit log", sql: "
+CREATE TABLE audit_log (
+    id              BIGSERIAL PRIMARY KEY,
+    timestamp       TIMESTAMPTZ NOT NULL DEFAULT now(),
+    source          TEXT NOT NULL,
+    identity        TEXT,
+    action          TEXT NOT NULL,
+    detail          TEXT NOT NULL
+);
+CREATE INDEX audit_log_timestamp_idx ON audit_log (timestamp);
+CREATE FUNCTION audit_log_append_only() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END
+$$;
+CREATE TRIGGER audit_log_append_only BEFORE UPDATE OR DELETE OR TRUNCATE ON audit_log
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only();
+" },
+    Migration { version: 6, name: "pump run end", sql: "
+ALTER TABLE climate_data
+    ADD COLUMN pump_ended_at    TIMESTAMPTZ,
+    ADD COLUMN pump_seconds     DOUBLE PRECISION;
+" },
+    Migration { version: 7, name: "system events", sql: "
+CREATE TABLE system_events (
+    id              BIGSERIAL PRIMARY KEY,
+    timestamp       TIMESTAMPTZ NOT NULL DEFAULT now(),
+    event           TEXT NOT NULL,
+    detail          TEXT
+);
+CREATE INDEX system_events_timestamp_idx ON system_events (event, timestamp);
+" },
+    Migration { version: 8, name: "actuator events", sql: "
+CREATE TABLE actuator_events (
+    timestamp       TIMESTAMPTZ NOT NULL,
+    actuator        TEXT NOT NULL,
+    is_on           BOOLEAN NOT NULL
+);
+CREATE INDEX actuator_events_timestamp_idx ON actuator_events (actuator, timestamp);
+" },
+    Migration { version: 9, name: "power readings", sql: "
+CREATE TABLE power_data (
+    timestamp       TIMESTAMPTZ NOT NULL,
+    actuator        TEXT NOT NULL,
+    commanded_on    BOOLEAN NOT NULL,
+    bus_volts       DOUBLE PRECISION NOT NULL,
+    current_amps    DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX power_data_timestamp_idx ON power_data (actuator, timestamp);
+" },
+    Migration { version: 10, name: "monthly usage", sql: "
+CREATE TABLE usage_monthly (
+    month           DATE NOT NULL,
+    actuator        TEXT NOT NULL,
+    seconds         DOUBLE PRECISION NOT NULL DEFAULT 0,
+    volume_ml       DOUBLE PRECISION NOT NULL DEFAULT 0,
+    PRIMARY KEY (month, actuator)
+);
+" },
+    Migration { version: 11, name: "maintenance log", sql: "
+CREATE TABLE maintenance_log (
+    id              BIGSERIAL PRIMARY KEY,
+    consumable      TEXT NOT NULL,
+    serviced_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    source          TEXT NOT NULL,
+    identity        TEXT,
+    note            TEXT
+);
+CREATE INDEX maintenance_log_consumable_idx ON maintenance_log (consumable, serviced_at);
+" },
+    Migration { version: 12, name: "experiment variants", sql: "
+ALTER TABLE climate_data ADD COLUMN variant TEXT;
+ALTER TABLE actuator_events ADD COLUMN variant TEXT;
+" },
+    Migration { version: 13, name: "plant sensors", sql: "
+CREATE TABLE plant_data (
+    id              BIGSERIAL PRIMARY KEY,
+    timestamp       TIMESTAMPTZ NOT NULL,
+    sensor          TEXT NOT NULL,
+    zone            TEXT,
+    metric          TEXT NOT NULL,
+    value           DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX plant_data_sensor_idx ON plant_data (sensor, metric, timestamp);
+" },
+    Migration { version: 14, name: "remote nodes", sql: "
+CREATE TABLE remote_data (
+    id              BIGSERIAL PRIMARY KEY,
+    timestamp       TIMESTAMPTZ NOT NULL,
+    node            TEXT NOT NULL,
+    metric          TEXT NOT NULL,
+    value           DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX remote_data_node_idx ON remote_data (node, metric, timestamp);
+" },
+    Migration { version: 15, name: "garden nodes", sql: "
+CREATE TABLE garden_data (
+    id              BIGSERIAL PRIMARY KEY,
+    timestamp       TIMESTAMPTZ NOT NULL,
+    node            TEXT NOT NULL,
+    metric          TEXT NOT NULL,
+    value           DOUBLE PRECISION NOT NULL,
+    rssi_dbm        INTEGER
+);
+CREATE INDEX garden_data_node_idx ON garden_data (node, metric, timestamp);
+" },
+    Migration { version: 16, name: "alerts and journal", sql: "
+CREATE TABLE alert_log (
+    timestamp       TIMESTAMPTZ NOT NULL,
+    kind            TEXT NOT NULL,
+    message         TEXT NOT NULL
+);
+CREATE INDEX alert_log_timestamp_idx ON alert_log (timestamp);
+
+CREATE TABLE journal_entries (
+    id              BIGSERIAL PRIMARY KEY,
+    timestamp       TIMESTAMPTZ NOT NULL,
+    note            TEXT NOT NULL
+);
+CREATE INDEX journal_entries_timestamp_idx ON journal_entries (timestamp);
+" },
+    Migration { version: 17, name: "wind", sql: "
+CREATE TABLE wind_data (
+    timestamp       TIMESTAMPTZ NOT NULL,
+    speed_kmh       DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX wind_data_timestamp_idx ON wind_data (timestamp);
+" },
+    Migration { version: 18, name: "leaf wetness", sql: "
+CREATE TABLE leaf_wetness (
+    timestamp       TIMESTAMPTZ NOT NULL,
+    wetness         DOUBLE PRECISION NOT NULL,
+    fungal_risk     DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX leaf_wetness_timestamp_idx ON leaf_wetness (timestamp);
+" },
+    Migration { version: 19, name: "soil probe calibration", sql: "
+CREATE TABLE probe_calibrations (
+    channel         TEXT NOT NULL,
+    calibrated_at   TIMESTAMPTZ NOT NULL,
+    air_volts       DOUBLE PRECISION NOT NULL,
+    water_volts     DOUBLE PRECISION NOT NULL,
+    soil_volts      DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (channel, calibrated_at)
+);
+
+ALTER TABLE analog_data ADD COLUMN moisture DOUBLE PRECISION;
+" },
+    Migration { version: 20, name: "instances and leases", sql: "
+CREATE FUNCTION current_instance() RETURNS TEXT LANGUAGE sql STABLE AS $$
+    SELECT COALESCE(current_setting('bonsaibot.instance_id', true), '')
+$$;
+-- rows from before instances were tracked belong to everyone
+CREATE FUNCTION mine(TEXT) RETURNS BOOLEAN LANGUAGE sql STABLE AS $$
+    SELECT $1 IN (current_instance(), '')
+$$;
+
+DO $$
+DECLARE t TEXT;
+BEGIN
+    FOREACH t IN ARRAY ARRAY['climate_data', 'analog_data', 'irrigation_data', 'ambient_data', 'door_log',
+            'audit_log', 'system_events', 'actuator_events', 'power_data', 'usage_monthly', 'maintenance_log',
+            'plant_data', 'remote_data', 'garden_data', 'alert_log', 'journal_entries', 'wind_data',
+            'leaf_wetness', 'probe_calibrations'] LOOP
+        EXECUTE format('ALTER TABLE %I ADD COLUMN instance_id TEXT NOT NULL DEFAULT ''''', t);
+        EXECUTE format('ALTER TABLE %I ALTER COLUMN instance_id SET DEFAULT current_instance()', t);
+    END LOOP;
+END
+$$;
+
+DROP INDEX climate_data_pump_idx;
+CREATE INDEX climate_data_pump_idx ON climate_data (instance_id, timestamp) WHERE is_pump_start;
+ALTER TABLE usage_monthly DROP CONSTRAINT usage_monthly_pkey, ADD PRIMARY KEY (instance_id, month, actuator);
+
+CREATE TABLE leases (
+    name            TEXT PRIMARY KEY,
+    holder          TEXT NOT NULL,
+    expires_at      TIMESTAMPTZ NOT NULL
+);
+" },
+    Migration { version: 21, name: "devices", sql: "
+CREATE TABLE devices (
+    id              TEXT PRIMARY KEY,
+    hostname        TEXT NOT NULL DEFAULT '',
+    version         TEXT NOT NULL DEFAULT '',
+    zones           TEXT[] NOT NULL DEFAULT '{}',
+    first_seen      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    started_at      TIMESTAMPTZ
+);
+-- stands for whoever wrote the rows from before devices were tracked
+INSERT INTO devices (id) VALUES ('');
+
+DO $$
+DECLARE t TEXT;
+BEGIN
+    FOREACH t IN ARRAY ARRAY['climate_data', 'analog_data', 'irrigation_data', 'ambient_data', 'door_log',
+            'audit_log', 'system_events', 'actuator_events', 'power_data', 'usage_monthly', 'maintenance_log',
+            'plant_data', 'remote_data', 'garden_data', 'alert_log', 'journal_entries', 'wind_data',
+            'leaf_wetness', 'probe_calibrations'] LOOP
+        EXECUTE format('ALTER TABLE %I RENAME COLUMN instance_id TO device_id', t);
+        EXECUTE format('INSERT INTO devices (id) SELECT DISTINCT device_id FROM %I ON CONFLICT DO NOTHING', t);
+        EXECUTE format('ALTER TABLE %I ADD FOREIGN KEY (device_id) REFERENCES devices (id)', t);
+    END LOOP;
+END
+$$;
+" },
+    Migration { version: 22, name: "load cells", sql: "
+CREATE TABLE weight_data (
+    timestamp       TIMESTAMPTZ NOT NULL,
+    cell            TEXT NOT NULL,
+    grams           DOUBLE PRECISION NOT NULL,
+    device_id       TEXT NOT NULL DEFAULT current_instance() REFERENCES devices (id)
+);
+CREATE INDEX weight_data_timestamp_idx ON weight_data (cell, timestamp);
+" },
+    Migration { version: 23, name: "stability reports", sql: "
+CREATE TABLE stability_reports (
+    week_start           TIMESTAMPTZ NOT NULL,
+    rh_low               DOUBLE PRECISION NOT NULL,
+    rh_high              DOUBLE PRECISION NOT NULL,
+    in_band_pct          DOUBLE PRECISION NOT NULL,
+    duty_pct             DOUBLE PRECISION NOT NULL,
+    max_above            DOUBLE PRECISION NOT NULL,
+    max_below            DOUBLE PRECISION NOT NULL,
+    longest_outside_mins DOUBLE PRECISION NOT NULL,
+    device_id            TEXT NOT NULL DEFAULT current_instance() REFERENCES devices (id),
+    PRIMARY KEY (device_id, week_start)
+);
+" },
+    Migration { version: 24, name: "pump current profiles", sql: "
+ALTER TABLE climate_data
+    ADD COLUMN pump_current_amps    DOUBLE PRECISION[],
+    ADD COLUMN pump_current_anomaly TEXT;
+" },
+    Migration { version: 25, name: "fractional zone seconds", sql: "
+ALTER TABLE irrigation_data ALTER COLUMN seconds TYPE DOUBLE PRECISION;
+" },
+    Migration { version: 26, name: "rollups per device", sql: "
+DO $$
+DECLARE t TEXT;
+BEGIN
+    FOREACH t IN ARRAY ARRAY['climate_hourly', 'climate_daily'] LOOP
+        EXECUTE format('ALTER TABLE %I ADD COLUMN device_id TEXT NOT NULL DEFAULT '''' REFERENCES devices (id)', t);
+        EXECUTE format('ALTER TABLE %I ALTER COLUMN device_id SET DEFAULT current_instance()', t);
+        EXECUTE format('ALTER TABLE %I DROP CONSTRAINT %I, ADD PRIMARY KEY (device_id, bucket)', t, t || '_pkey');
+    END LOOP;
+END
+$$;
+
+-- the rollups so far mixed every device's readings: rebuild the hours the raw rows still
+-- cover per device, except the oldest if it was rolled up, pruning may have cut into it
+DELETE FROM climate_hourly
+WHERE bucket > (SELECT date_trunc('hour', MIN(timestamp)) FROM climate_data WHERE NOT is_pump_start);
+INSERT INTO climate_hourly (device_id, bucket, temperature_min, temperature_avg, temperature_max,
+                            humidity_min, humidity_avg, humidity_max, samples)
+SELECT device_id, date_trunc('hour', timestamp) AS hour,
+       MIN(temperature), AVG(temperature), MAX(temperature),
+       MIN(humidity), AVG(humidity), MAX(humidity), COUNT(*)
+FROM climate_data
+WHERE NOT is_pump_start
+  AND date_trunc('hour', timestamp) NOT IN (SELECT bucket FROM climate_hourly)
+GROUP BY device_id, hour;
+
+DELETE FROM climate_daily;
+INSERT INTO climate_daily (device_id, bucket, temperature_min, temperature_avg, temperature_max,
+                           humidity_min, humidity_avg, humidity_max, samples)
+SELECT device_id, date_trunc('day', bucket) AS day,
+       MIN(temperature_min), SUM(temperature_avg * samples) / SUM(samples), MAX(temperature_max),
+       MIN(humidity_min), SUM(humidity_avg * samples) / SUM(samples), MAX(humidity_max),
+       SUM(samples)::BIGINT
+FROM climate_hourly
+GROUP BY device_id, day;
+" },
+];
+
+///
+/// @brief which bonsai-bot this is when several share a database, the id of its row in
+///        `devices`: BONSAIBOT_INSTANCE_ID, or the hostname
+///
+pub fn instance_id() -> error::Result<String> {
+    let id = match std::env::var("BONSAIBOT_INSTANCE_ID") {
+        Ok(id) => id,
+        Err(_) => std::fs::read_to_string("/proc/sys/kernel/hostname").map(|name| name.trim().to_string())
+            .unwrap_or_else(|_| "bonsai-bot".to_string()),
+    };
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+        return Err(BonsaiError::Config(format!("instance id {:?} may only use letters, digits, '-', '_' and '.'", id)));
+    }
+    Ok(id)
+}
+
+///
+/// @brief Establishes client connection to the postgres DB
+///
+/// @note the session carries the instance id, which every row it inserts is stamped with
+///       and `mine(device_id)` in a query compares against
+///
+/// @note the connection string is the database-url credential, or BONSAIBOT_DATABASE_URL
+///
+pub async fn establish_connection() -> error::Result<(Client, Connection)> {
+    let database_url = secrets::database_url()?;
+    let mut config: tokio_postgres::Config = database_url.parse()?;
+    let options = format!("{} -c bonsaibot.instance_id={}", config.get_options().unwrap_or_default(), instance_id()?);
+    config.options(options.trim_start());
+    let (client, connection) = config.connect(NoTls).await?;
+    Ok((client, Box::pin(connection)))
+}
+
+///
+/// @brief Connects to the given database url
+///
+pub async fn connect(database_url: &str) -> error::Result<(Client, Connection)> {
+    let (client, connection) = tokio_postgres::connect(database_url, NoTls).await?;
+    Ok((client, Box::pin(connection)))
+}
+
+///
+/// @brief Applies every migration the database hasn't seen yet, each in its own transaction
+///
+pub async fn migrate(client: &mut Client) -> Result<(), tokio_postgres::Error> {
+    client.batch_execute("
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version     INTEGER PRIMARY KEY,
+            name        TEXT NOT NULL,
+            applied_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+        )").await?;
+    let current: i32 = client.query_one("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", &[]).await?.get(0);
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = client.transaction().await?;
+        tx.batch_execute(migration.sql).await?;
+        tx.execute("INSERT INTO schema_migrations (version, name) VALUES ($1, $2)", &[&migration.version, &migration.name]).await?;
+        tx.commit().await?;
+        logging::print(6, &format!("Applied migration {} ({})", migration.version, migration.name));
+    }
+
+    Ok(())
+}
+
+//
+// @brief  how long to wait before reconnecting after a connection closed
+//
+pub const RECONNECT_SECS: u64 = 10;
+
+///
+/// @brief reconnects on a task of its own, retrying every RECONNECT_SECS until it gets
+///        through, and hands the new client over on `tx`
+///
+/// @note the control loop keeps running on the closed client meanwhile, its reads fail
+///       and its writes wait in the storage queue
+///
+pub fn spawn_reconnect(tx: tokio::sync::mpsc::Sender<Client>) {
+    tokio::spawn(async move {
+        loop {
+            match establish_connection().await {
+                Ok((client, connection)) => {
+                    tokio::spawn(async move {
+                        if let Err(e) = connection.await {
+                            logging::print(3, &format!("Reconnection error: {}", e));
+                        }
+                    });
+                    let _ = tx.send(client).await;
+                    return;
+                },
+                Err(e) => logging::send(3, &format!("Could not reconnect to the database: {}", e),
+                    &[("SERVICE", &"database"), ("EVENT", &"reconnect_failed")]),
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(RECONNECT_SECS)).await;
+        }
+    });
+}
+
+/// 
+/// @brief Tries to reconnect to the postgres client
+///
+pub async fn try_reconnect(postgres_client: &mut tokio_postgres::Client) -> error::Result<()> {
+    if postgres_client.is_closed() {
+        let (new_client, new_connection) = establish_connection().await?;
+        *postgres_client = new_client;
+
+        tokio::spawn(async move {
+            if let Err(e) = new_connection.await {
+                logging::print(3, &format!("Reconnection error: {}", e));
+            }
+        });
+    }
+    Ok(())
+}
+
+///
+/// @brief how finely history is bucketed before it leaves the database
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    Raw,
+    #[default]
+    Hourly,
+    Daily,
+}
+
+///
+/// @brief one bucket of climate history; for raw rows min, avg and max are the reading itself
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryPoint {
+    pub timestamp: DateTime<Utc>,
+    pub temperature_min: Option<f64>,
+    pub temperature_avg: Option<f64>,
+    pub temperature_max: Option<f64>,
+    pub humidity_min: Option<f64>,
+    pub humidity_avg: Option<f64>,
+    pub humidity_max: Option<f64>,
+    pub samples: i64,
+}
+
+///
+/// @brief climate readings in [from, to), aggregated server side at the given resolution
+///
+/// @note only this device's readings; hourly and daily come from the rollup tables, which lag
+///       by up to one retention run
+///
+pub async fn history(
+    client: &Client,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    resolution: Resolution
+) -> Result<Vec<HistoryPoint>, tokio_postgres::Error> {
+    let query = match resolution {
+        Resolution::Raw => "
+            SELECT timestamp, temperature, temperature, temperature, humidity, humidity, humidity, 1::BIGINT
+            FROM climate_data
+            WHERE NOT is_pump_start AND mine(device_id) AND timestamp >= $1 AND timestamp < $2
+            ORDER BY timestamp",
+        Resolution::Hourly => "
+            SELECT bucket, temperature_min, temperature_avg, temperature_max,
+                   humidity_min, humidity_avg, humidity_max, samples
+            FROM climate_hourly
+            WHERE mine(device_id) AND bucket >= $1 AND bucket < $2
+            ORDER BY bucket",
+        Resolution::Daily => "
+            SELECT bucket, temperature_min, temperature_avg, temperature_max,
+                   humidity_min, humidity_avg, humidity_max, samples
+            FROM climate_daily
+            WHERE mine(device_id) AND bucket >= $1 AND bucket < $2
+            ORDER BY bucket",
+    };
+
+    let rows = client.query(query, &[&from, &to]).await?;
+    Ok(rows.iter().map(|row| HistoryPoint {
+        timestamp: row.get(0),
+        temperature_min: row.get(1),
+        temperature_avg: row.get(2),
+        temperature_max: row.get(3),
+        humidity_min: row.get(4),
+        humidity_avg: row.get(5),
+        humidity_max: row.get(6),
+        samples: row.get(7),
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_migrations() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i32 + 1, "migration versions must be consecutive");
+        }
+    }
+}
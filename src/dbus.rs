@@ -0,0 +1,124 @@
+use crate::audit::{Origin, Source};
+use crate::ipc::{Command, Request, Response, StatusReport};
+use crate::logging;
+use tokio::sync::{mpsc, oneshot, watch};
+use zbus::{fdo, interface, message::Header, Connection};
+
+pub const BUS_NAME: &str    = "org.bonsaibot.BonsaiBot";
+pub const OBJECT_PATH: &str = "/org/bonsaibot/Climate";
+
+///
+/// @brief org.bonsaibot.Climate1, a read-only view of the daemon status plus manual actuation
+///
+/// @note NaN temperature/humidity means no reading has been taken yet
+///
+pub struct Climate {
+    status: watch::Receiver<StatusReport>,
+    control: mpsc::Sender<Command>,
+}
+
+#[interface(name = "org.bonsaibot.Climate1")]
+impl Climate {
+
+    #[zbus(property)]
+    fn temperature(&self) -> f64 {
+        self.status.borrow().temperature.unwrap_or(f64::NAN)
+    }
+
+    #[zbus(property)]
+    fn humidity(&self) -> f64 {
+        self.status.borrow().humidity.unwrap_or(f64::NAN)
+    }
+
+    #[zbus(property)]
+    fn humidifier_on(&self) -> bool {
+        self.status.borrow().humidifier_on
+    }
+
+    #[zbus(property)]
+    fn pump_on(&self) -> bool {
+        self.status.borrow().pump_on
+    }
+
+    #[zbus(property)]
+    fn fan_on(&self) -> bool {
+        self.status.borrow().fan_on
+    }
+
+    async fn run_pump(&self, #[zbus(header)] header: Header<'_>, seconds: f64) -> fdo::Result<String> {
+        self.forward(Request::RunPump { seconds }, &header).await
+    }
+
+    async fn run_fan(&self, #[zbus(header)] header: Header<'_>, seconds: f64) -> fdo::Result<String> {
+        self.forward(Request::RunFan { seconds }, &header).await
+    }
+}
+
+impl Climate {
+
+    ///
+    /// @brief hands the request to the daemon loop, same path the control socket uses
+    ///
+    async fn forward(&self, request: Request, header: &Header<'_>) -> fdo::Result<String> {
+        let origin = Origin::new(Source::Dbus, header.sender().map(|sender| sender.to_string()));
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.control.send((request, origin, reply_tx)).await
+            .map_err(|_| fdo::Error::Failed("Daemon is shutting down".to_string()))?;
+        match reply_rx.await {
+            Ok(Response::Ok { message }) => Ok(message),
+            Ok(Response::Error { message }) => Err(fdo::Error::Failed(message)),
+            Ok(Response::Status(_)) | Ok(Response::Metrics { .. }) | Ok(Response::Maintenance { .. }) | Ok(Response::Recent { .. }) | Err(_) =>
+                Err(fdo::Error::Failed("Unexpected response".to_string())),
+        }
+    }
+}
+
+///
+/// @brief claims the bus name on the system bus and keeps property change signals flowing
+///
+pub async fn serve(status: watch::Receiver<StatusReport>, control: mpsc::Sender<Command>) -> zbus::Result<()> {
+    let mut changes = status.clone();
+    let connection = zbus::connection::Builder::system()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Climate { status, control })?
+        .build()
+        .await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = emit_changes(&connection, &mut changes).await {
+            logging::print(3, &format!("D-Bus signal error: {}", e));
+        }
+    });
+
+    Ok(())
+}
+
+async fn emit_changes(connection: &Connection, changes: &mut watch::Receiver<StatusReport>) -> zbus::Result<()> {
+    let iface_ref = connection.object_server().interface::<_, Climate>(OBJECT_PATH).await?;
+    let mut last = changes.borrow().clone();
+
+    while changes.changed().await.is_ok() {
+        let current = changes.borrow_and_update().clone();
+        let iface = iface_ref.get().await;
+        let ctxt = iface_ref.signal_context();
+
+        if current.temperature != last.temperature {
+            iface.temperature_changed(ctxt).await?;
+        }
+        if current.humidity != last.humidity {
+            iface.humidity_changed(ctxt).await?;
+        }
+        if current.humidifier_on != last.humidifier_on {
+            iface.humidifier_on_changed(ctxt).await?;
+        }
+        if current.pump_on != last.pump_on {
+            iface.pump_on_changed(ctxt).await?;
+        }
+        if current.fan_on != last.fan_on {
+            iface.fan_on_changed(ctxt).await?;
+        }
+        last = current;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,677 @@
+use crate::ads1115::ADS1115;
+use crate::config::{AdcChannelSpec, Backend, Config, LoadCellSpec, NotifierSpec, TelemetrySpec, DEFAULT_I2C_DEVICE};
+use crate::{output, pins, wetting};
+use crate::sx127x;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+
+//
+// @brief  actuators which current sensors and consumables can refer to
+//
+const ACTUATORS: [&str; 3] = ["humidifier", "pump", "fan"];
+
+//
+// @brief  the longest gap between stored readings that dormancy.rs and tuner.rs still
+//         count as continuous
+//
+const COMPACT_MAX_KEYFRAME_MINS: u64 = 30;
+
+//
+// @brief  how far back the in-memory readings reliably reach at the default sampling,
+//         alongside the actuator events sharing the buffer
+//
+const MAX_RATE_WINDOW_MINS: u64 = 120;
+
+//
+// @brief  bus events webhooks can subscribe to
+//
+const WEBHOOK_EVENTS: [&str; 6] = ["reading", "actuator", "pump_complete", "door", "alert", "summary"];
+
+//
+// @brief  what a [[notify.routes]] template can fill in
+//
+const TEMPLATE_FIELDS: [&str; 5] = ["kind", "severity", "message", "device", "at"];
+
+///
+/// @brief one problem with the config, `path` being the TOML key, e.g. `zones[1].seconds`
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+#[derive(Default)]
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(Diagnostic { path: path.into(), message: message.into() });
+    }
+
+    fn nonzero(&mut self, path: impl Into<String>, value: u64) {
+        if value == 0 {
+            self.error(path, "must be greater than zero");
+        }
+    }
+
+    fn positive(&mut self, path: impl Into<String>, value: f64) {
+        if value.is_nan() || value <= 0.0 {
+            self.error(path, format!("must be greater than zero, not {}", value));
+        }
+    }
+
+    fn band(&mut self, section: &str, low: f64, high: f64) {
+        if !(0.0 <= low && low < high && high <= 100.0) {
+            self.error(format!("{}.rh_high", section), format!("{} % to {} % is not a band within 0-100 %", low, high));
+        }
+    }
+
+    fn actuator(&mut self, path: impl Into<String>, name: &str) {
+        if !ACTUATORS.contains(&name) {
+            self.error(path, format!("unknown actuator {:?}, expected one of {:?}", name, ACTUATORS));
+        }
+    }
+
+    fn load_cell(&mut self, section: &str, cell: &LoadCellSpec) {
+        if cell.counts_per_gram == 0.0 || cell.counts_per_gram.is_nan() {
+            self.error(format!("{}.counts_per_gram", section), "must not be zero, weigh something known to work it out");
+        }
+        self.nonzero(format!("{}.samples", section), cell.samples as u64);
+    }
+
+    fn unique<'a>(&mut self, section: &str, names: impl Iterator<Item = &'a str>) {
+        let mut seen = HashSet::new();
+        for (i, name) in names.enumerate() {
+            if !seen.insert(name) {
+                self.error(format!("{}[{}].name", section, i), format!("{:?} is used more than once", name));
+            }
+        }
+    }
+}
+
+///
+/// @brief everything wrong with a parsed config that would only show up once the daemon
+///        runs into it, in the order of the config sections
+///
+pub fn check(config: &Config) -> Vec<Diagnostic> {
+    let mut d = Diagnostics::default();
+
+    if config.platform.backend == Backend::Rppal && config.platform.i2c_device != DEFAULT_I2C_DEVICE {
+        d.error("platform.i2c_device", "only applies to backend = \"cdev\", rppal always opens /dev/i2c-<bus>");
+    }
+    if let Err(e) = pins::validate(config) {
+        d.error("pins", e.to_string());
+    }
+    if config.ipc.socket.is_empty() {
+        d.error("ipc.socket", "must not be empty");
+    }
+
+    if let Some(adc) = &config.adc {
+        for (i, channel) in adc.channels.iter().enumerate() {
+            check_adc_channel(&mut d, i, channel);
+        }
+        d.unique("adc.channels", adc.channels.iter().map(|c| c.name.as_str()));
+    }
+
+    d.positive("pump.pwm_frequency", config.pump.pwm_frequency);
+    if let Some(flow) = config.pump.flow_ml_per_min {
+        d.positive("pump.flow_ml_per_min", flow);
+    }
+    for (i, zone) in config.zones.iter().enumerate() {
+        d.positive(format!("zones[{}].seconds", i), zone.seconds);
+        if let Some(flow) = zone.flow_ml_per_min {
+            d.positive(format!("zones[{}].flow_ml_per_min", i), flow);
+        }
+        match (zone.emitters, zone.emitter_lph) {
+            (Some(emitters), Some(lph)) => {
+                d.nonzero(format!("zones[{}].emitters", i), emitters as u64);
+                d.positive(format!("zones[{}].emitter_lph", i), lph);
+            },
+            (Some(_), None) => d.error(format!("zones[{}].emitter_lph", i), "is needed to model the flow of `emitters`"),
+            (None, Some(_)) => d.error(format!("zones[{}].emitters", i), "is needed to model the flow of `emitter_lph`"),
+            (None, None) => {},
+        }
+        if let Some(volume) = zone.volume_ml {
+            d.positive(format!("zones[{}].volume_ml", i), volume);
+            if zone.flow_ml_per_min().is_none() {
+                d.error(format!("zones[{}].volume_ml", i), "needs flow_ml_per_min, or emitters and emitter_lph, to work out a duration");
+            }
+        }
+    }
+    d.unique("zones", config.zones.iter().map(|z| z.name.as_str()));
+
+    if let Some(http) = &config.http {
+        if http.listen.parse::<SocketAddr>().is_err() {
+            d.error("http.listen", format!("{:?} is not an address:port, e.g. \"0.0.0.0:8080\"", http.listen));
+        }
+        d.nonzero("http.control_per_min", http.control_per_min as u64);
+        for (i, token) in http.tokens.iter().enumerate() {
+            if token.sha256.len() != 64 || !token.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                d.error(format!("http.tokens[{}].sha256", i), "must be 64 hex digits, see `bonsai-bot token`");
+            }
+        }
+        d.unique("http.tokens", http.tokens.iter().map(|t| t.name.as_str()));
+        d.nonzero("http.calendar.days_ahead", http.calendar.days_ahead.max(0) as u64);
+        for (i, reminder) in http.calendar.reminders.iter().enumerate() {
+            d.nonzero(format!("http.calendar.reminders[{}].every_days", i), reminder.every_days as u64);
+        }
+    }
+
+    d.nonzero("actuation.max_manual_secs", config.actuation.max_manual_secs);
+    d.nonzero("retention.interval_mins", config.retention.interval_mins);
+    if config.retention.raw_days == Some(0) {
+        d.error("retention.raw_days", "must be greater than zero, or left out to keep raw readings");
+    }
+
+    let humidifier = &config.humidifier;
+    if humidifier.predictive && humidifier.trend_readings < 2 {
+        d.error("humidifier.trend_readings", "needs at least 2 readings to make a trend");
+    }
+    if !(0.0..=1.0).contains(&humidifier.fallback_duty) {
+        d.error("humidifier.fallback_duty", format!("must be between 0.0 and 1.0, not {}", humidifier.fallback_duty));
+    }
+    d.nonzero("humidifier.fallback_cycle_mins", humidifier.fallback_cycle_mins);
+    d.band("humidifier", humidifier.rh_low, humidifier.rh_high);
+    for (i, setpoint) in humidifier.schedule.iter().enumerate() {
+        d.band(&format!("humidifier.schedule[{}]", i), setpoint.rh_low, setpoint.rh_high);
+        if humidifier.schedule[..i].iter().any(|earlier| earlier.from == setpoint.from) {
+            d.error(format!("humidifier.schedule[{}].from", i), format!("{} is already the start of another entry", setpoint.from.format("%H:%M")));
+        }
+    }
+    if humidifier.mix_fan_after_secs.is_some() {
+        d.positive("humidifier.mix_fan_secs", humidifier.mix_fan_secs);
+    }
+    if !(humidifier.rh_high..=100.0).contains(&humidifier.emergency_rh) {
+        d.error("humidifier.emergency_rh", format!("must be between the {} % high threshold and 100 %, not {}",
+            humidifier.rh_high, humidifier.emergency_rh));
+    }
+
+    if let Some(experiment) = &config.experiment {
+        d.band("experiment.a", experiment.a.rh_low, experiment.a.rh_high);
+        d.band("experiment.b", experiment.b.rh_low, experiment.b.rh_high);
+        if experiment.end.is_some_and(|end| end <= experiment.start) {
+            d.error("experiment.end", format!("must be after the start, {}", experiment.start));
+        }
+    }
+
+    if let Some(tuner) = &config.tuner {
+        d.nonzero("tuner.days", tuner.days as u64);
+        d.positive("tuner.max_cycles_per_hour", tuner.max_cycles_per_hour);
+        if !(0.0 < tuner.min_band && tuner.min_band <= tuner.max_band && tuner.max_band <= 100.0) {
+            d.error("tuner.max_band", format!("{} % to {} % is not a range of band widths", tuner.min_band, tuner.max_band));
+        }
+    }
+
+    if let Some(condensation) = &config.condensation {
+        match &condensation.probe {
+            Some(probe) if !config.adc.iter().flat_map(|adc| &adc.channels).any(|c| c.name == probe.channel) =>
+                d.error("condensation.probe.channel", format!("no [[adc.channels]] entry is named {:?}", probe.channel)),
+            Some(_) => {},
+            None if config.ambient.is_none() =>
+                d.error("condensation.probe", "needs a glass probe, or an [ambient] sensor to estimate the glass temperature from"),
+            None => {},
+        }
+        if !(0.0..=1.0).contains(&condensation.wall_factor) {
+            d.error("condensation.wall_factor", format!("must be between 0.0 and 1.0, not {}", condensation.wall_factor));
+        }
+    }
+
+    if let Some(compact) = &config.compact {
+        for (key, band) in [("temperature_c", compact.temperature_c), ("humidity_pct", compact.humidity_pct), ("volts", compact.volts)] {
+            if band.is_nan() || band < 0.0 {
+                d.error(format!("compact.{}", key), format!("must not be negative, not {}", band));
+            }
+        }
+        d.nonzero("compact.keyframe_mins", compact.keyframe_mins);
+        if compact.keyframe_mins > COMPACT_MAX_KEYFRAME_MINS {
+            d.error("compact.keyframe_mins", format!("chill hours and the tuner read gaps over {} min as missing data", COMPACT_MAX_KEYFRAME_MINS));
+        }
+    }
+
+    if let Some(heater) = &config.heater {
+        if heater.on_below >= heater.off_above {
+            d.error("heater.off_above", format!("must be above on_below ({})", heater.on_below));
+        }
+        d.nonzero("heater.stall_mins", heater.stall_mins);
+        d.positive("heater.min_rise_c", heater.min_rise_c);
+        d.positive("heater.max_rise_c_per_min", heater.max_rise_c_per_min);
+    }
+    if let Some(light) = &config.light {
+        if light.on == light.off {
+            d.error("light.off", "is the same time as on");
+        }
+        if !config.plant_sensors.iter().any(|s| s.name == light.sensor) {
+            d.error("light.sensor", format!("no [[plant_sensors]] entry is named {:?}", light.sensor));
+        }
+        d.positive("light.min_rise_lux", light.min_rise_lux);
+        d.nonzero("light.check_mins", light.check_mins);
+    }
+    if let Some(misting) = &config.misting {
+        d.positive("misting.pulse_secs", misting.pulse_secs);
+        d.nonzero("misting.every_mins", misting.every_mins);
+        d.nonzero("misting.max_pulses_per_hour", misting.max_pulses_per_hour as u64);
+        if misting.max_secs_per_day < misting.pulse_secs {
+            d.error("misting.max_secs_per_day", format!("is less than one pulse of {} s", misting.pulse_secs));
+        }
+        if misting.pulse_secs >= 60.0 * misting.every_mins as f64 {
+            d.error("misting.pulse_secs", format!("runs into the next pulse every {} min, use the humidifier for continuous misting", misting.every_mins));
+        }
+    }
+
+    if let Some(leaf) = &config.leaf_wetness {
+        if !config.adc.iter().flat_map(|adc| &adc.channels).any(|c| c.name == leaf.channel) {
+            d.error("leaf_wetness.channel", format!("no [[adc.channels]] entry is named {:?}", leaf.channel));
+        }
+        if leaf.dry_volts == leaf.wet_volts {
+            d.error("leaf_wetness.wet_volts", "must differ from dry_volts");
+        }
+        if !(0.0..=100.0).contains(&leaf.wet_percent) {
+            d.error("leaf_wetness.wet_percent", format!("must be between 0 and 100, not {}", leaf.wet_percent));
+        }
+        d.positive("leaf_wetness.min_wet_hours", leaf.min_wet_hours);
+    }
+
+    let dormancy = &config.dormancy;
+    d.band("dormancy", dormancy.rh_low, dormancy.rh_high);
+    d.nonzero("dormancy.water_every_days", dormancy.water_every_days as u64);
+    if let (Some(enter), Some(exit)) = (dormancy.enter_chill_hours, dormancy.exit_chill_hours) {
+        if exit > enter {
+            d.error("dormancy.exit_chill_hours", format!("is above enter_chill_hours ({}), dormancy would flap", enter));
+        }
+    }
+    for (i, rule) in config.alerts.temp_rate.iter().enumerate() {
+        if rule.change_c == 0.0 || rule.change_c.is_nan() {
+            d.error(format!("alerts.temp_rate[{}].change_c", i), "must not be zero, positive for a rise or negative for a fall");
+        }
+        d.nonzero(format!("alerts.temp_rate[{}].within_mins", i), rule.within_mins);
+        if rule.within_mins > MAX_RATE_WINDOW_MINS {
+            d.error(format!("alerts.temp_rate[{}].within_mins", i), format!("the readings kept in memory only go back {} min", MAX_RATE_WINDOW_MINS));
+        }
+    }
+    for (section, low, high) in [("alerts", config.alerts.temp_low, config.alerts.temp_high), ("dormancy", dormancy.temp_low, dormancy.temp_high)] {
+        if let (Some(low), Some(high)) = (low, high) {
+            if low >= high {
+                d.error(format!("{}.temp_high", section), format!("must be above temp_low ({})", low));
+            }
+        }
+    }
+
+    if let Some(backup) = &config.backup {
+        if backup.dir.is_empty() {
+            d.error("backup.dir", "must not be empty");
+        }
+        d.nonzero("backup.every_hours", backup.every_hours);
+        d.nonzero("backup.keep", backup.keep as u64);
+        if !backup.upload.is_empty() && !backup.upload.iter().any(|arg| arg.contains("{file}")) {
+            d.error("backup.upload", "never mentions {file}, so the dump wouldn't be uploaded");
+        }
+    }
+
+    if let Some(archive) = &config.archive {
+        if archive.dir.is_empty() {
+            d.error("archive.dir", "must not be empty");
+        }
+        d.nonzero("archive.after_days", archive.after_days as u64);
+        d.nonzero("archive.every_hours", archive.every_hours);
+        if config.retention.raw_days.is_some_and(|days| days <= archive.after_days) {
+            d.error("retention.raw_days", format!("prunes raw readings before they are {} days old and archived", archive.after_days));
+        }
+    }
+
+    if let Some(update) = &config.update {
+        if !update.release_url.starts_with("https://") {
+            d.error("update.release_url", "must be https://, binaries it points to are run as the daemon");
+        }
+        if update.public_key.len() != 64 || !update.public_key.chars().all(|c| c.is_ascii_hexdigit()) {
+            d.error("update.public_key", "must be the 32 byte ed25519 public key in hex");
+        }
+        d.nonzero("update.every_hours", update.every_hours);
+    }
+
+    if let Some(chaos) = &config.chaos {
+        for (key, chance) in [("i2c_nak", chaos.i2c_nak), ("gpio", chaos.gpio), ("db_disconnect", chaos.db_disconnect), ("clock_jump", chaos.clock_jump)] {
+            if !(0.0..=1.0).contains(&chance) {
+                d.error(format!("chaos.{}", key), format!("is a probability, between 0.0 and 1.0, not {}", chance));
+            }
+        }
+    }
+
+    if let Some(blackbox) = &config.blackbox {
+        if blackbox.dir.is_empty() {
+            d.error("blackbox.dir", "must not be empty");
+        }
+        if blackbox.max_kib < 64 {
+            d.error("blackbox.max_kib", format!("{} KiB holds only minutes of records, use at least 64", blackbox.max_kib));
+        }
+    }
+
+    for (i, spec) in config.telemetry.iter().enumerate() {
+        if let TelemetrySpec::Thingspeak { temperature_field, humidity_field, .. } = spec {
+            for (key, field) in [("temperature_field", temperature_field), ("humidity_field", humidity_field)] {
+                if !(1..=8).contains(field) {
+                    d.error(format!("telemetry[{}].{}", i, key), format!("ThingSpeak channels have fields 1 to 8, not {}", field));
+                }
+            }
+            if temperature_field == humidity_field {
+                d.error(format!("telemetry[{}].humidity_field", i), "is the same field as temperature_field");
+            }
+        }
+    }
+
+    for (i, sensor) in config.plant_sensors.iter().enumerate() {
+        let octets: Vec<&str> = sensor.address.split(':').collect();
+        if octets.len() != 6 || !octets.iter().all(|o| o.len() == 2 && u8::from_str_radix(o, 16).is_ok()) {
+            d.error(format!("plant_sensors[{}].address", i), format!("{:?} is not a MAC address like C4:7C:8D:6A:12:34", sensor.address));
+        }
+        if let Some(zone) = &sensor.zone {
+            if !config.zones.iter().any(|z| &z.name == zone) {
+                d.error(format!("plant_sensors[{}].zone", i), format!("no zone is named {:?}", zone));
+            }
+        }
+    }
+    d.unique("plant_sensors", config.plant_sensors.iter().map(|s| s.name.as_str()));
+
+    if let Some(mqtt) = &config.mqtt {
+        if mqtt.host.is_empty() {
+            d.error("mqtt.host", "must not be empty");
+        }
+        if mqtt.password.is_some() && mqtt.username.is_none() {
+            d.error("mqtt.password", "is set without a username");
+        }
+        for (i, node) in mqtt.nodes.iter().enumerate() {
+            if node.topic().is_empty() || node.topic().contains(['+', '#']) {
+                d.error(format!("mqtt.nodes[{}].topic", i), format!("{:?} is not a topic prefix", node.topic()));
+            }
+            if let Some(humidifier) = &node.humidifier {
+                if !node.relays.contains(&humidifier.relay) {
+                    d.error(format!("mqtt.nodes[{}].humidifier.relay", i), format!("{:?} is not one of the node's relays", humidifier.relay));
+                }
+                if humidifier.rh_low >= humidifier.rh_high {
+                    d.error(format!("mqtt.nodes[{}].humidifier.rh_high", i), format!("must be above rh_low ({})", humidifier.rh_low));
+                }
+            }
+        }
+        d.unique("mqtt.nodes", mqtt.nodes.iter().map(|n| n.name.as_str()));
+    }
+
+    for (i, hook) in config.webhooks.iter().enumerate() {
+        if !hook.url.starts_with("http://") && !hook.url.starts_with("https://") {
+            d.error(format!("webhooks[{}].url", i), format!("{:?} is not an http(s) URL", hook.url));
+        }
+        if hook.events.is_empty() {
+            d.error(format!("webhooks[{}].events", i), format!("is empty, pick from {:?}", WEBHOOK_EVENTS));
+        }
+        for event in hook.events.iter().filter(|e| !WEBHOOK_EVENTS.contains(&e.as_str())) {
+            d.error(format!("webhooks[{}].events", i), format!("unknown event {:?}, expected one of {:?}", event, WEBHOOK_EVENTS));
+        }
+    }
+
+    if let Some(notify) = &config.notify {
+        d.unique("notify.notifiers", notify.notifiers.iter().map(|n| n.name()));
+        for (i, notifier) in notify.notifiers.iter().enumerate() {
+            match notifier {
+                NotifierSpec::Ntfy { url, .. } | NotifierSpec::Webhook { url, .. }
+                        if !url.starts_with("http://") && !url.starts_with("https://") =>
+                    d.error(format!("notify.notifiers[{}].url", i), format!("{:?} is not an http(s) URL", url)),
+                NotifierSpec::Email { command, .. } if command.is_empty() =>
+                    d.error(format!("notify.notifiers[{}].command", i), "must not be empty"),
+                _ => {},
+            }
+        }
+        if notify.routes.is_empty() {
+            d.error("notify.routes", "is empty, alerts would go nowhere");
+        }
+        for (i, route) in notify.routes.iter().enumerate() {
+            if route.to.is_empty() {
+                d.error(format!("notify.routes[{}].to", i), "is empty");
+            }
+            for name in route.to.iter().filter(|name| !notify.notifiers.iter().any(|n| n.name() == name.as_str())) {
+                d.error(format!("notify.routes[{}].to", i), format!("no [[notify.notifiers]] is named {:?}", name));
+            }
+            for field in route.template.split('{').skip(1).filter_map(|part| part.split_once('}')).map(|(field, _)| field) {
+                if !TEMPLATE_FIELDS.contains(&field) {
+                    d.error(format!("notify.routes[{}].template", i), format!("unknown field {{{}}}, expected one of {:?}", field, TEMPLATE_FIELDS));
+                }
+            }
+        }
+        for (i, escalation) in notify.escalations.iter().enumerate() {
+            d.nonzero(format!("notify.escalations[{}].after_mins", i), escalation.after_mins);
+            if escalation.to.is_empty() {
+                d.error(format!("notify.escalations[{}].to", i), "is empty");
+            }
+            for name in escalation.to.iter().filter(|name| !notify.notifiers.iter().any(|n| n.name() == name.as_str())) {
+                d.error(format!("notify.escalations[{}].to", i), format!("no [[notify.notifiers]] is named {:?}", name));
+            }
+        }
+    }
+
+    if let Some(homekit) = &config.homekit {
+        let digits: String = homekit.pin.chars().filter(|c| *c != '-').collect();
+        if digits.len() != 8 || !digits.chars().all(|c| c.is_ascii_digit()) {
+            d.error("homekit.pin", format!("{:?} is not an eight digit setup code like \"031-45-154\"", homekit.pin));
+        } else if digits.bytes().all(|b| b == digits.as_bytes()[0]) || digits == "12345678" || digits == "87654321" {
+            d.error("homekit.pin", "is one of the setup codes HomeKit refuses as too easy to guess");
+        }
+    }
+
+    if let Some(watchdog) = &config.watchdog {
+        if watchdog.device.is_empty() {
+            d.error("watchdog.device", "must not be empty");
+        }
+        d.nonzero("watchdog.stall_secs", watchdog.stall_secs);
+    }
+
+    if let Some(lora) = &config.lora {
+        if !(137.0..=1020.0).contains(&lora.frequency_mhz) {
+            d.error("lora.frequency_mhz", format!("the SX127x tunes 137 MHz to 1020 MHz, not {}", lora.frequency_mhz));
+        }
+        if !(6..=12).contains(&lora.spreading_factor) {
+            d.error("lora.spreading_factor", format!("must be between 6 and 12, not {}", lora.spreading_factor));
+        }
+        if !sx127x::BANDWIDTHS_KHZ.contains(&lora.bandwidth_khz) {
+            d.error("lora.bandwidth_khz", format!("must be one of {:?}, not {}", sx127x::BANDWIDTHS_KHZ, lora.bandwidth_khz));
+        }
+    }
+
+    d.nonzero("sampling.climate_secs", config.sampling.climate_secs);
+    d.nonzero("sampling.tick_budget_secs", config.sampling.tick_budget_secs);
+    if config.sampling.burst_window_mins > 0 {
+        d.nonzero("sampling.burst_period_secs", config.sampling.burst_period_secs);
+    }
+
+    if let Some(door) = &config.door {
+        d.nonzero("door.alert_after_mins", door.alert_after_mins);
+    }
+
+    if let Some(wind) = &config.wind {
+        d.positive("wind.kmh_per_hz", wind.kmh_per_hz);
+        d.nonzero("wind.log_secs", wind.log_secs);
+        if wind.boost < 1.0 {
+            d.error("wind.boost", format!("must be at least 1, not {}", wind.boost));
+        }
+        d.nonzero("wind.water_every_hours", wind.water_every_hours);
+    }
+
+    if let Some(tank) = &config.tank {
+        d.load_cell("tank.cell", &tank.cell);
+        d.positive("tank.low_grams", tank.low_grams);
+        d.nonzero("tank.every_secs", tank.every_secs);
+    }
+    d.nonzero("watering_feedback.settle_mins", config.watering_feedback.settle_mins);
+    d.positive("watering_feedback.min_grams", config.watering_feedback.min_grams);
+    d.positive("watering_feedback.min_moisture", config.watering_feedback.min_moisture);
+    if let Some(until_wet) = &config.until_wet {
+        if !config.adc.iter().flat_map(|adc| &adc.channels).any(|c| c.name == until_wet.channel) {
+            d.error("until_wet.channel", format!("no [[adc.channels]] entry is named {:?}", until_wet.channel));
+        }
+        if !(0.0..=100.0).contains(&until_wet.target_moisture) {
+            d.error("until_wet.target_moisture", format!("must be a moisture percentage, not {}", until_wet.target_moisture));
+        }
+        d.positive("until_wet.burst_secs", until_wet.burst_secs);
+        d.nonzero("until_wet.soak_mins", until_wet.soak_mins);
+        d.positive("until_wet.max_volume_ml", until_wet.max_volume_ml);
+        match wetting::burst_ml(config) {
+            None => d.error("until_wet", "needs pump.flow_ml_per_min, or the flow of every zone, to tell how much went in"),
+            Some(ml) if ml > until_wet.max_volume_ml =>
+                d.error("until_wet.max_volume_ml", format!("is less than one burst of {:.0} ml", ml)),
+            Some(_) => {},
+        }
+    }
+    if let Some(pot) = &config.pot {
+        d.load_cell("pot.cell", &pot.cell);
+        d.positive("pot.dry_grams", pot.dry_grams);
+        d.nonzero("pot.every_secs", pot.every_secs);
+    }
+
+    for (i, sensor) in config.current.iter().enumerate() {
+        d.actuator(format!("current[{}].actuator", i), &sensor.actuator);
+        d.positive(format!("current[{}].shunt_ohms", i), sensor.shunt_ohms);
+        if sensor.on_min_amps <= sensor.off_max_amps {
+            d.error(format!("current[{}].on_min_amps", i), format!("must be above off_max_amps ({})", sensor.off_max_amps));
+        }
+        d.positive(format!("current[{}].max_deviation", i), sensor.max_deviation);
+    }
+
+    if let Some(self_test) = &config.self_test {
+        d.nonzero("self_test.pulse_ms", self_test.pulse_ms);
+        let zones: Vec<&str> = config.zones.iter().map(|zone| zone.name.as_str()).collect();
+        for (i, relay) in self_test.relays.iter().flatten().enumerate() {
+            if !ACTUATORS.contains(&relay.as_str()) && !["mister", "heater"].contains(&relay.as_str()) && !zones.contains(&relay.as_str()) {
+                d.error(format!("self_test.relays[{}]", i), format!("unknown relay {:?}, expected an actuator or zone name", relay));
+            }
+        }
+    }
+
+    for (i, consumable) in config.consumables.iter().enumerate() {
+        if consumable.every_days.is_none() && consumable.every_runtime_hours.is_none() {
+            d.error(format!("consumables[{}]", i), "needs every_days, every_runtime_hours or both");
+        }
+        if consumable.every_days == Some(0) {
+            d.error(format!("consumables[{}].every_days", i), "must be greater than zero");
+        }
+        match (&consumable.actuator, consumable.every_runtime_hours) {
+            (Some(actuator), _) => d.actuator(format!("consumables[{}].actuator", i), actuator),
+            (None, Some(_)) => d.error(format!("consumables[{}].actuator", i), "every_runtime_hours needs an actuator to count runtime of"),
+            (None, None) => {},
+        }
+    }
+    d.unique("consumables", config.consumables.iter().map(|c| c.name.as_str()));
+
+    d.0
+}
+
+fn check_adc_channel(d: &mut Diagnostics, i: usize, channel: &AdcChannelSpec) {
+    if let Err(e) = ADS1115::config_word(channel) {
+        d.error(format!("adc.channels[{}]", i), e.to_string());
+    }
+    if channel.interval_secs == Some(0) {
+        d.error(format!("adc.channels[{}].interval_secs", i), "must be greater than zero");
+    }
+}
+
+///
+/// @brief `bonsai-bot check-config`: parses and checks the config, printing every problem
+///
+pub fn run(path: Option<String>, json: bool) -> Result<(), Box<dyn Error>> {
+    let path = path.unwrap_or_else(Config::path);
+    let contents = std::fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let config = match Config::parse(&contents) {
+        Ok(config) => config,
+        Err(e) if json => return Err(format!("{}: {}", path, e).into()),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return Err("config does not parse".into());
+        },
+    };
+
+    let mut diagnostics = check(&config);
+    // each profile is its own config, only what it gets wrong beyond the base is news
+    for name in config.profiles.keys() {
+        match Config::parse_as(&contents, name) {
+            Ok(profiled) => {
+                let extra: Vec<Diagnostic> = check(&profiled).into_iter().filter(|d| !diagnostics.contains(d)).collect();
+                diagnostics.extend(extra.into_iter().map(|d| Diagnostic { path: format!("profiles.{}: {}", name, d.path), message: d.message }));
+            },
+            Err(e) => diagnostics.push(Diagnostic { path: format!("profiles.{}", name), message: e }),
+        }
+    }
+    if json {
+        output::json(&json!({ "path": path, "problems": diagnostics }));
+    } else {
+        for diagnostic in &diagnostics {
+            eprintln!("{}: {}", path, diagnostic);
+        }
+    }
+    match diagnostics.len() {
+        0 => {
+            if !json {
+                println!("{} looks good", path);
+            }
+            Ok(())
+        },
+        // the problems are already out, the exit status says there were some
+        _ if json => std::process::exit(1),
+        n => Err(format!("{} problem(s) found", n).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_check() {
+        assert_eq!(check(&Config::default()), vec![]);
+
+        let config = Config::parse(r#"
+            [sampling]
+            climate_secs = 0
+
+            [humidifier]
+            fallback_duty = 1.5
+
+            [[zones]]
+            name  = "juniper"
+            valve = { pin = 5 }
+
+            [[zones]]
+            name    = "juniper"
+            valve   = { pin = 6 }
+            seconds = 0
+
+            [[consumables]]
+            name = "wick"
+            every_runtime_hours = 200
+        "#).expect("Config did not parse");
+
+        let paths: Vec<_> = check(&config).into_iter().map(|d| d.path).collect();
+        assert_eq!(paths, vec![
+            "zones[1].seconds", "zones[1].name", "humidifier.fallback_duty", "sampling.climate_secs", "consumables[0].actuator",
+        ]);
+
+        let config = Config::parse(r#"
+            [notify]
+            notifiers = [
+                { name = "phone", type = "ntfy", url = "https://ntfy.sh/bonsai" },
+                { name = "mail", type = "email", to = "jo@example.com" },
+            ]
+            routes = [
+                { severity = "critical", to = ["phone", "pager"] },
+                { severity = "info", to = ["mail"], template = "{kind}: {mesage}" },
+            ]
+        "#).expect("Config did not parse");
+        let paths: Vec<_> = check(&config).into_iter().map(|d| d.path).collect();
+        assert_eq!(paths, vec!["notify.routes[0].to", "notify.routes[1].template"]);
+    }
+}
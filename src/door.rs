@@ -0,0 +1,123 @@
+use crate::config::DoorConfig;
+use crate::hal::{self, InputLine, Platform};
+use chrono::{DateTime, Duration, Utc};
+use crate::logging;
+
+///
+/// @brief what a poll of the door switch found out
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum DoorChange {
+    Opened,
+    Closed { opened_at: DateTime<Utc>, closed_at: DateTime<Utc> },
+    LeftOpen { since: DateTime<Utc> },
+}
+
+///
+/// @brief a reed switch on the enclosure door, debounced over two consecutive polls
+///
+pub struct Door {
+    line: Box<dyn InputLine>,
+    active_low: bool,
+    alert_after: Duration,
+    open: bool,
+    opened_at: Option<DateTime<Utc>>,
+    alerted: bool,
+    pending: Option<bool>,
+}
+
+impl Door {
+    pub fn new(platform: &dyn Platform, config: &DoorConfig) -> hal::Result<Door> {
+        Ok(Door {
+            line: platform.input(config.switch.pin, config.pull_up)?,
+            active_low: config.switch.active_low,
+            alert_after: Duration::minutes(config.alert_after_mins as i64),
+            open: false,
+            opened_at: None,
+            alerted: false,
+            pending: None,
+        })
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn poll(&mut self) -> Option<DoorChange> {
+        let open = match self.line.is_high() {
+            Ok(high) => high != self.active_low,
+            Err(e) => {
+                logging::print(3, &format!("Door switch read error: {}", e));
+                return None;
+            },
+        };
+        self.update(open, Utc::now())
+    }
+
+    fn update(&mut self, open: bool, now: DateTime<Utc>) -> Option<DoorChange> {
+        if open == self.open {
+            self.pending = None;
+            return match self.opened_at {
+                Some(since) if open && !self.alerted && now - since >= self.alert_after => {
+                    self.alerted = true;
+                    Some(DoorChange::LeftOpen { since })
+                },
+                _ => None,
+            };
+        }
+
+        // a bouncing contact has to read the same twice in a row before we believe it
+        if self.pending != Some(open) {
+            self.pending = Some(open);
+            return None;
+        }
+        self.pending = None;
+        self.open = open;
+
+        if open {
+            self.opened_at = Some(now);
+            self.alerted = false;
+            Some(DoorChange::Opened)
+        } else {
+            self.opened_at.take().map(|opened_at| DoorChange::Closed { opened_at, closed_at: now })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fake;
+
+    impl InputLine for Fake {
+        fn is_high(&mut self) -> hal::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    #[test]
+    pub fn test_door() {
+        let mut door = Door {
+            line: Box::new(Fake),
+            active_low: false,
+            alert_after: Duration::minutes(10),
+            open: false,
+            opened_at: None,
+            alerted: false,
+            pending: None,
+        };
+        let t0 = Utc::now();
+
+        assert_eq!(door.update(true, t0), None);
+        assert_eq!(door.update(false, t0), None);
+        assert_eq!(door.update(true, t0), None);
+        assert_eq!(door.update(true, t0), Some(DoorChange::Opened));
+        assert_eq!(door.update(true, t0 + Duration::minutes(5)), None);
+        assert_eq!(door.update(true, t0 + Duration::minutes(11)), Some(DoorChange::LeftOpen { since: t0 }));
+        assert_eq!(door.update(true, t0 + Duration::minutes(12)), None);
+        assert_eq!(door.update(false, t0 + Duration::minutes(13)), None);
+        assert_eq!(door.update(false, t0 + Duration::minutes(13)),
+            Some(DoorChange::Closed { opened_at: t0, closed_at: t0 + Duration::minutes(13) }));
+    }
+}
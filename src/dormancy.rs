@@ -0,0 +1,178 @@
+use crate::config::DormancyConfig;
+use crate::logging;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Client;
+
+//
+// @brief  chill hours are counted over this many days back
+//
+pub const CHILL_WINDOW_DAYS: i64 = 7;
+
+//
+// @brief  a gap between readings counts for at most this long, so a dead sensor over a cold
+//         night doesn't count as a week of chill
+//
+const MAX_GAP_MINS: i64 = 30;
+
+///
+/// @brief `bonsai-bot dormancy on|off|auto`: force it either way, or hand it back to the
+///        chill hour trigger
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    On,
+    Off,
+    Auto,
+}
+
+///
+/// @brief whether the tree is dormant, and whether a person said so (in which case chill hours
+///        don't change it until `dormancy auto`)
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct State {
+    pub dormant: bool,
+    pub manual: bool,
+}
+
+///
+/// @brief rebuilds the state from dormancy rows in system_events, newest first
+///
+pub fn restore(events: &[(String, Option<String>)]) -> State {
+    let mut auto = false;
+    for (event, detail) in events {
+        match event.as_str() {
+            "dormancy_auto" => auto = true,
+            "dormancy_start" | "dormancy_end" => return State {
+                dormant: event == "dormancy_start",
+                manual: !auto && detail.as_deref().is_some_and(|d| d.starts_with("manual")),
+            },
+            _ => {},
+        }
+    }
+    State::default()
+}
+
+///
+/// @brief the state the last run left behind
+///
+pub async fn load(client: &Client) -> Result<State, tokio_postgres::Error> {
+    let rows = client.query("SELECT event, detail FROM system_events WHERE event LIKE 'dormancy\\_%' AND mine(device_id)
+        ORDER BY id DESC LIMIT 100", &[]).await?;
+    let events: Vec<(String, Option<String>)> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+    Ok(restore(&events))
+}
+
+///
+/// @brief hours the enclosure spent below `below_c` over the last CHILL_WINDOW_DAYS
+///
+pub async fn chill_hours(client: &Client, below_c: f64) -> Result<f64, tokio_postgres::Error> {
+    let since = Utc::now() - Duration::days(CHILL_WINDOW_DAYS);
+    let row = client.query_one("
+        SELECT COALESCE(SUM(EXTRACT(EPOCH FROM LEAST(next_at - timestamp, $3::bigint * interval '1 minute'))::float8), 0) / 3600
+        FROM (
+            SELECT timestamp, temperature, LEAD(timestamp) OVER (ORDER BY timestamp) AS next_at
+            FROM climate_data
+            WHERE NOT is_pump_start AND temperature IS NOT NULL AND timestamp >= $1 AND mine(device_id)
+        ) r
+        WHERE temperature < $2 AND next_at IS NOT NULL", &[&since, &below_c, &MAX_GAP_MINS]).await?;
+    Ok(row.get(0))
+}
+
+///
+/// @brief what the chill hour trigger makes of `chill_hours`, leaving manual decisions alone
+///
+/// @return Some(true) to go dormant, Some(false) to wake up
+///
+pub fn decide(config: &DormancyConfig, state: State, chill_hours: f64) -> Option<bool> {
+    if state.manual {
+        return None;
+    }
+    if !state.dormant && config.enter_chill_hours.is_some_and(|hours| chill_hours >= hours) {
+        return Some(true);
+    }
+    if state.dormant && config.exit_chill_hours.is_some_and(|hours| chill_hours < hours) {
+        return Some(false);
+    }
+    None
+}
+
+///
+/// @brief switches dormancy on or off, logging it
+///
+/// @return the system_events row to record it with: the event and the reason, which starts
+///         with "manual" when a person asked
+///
+pub fn switch(state: &mut State, dormant: bool, reason: &str) -> (&'static str, String) {
+    let event = if dormant { "dormancy_start" } else { "dormancy_end" };
+    if state.dormant != dormant {
+        let verb = if dormant { "Entering" } else { "Leaving" };
+        logging::send(6, &format!("{} dormancy ({})", verb, reason), &[("SERVICE", &"climate"), ("EVENT", &event)]);
+    }
+    state.dormant = dormant;
+    state.manual = reason.starts_with("manual");
+    (event, reason.to_string())
+}
+
+///
+/// @brief applies a manual `mode`, `who` being whoever asked
+///
+/// @return the system_events row to record it with, and what to tell whoever asked
+///
+pub fn apply(state: &mut State, mode: Mode, who: &str) -> ((&'static str, String), String) {
+    match mode {
+        Mode::On | Mode::Off => {
+            let row = switch(state, mode == Mode::On, &format!("manual, {}", who));
+            (row, format!("Dormancy {} until `dormancy auto`", if state.dormant { "on" } else { "off" }))
+        },
+        Mode::Auto => {
+            state.manual = false;
+            (("dormancy_auto", who.to_string()), "Dormancy follows chill hours again".to_string())
+        },
+    }
+}
+
+///
+/// @brief applies a manual `mode` and records it straight away, for the CLI without a daemon
+///
+pub async fn set(client: &Client, state: &mut State, mode: Mode, who: &str) -> Result<String, tokio_postgres::Error> {
+    let mut applied = *state;
+    let ((event, detail), message) = apply(&mut applied, mode, who);
+    client.execute("INSERT INTO system_events (event, detail) VALUES ($1, $2)", &[&event, &detail]).await?;
+    *state = applied;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_dormancy() {
+        let row = |event: &str, detail: &str| (event.to_string(), Some(detail.to_string()));
+        assert_eq!(restore(&[]), State::default());
+        assert_eq!(restore(&[row("dormancy_start", "chill 52.0 h")]), State { dormant: true, manual: false });
+        assert_eq!(restore(&[row("dormancy_end", "manual, cli uid 1000"), row("dormancy_start", "chill 52.0 h")]),
+            State { dormant: false, manual: true });
+        assert_eq!(restore(&[row("dormancy_auto", "cli uid 1000"), row("dormancy_start", "manual, cli uid 1000")]),
+            State { dormant: true, manual: false });
+
+        let config = DormancyConfig { enter_chill_hours: Some(40.0), exit_chill_hours: Some(10.0), ..Default::default() };
+        let awake = State::default();
+        let dormant = State { dormant: true, manual: false };
+        assert_eq!(decide(&config, awake, 39.0), None);
+        assert_eq!(decide(&config, awake, 40.0), Some(true));
+        assert_eq!(decide(&config, dormant, 20.0), None);
+        assert_eq!(decide(&config, dormant, 5.0), Some(false));
+        assert_eq!(decide(&config, State { dormant: true, manual: true }, 0.0), None);
+        assert_eq!(decide(&DormancyConfig::default(), awake, 168.0), None);
+
+        let mut state = State { dormant: true, manual: true };
+        assert_eq!(apply(&mut state, Mode::Auto, "cli uid 1000").0, ("dormancy_auto", "cli uid 1000".to_string()));
+        assert_eq!(state, dormant);
+        assert_eq!(apply(&mut state, Mode::Off, "http").0, ("dormancy_end", "manual, http".to_string()));
+        assert_eq!(state, State { dormant: false, manual: true });
+    }
+}
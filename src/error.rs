@@ -0,0 +1,101 @@
+use crate::ads1115::AdsError;
+use crate::hal::HalError;
+use crate::hx711::HxError;
+use crate::ina219::InaError;
+use crate::pins::PinError;
+use crate::sht20::ShtError;
+use thiserror::Error;
+
+///
+/// @brief what went wrong in a service, by class, so callers can decide whether to retry,
+///        reconnect or alert without sniffing strings
+///
+#[derive(Debug, Error)]
+pub enum BonsaiError {
+    #[error("sensor error: {0}")]
+    Sensor(#[from] SensorError),
+    #[error("gpio error: {0}")]
+    Gpio(#[from] GpioError),
+    #[error("database error: {0}")]
+    Db(#[from] tokio_postgres::Error),
+    #[error("config error: {0}")]
+    Config(String),
+    #[error("schedule error: {0}")]
+    Schedule(String),
+    #[error("{0} tick ran past its {1} s budget")]
+    Timeout(&'static str, u64),
+}
+
+#[derive(Debug, Error)]
+pub enum SensorError {
+    #[error("SHT20: {0}")]
+    Sht20(#[from] ShtError),
+    #[error("ADS1115: {0}")]
+    Ads1115(#[from] AdsError),
+    #[error("INA219: {0}")]
+    Ina219(#[from] InaError),
+    #[error("HX711: {0}")]
+    Hx711(#[from] HxError),
+}
+
+#[derive(Debug, Error)]
+pub enum GpioError {
+    #[error(transparent)]
+    Hal(#[from] HalError),
+    #[error(transparent)]
+    Pin(#[from] PinError),
+}
+
+impl From<ShtError> for BonsaiError {
+    fn from(e: ShtError) -> Self {
+        BonsaiError::Sensor(e.into())
+    }
+}
+
+impl From<AdsError> for BonsaiError {
+    fn from(e: AdsError) -> Self {
+        BonsaiError::Sensor(e.into())
+    }
+}
+
+impl From<InaError> for BonsaiError {
+    fn from(e: InaError) -> Self {
+        BonsaiError::Sensor(e.into())
+    }
+}
+
+impl From<HxError> for BonsaiError {
+    fn from(e: HxError) -> Self {
+        BonsaiError::Sensor(e.into())
+    }
+}
+
+impl From<HalError> for BonsaiError {
+    fn from(e: HalError) -> Self {
+        BonsaiError::Gpio(e.into())
+    }
+}
+
+impl From<PinError> for BonsaiError {
+    fn from(e: PinError) -> Self {
+        BonsaiError::Gpio(e.into())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, BonsaiError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_error_class() {
+        let e: BonsaiError = ShtError::BytesReadMismatch.into();
+        assert!(matches!(e, BonsaiError::Sensor(SensorError::Sht20(ShtError::BytesReadMismatch))));
+        assert_eq!(e.to_string(), "sensor error: SHT20: Unexpected number of bytes read");
+
+        let e: BonsaiError = HalError::Unsupported("pwm").into();
+        assert!(matches!(e, BonsaiError::Gpio(GpioError::Hal(_))));
+        assert_eq!(e.to_string(), "gpio error: pwm is not supported by this backend");
+    }
+}
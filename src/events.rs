@@ -0,0 +1,103 @@
+use crate::ipc::StatusReport;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::{broadcast, watch};
+
+//
+// @brief  events a slow subscriber may fall behind by before it starts missing some
+//
+const BUS_CAPACITY: usize = 64;
+
+///
+/// @brief something that happened in the enclosure, published on the event bus
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Reading { at: DateTime<Utc>, temperature: f64, humidity: f64 },
+    Actuator { at: DateTime<Utc>, name: &'static str, on: bool },
+    Door { at: DateTime<Utc>, open: bool },
+    Alert { at: DateTime<Utc>, kind: &'static str, message: String },
+    // a periodic report, e.g. the weekly stability one
+    Summary { at: DateTime<Utc>, kind: &'static str, message: String },
+}
+
+impl Event {
+    pub fn at(&self) -> DateTime<Utc> {
+        match self {
+            Event::Reading { at, .. } | Event::Actuator { at, .. } | Event::Door { at, .. } | Event::Alert { at, .. }
+                | Event::Summary { at, .. } => *at,
+        }
+    }
+}
+
+pub type Bus = broadcast::Sender<Event>;
+
+pub fn bus() -> Bus {
+    broadcast::channel(BUS_CAPACITY).0
+}
+
+///
+/// @brief the events implied by the status going from `old` to `new`
+///
+pub fn diff(old: &StatusReport, new: &StatusReport, at: DateTime<Utc>) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    if let (Some(temperature), Some(humidity)) = (new.temperature, new.humidity) {
+        if new.measured_at != old.measured_at {
+            events.push(Event::Reading { at: new.measured_at.unwrap_or(at), temperature, humidity });
+        }
+    }
+    for (name, was, is) in [
+        ("humidifier", old.humidifier_on, new.humidifier_on),
+        ("pump", old.pump_on, new.pump_on),
+        ("fan", old.fan_on, new.fan_on),
+        ("mister", old.mister_on, new.mister_on),
+        ("heater", old.heater_on, new.heater_on),
+        ("light", old.light_on, new.light_on),
+    ] {
+        if was != is {
+            events.push(Event::Actuator { at, name, on: is });
+        }
+    }
+    if new.door_open != old.door_open {
+        events.push(Event::Door { at, open: new.door_open });
+    }
+
+    events
+}
+
+///
+/// @brief publishes a new status to watchers and whatever it changed to the event bus
+///
+pub fn publish(status_tx: &watch::Sender<StatusReport>, bus: &Bus, status: &StatusReport) {
+    let old = status_tx.send_replace(status.clone());
+    for event in diff(&old, status, Utc::now()) {
+        // no subscribers is fine
+        let _ = bus.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_diff() {
+        let at = Utc::now();
+        let old = StatusReport::default();
+        let new = StatusReport {
+            temperature: Some(21.0),
+            humidity: Some(68.0),
+            measured_at: Some(at),
+            humidifier_on: true,
+            ..Default::default()
+        };
+
+        assert_eq!(diff(&old, &new, at), vec![
+            Event::Reading { at, temperature: 21.0, humidity: 68.0 },
+            Event::Actuator { at, name: "humidifier", on: true },
+        ]);
+        assert!(diff(&new, &new, at).is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::actuator::Actuator;
+use crate::config::{ExpanderKind, ExpanderSpec};
+use crate::hal::{self, I2cDevice, Platform};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::logging;
+
+//
+// @brief  MCP23017 registers (IOCON.BANK = 0, the power-on default)
+//
+const MCP_IODIRA: u8 = 0x00;
+const MCP_OLATA: u8  = 0x14;
+
+///
+/// @brief an I2C GPIO expander driving relay inputs, shared by every relay wired to it
+///
+/// @note the output latch is cached so flipping one channel never disturbs its neighbours
+///
+pub struct Expander {
+    i2c: Box<dyn I2cDevice>,
+    kind: ExpanderKind,
+    latch: u16,
+    direction: u16,
+}
+
+pub type SharedExpander = Arc<Mutex<Expander>>;
+
+impl Expander {
+
+    pub fn new(platform: &dyn Platform, spec: &ExpanderSpec) -> hal::Result<Expander> {
+        let i2c = platform.i2c(spec.bus, spec.address)?;
+
+        // power-on state: PCF8574 is all weak-high, MCP23017 is all inputs
+        Ok(Expander {
+            i2c,
+            kind: spec.kind,
+            latch: match spec.kind { ExpanderKind::Pcf8574 => 0x00ff, ExpanderKind::Mcp23017 => 0x0000 },
+            direction: 0xffff,
+        })
+    }
+
+    pub fn channels(kind: ExpanderKind) -> u8 {
+        match kind {
+            ExpanderKind::Pcf8574 => 8,
+            ExpanderKind::Mcp23017 => 16,
+        }
+    }
+
+    ///
+    /// @brief latches the initial level for a channel and only then switches it to an output
+    ///
+    pub fn configure_output(&mut self, channel: u8, high: bool) -> hal::Result<()> {
+        self.write_channel(channel, high)?;
+        self.direction &= !(1 << channel);
+        match self.kind {
+            // quasi-bidirectional, there is no direction register
+            ExpanderKind::Pcf8574 => Ok(()),
+            ExpanderKind::Mcp23017 => {
+                let [lo, hi] = self.direction.to_le_bytes();
+                self.i2c.write(&[MCP_IODIRA, lo, hi])
+            },
+        }
+    }
+
+    pub fn write_channel(&mut self, channel: u8, high: bool) -> hal::Result<()> {
+        if high {
+            self.latch |= 1 << channel;
+        } else {
+            self.latch &= !(1 << channel);
+        }
+
+        let [lo, hi] = self.latch.to_le_bytes();
+        match self.kind {
+            ExpanderKind::Pcf8574 => self.i2c.write(&[lo]),
+            ExpanderKind::Mcp23017 => self.i2c.write(&[MCP_OLATA, lo, hi]),
+        }
+    }
+}
+
+///
+/// @brief opens every configured expander, keyed by its config name
+///
+pub fn open_all(
+    platform: &dyn Platform,
+    specs: &std::collections::BTreeMap<String, ExpanderSpec>
+) -> hal::Result<HashMap<String, SharedExpander>> {
+    let mut expanders = HashMap::new();
+    for (name, spec) in specs {
+        expanders.insert(name.clone(), Arc::new(Mutex::new(Expander::new(platform, spec)?)));
+    }
+    Ok(expanders)
+}
+
+///
+/// @brief a relay wired to one channel of an expander
+///
+pub struct ExpanderRelay {
+    expander: SharedExpander,
+    channel: u8,
+    active_low: bool,
+    on: bool,
+}
+
+impl ExpanderRelay {
+
+    pub fn new(expander: SharedExpander, channel: u8, active_low: bool) -> hal::Result<ExpanderRelay> {
+        expander.lock().unwrap().configure_output(channel, active_low)?;
+        Ok(ExpanderRelay { expander, channel, active_low, on: false })
+    }
+
+    fn drive(&mut self, on: bool) {
+        let high = on != self.active_low;
+        match self.expander.lock().unwrap().write_channel(self.channel, high) {
+            Ok(_) => self.on = on,
+            Err(e) => {
+                logging::print(3, &format!("Expander write error on channel {}: {}", self.channel, e));
+            },
+        }
+    }
+}
+
+impl Actuator for ExpanderRelay {
+    fn set_on(&mut self) {
+        self.drive(true);
+    }
+
+    fn set_off(&mut self) {
+        self.drive(false);
+    }
+
+    fn is_on(&self) -> bool {
+        self.on
+    }
+}
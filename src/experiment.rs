@@ -0,0 +1,116 @@
+use crate::config::{Config, SetpointSpec};
+use chrono::{NaiveDateTime, NaiveTime};
+
+///
+/// @brief the humidifier settings in force at a given time, and the experiment variant or
+///        schedule entry (by its start) they came from
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    pub variant: Option<&'static str>,
+    pub setpoint: Option<NaiveTime>,
+    pub rh_low: f64,
+    pub rh_high: f64,
+    pub predictive: bool,
+}
+
+///
+/// @return the schedule entry in force at `time`: the latest to start by then, or before the
+///         first start of the day the last one, still running from the night before
+///
+pub fn setpoint(schedule: &[SetpointSpec], time: NaiveTime) -> Option<&SetpointSpec> {
+    let started = schedule.iter().filter(|s| s.from <= time).max_by_key(|s| s.from);
+    started.or_else(|| schedule.iter().max_by_key(|s| s.from))
+}
+
+///
+/// @brief picks the settings for `now` (local): variant a on even days since the experiment
+///        started, b on odd days, the plain [humidifier] settings before the start and after
+///        the end, their schedule if there is one
+///
+pub fn params(config: &Config, now: NaiveDateTime) -> Params {
+    let humidifier = &config.humidifier;
+    let today = now.date();
+    match &config.experiment {
+        Some(experiment) if today >= experiment.start && experiment.end.is_none_or(|end| today < end) => {
+            let (variant, spec) = if (today - experiment.start).num_days() % 2 == 0 {
+                ("a", &experiment.a)
+            } else {
+                ("b", &experiment.b)
+            };
+            Params {
+                variant: Some(variant),
+                setpoint: None,
+                rh_low: spec.rh_low,
+                rh_high: spec.rh_high,
+                predictive: spec.predictive.unwrap_or(humidifier.predictive),
+            }
+        },
+        _ => match setpoint(&humidifier.schedule, now.time()) {
+            Some(setpoint) => Params {
+                variant: None,
+                setpoint: Some(setpoint.from),
+                rh_low: setpoint.rh_low,
+                rh_high: setpoint.rh_high,
+                predictive: humidifier.predictive,
+            },
+            None => Params {
+                variant: None,
+                setpoint: None,
+                rh_low: humidifier.rh_low,
+                rh_high: humidifier.rh_high,
+                predictive: humidifier.predictive,
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    pub fn test_params() {
+        let config = Config::parse(r#"
+            [humidifier]
+            predictive = true
+
+            [experiment]
+            start = "2024-05-01"
+            end   = "2024-05-29"
+            a     = { rh_low = 70.0, rh_high = 80.0 }
+            b     = { rh_low = 74.0, rh_high = 78.0, predictive = false }
+        "#).expect("Config did not parse");
+        let day = |d: u32| params(&config, NaiveDate::from_ymd_opt(2024, 5, d).unwrap().and_hms_opt(12, 0, 0).unwrap());
+
+        assert_eq!(day(1), Params { variant: Some("a"), setpoint: None, rh_low: 70.0, rh_high: 80.0, predictive: true });
+        assert_eq!(day(2), Params { variant: Some("b"), setpoint: None, rh_low: 74.0, rh_high: 78.0, predictive: false });
+        assert_eq!(day(28).variant, Some("b"));
+        assert_eq!(day(29).variant, None);
+        assert_eq!(params(&config, NaiveDate::from_ymd_opt(2024, 4, 30).unwrap().and_hms_opt(12, 0, 0).unwrap()).variant, None);
+    }
+
+    #[test]
+    pub fn test_schedule() {
+        let config = Config::parse(r#"
+            [[humidifier.schedule]]
+            from    = "22:00"
+            rh_low  = 82.0
+            rh_high = 88.0
+
+            [[humidifier.schedule]]
+            from    = "06:00"
+            rh_low  = 72.0
+            rh_high = 78.0
+        "#).expect("Config did not parse");
+        let at = |h: u32, m: u32| params(&config, NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_opt(h, m, 0).unwrap());
+        let time = |h: u32| NaiveTime::from_hms_opt(h, 0, 0);
+
+        assert_eq!((at(6, 0).setpoint, at(6, 0).rh_low, at(6, 0).rh_high), (time(6), 72.0, 78.0));
+        assert_eq!(at(21, 59).setpoint, time(6));
+        assert_eq!((at(22, 0).setpoint, at(22, 0).rh_low), (time(22), 82.0));
+        assert_eq!(at(3, 0).setpoint, time(22));
+        assert_eq!(params(&Config::default(), NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_opt(3, 0, 0).unwrap()).setpoint, None);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::cli::{ExportArgs, ExportFormat};
+use crate::output;
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio_postgres::Client;
+
+//
+// @brief  subdirectory of the output directory the monthly files land in
+//
+const TABLE: &str = "climate_data";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClimateRow {
+    pub timestamp: DateTime<Utc>,
+    pub temperature: Option<f64>,
+    pub humidity: Option<f64>,
+    pub is_pump_start: bool,
+}
+
+///
+/// @brief writes climate_data as one file per calendar month (UTC), e.g. climate_data/2024-05.csv
+///
+/// @note with `json` nothing is printed per file, one list of them all is at the end
+///
+pub async fn run(client: &Client, args: &ExportArgs, json: bool) -> Result<(), Box<dyn Error>> {
+    #[cfg(not(feature = "parquet"))]
+    if args.format == ExportFormat::Parquet {
+        return Err("this build lacks the parquet feature, rebuild with --features parquet".into());
+    }
+
+    let bounds = client.query_one("SELECT MIN(timestamp), MAX(timestamp) FROM climate_data", &[]).await?;
+    let (first, last): (Option<DateTime<Utc>>, Option<DateTime<Utc>>) = (bounds.get(0), bounds.get(1));
+    let (Some(first), Some(last)) = (first, last) else {
+        match json {
+            true => output::json(&serde_json::json!({ "files": [] })),
+            false => println!("climate_data is empty, nothing to export"),
+        }
+        return Ok(());
+    };
+    let from = args.from.map_or(first, |f| f.max(first));
+    let to = args.to.unwrap_or(last + chrono::Duration::seconds(1));
+
+    let dir = Path::new(&args.out).join(TABLE);
+    std::fs::create_dir_all(&dir)?;
+
+    let stmt = client.prepare("
+        SELECT timestamp, temperature, humidity, is_pump_start
+        FROM climate_data
+        WHERE timestamp >= $1 AND timestamp < $2
+        ORDER BY timestamp").await?;
+
+    let mut files = Vec::new();
+    let mut month = month_start(from);
+    while month < to {
+        let next = next_month(month);
+        let rows: Vec<ClimateRow> = client.query(&stmt, &[&month.max(from), &next.min(to)]).await?
+            .iter()
+            .map(|row| ClimateRow {
+                timestamp: row.get(0),
+                temperature: row.get(1),
+                humidity: row.get(2),
+                is_pump_start: row.get(3),
+            })
+            .collect();
+
+        if !rows.is_empty() {
+            let path = partition_path(&dir, month, args.format);
+            match args.format {
+                ExportFormat::Csv => write_csv(&rows, std::io::BufWriter::new(std::fs::File::create(&path)?))?,
+                #[cfg(feature = "parquet")]
+                ExportFormat::Parquet => write_parquet(&rows, std::fs::File::create(&path)?)?,
+                #[cfg(not(feature = "parquet"))]
+                ExportFormat::Parquet => unreachable!(),
+            }
+            if !json {
+                println!("{} rows -> {}", rows.len(), path.display());
+            }
+            files.push(serde_json::json!({ "path": path, "rows": rows.len() }));
+        }
+        month = next;
+    }
+    if json {
+        output::json(&serde_json::json!({ "files": files }));
+    }
+
+    Ok(())
+}
+
+pub fn month_start(t: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(t.year(), t.month(), 1, 0, 0, 0).unwrap()
+}
+
+pub fn next_month(month: DateTime<Utc>) -> DateTime<Utc> {
+    match month.month() {
+        12 => Utc.with_ymd_and_hms(month.year() + 1, 1, 1, 0, 0, 0).unwrap(),
+        m => Utc.with_ymd_and_hms(month.year(), m + 1, 1, 0, 0, 0).unwrap(),
+    }
+}
+
+fn partition_path(dir: &Path, month: DateTime<Utc>, format: ExportFormat) -> PathBuf {
+    let extension = match format {
+        ExportFormat::Csv => "csv",
+        ExportFormat::Parquet => "parquet",
+    };
+    dir.join(format!("{}.{}", month.format("%Y-%m"), extension))
+}
+
+pub fn write_csv(rows: &[ClimateRow], mut out: impl Write) -> std::io::Result<()> {
+    let field = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+
+    writeln!(out, "timestamp,temperature,humidity,is_pump_start")?;
+    for row in rows {
+        writeln!(out, "{},{},{},{}", row.timestamp.to_rfc3339(), field(row.temperature),
+            field(row.humidity), row.is_pump_start)?;
+    }
+    out.flush()
+}
+
+#[cfg(feature = "parquet")]
+pub fn write_parquet(rows: &[ClimateRow], out: std::fs::File) -> Result<(), Box<dyn Error>> {
+    use arrow_array::{ArrayRef, BooleanArray, Float64Array, RecordBatch, TimestampMicrosecondArray};
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+    use std::sync::Arc;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("timestamp", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), false),
+        Field::new("temperature", DataType::Float64, true),
+        Field::new("humidity", DataType::Float64, true),
+        Field::new("is_pump_start", DataType::Boolean, false),
+    ]));
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(TimestampMicrosecondArray::from_iter_values(rows.iter().map(|r| r.timestamp.timestamp_micros()))
+            .with_timezone("UTC")),
+        Arc::new(Float64Array::from_iter(rows.iter().map(|r| r.temperature))),
+        Arc::new(Float64Array::from_iter(rows.iter().map(|r| r.humidity))),
+        Arc::new(BooleanArray::from_iter(rows.iter().map(|r| Some(r.is_pump_start)))),
+    ];
+
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(out, schema, Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_write_csv() {
+        let t = Utc.with_ymd_and_hms(2024, 12, 31, 23, 55, 0).unwrap();
+        let rows = vec![
+            ClimateRow { timestamp: t, temperature: Some(21.5), humidity: Some(74.25), is_pump_start: false },
+            ClimateRow { timestamp: t, temperature: None, humidity: None, is_pump_start: true },
+        ];
+        let mut out = Vec::new();
+        write_csv(&rows, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "timestamp,temperature,humidity,is_pump_start\n\
+            2024-12-31T23:55:00+00:00,21.5,74.25,false\n\
+            2024-12-31T23:55:00+00:00,,,true\n");
+        assert_eq!(next_month(month_start(t)), Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap());
+    }
+}
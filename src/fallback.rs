@@ -0,0 +1,101 @@
+use crate::config::HumidifierConfig;
+use tokio::time::{sleep_until, Duration, Instant};
+
+///
+/// @brief time-based humidifier control for when the SHT20 stops answering, so the humidifier
+///        isn't stuck in whatever state it was in when the sensor died
+///
+/// @note after `fallback_after_failures` consecutive failed climate readings, the humidifier
+///       runs for `fallback_duty` of every `fallback_cycle_mins` until a reading succeeds again
+///
+pub struct Fallback {
+    after: u32,
+    on: Duration,
+    off: Duration,
+    failures: u32,
+    next: Option<(Instant, bool)>,
+}
+
+impl Fallback {
+    pub fn new(config: &HumidifierConfig) -> Fallback {
+        let cycle = Duration::from_secs(60 * config.fallback_cycle_mins.max(1));
+        let on = cycle.mul_f64(config.fallback_duty.clamp(0.0, 1.0));
+        Fallback {
+            after: config.fallback_after_failures,
+            on,
+            off: cycle - on,
+            failures: 0,
+            next: None,
+        }
+    }
+
+    ///
+    /// @brief counts a failed reading
+    ///
+    /// @return true if this failure switched to the duty cycle
+    ///
+    pub fn failure(&mut self) -> bool {
+        self.failures = self.failures.saturating_add(1);
+        if self.after > 0 && self.failures == self.after {
+            self.next = Some((Instant::now(), true));
+            return true;
+        }
+        false
+    }
+
+    ///
+    /// @brief a reading worked, back to controlling on RH
+    ///
+    /// @return true if the duty cycle was running
+    ///
+    pub fn success(&mut self) -> bool {
+        self.failures = 0;
+        self.next.take().is_some()
+    }
+
+    ///
+    /// @brief waits for the next switch of the duty cycle; never completes while it isn't running
+    ///
+    /// @return whether the humidifier should now be on
+    ///
+    pub async fn tick(&mut self) -> bool {
+        let Some((at, on)) = self.next else {
+            return std::future::pending().await;
+        };
+        sleep_until(at).await;
+
+        self.next = Some((at + if on { self.on } else { self.off }, !on));
+        on
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_fallback() {
+        let mut fallback = Fallback {
+            after: 2,
+            on: Duration::from_millis(20),
+            off: Duration::from_millis(80),
+            failures: 0,
+            next: None,
+        };
+        assert!(!fallback.failure());
+        assert!(tokio::time::timeout(Duration::from_millis(50), fallback.tick()).await.is_err());
+
+        assert!(fallback.failure());
+        assert!(!fallback.failure());
+        assert!(fallback.tick().await);
+        let started = Instant::now();
+        assert!(!fallback.tick().await);
+        assert!(started.elapsed() >= Duration::from_millis(15));
+        assert!(fallback.tick().await);
+        assert!(started.elapsed() >= Duration::from_millis(95));
+
+        assert!(fallback.success());
+        assert!(!fallback.success());
+        assert!(tokio::time::timeout(Duration::from_millis(50), fallback.tick()).await.is_err());
+    }
+}
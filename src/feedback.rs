@@ -0,0 +1,121 @@
+use crate::config::WateringFeedbackConfig;
+use std::fmt::Write;
+use tokio::time::{sleep_until, Duration, Instant};
+
+///
+/// @brief what a pump run should change: the pot's weight and each calibrated probe's
+///        moisture, as last measured
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub pot_grams: Option<f64>,
+    pub moisture: Vec<(String, f64)>,
+}
+
+impl Snapshot {
+    fn is_empty(&self) -> bool {
+        self.pot_grams.is_none() && self.moisture.is_empty()
+    }
+
+    pub fn moisture_of(&self, channel: &str) -> Option<f64> {
+        self.moisture.iter().find(|(c, _)| c == channel).map(|(_, moisture)| *moisture)
+    }
+}
+
+///
+/// @brief how much each measurement moved between `before` and `after`, those missing from
+///        either left out: ("pot", grams) first, then (channel, moisture percent)
+///
+pub fn deltas(before: &Snapshot, after: &Snapshot) -> Vec<(String, f64)> {
+    let mut deltas = Vec::new();
+    if let (Some(before), Some(after)) = (before.pot_grams, after.pot_grams) {
+        deltas.push(("pot".to_string(), after - before));
+    }
+    for (channel, before) in &before.moisture {
+        if let Some((_, after)) = after.moisture.iter().find(|(c, _)| c == channel) {
+            deltas.push((channel.clone(), after - before));
+        }
+    }
+    deltas
+}
+
+///
+/// @return whether none of the measurements rose by its threshold; false when there was
+///         nothing to compare
+///
+pub fn ineffective(deltas: &[(String, f64)], config: &WateringFeedbackConfig) -> bool {
+    !deltas.is_empty() && deltas.iter().all(|(source, delta)| match source.as_str() {
+        "pot" => *delta < config.min_grams,
+        _ => *delta < config.min_moisture,
+    })
+}
+
+pub fn describe(deltas: &[(String, f64)]) -> String {
+    let mut described = String::new();
+    for (i, (source, delta)) in deltas.iter().enumerate() {
+        let unit = if source == "pot" { " g" } else { " %" };
+        let _ = write!(described, "{}{} {:+.0}{}", if i > 0 { ", " } else { "" }, source, delta, unit);
+    }
+    described
+}
+
+///
+/// @brief checks whether a pump run did anything, `settle_mins` after it, once the water has
+///        had time to soak in
+///
+/// @note a clogged drip line shows up here the same day rather than as a wilting tree
+///
+pub struct Feedback {
+    settle: Duration,
+    pending: Option<(Instant, Snapshot)>,
+}
+
+impl Feedback {
+    pub fn new(config: &WateringFeedbackConfig) -> Feedback {
+        Feedback { settle: Duration::from_secs(60 * config.settle_mins), pending: None }
+    }
+
+    ///
+    /// @brief the pump just ran, `before` being the measurements from before it did
+    ///
+    pub fn watered(&mut self, before: Snapshot) {
+        if !before.is_empty() {
+            self.pending = Some((Instant::now() + self.settle, before));
+        }
+    }
+
+    ///
+    /// @brief waits until a run is due its check; never completes while none is
+    ///
+    /// @return the measurements from before that run
+    ///
+    pub async fn due(&mut self) -> Snapshot {
+        let Some((at, _)) = self.pending else {
+            return std::future::pending().await;
+        };
+        sleep_until(at).await;
+        self.pending.take().map(|(_, before)| before).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_feedback() {
+        let config = WateringFeedbackConfig::default();
+        let before = Snapshot { pot_grams: Some(2600.0), moisture: vec![("bonsai".to_string(), 31.0), ("moss".to_string(), 40.0)] };
+
+        let watered = Snapshot { pot_grams: Some(2910.0), moisture: vec![("bonsai".to_string(), 48.0)] };
+        let moved = deltas(&before, &watered);
+        assert_eq!(moved, vec![("pot".to_string(), 310.0), ("bonsai".to_string(), 17.0)]);
+        assert!(!ineffective(&moved, &config));
+        assert_eq!(describe(&moved), "pot +310 g, bonsai +17 %");
+
+        let clogged = Snapshot { pot_grams: Some(2595.0), moisture: vec![("bonsai".to_string(), 31.5), ("moss".to_string(), 40.0)] };
+        assert!(ineffective(&deltas(&before, &clogged), &config));
+
+        assert!(!ineffective(&deltas(&before, &Snapshot::default()), &config));
+    }
+}
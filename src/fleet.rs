@@ -0,0 +1,146 @@
+use crate::{db, lifecycle, output};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::error::Error;
+use tokio_postgres::Client;
+
+//
+// @brief  alerts raised this recently are listed by `fleet status`
+//
+const ALERT_WINDOW_HOURS: i64 = 24;
+
+///
+/// @brief records this device in `devices` at startup, or refreshes what it says about it:
+///        hostname, version and the names of its drip zones
+///
+/// @note the rows it writes reference it, so this runs before anything else is stored
+///
+pub async fn register(client: &Client, id: &str, zones: &[String]) -> Result<(), tokio_postgres::Error> {
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+    client.execute("
+        INSERT INTO devices (id, hostname, version, zones, started_at) VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (id) DO UPDATE SET
+            hostname   = EXCLUDED.hostname,
+            version    = EXCLUDED.version,
+            zones      = EXCLUDED.zones,
+            started_at = EXCLUDED.started_at",
+        &[&id, &hostname.trim(), &env!("CARGO_PKG_VERSION"), &zones]).await?;
+    Ok(())
+}
+
+///
+/// @brief makes sure this device has a row in `devices`, for commands that write without
+///        the daemon having run here
+///
+pub async fn ensure(client: &Client) -> Result<(), tokio_postgres::Error> {
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+    client.execute("INSERT INTO devices (id, hostname) VALUES (current_instance(), $1) ON CONFLICT DO NOTHING",
+        &[&hostname.trim()]).await?;
+    Ok(())
+}
+
+///
+/// @brief one device's row in `bonsai-bot fleet status`
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceStatus {
+    pub id: String,
+    pub version: String,
+    pub running: bool,
+    pub last_reading: Option<DateTime<Utc>>,
+    pub temperature: Option<f64>,
+    pub humidity: Option<f64>,
+    pub alerts: Vec<String>,
+}
+
+///
+/// @brief every registered device with its latest climate reading and the kinds of alert it
+///        raised in the last ALERT_WINDOW_HOURS
+///
+/// @note a device counts as running while it holds the lease on its instance id
+///
+pub async fn status(client: &Client) -> Result<Vec<DeviceStatus>, tokio_postgres::Error> {
+    let rows = client.query("
+        SELECT d.id, d.version, COALESCE(l.expires_at >= now(), FALSE), c.timestamp, c.temperature, c.humidity,
+               COALESCE((SELECT array_agg(DISTINCT a.kind ORDER BY a.kind) FROM alert_log a
+                         WHERE a.device_id = d.id AND a.timestamp > now() - $1::bigint * interval '1 hour'), '{}')
+        FROM devices d
+        LEFT JOIN leases l ON l.name = 'instance/' || d.id
+        LEFT JOIN LATERAL (
+            SELECT timestamp, temperature, humidity FROM climate_data
+            WHERE device_id = d.id AND NOT is_pump_start ORDER BY timestamp DESC LIMIT 1
+        ) c ON TRUE
+        WHERE d.id <> ''
+        ORDER BY d.id", &[&ALERT_WINDOW_HOURS]).await?;
+    Ok(rows.iter().map(|row| DeviceStatus {
+        id: row.get(0),
+        version: row.get(1),
+        running: row.get(2),
+        last_reading: row.get(3),
+        temperature: row.get(4),
+        humidity: row.get(5),
+        alerts: row.get(6),
+    }).collect())
+}
+
+fn format_row(device: &DeviceStatus, now: DateTime<Utc>) -> String {
+    let reading = match device.last_reading {
+        Some(at) => format!("{} ago", lifecycle::format_uptime(now - at)),
+        None => "never".to_string(),
+    };
+    let value = |v: Option<f64>, unit| v.map_or("-".to_string(), |v| format!("{:.1}{}", v, unit));
+    format!("{:<16} {:<8} {:<5} {:<16} {:>7} {:>7}  {}", device.id, device.version, if device.running { "up" } else { "down" },
+        reading, value(device.temperature, " C"), value(device.humidity, " %"),
+        if device.alerts.is_empty() { "-".to_string() } else { device.alerts.join(", ") })
+}
+
+///
+/// @brief `bonsai-bot fleet status`: a line per device sharing BONSAIBOT_DATABASE_URL, or
+///        with `json` an array of them
+///
+pub async fn print_status(json: bool) -> Result<(), Box<dyn Error>> {
+    let (client, connection) = db::establish_connection().await?;
+    let connection = tokio::spawn(connection);
+    let devices = status(&client).await?;
+    drop(client);
+    let _ = connection.await;
+
+    if json {
+        output::json(&devices);
+        return Ok(());
+    }
+    if devices.is_empty() {
+        println!("No devices registered yet, they register when the daemon starts");
+        return Ok(());
+    }
+    println!("{:<16} {:<8} {:<5} {:<16} {:>7} {:>7}  ALERTS ({} h)", "DEVICE", "VERSION", "STATE", "LAST READING", "TEMP", "RH", ALERT_WINDOW_HOURS);
+    let now = Utc::now();
+    for device in &devices {
+        println!("{}", format_row(device, now));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    pub fn test_format_row() {
+        let now = Utc::now();
+        let mut device = DeviceStatus {
+            id: "shelf".to_string(),
+            version: "0.1.1".to_string(),
+            running: true,
+            last_reading: Some(now - Duration::minutes(4)),
+            temperature: Some(21.46),
+            humidity: Some(72.0),
+            alerts: vec!["rh_low".to_string(), "temp_high".to_string()],
+        };
+        assert_eq!(format_row(&device, now), "shelf            0.1.1    up    4 min ago         21.5 C  72.0 %  rh_low, temp_high");
+
+        (device.running, device.last_reading, device.temperature, device.humidity, device.alerts) = (false, None, None, None, vec![]);
+        assert_eq!(format_row(&device, now), "shelf            0.1.1    down  never                  -       -  -");
+    }
+}
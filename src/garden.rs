@@ -0,0 +1,106 @@
+use crate::config::LoraConfig;
+use crate::db;
+use crate::logging;
+use crate::sx127x::{Packet, SX127x};
+use chrono::Utc;
+use tokio::time::{interval, Duration};
+
+//
+// @brief  how often the receiver's IRQ flags are checked; shorter than a packet's airtime
+//
+const POLL_MS: u64 = 100;
+
+///
+/// @brief what a garden node sent: its name and metric/value pairs
+///
+/// @note nodes send plain text, `<node>;<metric>=<value>;...`, e.g.
+///       `maple;moisture=41.5;temperature=12.3;battery=3.71`
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub node: String,
+    pub values: Vec<(String, f64)>,
+}
+
+///
+/// @brief decodes a packet's payload
+///
+/// @return None for anything that isn't a report, such as a neighbour's LoRa traffic
+///
+pub fn parse(payload: &[u8]) -> Option<Report> {
+    let text = std::str::from_utf8(payload).ok()?;
+    let mut fields = text.trim().split(';');
+    let node = fields.next().filter(|n| !n.is_empty() && !n.contains('='))?.to_string();
+    let values = fields.map(|field| {
+        let (metric, value) = field.split_once('=')?;
+        let value: f64 = value.trim().parse().ok().filter(|v: &f64| v.is_finite())?;
+        Some((metric.trim().to_string(), value))
+    }).collect::<Option<Vec<_>>>()?;
+    if values.is_empty() {
+        return None;
+    }
+    Some(Report { node, values })
+}
+
+///
+/// @brief listens for garden nodes on its own database connection, storing each value they
+///        report in garden_data alongside the packet's signal strength
+///
+pub async fn spawn(mut radio: SX127x, config: LoraConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let (client, connection) = db::establish_connection().await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            logging::print(3, &format!("Garden node connection error: {}", e));
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut poll = interval(Duration::from_millis(POLL_MS));
+        loop {
+            poll.tick().await;
+            let Packet { payload, rssi_dbm, snr_db } = match radio.receive() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => continue,
+                Err(e) => {
+                    logging::print(3, &format!("LoRa receive error: {}", e));
+                    continue;
+                },
+            };
+            let Some(report) = parse(&payload) else {
+                continue;
+            };
+            if !config.nodes.is_empty() && !config.nodes.contains(&report.node) {
+                continue;
+            }
+
+            let at = Utc::now();
+            for (metric, value) in &report.values {
+                if let Err(e) = client.execute("INSERT INTO garden_data (timestamp, node, metric, value, rssi_dbm) VALUES ($1, $2, $3, $4, $5)",
+                        &[&at, &report.node, metric, value, &(rssi_dbm as i32)]).await {
+                    logging::print(3, &format!("Database execute error {:?}", e));
+                }
+            }
+            let values: Vec<String> = report.values.iter().map(|(m, v)| format!("{}={}", m, v)).collect();
+            logging::send(6, &format!("{} {} ({} dBm, SNR {} dB)", report.node, values.join(" "), rssi_dbm, snr_db),
+                &[("SERVICE", &"garden"), ("EVENT", &"reading"), ("NODE", &report.node), ("RSSI", &rssi_dbm), ("SNR", &snr_db)]);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_parse() {
+        assert_eq!(parse(b"maple;moisture=41.5;temperature=-2.5;battery=3.71"), Some(Report {
+            node: "maple".to_string(),
+            values: vec![("moisture".to_string(), 41.5), ("temperature".to_string(), -2.5), ("battery".to_string(), 3.71)],
+        }));
+        assert_eq!(parse(b"maple"), None);
+        assert_eq!(parse(b"maple;moisture=nan"), None);
+        assert_eq!(parse(b"moisture=41.5"), None);
+        assert_eq!(parse(&[0xa3, 0x01, 0xff, 0x3b]), None);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::config::Config;
+use serde_json::{json, Value};
+use std::error::Error;
+
+//
+// @brief  the import input Grafana asks to be pointed at the bonsai-bot Postgres
+//
+const DATASOURCE: &str = "${DS_BONSAI}";
+
+//
+// @brief  panels are laid out two to a row on Grafana's 24 column grid
+//
+const PANEL_WIDTH: u32 = 12;
+const PANEL_HEIGHT: u32 = 8;
+
+fn datasource() -> Value {
+    json!({ "type": "postgres", "uid": DATASOURCE })
+}
+
+fn target(sql: &str, format: &str) -> Value {
+    json!({ "datasource": datasource(), "refId": "A", "rawQuery": true, "editorMode": "code", "format": format, "rawSql": sql })
+}
+
+fn panel(kind: &str, title: &str, unit: &str, sql: &str) -> Value {
+    let format = if kind == "table" { "table" } else { "time_series" };
+    json!({
+        "type": kind,
+        "title": title,
+        "datasource": datasource(),
+        "fieldConfig": { "defaults": { "unit": unit }, "overrides": [] },
+        "targets": [target(sql, format)],
+    })
+}
+
+fn annotation(name: &str, color: &str, sql: &str) -> Value {
+    json!({
+        "name": name,
+        "datasource": datasource(),
+        "enable": true,
+        "iconColor": color,
+        "target": target(sql, "table"),
+    })
+}
+
+///
+/// @brief the panels worth having with this config: climate always, the rest only for the
+///        hardware that is configured and so has tables filling up
+///
+/// @note long ranges read the climate_hourly and climate_daily rollups, raw climate_data is
+///       for the last few days
+///
+pub fn panels(config: &Config) -> Vec<Value> {
+    let mut panels = vec![
+        panel("timeseries", "Temperature", "celsius",
+            "SELECT timestamp AS time, temperature FROM climate_data WHERE $__timeFilter(timestamp) AND NOT is_pump_start ORDER BY 1"),
+        panel("timeseries", "Humidity", "percent",
+            "SELECT timestamp AS time, humidity FROM climate_data WHERE $__timeFilter(timestamp) AND NOT is_pump_start ORDER BY 1"),
+        panel("timeseries", "Temperature, hourly", "celsius",
+            "SELECT bucket AS time, temperature_min AS min, temperature_avg AS avg, temperature_max AS max FROM climate_hourly WHERE $__timeFilter(bucket) ORDER BY 1"),
+        panel("timeseries", "Humidity, hourly", "percent",
+            "SELECT bucket AS time, humidity_min AS min, humidity_avg AS avg, humidity_max AS max FROM climate_hourly WHERE $__timeFilter(bucket) ORDER BY 1"),
+        panel("timeseries", "Climate, daily", "none",
+            "SELECT bucket AS time, temperature_avg AS \"temperature C\", humidity_avg AS \"humidity %\" FROM climate_daily WHERE $__timeFilter(bucket) ORDER BY 1"),
+        panel("state-timeline", "Actuators", "bool_on_off",
+            "SELECT timestamp AS time, actuator AS metric, CASE WHEN is_on THEN 1 ELSE 0 END AS value FROM actuator_events WHERE $__timeFilter(timestamp) ORDER BY 1"),
+        panel("table", "Monthly usage", "none",
+            "SELECT month, actuator, round((seconds / 3600)::numeric, 1) AS hours, round((volume_ml / 1000)::numeric, 1) AS liters FROM usage_monthly ORDER BY month DESC, actuator"),
+    ];
+    if config.ambient.is_some() {
+        panels.push(panel("timeseries", "Enclosure minus room", "none",
+            "SELECT timestamp AS time, temperature_delta AS \"temperature C\", humidity_delta AS \"humidity %\" FROM ambient_data WHERE $__timeFilter(timestamp) ORDER BY 1"));
+    }
+    if !config.zones.is_empty() {
+        panels.push(panel("barchart", "Water per zone", "ml",
+            "SELECT $__timeGroupAlias(timestamp, 1d), zone AS metric, sum(volume_ml) AS value FROM irrigation_data WHERE $__timeFilter(timestamp) GROUP BY 1, 2 ORDER BY 1"));
+    }
+    if config.adc.is_some() {
+        panels.push(panel("timeseries", "Analog inputs", "volt",
+            "SELECT timestamp AS time, channel AS metric, voltage AS value FROM analog_data WHERE $__timeFilter(timestamp) ORDER BY 1"));
+        panels.push(panel("timeseries", "Soil moisture", "percent",
+            "SELECT timestamp AS time, channel AS metric, moisture AS value FROM analog_data WHERE $__timeFilter(timestamp) AND moisture IS NOT NULL ORDER BY 1"));
+    }
+    if config.leaf_wetness.is_some() {
+        panels.push(panel("timeseries", "Leaf wetness and fungal risk", "percent",
+            "SELECT timestamp AS time, wetness, fungal_risk AS \"fungal risk\" FROM leaf_wetness WHERE $__timeFilter(timestamp) ORDER BY 1"));
+    }
+    if config.wind.is_some() {
+        panels.push(panel("timeseries", "Wind", "velocitykmh",
+            "SELECT timestamp AS time, speed_kmh AS wind FROM wind_data WHERE $__timeFilter(timestamp) ORDER BY 1"));
+    }
+    if config.tank.is_some() || config.pot.is_some() {
+        panels.push(panel("timeseries", "Weight", "massg",
+            "SELECT timestamp AS time, cell AS metric, grams AS value FROM weight_data WHERE $__timeFilter(timestamp) ORDER BY 1"));
+    }
+    if !config.current.is_empty() {
+        panels.push(panel("timeseries", "Actuator current", "amp",
+            "SELECT timestamp AS time, actuator AS metric, current_amps AS value FROM power_data WHERE $__timeFilter(timestamp) ORDER BY 1"));
+    }
+
+    for (i, panel) in panels.iter_mut().enumerate() {
+        let i = i as u32;
+        panel["id"] = json!(i + 1);
+        panel["gridPos"] = json!({ "x": i % 2 * PANEL_WIDTH, "y": i / 2 * PANEL_HEIGHT, "w": PANEL_WIDTH, "h": PANEL_HEIGHT });
+    }
+    panels
+}
+
+///
+/// @brief vertical markers for pump runs, door openings, restarts and dormancy changes,
+///        servicing, alerts and journal entries
+///
+pub fn annotations() -> Vec<Value> {
+    vec![
+        annotation("Pump runs", "blue",
+            "SELECT timestamp AS time, pump_ended_at AS timeend, 'Watered' AS text FROM climate_data WHERE is_pump_start AND $__timeFilter(timestamp)"),
+        annotation("Door open", "orange",
+            "SELECT opened_at AS time, closed_at AS timeend, 'Door open' AS text FROM door_log WHERE $__timeFilter(opened_at)"),
+        annotation("System events", "purple",
+            "SELECT timestamp AS time, event || coalesce(': ' || detail, '') AS text FROM system_events WHERE $__timeFilter(timestamp)"),
+        annotation("Maintenance", "green",
+            "SELECT serviced_at AS time, 'Serviced ' || consumable || coalesce(': ' || note, '') AS text FROM maintenance_log WHERE $__timeFilter(serviced_at)"),
+        annotation("Alerts", "red",
+            "SELECT timestamp AS time, kind || ': ' || message AS text FROM alert_log WHERE $__timeFilter(timestamp)"),
+        annotation("Journal", "yellow",
+            "SELECT timestamp AS time, note AS text FROM journal_entries WHERE $__timeFilter(timestamp)"),
+    ]
+}
+
+///
+/// @brief the whole dashboard, in the form Grafana's Import dashboard page takes
+///
+pub fn dashboard(config: &Config) -> Value {
+    json!({
+        "__inputs": [{
+            "name": "DS_BONSAI",
+            "label": "bonsai-bot",
+            "description": "The Postgres database bonsai-bot writes to",
+            "type": "datasource",
+            "pluginId": "grafana-postgresql-datasource",
+            "pluginName": "PostgreSQL",
+        }],
+        "title": "Bonsai",
+        "uid": "bonsai-bot",
+        "tags": ["bonsai-bot"],
+        "timezone": "browser",
+        "schemaVersion": 39,
+        "time": { "from": "now-7d", "to": "now" },
+        "refresh": "5m",
+        "annotations": { "list": annotations() },
+        "panels": panels(config),
+    })
+}
+
+///
+/// @brief prints the dashboard for the config the daemon would load
+///
+pub fn print() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    println!("{}", serde_json::to_string_pretty(&dashboard(&config))?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_dashboard() {
+        let config = Config::parse(r#"
+            [[zones]]
+            name = "juniper"
+            valve = { pin = 5 }
+        "#).expect("Config did not parse");
+        let dashboard = dashboard(&config);
+        let panels = dashboard["panels"].as_array().expect("panels");
+        let titles: Vec<&str> = panels.iter().filter_map(|p| p["title"].as_str()).collect();
+        assert!(titles.contains(&"Water per zone"));
+        assert!(!titles.contains(&"Analog inputs"));
+        assert_eq!(panels[7]["gridPos"], json!({ "x": 12, "y": 24, "w": 12, "h": 8 }));
+        assert_eq!(dashboard["annotations"]["list"][0]["datasource"]["uid"], "${DS_BONSAI}");
+    }
+}
@@ -0,0 +1,318 @@
+use crate::config::{Backend, PlatformConfig};
+use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
+use i2cdev::core::{I2CDevice, I2CMessage, I2CTransfer};
+use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError, LinuxI2CMessage};
+use rppal::gpio::Gpio;
+use rppal::i2c::I2c;
+use rppal::spi::Spi;
+use std::sync::Mutex;
+use std::{error, fmt};
+
+//
+// @brief  consumer label shown by `gpioinfo` for lines we hold
+//
+const CDEV_CONSUMER: &str = "bonsai-bot";
+
+#[derive(Debug)]
+pub enum HalError {
+    Rppal(rppal::gpio::Error),
+    RppalI2c(rppal::i2c::Error),
+    RppalSpi(rppal::spi::Error),
+    Cdev(gpio_cdev::Error),
+    I2cdev(LinuxI2CError),
+    Unsupported(&'static str),
+    #[cfg(feature = "chaos")]
+    Injected(&'static str),
+}
+
+impl fmt::Display for HalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HalError::Rppal(e) => write!(f, "rppal gpio error: {}", e),
+            HalError::RppalI2c(e) => write!(f, "rppal i2c error: {}", e),
+            HalError::RppalSpi(e) => write!(f, "rppal spi error: {}", e),
+            HalError::Cdev(e) => write!(f, "gpio character device error: {}", e),
+            HalError::I2cdev(e) => write!(f, "i2c-dev error: {}", e),
+            HalError::Unsupported(what) => write!(f, "{} is not supported by this backend", what),
+            #[cfg(feature = "chaos")]
+            HalError::Injected(what) => write!(f, "injected {}", what),
+        }
+    }
+}
+
+impl error::Error for HalError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            HalError::Rppal(e) => Some(e),
+            HalError::RppalI2c(e) => Some(e),
+            HalError::RppalSpi(e) => Some(e),
+            HalError::Cdev(e) => Some(e),
+            HalError::I2cdev(e) => Some(e),
+            HalError::Unsupported(_) => None,
+            #[cfg(feature = "chaos")]
+            HalError::Injected(_) => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, HalError>;
+
+///
+/// @brief a digital output line, shaped after embedded-hal's OutputPin
+///
+pub trait OutputLine: Send {
+    fn set_high(&mut self) -> Result<()>;
+    fn set_low(&mut self) -> Result<()>;
+}
+
+///
+/// @brief a digital input line, shaped after embedded-hal's InputPin
+///
+pub trait InputLine: Send {
+    fn is_high(&mut self) -> Result<bool>;
+}
+
+///
+/// @brief a pulse width modulated output, duty cycle from 0.0 (off) to 1.0 (fully on)
+///
+pub trait PwmLine: Send {
+    fn set_duty(&mut self, duty: f64) -> Result<()>;
+}
+
+///
+/// @brief an I2C device at a fixed address, shaped after embedded-hal's I2c
+///
+pub trait I2cDevice: Send {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+    fn write(&mut self, bytes: &[u8]) -> Result<()>;
+    fn write_read(&mut self, bytes: &[u8], buf: &mut [u8]) -> Result<()>;
+}
+
+///
+/// @brief an SPI device behind one chip select, shaped after embedded-hal's SpiDevice
+///
+pub trait SpiDevice: Send {
+    ///
+    /// @brief clocks out `write` while clocking the same number of bytes into `read`
+    ///
+    fn transfer(&mut self, write: &[u8], read: &mut [u8]) -> Result<()>;
+}
+
+///
+/// @brief hands out gpio lines and i2c devices for whatever board we are running on
+///
+pub trait Platform {
+    fn output(&self, pin: u8, initial_high: bool) -> Result<Box<dyn OutputLine>>;
+    fn i2c(&self, bus: u8, address: u16) -> Result<Box<dyn I2cDevice>>;
+    fn input(&self, pin: u8, pull_up: bool) -> Result<Box<dyn InputLine>>;
+
+    fn pwm(&self, _pin: u8, _frequency: f64) -> Result<Box<dyn PwmLine>> {
+        Err(HalError::Unsupported("pwm"))
+    }
+
+    fn spi(&self, _bus: u8, _chip_select: u8, _clock_hz: u32) -> Result<Box<dyn SpiDevice>> {
+        Err(HalError::Unsupported("spi"))
+    }
+}
+
+///
+/// @brief opens the configured backend
+///
+pub fn open(config: &PlatformConfig) -> Result<Box<dyn Platform>> {
+    match config.backend {
+        Backend::Rppal => Ok(Box::new(RppalPlatform::new()?)),
+        Backend::Cdev => Ok(Box::new(CdevPlatform::new(&config.gpiochip, &config.i2c_device)?)),
+    }
+}
+
+///
+/// @brief Raspberry Pi backend using rppal's memory-mapped gpio
+///
+pub struct RppalPlatform {
+    gpio: Gpio,
+}
+
+impl RppalPlatform {
+    pub fn new() -> Result<RppalPlatform> {
+        Ok(RppalPlatform { gpio: Gpio::new().map_err(HalError::Rppal)? })
+    }
+}
+
+impl Platform for RppalPlatform {
+    fn output(&self, pin: u8, initial_high: bool) -> Result<Box<dyn OutputLine>> {
+        let pin = self.gpio.get(pin).map_err(HalError::Rppal)?;
+        let pin = if initial_high { pin.into_output_high() } else { pin.into_output_low() };
+        Ok(Box::new(pin))
+    }
+
+    fn i2c(&self, bus: u8, address: u16) -> Result<Box<dyn I2cDevice>> {
+        let mut i2c = I2c::with_bus(bus).map_err(HalError::RppalI2c)?;
+        i2c.set_slave_address(address).map_err(HalError::RppalI2c)?;
+        Ok(Box::new(i2c))
+    }
+
+    fn input(&self, pin: u8, pull_up: bool) -> Result<Box<dyn InputLine>> {
+        let pin = self.gpio.get(pin).map_err(HalError::Rppal)?;
+        let pin = if pull_up { pin.into_input_pullup() } else { pin.into_input() };
+        Ok(Box::new(pin))
+    }
+
+    fn pwm(&self, pin: u8, frequency: f64) -> Result<Box<dyn PwmLine>> {
+        let pin = self.gpio.get(pin).map_err(HalError::Rppal)?.into_output_low();
+        Ok(Box::new(RppalSoftPwm { pin, frequency }))
+    }
+
+    fn spi(&self, bus: u8, chip_select: u8, clock_hz: u32) -> Result<Box<dyn SpiDevice>> {
+        use rppal::spi::{Bus, Mode, SlaveSelect};
+        let bus = match bus {
+            0 => Bus::Spi0,
+            1 => Bus::Spi1,
+            _ => return Err(HalError::Unsupported("spi bus other than 0 or 1")),
+        };
+        let chip_select = match chip_select {
+            0 => SlaveSelect::Ss0,
+            1 => SlaveSelect::Ss1,
+            2 => SlaveSelect::Ss2,
+            _ => return Err(HalError::Unsupported("spi chip select other than 0 to 2")),
+        };
+        Ok(Box::new(Spi::new(bus, chip_select, clock_hz, Mode::Mode0).map_err(HalError::RppalSpi)?))
+    }
+}
+
+///
+/// @brief rppal's software pwm, which works on any header pin and is plenty for a motor driver
+///
+pub struct RppalSoftPwm {
+    pin: rppal::gpio::OutputPin,
+    frequency: f64,
+}
+
+impl PwmLine for RppalSoftPwm {
+    fn set_duty(&mut self, duty: f64) -> Result<()> {
+        if duty <= 0.0 {
+            self.pin.clear_pwm().map_err(HalError::Rppal)?;
+            self.pin.set_low();
+        } else if duty >= 1.0 {
+            self.pin.clear_pwm().map_err(HalError::Rppal)?;
+            self.pin.set_high();
+        } else {
+            self.pin.set_pwm_frequency(self.frequency, duty).map_err(HalError::Rppal)?;
+        }
+        Ok(())
+    }
+}
+
+impl OutputLine for rppal::gpio::OutputPin {
+    fn set_high(&mut self) -> Result<()> {
+        rppal::gpio::OutputPin::set_high(self);
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<()> {
+        rppal::gpio::OutputPin::set_low(self);
+        Ok(())
+    }
+}
+
+impl InputLine for rppal::gpio::InputPin {
+    fn is_high(&mut self) -> Result<bool> {
+        Ok(rppal::gpio::InputPin::is_high(self))
+    }
+}
+
+impl I2cDevice for I2c {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        I2c::read(self, buf).map_err(HalError::RppalI2c)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        I2c::write(self, bytes).map(|_| ()).map_err(HalError::RppalI2c)
+    }
+
+    fn write_read(&mut self, bytes: &[u8], buf: &mut [u8]) -> Result<()> {
+        I2c::write_read(self, bytes, buf).map_err(HalError::RppalI2c)
+    }
+}
+
+impl SpiDevice for Spi {
+    fn transfer(&mut self, write: &[u8], read: &mut [u8]) -> Result<()> {
+        Spi::transfer(self, read, write).map(|_| ()).map_err(HalError::RppalSpi)
+    }
+}
+
+///
+/// @brief generic Linux backend using the gpio character device and i2c-dev,
+///        for boards rppal does not know about (Orange Pi, BeagleBone, ...)
+///
+/// @note `pin` is the line offset on the configured gpiochip, not a BCM number
+///
+pub struct CdevPlatform {
+    chip: Mutex<Chip>,
+    i2c_device: String,
+}
+
+impl CdevPlatform {
+    pub fn new(path: &str, i2c_device: &str) -> Result<CdevPlatform> {
+        Ok(CdevPlatform { chip: Mutex::new(Chip::new(path).map_err(HalError::Cdev)?), i2c_device: i2c_device.to_string() })
+    }
+}
+
+impl Platform for CdevPlatform {
+    fn output(&self, pin: u8, initial_high: bool) -> Result<Box<dyn OutputLine>> {
+        let line = self.chip.lock().unwrap().get_line(pin as u32).map_err(HalError::Cdev)?;
+        let handle = line.request(LineRequestFlags::OUTPUT, initial_high as u8, CDEV_CONSUMER)
+            .map_err(HalError::Cdev)?;
+        Ok(Box::new(handle))
+    }
+
+    fn i2c(&self, bus: u8, address: u16) -> Result<Box<dyn I2cDevice>> {
+        let device = LinuxI2CDevice::new(self.i2c_device.replace("{bus}", &bus.to_string()), address).map_err(HalError::I2cdev)?;
+        Ok(Box::new(device))
+    }
+
+    ///
+    /// @note gpio-cdev can't set line bias, so `pull_up` needs an external resistor or overlay
+    ///
+    fn input(&self, pin: u8, _pull_up: bool) -> Result<Box<dyn InputLine>> {
+        let line = self.chip.lock().unwrap().get_line(pin as u32).map_err(HalError::Cdev)?;
+        let handle = line.request(LineRequestFlags::INPUT, 0, CDEV_CONSUMER).map_err(HalError::Cdev)?;
+        Ok(Box::new(CdevInput(handle)))
+    }
+}
+
+///
+/// @brief an input line handle; LineHandle already implements OutputLine
+///
+pub struct CdevInput(LineHandle);
+
+impl InputLine for CdevInput {
+    fn is_high(&mut self) -> Result<bool> {
+        Ok(self.0.get_value().map_err(HalError::Cdev)? == 1)
+    }
+}
+
+impl OutputLine for LineHandle {
+    fn set_high(&mut self) -> Result<()> {
+        self.set_value(1).map_err(HalError::Cdev)
+    }
+
+    fn set_low(&mut self) -> Result<()> {
+        self.set_value(0).map_err(HalError::Cdev)
+    }
+}
+
+impl I2cDevice for LinuxI2CDevice {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        I2CDevice::read(self, buf).map_err(HalError::I2cdev)?;
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        I2CDevice::write(self, bytes).map_err(HalError::I2cdev)
+    }
+
+    fn write_read(&mut self, bytes: &[u8], buf: &mut [u8]) -> Result<()> {
+        let mut messages = [LinuxI2CMessage::write(bytes), LinuxI2CMessage::read(buf)];
+        self.transfer(&mut messages).map(|_| ()).map_err(HalError::I2cdev)
+    }
+}
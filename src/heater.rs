@@ -0,0 +1,224 @@
+use crate::config::HeaterConfig;
+use crate::logging;
+use chrono::{DateTime, Duration, Utc};
+use tokio_postgres::Client;
+
+//
+// @brief  the rise rate is measured over at least this long, over seconds sensor noise alone
+//         would outrun max_rise_c_per_min
+//
+const RATE_MINS: i64 = 1;
+
+///
+/// @brief what the thermostat wants from the heater after a reading
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Heat {
+    On,
+    Off,
+    Runaway(String),
+}
+
+///
+/// @brief a thermostat between `on_below` and `off_above` with thermal runaway protection,
+///        as 3D printer firmware has: while it heats, the enclosure has to warm `min_rise_c`
+///        every `stall_mins` and never faster than `max_rise_c_per_min`
+///
+/// @note for `stall_mins` after it switches the heater off, the enclosure mustn't warm
+///       `min_rise_c` either: relay contacts weld as they open, and keep the heater on
+/// @note a trip latches the heater off, across restarts too, until `bonsai-bot heater-clear`
+/// @note in shadow mode the relay heats nothing, only the thermostat runs
+///
+pub struct Heater {
+    config: HeaterConfig,
+    shadow: bool,
+    latched: Option<String>,
+    // the reading the heater has to beat by min_rise_c while it's on
+    baseline: Option<(DateTime<Utc>, f64)>,
+    // when it was switched off, and the coolest reading since
+    switched_off: Option<(DateTime<Utc>, f64)>,
+    // the reading the rise rate is measured from once it's RATE_MINS old
+    rate_from: Option<(DateTime<Utc>, f64)>,
+}
+
+impl Heater {
+    pub fn new(config: &HeaterConfig, latched: Option<String>, shadow: bool) -> Heater {
+        Heater { config: config.clone(), shadow, latched, baseline: None, switched_off: None, rate_from: None }
+    }
+
+    ///
+    /// @return why the heater is latched off, None when it isn't
+    ///
+    pub fn latched(&self) -> Option<&str> {
+        self.latched.as_deref()
+    }
+
+    ///
+    /// @brief notes the heater switched off at `temp`, from here the enclosure mustn't keep
+    ///        warming; the thermostat calls it itself, anything else switching it off has to
+    ///
+    pub fn switched_off(&mut self, at: DateTime<Utc>, temp: f64) {
+        self.baseline = None;
+        self.switched_off = Some((at, temp));
+    }
+
+    pub fn clear(&mut self) {
+        self.latched = None;
+        self.baseline = None;
+    }
+
+    ///
+    /// @param on  whether the heater was on up to this reading
+    ///
+    pub fn update(&mut self, temp: f64, at: DateTime<Utc>, on: bool) -> Heat {
+        let rate_from = match self.rate_from {
+            Some((since, _)) if at - since < Duration::minutes(RATE_MINS) => None,
+            _ => self.rate_from.replace((at, temp)),
+        };
+        if self.latched.is_some() {
+            return Heat::Off;
+        }
+        let stall = Duration::minutes(self.config.stall_mins as i64);
+        if !on {
+            self.baseline = None;
+            match self.switched_off {
+                Some((since, coolest)) if !self.shadow && at - since < stall => {
+                    if temp - coolest >= self.config.min_rise_c {
+                        return self.trip(format!("temperature rose {:.1} C in {} min with the heater off, its relay may be stuck on",
+                            temp - coolest, (at - since).num_minutes()));
+                    }
+                    self.switched_off = Some((since, coolest.min(temp)));
+                },
+                _ => self.switched_off = None,
+            }
+            return if temp < self.config.on_below { Heat::On } else { Heat::Off };
+        }
+        if self.shadow {
+            return if temp >= self.config.off_above { Heat::Off } else { Heat::On };
+        }
+
+        if let Some((since, from)) = rate_from {
+            let minutes = (at - since).num_seconds() as f64 / 60.0;
+            if minutes > 0.0 && (temp - from) / minutes > self.config.max_rise_c_per_min {
+                return self.trip(format!("temperature rising {:.1} C/min with the heater on", (temp - from) / minutes));
+            }
+        }
+        match self.baseline {
+            Some((_, base)) if temp - base >= self.config.min_rise_c => self.baseline = Some((at, temp)),
+            Some((since, base)) if at - since >= stall =>
+                return self.trip(format!("temperature rose only {:.1} C in {} min with the heater on", temp - base, (at - since).num_minutes())),
+            Some(_) => {},
+            None => self.baseline = Some((at, temp)),
+        }
+        if temp >= self.config.off_above {
+            self.switched_off(at, temp);
+            return Heat::Off;
+        }
+        Heat::On
+    }
+
+    fn trip(&mut self, reason: String) -> Heat {
+        self.latched = Some(reason.clone());
+        self.baseline = None;
+        self.switched_off = None;
+        Heat::Runaway(reason)
+    }
+}
+
+///
+/// @brief why the heater was latched off by the last run, from system_events
+///
+pub async fn load_latch(client: &Client) -> Result<Option<String>, tokio_postgres::Error> {
+    let row = client.query_opt("SELECT event, detail FROM system_events
+        WHERE event IN ('heater_runaway', 'heater_cleared') AND mine(device_id) ORDER BY id DESC LIMIT 1", &[]).await?;
+    Ok(row.filter(|row| row.get::<_, String>(0) == "heater_runaway").and_then(|row| row.get(1)))
+}
+
+pub async fn record_trip(client: &Client, reason: &str) -> Result<(), tokio_postgres::Error> {
+    client.execute("INSERT INTO system_events (event, detail) VALUES ('heater_runaway', $1)", &[&reason]).await?;
+    Ok(())
+}
+
+///
+/// @brief lets the heater run again, `who` being whoever cleared it
+///
+pub async fn record_clear(client: &Client, who: &str) -> Result<(), tokio_postgres::Error> {
+    client.execute("INSERT INTO system_events (event, detail) VALUES ('heater_cleared', $1)", &[&who]).await?;
+    logging::send(5, &format!("Heater runaway latch cleared by {}", who), &[("SERVICE", &"heater"), ("EVENT", &"heater_cleared")]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PinSpec;
+
+    #[test]
+    pub fn test_runaway() {
+        let config = HeaterConfig {
+            relay: PinSpec::native(25),
+            on_below: 10.0,
+            off_above: 14.0,
+            stall_mins: 20,
+            min_rise_c: 1.0,
+            max_rise_c_per_min: 2.0,
+        };
+        let t0 = Utc::now();
+        let at = |mins| t0 + Duration::minutes(mins);
+
+        let mut heater = Heater::new(&config, None, false);
+        assert_eq!(heater.update(9.0, at(0), false), Heat::On);
+        assert_eq!(heater.update(9.5, at(10), true), Heat::On);
+        // warmed a degree in time, the next 20 minutes are counted from here
+        assert_eq!(heater.update(10.5, at(19), true), Heat::On);
+        assert_eq!(heater.update(11.0, at(30), true), Heat::On);
+        assert_eq!(heater.update(14.0, at(35), true), Heat::Off);
+        assert_eq!(heater.update(13.5, at(45), false), Heat::Off);
+        assert_eq!(heater.update(12.0, at(60), false), Heat::Off);
+
+        // a heater that doesn't heat, a relay or element gone
+        assert_eq!(heater.update(9.0, at(70), false), Heat::On);
+        assert_eq!(heater.update(9.2, at(75), true), Heat::On);
+        assert_eq!(heater.update(9.4, at(95), true),
+            Heat::Runaway("temperature rose only 0.2 C in 20 min with the heater on".to_string()));
+        assert_eq!(heater.update(8.0, at(100), false), Heat::Off);
+        assert!(heater.latched().is_some());
+        heater.clear();
+        assert_eq!(heater.update(8.0, at(105), false), Heat::On);
+
+        // a sensor next to the element, or one reading garbage
+        assert_eq!(heater.update(8.0, at(106), true), Heat::On);
+        assert_eq!(heater.update(12.0, at(107), true),
+            Heat::Runaway("temperature rising 4.0 C/min with the heater on".to_string()));
+
+        // noisy readings seconds apart, from a SIGUSR1 or burst sampling, aren't a runaway
+        heater.clear();
+        let seconds = |secs| at(110) + Duration::seconds(secs);
+        assert_eq!(heater.update(9.0, seconds(0), false), Heat::On);
+        for (secs, temp) in [(5, 9.2), (10, 9.0), (15, 9.25), (20, 9.05), (65, 9.3)] {
+            assert_eq!(heater.update(temp, seconds(secs), true), Heat::On);
+        }
+        assert!(heater.latched().is_none());
+
+        // switched off at 14 C, and still warming: a welded relay
+        heater.clear();
+        assert_eq!(heater.update(14.2, at(120), true), Heat::Off);
+        assert_eq!(heater.update(14.0, at(125), false), Heat::Off);
+        assert_eq!(heater.update(15.1, at(135), false),
+            Heat::Runaway("temperature rose 1.1 C in 15 min with the heater off, its relay may be stuck on".to_string()));
+
+        // switched off blind after a failed reading, the same goes
+        heater.clear();
+        assert_eq!(heater.update(11.0, at(140), true), Heat::On);
+        heater.switched_off(at(141), 11.0);
+        assert_eq!(heater.update(12.2, at(150), false),
+            Heat::Runaway("temperature rose 1.2 C in 9 min with the heater off, its relay may be stuck on".to_string()));
+
+        // in shadow mode nothing heats, which is no reason to latch the real heater off
+        let mut shadow = Heater::new(&config, None, true);
+        assert_eq!(shadow.update(9.0, at(0), false), Heat::On);
+        assert_eq!(shadow.update(9.0, at(30), true), Heat::On);
+        assert_eq!(shadow.update(9.0, at(90), true), Heat::On);
+        assert!(shadow.latched().is_none());
+    }
+}
@@ -0,0 +1,179 @@
+// hap can't be a dependency until its own dependency graph resolves, see Cargo.toml
+compile_error!("the homekit feature needs hap, which Cargo can't resolve: its get_if_addrs and libmdns dependencies both link \"ifaddrs\"");
+
+use crate::audit::{Origin, Source};
+use crate::config::HomekitConfig;
+use crate::ipc::{Command, Request, Response, StatusReport};
+use futures::FutureExt;
+use hap::accessory::bridge::BridgeAccessory;
+use hap::accessory::humidity_sensor::HumiditySensorAccessory;
+use hap::accessory::switch::SwitchAccessory;
+use hap::accessory::temperature_sensor::TemperatureSensorAccessory;
+use hap::accessory::{AccessoryCategory, AccessoryInformation};
+use hap::characteristic::AsyncCharacteristicCallbacks;
+use hap::pointer;
+use hap::server::{IpServer, Server};
+use hap::storage::{FileStorage, Storage};
+use hap::{HapType, MacAddress, Pin};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::logging;
+use tokio::sync::{mpsc, oneshot, watch};
+
+///
+/// @brief the setup code as the eight digits HAP wants, None unless it is exactly eight digits
+///
+pub fn pin_digits(pin: &str) -> Option<[u8; 8]> {
+    let digits: Vec<u8> = pin.chars().filter(|c| *c != '-').map(|c| c.to_digit(10).map(|d| d as u8)).collect::<Option<_>>()?;
+    digits.try_into().ok()
+}
+
+///
+/// @brief a stable device id for `name`; HomeKit treats a new id as a new, unpaired bridge
+///
+pub fn device_id(name: &str) -> [u8; 6] {
+    let hash = Sha256::digest(name.as_bytes());
+    let mut id = [0u8; 6];
+    id.copy_from_slice(&hash[..6]);
+    // locally administered, unicast
+    id[0] = (id[0] | 0x02) & !0x01;
+    id
+}
+
+///
+/// @brief the accessories the bridge exposes, kept to push status changes into
+///
+struct Accessories {
+    temperature: pointer::Accessory,
+    humidity: pointer::Accessory,
+    humidifier: pointer::Accessory,
+    water: pointer::Accessory,
+}
+
+///
+/// @brief publishes the enclosure as a HomeKit bridge with temperature and humidity sensors,
+///        a humidifier switch and a "water plant" switch that runs the pump
+///
+/// @note the humidifier switch only shows whether the humidifier runs, the daemon keeps
+///       control of it; pairing data lives in `storage_dir`
+///
+pub async fn serve(config: &HomekitConfig, status: watch::Receiver<StatusReport>, control: mpsc::Sender<Command>) -> Result<(), Box<dyn std::error::Error>> {
+    let digits = pin_digits(&config.pin).ok_or("homekit.pin must be eight digits")?;
+    let mut storage = FileStorage::new(std::path::Path::new(&config.storage_dir)).await?;
+    let hap_config = match storage.load_config().await {
+        Ok(mut hap_config) => {
+            hap_config.redetermine_local_ip();
+            storage.save_config(&hap_config).await?;
+            hap_config
+        },
+        Err(_) => {
+            let hap_config = hap::Config {
+                pin: Pin::new(digits)?,
+                name: config.name.clone(),
+                device_id: MacAddress::from(device_id(&config.name)),
+                category: AccessoryCategory::Bridge,
+                ..Default::default()
+            };
+            storage.save_config(&hap_config).await?;
+            hap_config
+        },
+    };
+    let server = IpServer::new(hap_config, storage).await?;
+
+    let info = |name: &str| AccessoryInformation { name: name.to_string(), manufacturer: "bonsai-bot".to_string(), ..Default::default() };
+    let bridge = BridgeAccessory::new(1, info(&config.name))?;
+    let temperature = TemperatureSensorAccessory::new(2, info("Temperature"))?;
+    let humidity = HumiditySensorAccessory::new(3, info("Humidity"))?;
+    let humidifier = SwitchAccessory::new(4, info("Humidifier"))?;
+    let mut water = SwitchAccessory::new(5, info("Water plant"))?;
+
+    water.switch.power_state.on_update_async(Some(move |current: bool, new: bool| {
+        let control = control.clone();
+        async move {
+            if new && !current {
+                // answering HomeKit can't wait for the run to finish
+                tokio::spawn(run_pump(control));
+            }
+            Ok(())
+        }.boxed()
+    }));
+
+    server.add_accessory(bridge).await?;
+    let accessories = Accessories {
+        temperature: server.add_accessory(temperature).await?,
+        humidity: server.add_accessory(humidity).await?,
+        humidifier: server.add_accessory(humidifier).await?,
+        water: server.add_accessory(water).await?,
+    };
+
+    let handle = server.run_handle();
+    tokio::spawn(async move {
+        if let Err(e) = handle.await {
+            logging::print(3, &format!("HomeKit server error: {}", e));
+        }
+    });
+    tokio::spawn(push_status(accessories, status));
+    Ok(())
+}
+
+async fn run_pump(control: mpsc::Sender<Command>) {
+    let request = Request::RunPump { seconds: crate::PUMP_DURATION_SECS };
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if control.send((request, Origin::new(Source::Homekit, None), reply_tx)).await.is_err() {
+        return;
+    }
+    if let Ok(Response::Error { message }) = reply_rx.await {
+        logging::print(4, &format!("HomeKit pump run refused: {}", message));
+    }
+}
+
+async fn set(accessory: &pointer::Accessory, service: HapType, characteristic: HapType, value: Value) -> hap::Result<()> {
+    let mut accessory = accessory.lock().await;
+    let characteristic = accessory.get_mut_service(service).and_then(|s| s.get_mut_characteristic(characteristic));
+    if let Some(characteristic) = characteristic {
+        characteristic.set_value(value).await?;
+    }
+    Ok(())
+}
+
+///
+/// @brief mirrors every status the daemon publishes onto the accessories
+///
+/// @note this also sets a humidifier switch toggled from the Home app back to what the
+///       humidifier is actually doing
+///
+async fn push_status(accessories: Accessories, mut status: watch::Receiver<StatusReport>) {
+    while status.changed().await.is_ok() {
+        let current = status.borrow_and_update().clone();
+        let mut updates = vec![
+            (&accessories.humidifier, HapType::Switch, HapType::PowerState, Value::from(current.humidifier_on)),
+            (&accessories.water, HapType::Switch, HapType::PowerState, Value::from(current.pump_on)),
+        ];
+        if let Some(temperature) = current.temperature {
+            updates.push((&accessories.temperature, HapType::TemperatureSensor, HapType::CurrentTemperature, Value::from(temperature)));
+        }
+        if let Some(humidity) = current.humidity {
+            updates.push((&accessories.humidity, HapType::HumiditySensor, HapType::CurrentRelativeHumidity, Value::from(humidity)));
+        }
+        for (accessory, service, characteristic, value) in updates {
+            if let Err(e) = set(accessory, service, characteristic, value).await {
+                logging::print(4, &format!("HomeKit update failed: {}", e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_pin_digits() {
+        assert_eq!(pin_digits("031-45-154"), Some([0, 3, 1, 4, 5, 1, 5, 4]));
+        assert_eq!(pin_digits("03145154"), Some([0, 3, 1, 4, 5, 1, 5, 4]));
+        assert_eq!(pin_digits("0314515"), None);
+        assert_eq!(pin_digits("0314515x"), None);
+        assert_eq!(device_id("Bonsai")[0] & 0x03, 0x02);
+        assert_eq!(device_id("Bonsai"), device_id("Bonsai"));
+    }
+}
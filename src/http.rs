@@ -0,0 +1,447 @@
+use crate::annotations::{self, Annotation, AnnotationRequest};
+use crate::audit::{Origin, Source};
+use crate::auth;
+use crate::calendar;
+use crate::config::{CalendarConfig, HttpConfig, Scope, TlsConfig, TokenSpec};
+use crate::db::{self, HistoryPoint, Resolution};
+use crate::events::Event;
+use crate::ipc::{self, Command, Request, Response};
+use crate::maintenance::ConsumableStatus;
+use crate::ratelimit::RateLimiter;
+use crate::recent::SharedRecent;
+use crate::usage::{self, Usage};
+use crate::version::BuildInfo;
+use axum::extract::{self, Extension, Query, State};
+use axum::http::{header, HeaderMap, Method, StatusCode, Uri};
+use axum::middleware::{self, Next};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use crate::logging;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio_postgres::Client;
+
+//
+// @brief  default query window, and the longest window raw rows may be asked for
+//
+const DEFAULT_SPAN_DAYS: i64 = 7;
+const MAX_RAW_SPAN_DAYS: i64 = 31;
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, String)>;
+
+#[derive(Clone)]
+struct AppState {
+    client: Arc<Mutex<Client>>,
+    recent: SharedRecent,
+    tokens: Arc<Vec<TokenSpec>>,
+    control: mpsc::Sender<Command>,
+    limiter: Arc<std::sync::Mutex<RateLimiter>>,
+    build: Arc<BuildInfo>,
+    calendar: Arc<CalendarConfig>,
+    max_manual_secs: u64,
+}
+
+///
+/// @brief name of the token a request was authenticated with
+///
+#[derive(Debug, Clone)]
+struct Caller(String);
+
+///
+/// @brief query string of `GET /history`; times are RFC 3339, e.g. 2024-05-01T00:00:00Z
+///
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub resolution: Resolution,
+}
+
+///
+/// @brief query string of `GET /recent`, served from memory
+///
+#[derive(Debug, Deserialize)]
+pub struct RecentQuery {
+    #[serde(default = "default_recent_minutes")]
+    pub minutes: i64,
+}
+
+fn default_recent_minutes() -> i64 {
+    60
+}
+
+///
+/// @brief query string of `POST /pump` and `POST /fan`
+///
+#[derive(Debug, Deserialize)]
+pub struct RunQuery {
+    pub seconds: Option<f64>,
+}
+
+///
+/// @brief query string of `POST /maintenance/serviced`
+///
+#[derive(Debug, Deserialize)]
+pub struct ServicedQuery {
+    pub name: String,
+}
+
+///
+/// @brief query string of `POST /alerts/ack`, no kind acknowledges every pending alert
+///
+#[derive(Debug, Deserialize)]
+pub struct AckQuery {
+    pub kind: Option<String>,
+}
+
+///
+/// @brief query string of `POST /profile`
+///
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageResponse {
+    pub totals: Vec<Usage>,
+    pub monthly: Vec<Usage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryResponse {
+    pub resolution: Resolution,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub points: Vec<HistoryPoint>,
+}
+
+impl HistoryQuery {
+
+    ///
+    /// @brief fills in the default window (the week up to `now`) and rejects silly ranges
+    ///
+    pub fn range(&self, now: DateTime<Utc>) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+        let to = self.to.unwrap_or(now);
+        let from = self.from.unwrap_or(to - Duration::days(DEFAULT_SPAN_DAYS));
+
+        if from >= to {
+            return Err("`from` must be before `to`".to_string());
+        }
+        if self.resolution == Resolution::Raw && to - from > Duration::days(MAX_RAW_SPAN_DAYS) {
+            return Err(format!("raw history is limited to {} days, use hourly or daily", MAX_RAW_SPAN_DAYS));
+        }
+        Ok((from, to))
+    }
+}
+
+///
+/// @brief starts the HTTP API on its own database connection; control requests go to the
+///        daemon loop over `control`, like the CLI's
+///
+pub async fn serve(config: &HttpConfig, max_manual_secs: u64, recent: SharedRecent, control: mpsc::Sender<Command>, build: BuildInfo) -> Result<(), Box<dyn std::error::Error>> {
+    let listen = &config.listen;
+    let (client, connection) = db::establish_connection().await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            logging::print(3, &format!("HTTP API connection error: {}", e));
+        }
+    });
+
+    let state = AppState {
+        client: Arc::new(Mutex::new(client)),
+        recent,
+        tokens: Arc::new(config.tokens.clone()),
+        control,
+        limiter: Arc::new(std::sync::Mutex::new(RateLimiter::new(config.control_per_min, std::time::Duration::from_secs(60)))),
+        build: Arc::new(build),
+        calendar: Arc::new(config.calendar.clone()),
+        max_manual_secs,
+    };
+    let app = Router::new()
+        .route("/version", get(version))
+        .route("/history", get(history))
+        .route("/recent", get(recent_events))
+        .route("/usage", get(usage_stats))
+        .route("/pump", post(run_pump))
+        .route("/fan", post(run_fan))
+        .route("/maintenance", get(maintenance_status))
+        .route("/maintenance/serviced", post(mark_serviced))
+        .route("/alerts/ack", post(ack_alert))
+        .route("/profile", post(switch_profile))
+        .route("/calendar.ics", get(calendar_feed))
+        .route("/grafana/", get(datasource_check))
+        .route("/grafana/annotations", post(chart_annotations))
+        .layer(middleware::from_fn_with_state(state.clone(), authenticate))
+        .with_state(state);
+
+    if let Some(tls) = &config.tls {
+        return serve_tls(listen, tls, app).await;
+    }
+
+    let listener = TcpListener::bind(listen).await?;
+    logging::print(6, &format!("HTTP API listening on {}", listen));
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            logging::print(3, &format!("HTTP API error: {}", e));
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(feature = "tls")]
+async fn serve_tls(listen: &str, tls: &TlsConfig, app: Router) -> Result<(), Box<dyn std::error::Error>> {
+    let rustls = crate::tls::load(tls).await?;
+    let listener = std::net::TcpListener::bind(listen)?;
+    listener.set_nonblocking(true)?;
+    logging::print(6, &format!("HTTPS API listening on {}", listen));
+    tokio::spawn(async move {
+        if let Err(e) = axum_server::from_tcp_rustls(listener, rustls).serve(app.into_make_service()).await {
+            logging::print(3, &format!("HTTPS API error: {}", e));
+        }
+    });
+
+    Ok(())
+}
+
+///
+/// @brief refuses rather than falling back to plain HTTP, tokens would cross the network in clear
+///
+#[cfg(not(feature = "tls"))]
+async fn serve_tls(_listen: &str, _tls: &TlsConfig, _app: Router) -> Result<(), Box<dyn std::error::Error>> {
+    Err("http.tls is configured but this build lacks the tls feature".into())
+}
+
+///
+/// @brief GETs need a `read` token, anything else a `control` one; the token goes in
+///        `Authorization: Bearer <token>` or `X-Api-Key: <token>`
+///
+/// @note Grafana's JSON datasource POSTs its queries, under /grafana/ those are reads too
+///
+async fn authenticate(State(state): State<AppState>, mut request: extract::Request, next: Next) -> axum::response::Response {
+    let reading = request.method() == Method::GET || request.uri().path().starts_with("/grafana/");
+    let needed = if reading { Scope::Read } else { Scope::Control };
+
+    if state.tokens.is_empty() {
+        if needed == Scope::Read {
+            return next.run(request).await;
+        }
+        return (StatusCode::FORBIDDEN, "No API tokens are configured, control endpoints are disabled").into_response();
+    }
+
+    let Some(token) = presented_token(request.headers()).or_else(|| calendar_token(request.uri())) else {
+        return (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], "API token required").into_response();
+    };
+    match auth::check(&state.tokens, &token, needed) {
+        Ok(spec) => {
+            request.extensions_mut().insert(Caller(spec.name.clone()));
+            next.run(request).await
+        },
+        Err(true) => (StatusCode::FORBIDDEN, "Token lacks the control scope").into_response(),
+        Err(false) => (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], "Unknown API token").into_response(),
+    }
+}
+
+fn presented_token(headers: &HeaderMap) -> Option<String> {
+    if let Some(bearer) = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        return bearer.strip_prefix("Bearer ").map(|t| t.trim().to_string());
+    }
+    headers.get("x-api-key").and_then(|v| v.to_str().ok()).map(|t| t.trim().to_string())
+}
+
+///
+/// @brief calendar apps subscribe to a bare URL, so the feed alone also takes `?token=`
+///
+fn calendar_token(uri: &Uri) -> Option<String> {
+    if uri.path() != "/calendar.ics" {
+        return None;
+    }
+    uri.query()?.split('&').find_map(|pair| pair.strip_prefix("token=")).map(|t| t.to_string())
+}
+
+async fn run_pump(State(state): State<AppState>, Extension(caller): Extension<Caller>, Query(query): Query<RunQuery>) -> ApiResult<MessageResponse> {
+    let seconds = query.seconds.unwrap_or(crate::PUMP_DURATION_SECS);
+    ipc::check_run(seconds, state.max_manual_secs).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    forward(&state, caller, Request::RunPump { seconds }).await
+}
+
+async fn run_fan(State(state): State<AppState>, Extension(caller): Extension<Caller>, Query(query): Query<RunQuery>) -> ApiResult<MessageResponse> {
+    let seconds = query.seconds.unwrap_or(crate::FAN_DURATION_SECS);
+    ipc::check_run(seconds, state.max_manual_secs).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    forward(&state, caller, Request::RunFan { seconds }).await
+}
+
+async fn maintenance_status(State(state): State<AppState>) -> ApiResult<Vec<ConsumableStatus>> {
+    match ask(&state, Origin::new(Source::Http, None), Request::Maintenance).await? {
+        Response::Maintenance { consumables } => Ok(Json(consumables)),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "Unexpected response".to_string())),
+    }
+}
+
+async fn mark_serviced(State(state): State<AppState>, Extension(caller): Extension<Caller>, Query(query): Query<ServicedQuery>) -> ApiResult<MessageResponse> {
+    forward(&state, caller, Request::Serviced { name: query.name }).await
+}
+
+async fn ack_alert(State(state): State<AppState>, Extension(caller): Extension<Caller>, Query(query): Query<AckQuery>) -> ApiResult<MessageResponse> {
+    forward(&state, caller, Request::Ack { kind: query.kind }).await
+}
+
+async fn switch_profile(State(state): State<AppState>, Extension(caller): Extension<Caller>, Query(query): Query<ProfileQuery>) -> ApiResult<MessageResponse> {
+    forward(&state, caller, Request::Profile { name: query.name }).await
+}
+
+///
+/// @brief hands the request to the daemon loop and waits for it to finish, unless the
+///        caller has used up its control allowance for the minute
+///
+async fn forward(state: &AppState, caller: Caller, request: Request) -> ApiResult<MessageResponse> {
+    if let Err(wait) = state.limiter.lock().unwrap().check(&caller.0, std::time::Instant::now()) {
+        logging::print(4, &format!("Rate limited {:?} from token {}", request, caller.0));
+        return Err((StatusCode::TOO_MANY_REQUESTS, format!("Too many control requests, try again in {} s", wait.as_secs() + 1)));
+    }
+    match ask(state, Origin::new(Source::Http, Some(caller.0)), request).await? {
+        Response::Ok { message } => Ok(Json(MessageResponse { message })),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "Unexpected response".to_string())),
+    }
+}
+
+///
+/// @brief sends a request to the daemon loop and waits for its response; an error response
+///        becomes a 500
+///
+async fn ask(state: &AppState, origin: Origin, request: Request) -> Result<Response, (StatusCode, String)> {
+    let (reply_tx, reply_rx) = oneshot::channel();
+    state.control.send((request, origin, reply_tx)).await
+        .map_err(|_| (StatusCode::SERVICE_UNAVAILABLE, "Daemon is shutting down".to_string()))?;
+    match reply_rx.await {
+        Ok(Response::Error { message }) => Err((StatusCode::INTERNAL_SERVER_ERROR, message)),
+        Ok(response) => Ok(response),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Unexpected response".to_string())),
+    }
+}
+
+async fn version(State(state): State<AppState>) -> ApiResult<BuildInfo> {
+    Ok(Json(state.build.as_ref().clone()))
+}
+
+async fn history(State(state): State<AppState>, Query(query): Query<HistoryQuery>) -> ApiResult<HistoryResponse> {
+    let (from, to) = query.range(Utc::now()).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut client = state.client.lock().await;
+    if client.is_closed() {
+        db::try_reconnect(&mut client).await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Database unavailable: {}", e)))?;
+    }
+
+    let points = db::history(&client, from, to, query.resolution).await.map_err(|e| {
+        logging::print(3, &format!("History query error: {}", e));
+        (StatusCode::INTERNAL_SERVER_ERROR, "History query failed".to_string())
+    })?;
+
+    Ok(Json(HistoryResponse { resolution: query.resolution, from, to, points }))
+}
+
+async fn usage_stats(State(state): State<AppState>) -> ApiResult<UsageResponse> {
+    let mut client = state.client.lock().await;
+    if client.is_closed() {
+        db::try_reconnect(&mut client).await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Database unavailable: {}", e)))?;
+    }
+
+    let failed = |e: tokio_postgres::Error| {
+        logging::print(3, &format!("Usage query error: {}", e));
+        (StatusCode::INTERNAL_SERVER_ERROR, "Usage query failed".to_string())
+    };
+    let totals = usage::totals(&client).await.map_err(failed)?;
+    let monthly = usage::monthly(&client).await.map_err(failed)?;
+
+    Ok(Json(UsageResponse { totals, monthly }))
+}
+
+///
+/// @brief the upcoming pump runs, maintenance and reminders as an iCalendar feed
+///
+/// @note runs are listed at the usual interval from the next scheduled one; while dormant
+///       some of them will be skipped
+///
+async fn calendar_feed(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let now = Utc::now();
+    let origin = Origin::new(Source::Http, None);
+    let Response::Status(status) = ask(&state, origin.clone(), Request::Status).await? else {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Unexpected response".to_string()));
+    };
+    let Response::Maintenance { consumables } = ask(&state, origin, Request::Maintenance).await? else {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Unexpected response".to_string()));
+    };
+
+    let mut entries = Vec::new();
+    if let Some(next) = status.next_pump {
+        entries.extend(calendar::pump_runs(next, Duration::hours(crate::PUMP_PERIODIC_HRS),
+            Duration::milliseconds((crate::PUMP_DURATION_SECS * 1000.0) as i64), now + Duration::days(state.calendar.days_ahead)));
+    }
+    entries.extend(calendar::maintenance_due(&consumables, now.date_naive()));
+    entries.extend(calendar::reminders(&state.calendar.reminders));
+
+    Ok(([(header::CONTENT_TYPE, "text/calendar; charset=utf-8")], calendar::render(&entries, now)))
+}
+
+///
+/// @brief answers the JSON datasource's "Save & test"
+///
+async fn datasource_check() -> &'static str {
+    "ok"
+}
+
+///
+/// @brief pump runs, humidifier runs, alerts and journal entries in the dashboard's range,
+///        for Grafana's JSON datasource to draw as annotations
+///
+async fn chart_annotations(State(state): State<AppState>, Json(query): Json<AnnotationRequest>) -> ApiResult<Vec<Annotation>> {
+    let mut client = state.client.lock().await;
+    if client.is_closed() {
+        db::try_reconnect(&mut client).await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Database unavailable: {}", e)))?;
+    }
+
+    let kinds = annotations::kinds(&query.annotation.query);
+    let found = annotations::fetch(&client, query.range.from, query.range.to, &kinds).await.map_err(|e| {
+        logging::print(3, &format!("Annotation query error: {}", e));
+        (StatusCode::INTERNAL_SERVER_ERROR, "Annotation query failed".to_string())
+    })?;
+    Ok(Json(found))
+}
+
+async fn recent_events(State(state): State<AppState>, Query(query): Query<RecentQuery>) -> ApiResult<Vec<Event>> {
+    Ok(Json(state.recent.read().unwrap().last(query.minutes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_history_range() {
+        let now = Utc::now();
+        let (from, to) = HistoryQuery::default().range(now).unwrap();
+        assert_eq!((from, to), (now - Duration::days(DEFAULT_SPAN_DAYS), now));
+
+        let backwards = HistoryQuery { from: Some(now), to: Some(now - Duration::hours(1)), ..Default::default() };
+        assert!(backwards.range(now).is_err());
+
+        let raw_year = HistoryQuery { from: Some(now - Duration::days(365)), resolution: Resolution::Raw, ..Default::default() };
+        assert!(raw_year.range(now).is_err());
+        let daily_year = HistoryQuery { resolution: Resolution::Daily, ..raw_year };
+        assert!(daily_year.range(now).is_ok());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::config::LoadCellSpec;
+use crate::hal::{HalError, InputLine, OutputLine, Platform};
+use crate::reading::Reading;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+
+//
+// @brief  at 10 samples a second a conversion is ready within 100 ms
+//
+const READY_TIMEOUT_MILLIS: u64 = 200;
+
+//
+// @brief  how long the clock is held in each state; the HX711 wants at least 0.2 us, and
+//         powers down if it stays high for 60 us
+//
+const CLOCK_HALF_MICROS: u64 = 1;
+
+pub type Result<T> = std::result::Result<T, HxError>;
+
+#[derive(Debug)]
+pub enum HxError {
+    NotReady,
+    Gpio(HalError),
+}
+
+impl fmt::Display for HxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HxError::NotReady =>
+                write!(f, "No conversion within {} ms, check the wiring and power", READY_TIMEOUT_MILLIS),
+            HxError::Gpio(..) =>
+                write!(f, "GPIO error"),
+        }
+    }
+}
+
+impl error::Error for HxError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            HxError::Gpio(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+///
+/// @brief a load cell on an HX711 amplifier, its data and clock lines on native gpios and
+///        read on channel A at gain 128
+///
+/// @note the protocol is bit-banged; a read is taken as the median of `samples` conversions,
+///       which also throws out the odd one garbled by the thread being preempted mid-read
+///
+pub struct LoadCell {
+    data: Box<dyn InputLine>,
+    clock: Box<dyn OutputLine>,
+    zero_counts: i64,
+    counts_per_gram: f64,
+    samples: usize,
+    sensor_id: String,
+}
+
+fn pause() {
+    let until = Instant::now() + Duration::from_micros(CLOCK_HALF_MICROS);
+    while Instant::now() < until {
+        std::hint::spin_loop();
+    }
+}
+
+///
+/// @brief the 24 bit two's complement conversion as a signed number
+///
+fn sign_extend(raw: u32) -> i32 {
+    ((raw << 8) as i32) >> 8
+}
+
+impl LoadCell {
+    pub fn open(platform: &dyn Platform, spec: &LoadCellSpec, name: &str) -> Result<LoadCell> {
+        let data = platform.input(spec.data.pin, false).map_err(HxError::Gpio)?;
+        let clock = platform.output(spec.clock.pin, false).map_err(HxError::Gpio)?;
+        Ok(LoadCell {
+            data,
+            clock,
+            zero_counts: spec.zero_counts,
+            counts_per_gram: spec.counts_per_gram,
+            samples: spec.samples.max(1),
+            sensor_id: format!("hx711-{}@gpio{}", name, spec.data.pin),
+        })
+    }
+
+    fn read_counts(&mut self) -> Result<i32> {
+        // data goes low once a conversion is ready
+        let deadline = Instant::now() + Duration::from_millis(READY_TIMEOUT_MILLIS);
+        while self.data.is_high().map_err(HxError::Gpio)? {
+            if Instant::now() >= deadline {
+                return Err(HxError::NotReady);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let mut raw = 0u32;
+        for _ in 0..24 {
+            self.clock.set_high().map_err(HxError::Gpio)?;
+            pause();
+            raw = (raw << 1) | self.data.is_high().map_err(HxError::Gpio)? as u32;
+            self.clock.set_low().map_err(HxError::Gpio)?;
+            pause();
+        }
+        // a 25th pulse selects channel A at gain 128 for the next conversion
+        self.clock.set_high().map_err(HxError::Gpio)?;
+        pause();
+        self.clock.set_low().map_err(HxError::Gpio)?;
+        Ok(sign_extend(raw))
+    }
+
+    ///
+    /// @return the load in grams for the median of `counts`
+    ///
+    fn grams(&self, counts: &mut [i32]) -> f64 {
+        counts.sort_unstable();
+        (counts[counts.len() / 2] as i64 - self.zero_counts) as f64 / self.counts_per_gram
+    }
+
+    ///
+    /// @brief weighs on a blocking thread, a read takes about a tenth of a second a sample
+    ///
+    /// @return the load in grams, and the raw counts it came from for calibrating
+    ///
+    pub async fn weigh(cell: Arc<Mutex<LoadCell>>) -> Result<Reading<(f64, i32)>> {
+        tokio::task::spawn_blocking(move || {
+            let mut cell = cell.lock().unwrap_or_else(|e| e.into_inner());
+            let mut counts = (0..cell.samples).map(|_| cell.read_counts()).collect::<Result<Vec<i32>>>()?;
+            let grams = cell.grams(&mut counts);
+            Ok(Reading::now((grams, counts[counts.len() / 2]), &cell.sensor_id))
+        }).await.unwrap_or(Err(HxError::NotReady))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Line;
+
+    impl InputLine for Line {
+        fn is_high(&mut self) -> crate::hal::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    impl OutputLine for Line {
+        fn set_high(&mut self) -> crate::hal::Result<()> {
+            Ok(())
+        }
+
+        fn set_low(&mut self) -> crate::hal::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn test_load_cell() {
+        assert_eq!(sign_extend(0x000001), 1);
+        assert_eq!(sign_extend(0x7FFFFF), 8_388_607);
+        assert_eq!(sign_extend(0xFFFFFF), -1);
+        assert_eq!(sign_extend(0x800000), -8_388_608);
+
+        let mut cell = LoadCell {
+            data: Box::new(Line),
+            clock: Box::new(Line),
+            zero_counts: 84_000,
+            counts_per_gram: 420.0,
+            samples: 3,
+            sensor_id: "hx711-tank@gpio5".to_string(),
+        };
+        // one reading garbled by preemption doesn't move the median
+        assert_eq!(cell.grams(&mut [504_000, 8_000_000, 503_580]), 1000.0);
+        assert_eq!(cell.read_counts().unwrap(), 0);
+    }
+}
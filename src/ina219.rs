@@ -0,0 +1,123 @@
+use crate::hal::{HalError, I2cDevice, Platform};
+use std::{error, fmt};
+
+const CONFIG_REG: u8                 = 0x00;
+const SHUNT_VOLTAGE_REG: u8          = 0x01;
+const BUS_VOLTAGE_REG: u8            = 0x02;
+
+// 32 V bus range, shunt PGA /8 (+-320 mV), 12-bit bus and shunt ADC, continuous shunt and bus
+const CONFIG_DEFAULT: u16            = 0x399F;
+const SHUNT_LSB_VOLTS: f64           = 10e-6;
+const BUS_LSB_VOLTS: f64             = 4e-3;
+const BUS_OVF: u16                   = 1 << 0;   // math overflow, current and power are garbage
+
+pub type Result<T> = std::result::Result<T, InaError>;
+
+#[derive(Debug)]
+pub enum InaError {
+    Overflow,
+    I2c(HalError),
+}
+
+impl fmt::Display for InaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InaError::Overflow =>
+                write!(f, "Shunt voltage out of range"),
+            InaError::I2c(..) =>
+                write!(f, "I2C error"),
+        }
+    }
+}
+
+impl error::Error for InaError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            InaError::I2c(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+///
+/// @brief what the load draws: supply voltage on the load side of the shunt, and current
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerReading {
+    pub bus_volts: f64,
+    pub current_amps: f64,
+}
+
+impl PowerReading {
+    pub fn watts(&self) -> f64 {
+        self.bus_volts * self.current_amps
+    }
+}
+
+///
+/// @brief TI INA219 high-side current and bus voltage monitor, with a shunt of `shunt_ohms`
+///
+/// @note current is worked out from the shunt voltage here rather than through the
+///       calibration register, so the device runs on its power-on defaults
+///
+pub struct INA219 {
+    i2c: Box<dyn I2cDevice>,
+    shunt_ohms: f64,
+}
+
+impl INA219 {
+
+    pub fn new(platform: &dyn Platform, bus: u8, address: u16, shunt_ohms: f64) -> Result<INA219> {
+        let i2c = platform.i2c(bus, address).map_err(InaError::I2c)?;
+        let mut ina = INA219 { i2c, shunt_ohms };
+        let [hi, lo] = CONFIG_DEFAULT.to_be_bytes();
+        ina.i2c.write(&[CONFIG_REG, hi, lo]).map_err(InaError::I2c)?;
+        Ok(ina)
+    }
+
+    ///
+    /// @brief reads the latest conversion
+    ///
+    pub fn read(&mut self) -> Result<PowerReading> {
+        let shunt = self.read_register(SHUNT_VOLTAGE_REG)?;
+        let bus = self.read_register(BUS_VOLTAGE_REG)?;
+        Self::convert(shunt, bus, self.shunt_ohms)
+    }
+
+    fn read_register(&mut self, reg: u8) -> Result<u16> {
+        let mut buf = [0u8; 2];
+        self.i2c.write_read(&[reg], &mut buf).map_err(InaError::I2c)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    ///
+    /// @brief raw shunt and bus voltage registers to volts and amps (datasheet sec. 8.6.3)
+    ///
+    pub fn convert(shunt: u16, bus: u16, shunt_ohms: f64) -> Result<PowerReading> {
+        if bus & BUS_OVF != 0 {
+            return Err(InaError::Overflow);
+        }
+        let shunt_volts = shunt as i16 as f64 * SHUNT_LSB_VOLTS;
+        Ok(PowerReading {
+            bus_volts: (bus >> 3) as f64 * BUS_LSB_VOLTS,
+            current_amps: shunt_volts / shunt_ohms,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_convert() {
+        // 12.0 V bus (3000 << 3, conversion ready), 32 mV across a 0.1 ohm shunt
+        let reading = INA219::convert(3200, 3000 << 3 | 0b10, 0.1).unwrap();
+        assert!((reading.bus_volts - 12.0).abs() < 1e-9);
+        assert!((reading.current_amps - 0.32).abs() < 1e-9);
+        assert!((reading.watts() - 3.84).abs() < 1e-9);
+
+        assert!(INA219::convert(-100i16 as u16, 3000 << 3, 0.1).unwrap().current_amps < 0.0);
+        assert!(matches!(INA219::convert(0, 3000 << 3 | BUS_OVF, 0.1), Err(InaError::Overflow)));
+    }
+}
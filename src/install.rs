@@ -0,0 +1,301 @@
+use crate::cli::{GenArgs, InstallArgs};
+use crate::config::Config;
+use crate::sht20::SHT20;
+use crate::{db, expander, hal, irrigation, output, pins, secrets};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub const SERVICE_NAME: &str = "bonsai-bot.service";
+pub const UNIT_PATH: &str    = "/etc/systemd/system/bonsai-bot.service";
+
+//
+// @brief  the daemon exits with this after installing an update, systemd restarts it even
+//         though it stopped cleanly
+//
+pub const RESTART_EXIT_STATUS: i32 = 75;
+
+//
+// @brief  systemd restarts the daemon after this long without a WATCHDOG=1, which it
+//         sends every half of it
+//
+pub const WATCHDOG_SECS: u64 = 60;
+
+const STATE_DIR: &str   = "/var/lib/bonsai-bot";
+const RUNTIME_DIR: &str = "/run/bonsai-bot";
+
+///
+/// @brief what `install --json` prints once it's done
+///
+#[derive(Debug, Serialize)]
+struct Installed {
+    config: String,
+    config_written: bool,
+    hardware: Option<Hardware>,
+    credential: String,
+    unit: &'static str,
+    enabled: bool,
+}
+
+///
+/// @brief what the hardware check found working
+///
+#[derive(Debug, Default, Serialize)]
+struct Hardware {
+    relays: Vec<String>,
+    valves: Vec<String>,
+    temperature_c: f32,
+}
+
+///
+/// @brief provisions this machine: config file, database schema, hardware check, systemd unit
+///
+/// @note with `json` the steps aren't printed as they go, one summary is once they're all done
+///
+pub async fn run(args: InstallArgs, json: bool) -> Result<(), Box<dyn Error>> {
+    let say = |line: String| if !json { println!("{}", line) };
+
+    say(format!("[1/4] Config file {}", args.config));
+    let config_written = write_default_config(&args.config)?;
+    say(match config_written {
+        true => "      written with defaults, edit the pin mapping to match your board".to_string(),
+        false => "      already exists, leaving it alone".to_string(),
+    });
+
+    say("[2/4] Database schema".to_string());
+    let (mut client, connection) = db::connect(&args.database_url).await
+        .map_err(|e| format!("Could not connect to the database: {}", e))?;
+    let connection = tokio::spawn(connection);
+    db::migrate(&mut client).await?;
+    drop(client);
+    let _ = connection.await;
+    say("      tables created".to_string());
+
+    say("[3/4] Hardware access".to_string());
+    let hardware = if args.skip_hardware_check {
+        say("      skipped".to_string());
+        None
+    } else {
+        Some(check_hardware(&args.config, &say).await
+            .map_err(|e| format!("Hardware check failed (use --skip-hardware-check to install anyway): {}", e))?)
+    };
+
+    say(format!("[4/4] systemd unit {}", UNIT_PATH));
+    secrets::encrypt(secrets::DATABASE_URL, &args.database_url)
+        .map_err(|e| format!("Could not encrypt the database URL: {}", e))?;
+    let credential = secrets::path(secrets::DATABASE_URL);
+    say(format!("      database URL encrypted to {}", credential));
+    let exe = std::env::current_exe()?;
+    let unit = unit_file(&exe.to_string_lossy(), &args.user, &args.config, &Config::from_file_every_profile(&args.config)?);
+    std::fs::write(UNIT_PATH, unit)?;
+    systemctl(&["daemon-reload"])?;
+    if args.no_enable {
+        say(format!("      written, enable with: systemctl enable --now {}", SERVICE_NAME));
+    } else {
+        systemctl(&["enable", "--now", SERVICE_NAME])?;
+        say("      enabled and started".to_string());
+    }
+
+    if json {
+        output::json(&Installed {
+            config: args.config,
+            config_written,
+            hardware,
+            credential,
+            unit: UNIT_PATH,
+            enabled: !args.no_enable,
+        });
+    }
+    Ok(())
+}
+
+///
+/// @brief writes the built-in defaults unless a config is already there
+///
+/// @return whether it wrote one
+///
+fn write_default_config(path: &str) -> Result<bool, Box<dyn Error>> {
+    if Path::new(path).exists() {
+        return Ok(false);
+    }
+    if let Some(dir) = Path::new(path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, toml::to_string_pretty(&Config::default())?)?;
+    Ok(true)
+}
+
+///
+/// @brief opens the platform, claims every relay (leaving it off) and reads the SHT20 once,
+///        telling `say` about each as it goes
+///
+async fn check_hardware(config_path: &str, say: &impl Fn(String)) -> Result<Hardware, Box<dyn Error>> {
+    let config = Config::from_file(config_path)?;
+    pins::validate(&config)?;
+
+    let platform = hal::open(&config.platform)?;
+    let expanders = expander::open_all(platform.as_ref(), &config.expanders)?;
+    let mut hardware = Hardware::default();
+    for (role, spec) in config.pins.roles() {
+        if role == "pump" {
+            pins::claim_pump(platform.as_ref(), &expanders, &config)?;
+        } else {
+            pins::claim(platform.as_ref(), &expanders, role, spec)?;
+        }
+        say(format!("      {} relay ok", role));
+        hardware.relays.push(role.to_string());
+    }
+    for zone in irrigation::open_zones(platform.as_ref(), &expanders, &config)? {
+        say(format!("      {} valve ok", zone.name));
+        hardware.valves.push(zone.name);
+    }
+
+    let sht20 = Arc::new(Mutex::new(SHT20::new(platform.as_ref())?));
+    let temp = SHT20::get_temperature_celsius(sht20).await?;
+    say(format!("      SHT20 ok ({:3.2} C)", temp.value));
+    hardware.temperature_c = temp.value;
+    Ok(hardware)
+}
+
+///
+/// @brief the directories the daemon writes outside its StateDirectory and RuntimeDirectory,
+///        everything else is read-only under ProtectSystem=strict
+///
+fn writable_paths(exe: &str, config: &Config) -> BTreeSet<String> {
+    let parent = |path: &str| Path::new(path).parent().map(|dir| dir.to_string_lossy().into_owned());
+    let mut paths = BTreeSet::new();
+    paths.extend(config.backup.as_ref().map(|b| b.dir.clone()));
+    paths.extend(config.archive.as_ref().map(|a| a.dir.clone()));
+    paths.extend(config.blackbox.as_ref().map(|b| b.dir.clone()));
+    paths.extend(config.homekit.as_ref().map(|h| h.storage_dir.clone()));
+    // the updater renames the new binary over the old one, which needs its directory
+    paths.extend(config.update.as_ref().and_then(|u| parent(u.install_path.as_deref().unwrap_or(exe))));
+    paths.extend(parent(&config.ipc.socket));
+    paths.retain(|path| !path.is_empty() && !path.starts_with(STATE_DIR) && !path.starts_with(RUNTIME_DIR));
+    paths
+}
+
+///
+/// @brief renders the systemd unit for the daemon: restarted on failure or once it stops
+///        sending WATCHDOG=1, with only gpio, i2c, spi and the hardware watchdog to touch
+///        and only the directories `config` writes to writable
+///
+/// @note the database URL and any credential the config names are decrypted from the
+///       credstore into $CREDENTIALS_DIRECTORY, rather than sitting in the unit
+/// @note `profiles` is the config as every profile it has: switching one restarts the
+///       daemon under this same unit, which has to allow whatever that profile needs
+///
+pub fn unit_file(exe: &str, user: &str, config_path: &str, profiles: &[Config]) -> String {
+    let mut devices = vec!["/dev/gpiomem", "char-gpiochip", "char-i2c", "char-spidev"];
+    for device in profiles.iter().filter_map(|config| config.watchdog.as_ref().map(|w| w.device.as_str())) {
+        if !devices.contains(&device) {
+            devices.push(device);
+        }
+    }
+    let devices: String = devices.iter().map(|device| format!("DeviceAllow={} rw\n", device)).collect();
+    // "-": a directory that isn't there yet doesn't stop the unit from starting
+    let writable: BTreeSet<String> = profiles.iter().flat_map(|config| writable_paths(exe, config)).collect();
+    let writable: String = writable.iter().map(|path| format!("ReadWritePaths=-{}\n", path)).collect();
+    let mut credentials: BTreeSet<String> = profiles.iter().flat_map(secrets::references).collect();
+    credentials.insert(secrets::DATABASE_URL.to_string());
+    let credentials: String = credentials.iter().map(|name| format!("LoadCredentialEncrypted={}:{}\n", name, secrets::path(name))).collect();
+    format!("\
+[Unit]
+Description=Bonsai-Bot climate control
+After=network-online.target time-sync.target postgresql.service
+Wants=network-online.target
+StartLimitIntervalSec=600
+StartLimitBurst=5
+
+[Service]
+Type=notify
+NotifyAccess=main
+WatchdogSec={watchdog_secs}
+User={user}
+SupplementaryGroups=gpio i2c spi
+RuntimeDirectory=bonsai-bot
+StateDirectory=bonsai-bot
+Environment=BONSAIBOT_CONFIG={config_path}
+{credentials}ExecStart={exe} run
+Restart=on-failure
+RestartForceExitStatus={restart_status}
+RestartSec=10
+DevicePolicy=closed
+{devices}NoNewPrivileges=yes
+ProtectSystem=strict
+ProtectHome=yes
+PrivateTmp=yes
+ProtectKernelTunables=yes
+ProtectKernelModules=yes
+ProtectControlGroups=yes
+RestrictAddressFamilies=AF_UNIX AF_INET AF_INET6 AF_NETLINK
+RestrictRealtime=yes
+RestrictSUIDSGID=yes
+LockPersonality=yes
+{writable}
+[Install]
+WantedBy=multi-user.target
+", watchdog_secs = WATCHDOG_SECS, restart_status = RESTART_EXIT_STATUS)
+}
+
+///
+/// @brief `bonsai-bot systemd gen`: prints the unit `install` would write, for this binary
+///        and the config at `args.config`, or with `json` the unit as a string and its path
+///
+pub fn print_unit(args: GenArgs, json: bool) -> Result<(), Box<dyn Error>> {
+    let profiles = Config::from_file_every_profile(&args.config)?;
+    let exe = std::env::current_exe()?;
+    let unit = unit_file(&exe.to_string_lossy(), &args.user, &args.config, &profiles);
+    if json {
+        output::json(&serde_json::json!({ "path": UNIT_PATH, "unit": unit }));
+    } else {
+        print!("{}", unit);
+    }
+    Ok(())
+}
+
+fn systemctl(args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let status = Command::new("systemctl").args(args).status()?;
+    if !status.success() {
+        return Err(format!("systemctl {} failed with {}", args.join(" "), status).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_unit_file() {
+        let mut config = Config::default();
+        let unit = unit_file("/usr/local/bin/bonsai-bot", "pi", "/etc/bonsai-bot/config.toml", std::slice::from_ref(&config));
+        assert!(unit.contains("ExecStart=/usr/local/bin/bonsai-bot run\n"));
+        assert!(unit.contains("LoadCredentialEncrypted=database-url:/etc/credstore.encrypted/bonsai-bot.database-url\nExecStart"));
+        assert!(!unit.contains("BONSAIBOT_DATABASE_URL"));
+        assert!(unit.contains("User=pi\n"));
+        assert!(unit.contains("Type=notify\nNotifyAccess=main\nWatchdogSec=60\n"));
+        assert!(unit.contains("DeviceAllow=char-i2c rw\n"));
+        assert!(!unit.contains("ReadWritePaths"));
+
+        config.backup = toml::from_str("dir = \"/srv/backups\"").ok();
+        config.update = toml::from_str("release_url = \"https://example.com\"\npublic_key = \"00\"").ok();
+        config.watchdog = toml::from_str("").ok();
+        config.webhooks = toml::from_str::<Config>("[[webhooks]]\nurl = \"https://example.com\"\nevents = [\"alert\"]\nsecret = \"credential:hook\"").unwrap().webhooks;
+        let unit = unit_file("/usr/local/bin/bonsai-bot", "pi", "/etc/bonsai-bot/config.toml", std::slice::from_ref(&config));
+        assert!(unit.contains("LoadCredentialEncrypted=database-url:/etc/credstore.encrypted/bonsai-bot.database-url\n\
+            LoadCredentialEncrypted=hook:/etc/credstore.encrypted/bonsai-bot.hook\n"));
+        assert!(unit.contains("ReadWritePaths=-/srv/backups\nReadWritePaths=-/usr/local/bin\n"));
+        assert!(unit.contains("DeviceAllow=/dev/watchdog rw\n"));
+
+        // a profile the daemon isn't running yet gets what it needs too
+        let contents = "[profiles.away.mqtt]\nhost = \"broker\"\npassword = \"credential:broker\"\n";
+        let profiles = vec![Config::parse_as(contents, "default").unwrap(), Config::parse_as(contents, "away").unwrap()];
+        let unit = unit_file("/usr/local/bin/bonsai-bot", "pi", "/etc/bonsai-bot/config.toml", &profiles);
+        assert!(unit.contains("LoadCredentialEncrypted=broker:/etc/credstore.encrypted/bonsai-bot.broker\n"));
+    }
+}
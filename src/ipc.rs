@@ -0,0 +1,244 @@
+use crate::audit::{Origin, Source};
+use crate::maintenance::ConsumableStatus;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::PermissionsExt;
+use crate::logging;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+
+//
+// @brief  default control socket, systemd creates /run/bonsai-bot via RuntimeDirectory=
+//
+pub const DEFAULT_SOCKET_PATH: &str = "/run/bonsai-bot/control.sock";
+
+///
+/// @brief a request sent to the daemon, one JSON object per line
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    Metrics,
+    RunPump { seconds: f64 },
+    RunFan { seconds: f64 },
+    Maintenance,
+    Serviced { name: String },
+    Dormancy { mode: crate::dormancy::Mode },
+    HeaterClear,
+    Recent { minutes: i64 },
+    Relay { node: String, relay: String, on: bool },
+    Inject { fault: Fault },
+    Ack { kind: Option<String> },
+    Profile { name: String },
+}
+
+///
+/// @brief checks the run time a manual pump or fan run asks for
+///
+/// @return why `seconds` won't do, if it won't
+///
+pub fn check_run(seconds: f64, max_secs: u64) -> Result<(), String> {
+    if !(seconds.is_finite() && seconds >= 0.0) {
+        Err(format!("{} is not a run time in seconds", seconds))
+    } else if seconds > max_secs as f64 {
+        Err(format!("{} s is longer than a manual run may last, at most {} s (actuation.max_manual_secs)", seconds, max_secs))
+    } else {
+        Ok(())
+    }
+}
+
+///
+/// @brief a failure the daemon can be made to suffer on the bench, needs the `chaos` feature
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Fault {
+    /// The next I2C transfer is refused
+    I2cNak,
+    /// The next GPIO switch or read fails
+    Gpio,
+    /// The database connection drops at the next climate tick
+    DbDisconnect,
+    /// The system clock steps by clock_jump_secs at the next climate tick
+    ClockJump,
+}
+
+///
+/// @brief the daemon's answer to a Request, one JSON object per line
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Response {
+    Status(StatusReport),
+    Metrics { text: String },
+    Maintenance { consumables: Vec<ConsumableStatus> },
+    // the events as `GET /recent` serves them, Event itself only serializes
+    Recent { events: Vec<serde_json::Value> },
+    Ok { message: String },
+    Error { message: String },
+}
+
+///
+/// @brief what the daemon currently knows about the enclosure
+///
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatusReport {
+    pub temperature: Option<f64>,
+    pub humidity: Option<f64>,
+    pub measured_at: Option<DateTime<Utc>>,
+    pub humidifier_on: bool,
+    pub pump_on: bool,
+    pub fan_on: bool,
+    pub next_pump: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub door_open: bool,
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub restarts_last_day: i64,
+    #[serde(default)]
+    pub shadow: bool,
+    #[serde(default)]
+    pub variant: Option<String>,
+    #[serde(default)]
+    pub dormant: bool,
+    #[serde(default)]
+    pub wind_kmh: Option<f64>,
+    #[serde(default)]
+    pub mister_on: bool,
+    #[serde(default)]
+    pub tank_grams: Option<f64>,
+    #[serde(default)]
+    pub pot_grams: Option<f64>,
+    #[serde(default)]
+    pub heater_on: bool,
+    #[serde(default)]
+    pub heater_latched: Option<String>,
+    #[serde(default)]
+    pub light_on: bool,
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+///
+/// @brief a request, who sent it, and the channel its response goes back on
+///
+pub type Command = (Request, Origin, oneshot::Sender<Response>);
+
+///
+/// @brief binds the control socket and forwards every request to `tx`; the daemon's select
+///        loop services the other end alongside its timers
+///
+pub async fn spawn_server(path: &str, tx: mpsc::Sender<Command>) -> std::io::Result<()> {
+
+    // a leftover socket from a crash is fine to remove, a live one means a second daemon
+    if UnixStream::connect(path).await.is_ok() {
+        return Err(std::io::Error::new(std::io::ErrorKind::AddrInUse,
+            format!("another daemon is already listening on {}", path)));
+    }
+    let _ = std::fs::remove_file(path);
+    // systemd's RuntimeDirectory makes it under the unit, in a container nothing does
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660))?;
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream, tx.clone()));
+                },
+                Err(e) => {
+                    logging::print(3, &format!("Control socket accept error: {}", e));
+                },
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(stream: UnixStream, tx: mpsc::Sender<Command>) {
+    // the kernel vouches for the peer, anyone who can open the socket is in the group anyway
+    let origin = Origin::new(Source::Cli, stream.peer_cred().ok().map(|cred| format!("uid {}", cred.uid())));
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                if tx.send((request, origin.clone(), reply_tx)).await.is_err() {
+                    Response::Error { message: "Daemon is shutting down".to_string() }
+                } else {
+                    reply_rx.await.unwrap_or(Response::Error { message: "Request dropped".to_string() })
+                }
+            },
+            Err(e) => Response::Error { message: format!("Bad request: {}", e) },
+        };
+
+        let mut out = match serde_json::to_string(&response) {
+            Ok(s) => s,
+            Err(_) => break,
+        };
+        out.push('\n');
+        if write.write_all(out.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+///
+/// @brief sends one request to a running daemon
+///
+/// @note fails with NotFound or ConnectionRefused when no daemon is listening
+///
+pub async fn request(path: &str, request: &Request) -> std::io::Result<Response> {
+    let stream = UnixStream::connect(path).await?;
+    let (read, mut write) = stream.into_split();
+
+    let mut out = serde_json::to_string(request)?;
+    out.push('\n');
+    write.write_all(out.as_bytes()).await?;
+
+    let mut line = String::new();
+    BufReader::new(read).read_line(&mut line).await?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_round_trip() {
+        let path = std::env::temp_dir().join(format!("bonsai-bot-test-{}.sock", std::process::id()));
+        let path = path.to_str().unwrap();
+        let (tx, mut rx) = mpsc::channel(8);
+        spawn_server(path, tx).await.expect("Could not bind socket");
+
+        tokio::spawn(async move {
+            while let Some((request, origin, reply)) = rx.recv().await {
+                assert_eq!(origin.source, Source::Cli);
+                let _ = reply.send(match request {
+                    Request::RunPump { seconds } => Response::Ok { message: format!("{}", seconds) },
+                    _ => Response::Error { message: "unexpected".to_string() },
+                });
+            }
+        });
+
+        let response = super::request(path, &Request::RunPump { seconds: 1.5 }).await.expect("No response");
+        assert_eq!(response, Response::Ok { message: "1.5".to_string() });
+        let _ = std::fs::remove_file(path);
+
+        assert!(check_run(1.5, 600).is_ok());
+        assert!(check_run(-1.0, 600).is_err());
+        assert!(check_run(f64::NAN, 600).is_err());
+        assert!(check_run(1e9, 600).is_err());
+    }
+}
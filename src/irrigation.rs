@@ -0,0 +1,214 @@
+use crate::actuator::{self, Actuator, Stagger};
+use crate::config::Config;
+use crate::expander::SharedExpander;
+use crate::hal::Platform;
+use crate::pins::{self, PinError};
+use crate::logging;
+use std::collections::HashMap;
+use tokio::time::{sleep_until, Instant};
+
+///
+/// @brief a drip zone behind its own solenoid valve, fed by the shared pump
+///
+pub struct Zone {
+    pub name: String,
+    valve: Box<dyn Actuator>,
+    seconds: f64,
+    flow_ml_per_min: Option<f64>,
+}
+
+///
+/// @brief how long one zone was watered for, and roughly how much water that was
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub zone: String,
+    pub seconds: f64,
+    pub volume_ml: Option<f64>,
+}
+
+impl Zone {
+    pub fn valve(&mut self) -> &mut dyn Actuator {
+        self.valve.as_mut()
+    }
+
+    fn delivery(&self, seconds: f64) -> Delivery {
+        Delivery {
+            zone: self.name.clone(),
+            seconds,
+            volume_ml: self.flow_ml_per_min.map(|flow| flow * seconds / 60.0),
+        }
+    }
+}
+
+///
+/// @brief claims every configured zone valve, leaving them all closed
+///
+pub fn open_zones(
+    platform: &dyn Platform,
+    expanders: &HashMap<String, SharedExpander>,
+    config: &Config
+) -> Result<Vec<Zone>, PinError> {
+    config.zones.iter().map(|spec| {
+        Ok(Zone {
+            name: spec.name.clone(),
+            valve: pins::claim(platform, expanders, "valve", &spec.valve)?,
+            seconds: spec.run_seconds(),
+            flow_ml_per_min: spec.flow_ml_per_min(),
+        })
+    }).collect()
+}
+
+///
+/// @brief zones whose valves only log what they would do, for shadow mode
+///
+pub fn shadow_zones(config: &Config) -> Vec<Zone> {
+    config.zones.iter().map(|spec| Zone {
+        name: spec.name.clone(),
+        valve: actuator::Shadow::boxed(format!("{} valve", spec.name)),
+        seconds: spec.run_seconds(),
+        flow_ml_per_min: spec.flow_ml_per_min(),
+    }).collect()
+}
+
+///
+/// @brief waters each zone in turn with the pump running throughout
+///
+/// @note the next valve opens before the previous one closes so the pump never pushes against
+///       a closed line; `seconds` overrides every zone's configured duration, which is
+///       otherwise stretched by `scale`
+///
+pub async fn water(
+    pump: &mut dyn Actuator,
+    zones: &mut [Zone],
+    stagger: &mut Stagger,
+    seconds: Option<f64>,
+    scale: f64
+) -> Vec<Delivery> {
+    let mut run = Sequence::start(pump, zones, stagger, seconds, scale).await;
+    loop {
+        sleep_until(run.due()).await;
+        if let Some(deliveries) = run.advance(pump, zones, stagger).await {
+            return deliveries;
+        }
+    }
+}
+
+///
+/// @brief a watering run stepped by whoever holds it each time it falls `due`, so the daemon
+///        loop can go on handling readings in between
+///
+/// @note without zones it is the pump alone for `seconds`
+///
+pub struct Sequence {
+    seconds: Option<f64>,
+    scale: f64,
+    zone: usize,
+    due: Instant,
+    deliveries: Vec<Delivery>,
+}
+
+impl Sequence {
+    ///
+    /// @brief opens the first valve and switches the pump on
+    ///
+    pub async fn start(pump: &mut dyn Actuator, zones: &mut [Zone], stagger: &mut Stagger, seconds: Option<f64>, scale: f64) -> Sequence {
+        let mut run = Sequence { seconds, scale, zone: 0, due: Instant::now(), deliveries: Vec::with_capacity(zones.len()) };
+        match zones.first_mut() {
+            Some(first) => {
+                stagger.switch_on(first.valve.as_mut()).await;
+                stagger.switch_on(pump).await;
+                run.open(zones);
+            },
+            None => {
+                stagger.switch_on(pump).await;
+                run.due = Instant::now() + actuator::run_time(seconds.unwrap_or_default());
+            },
+        }
+        run
+    }
+
+    pub fn due(&self) -> Instant {
+        self.due
+    }
+
+    ///
+    /// @brief moves on to the next zone, or switches everything off after the last
+    ///
+    /// @return what each zone got, once the run is over
+    ///
+    pub async fn advance(&mut self, pump: &mut dyn Actuator, zones: &mut [Zone], stagger: &mut Stagger) -> Option<Vec<Delivery>> {
+        if let Some(zone) = zones.get(self.zone) {
+            let delivery = zone.delivery(self.secs(zone));
+            if let Some(ml) = delivery.volume_ml {
+                logging::send(6, &format!("Zone {} received about {:.0} ml", delivery.zone, ml),
+                    &[("SERVICE", &"pump"), ("EVENT", &"zone_delivery"), ("ZONE", &delivery.zone), ("VOLUME_ML", &format!("{:.0}", ml))]);
+            }
+            self.deliveries.push(delivery);
+        }
+
+        if self.zone + 1 < zones.len() {
+            self.zone += 1;
+            stagger.switch_on(zones[self.zone].valve.as_mut()).await;
+            zones[self.zone - 1].valve.set_off();
+            self.open(zones);
+            return None;
+        }
+
+        actuator::switch_off(pump).await;
+        if let Some(last) = zones.last_mut() {
+            last.valve.set_off();
+        }
+        Some(std::mem::take(&mut self.deliveries))
+    }
+
+    fn secs(&self, zone: &Zone) -> f64 {
+        self.seconds.unwrap_or_else(|| (zone.seconds * self.scale * 1000.0).round() / 1000.0)
+    }
+
+    // the current zone's valve is open, start its clock
+    fn open(&mut self, zones: &[Zone]) {
+        let zone = &zones[self.zone];
+        let secs = self.secs(zone);
+        logging::send(6, &format!("Watering zone {} for {} s", zone.name, secs),
+            &[("SERVICE", &"pump"), ("EVENT", &"zone_start"), ("ZONE", &zone.name), ("SECONDS", &secs)]);
+        self.due = Instant::now() + actuator::run_time(secs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actuator::Fake;
+    use tokio::time::Duration;
+
+    #[tokio::test]
+    pub async fn test_water() {
+        let zone = |name: &str, flow| Zone {
+            name: name.to_string(),
+            valve: Box::new(Fake::default()),
+            seconds: 0.0,
+            flow_ml_per_min: flow,
+        };
+        let mut zones = vec![zone("juniper", Some(120.0)), zone("maple", None)];
+        let mut pump = Fake::default();
+        let mut stagger = Stagger::new(Duration::from_millis(0));
+
+        let deliveries = water(&mut pump, &mut zones, &mut stagger, Some(0.0), 1.0).await;
+
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].volume_ml, Some(0.0));
+        assert_eq!(zones[0].delivery(1.5).volume_ml, Some(3.0));
+        assert_eq!(deliveries[1], Delivery { zone: "maple".to_string(), seconds: 0.0, volume_ml: None });
+        assert!(!pump.is_on());
+        assert!(zones.iter().all(|z| !z.valve.is_on()));
+
+        // stepped from outside, one zone at a time
+        let mut run = Sequence::start(&mut pump, &mut zones, &mut stagger, Some(0.0), 1.0).await;
+        assert!(pump.is_on() && zones[0].valve.is_on());
+        assert_eq!(run.advance(&mut pump, &mut zones, &mut stagger).await, None);
+        assert!(pump.is_on() && !zones[0].valve.is_on() && zones[1].valve.is_on());
+        assert_eq!(run.advance(&mut pump, &mut zones, &mut stagger).await.map(|d| d.len()), Some(2));
+        assert!(!pump.is_on() && !zones[1].valve.is_on());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::alerts;
+use crate::db;
+use crate::events::Bus;
+use crate::logging;
+use tokio::time::{sleep, Duration};
+use tokio_postgres::Client;
+
+//
+// @brief  how long an instance's lease outlives its last renewal; a daemon that died keeps
+//         others with its instance id out this long, though its own restart takes over
+//
+pub const INSTANCE_LEASE_SECS: u64 = 90;
+
+///
+/// @brief who holds a lease: this host, process and boot, so two daemons on one database never
+///        mistake each other's lease for their own, not even on a cloned SD card
+///
+pub fn holder() -> String {
+    let host = std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+    let boot = std::fs::read_to_string("/proc/sys/kernel/random/boot_id").unwrap_or_default();
+    format!("{} pid {} boot {}", host.trim(), std::process::id(), boot.trim().chars().take(8).collect::<String>())
+}
+
+///
+/// @return whether `other` is an earlier daemon on this host since this boot that has since
+///         died, whose lease is ours to take over rather than wait out after a restart
+///
+pub fn orphaned(other: &str, holder: &str, alive: impl Fn(u32) -> bool) -> bool {
+    let parse = |holder: &str| match holder.split_whitespace().collect::<Vec<_>>()[..] {
+        [host, "pid", pid, "boot", boot] => pid.parse::<u32>().ok().map(|pid| (host.to_string(), pid, boot.to_string())),
+        _ => None,
+    };
+    match (parse(other), parse(holder)) {
+        (Some((host, pid, boot)), Some((our_host, our_pid, our_boot))) =>
+            host == our_host && boot == our_boot && !boot.is_empty() && pid != our_pid && !alive(pid),
+        _ => false,
+    }
+}
+
+pub fn pid_alive(pid: u32) -> bool {
+    std::path::Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+///
+/// @brief hands the named lease from `from`, which `orphaned` says is dead, to `holder`
+///
+/// @return false if `from` no longer holds it
+///
+pub async fn take_over(client: &Client, name: &str, from: &str, holder: &str, ttl_secs: u64) -> Result<bool, tokio_postgres::Error> {
+    let row = client.query_opt("
+        UPDATE leases SET holder = $3, expires_at = now() + $4::bigint * interval '1 second'
+        WHERE name = $1 AND holder = $2 RETURNING holder", &[&name, &from, &holder, &(ttl_secs as i64)]).await?;
+    Ok(row.is_some())
+}
+
+///
+/// @brief takes the named lease for `ttl_secs`, or extends it if `holder` already has it
+///
+/// @return false if someone else holds it and it hasn't expired
+///
+pub async fn acquire(client: &Client, name: &str, holder: &str, ttl_secs: u64) -> Result<bool, tokio_postgres::Error> {
+    let row = client.query_opt("
+        INSERT INTO leases (name, holder, expires_at) VALUES ($1, $2, now() + $3::bigint * interval '1 second')
+        ON CONFLICT (name) DO UPDATE SET holder = EXCLUDED.holder, expires_at = EXCLUDED.expires_at
+        WHERE leases.holder = EXCLUDED.holder OR leases.expires_at < now()
+        RETURNING holder", &[&name, &holder, &(ttl_secs as i64)]).await?;
+    Ok(row.is_some())
+}
+
+///
+/// @return who holds the named lease, None if nobody does or it has expired
+///
+pub async fn held_by(client: &Client, name: &str) -> Result<Option<String>, tokio_postgres::Error> {
+    let row = client.query_opt("SELECT holder FROM leases WHERE name = $1 AND expires_at >= now()", &[&name]).await?;
+    Ok(row.map(|row| row.get(0)))
+}
+
+pub async fn release(client: &Client, name: &str, holder: &str) -> Result<(), tokio_postgres::Error> {
+    client.execute("DELETE FROM leases WHERE name = $1 AND holder = $2", &[&name, &holder]).await?;
+    Ok(())
+}
+
+///
+/// @brief the lease on this instance id, which keeps a second daemon with the same id (a
+///        cloned SD card, a dev unit with the same hostname) from scheduling off the same rows
+///
+pub fn instance_lease(instance_id: &str) -> String {
+    format!("instance/{}", instance_id)
+}
+
+///
+/// @brief renews a lease every third of `ttl_secs` on a connection of its own, so a long pump
+///        run in the control loop can't let it lapse; losing it raises a `lease_lost` alert
+///
+pub fn spawn_keeper(name: String, holder: String, ttl_secs: u64, bus: Bus) {
+    tokio::spawn(async move {
+        let mut held = true;
+        loop {
+            sleep(Duration::from_secs(ttl_secs / 3)).await;
+            let renewed = match db::establish_connection().await {
+                Ok((client, connection)) => {
+                    let connection = tokio::spawn(connection);
+                    let renewed = acquire(&client, &name, &holder, ttl_secs).await;
+                    drop(client);
+                    let _ = connection.await;
+                    renewed.map_err(|e| e.to_string())
+                },
+                Err(e) => Err(e.to_string()),
+            };
+            match renewed {
+                Ok(now_held) => {
+                    if held && !now_held {
+                        alerts::raise(&bus, "lease_lost", &format!("Another daemon took over the {} lease, check BONSAIBOT_INSTANCE_ID on both", name));
+                    }
+                    held = now_held;
+                },
+                Err(e) => { logging::print(4, &format!("Could not renew the {} lease: {}", name, e)); },
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_orphaned() {
+        let me = "bonsai pid 812 boot 3f9a01c2";
+        // the daemon systemd restarted us after, its pid gone
+        assert!(orphaned("bonsai pid 640 boot 3f9a01c2", me, |_| false));
+        assert!(!orphaned("bonsai pid 640 boot 3f9a01c2", me, |pid| pid == 640));
+        // a clone with our hostname, or the same host before a reboot, is waited out
+        assert!(!orphaned("bonsai pid 640 boot 77e0d415", me, |_| false));
+        assert!(!orphaned("maple pid 640 boot 3f9a01c2", me, |_| false));
+        assert!(!orphaned("bonsai pid 640", me, |_| false));
+        assert!(!orphaned(me, me, |_| false));
+    }
+}
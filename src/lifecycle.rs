@@ -0,0 +1,105 @@
+use chrono::{DateTime, Duration, Utc};
+use tokio_postgres::Client;
+
+///
+/// @brief what the system_events history says about this start
+///
+#[derive(Debug, Clone, Default)]
+pub struct Start {
+    pub at: DateTime<Utc>,
+    // set when the previous run started but never recorded a stop, i.e. it crashed or lost power
+    pub unclean_since: Option<DateTime<Utc>>,
+    pub restarts_last_hour: i64,
+    pub restarts_last_day: i64,
+}
+
+///
+/// @brief records a daemon start in system_events, along with the version and pid
+///
+pub async fn record_start(client: &Client) -> Result<Start, tokio_postgres::Error> {
+    let last = client.query_opt("SELECT timestamp, event FROM system_events WHERE event IN ('start', 'stop') AND mine(device_id) ORDER BY id DESC LIMIT 1", &[]).await?;
+    let unclean_since = last
+        .filter(|row| row.get::<_, String>(1) == "start")
+        .map(|row| row.get(0));
+
+    let detail = format!("version {} pid {}", env!("CARGO_PKG_VERSION"), std::process::id());
+    let at: DateTime<Utc> = client.query_one("INSERT INTO system_events (event, detail) VALUES ('start', $1) RETURNING timestamp",
+        &[&detail]).await?.get(0);
+
+    // every start but the first in a window is a restart
+    let row = client.query_one("SELECT
+            COUNT(*) FILTER (WHERE timestamp > $1::timestamptz - interval '1 hour'),
+            COUNT(*)
+        FROM system_events WHERE event = 'start' AND mine(device_id) AND timestamp > $1::timestamptz - interval '1 day'", &[&at]).await?;
+    let (last_hour, last_day): (i64, i64) = (row.get(0), row.get(1));
+
+    Ok(Start { at, unclean_since, restarts_last_hour: last_hour - 1, restarts_last_day: last_day - 1 })
+}
+
+///
+/// @brief records a clean shutdown, `reason` being e.g. the signal which asked for it
+///
+pub async fn record_stop(client: &Client, reason: &str) -> Result<(), tokio_postgres::Error> {
+    client.execute("INSERT INTO system_events (event, detail) VALUES ('stop', $1)", &[&reason]).await?;
+    Ok(())
+}
+
+///
+/// @brief the latest humidity reading no older than `max_age`, and whether the humidifier was
+///        last switched on, from climate_data and actuator_events
+///
+pub async fn last_humidifier(client: &Client, max_age: Duration) -> Result<(Option<f64>, Option<bool>), tokio_postgres::Error> {
+    let since = Utc::now() - max_age;
+    let rh = client.query_opt("SELECT humidity FROM climate_data WHERE timestamp > $1 AND humidity IS NOT NULL AND mine(device_id)
+        ORDER BY timestamp DESC LIMIT 1", &[&since]).await?.map(|row| row.get(0));
+    let was_on = client.query_opt("SELECT is_on FROM actuator_events WHERE actuator = 'humidifier' AND mine(device_id)
+        ORDER BY timestamp DESC LIMIT 1", &[]).await?.map(|row| row.get(0));
+    Ok((rh, was_on))
+}
+
+///
+/// @brief whether the humidifier should come back on at startup: below `lo` it would switch on
+///        anyway, above `hi` it would switch off, and in between it keeps its last state
+///
+/// @note without a recent reading it stays off until the first climate tick decides
+///
+pub fn resume_humidifier(rh: Option<f64>, was_on: Option<bool>, lo: f64, hi: f64) -> bool {
+    match rh {
+        Some(rh) if rh < lo => true,
+        Some(rh) if rh <= hi => was_on.unwrap_or(false),
+        _ => false,
+    }
+}
+
+///
+/// @brief uptime the way a person would say it: "3 d 4 h", "4 h 12 min", "12 min"
+///
+pub fn format_uptime(uptime: Duration) -> String {
+    let (days, hours, minutes) = (uptime.num_days(), uptime.num_hours() % 24, uptime.num_minutes() % 60);
+    match (days, hours) {
+        (0, 0) => format!("{} min", minutes),
+        (0, _) => format!("{} h {} min", hours, minutes),
+        _ => format!("{} d {} h", days, hours),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_format_uptime() {
+        assert_eq!(format_uptime(Duration::seconds(59)), "0 min");
+        assert_eq!(format_uptime(Duration::minutes(252)), "4 h 12 min");
+        assert_eq!(format_uptime(Duration::hours(76) + Duration::minutes(5)), "3 d 4 h");
+    }
+
+    #[test]
+    pub fn test_resume_humidifier() {
+        assert!(resume_humidifier(Some(65.0), None, 70.0, 80.0));
+        assert!(resume_humidifier(Some(75.0), Some(true), 70.0, 80.0));
+        assert!(!resume_humidifier(Some(75.0), Some(false), 70.0, 80.0));
+        assert!(!resume_humidifier(Some(85.0), Some(true), 70.0, 80.0));
+        assert!(!resume_humidifier(None, Some(true), 70.0, 80.0));
+    }
+}
@@ -0,0 +1,153 @@
+use crate::config::LightConfig;
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use tokio_postgres::Client;
+
+//
+// @brief  how often the schedule is looked at, and the lux checked while a check is due
+//
+pub const CHECK_EVERY_SECS: u64 = 60;
+
+///
+/// @return whether the schedule has the light on at `time` (local), from `on` until `off`
+///         and through midnight when `off` comes first
+///
+pub fn scheduled(config: &LightConfig, time: NaiveTime) -> bool {
+    if config.on <= config.off {
+        config.on <= time && time < config.off
+    } else {
+        time >= config.on || time < config.off
+    }
+}
+
+///
+/// @brief cross-checks the grow light against a plant sensor's illuminance: `check_mins`
+///        after it's switched on, the sensor has to read `min_rise_lux` more than it did in
+///        the dark, otherwise the bulb or its driver has failed
+///
+/// @note the dark reading has to be one taken since this daemon switched the light off;
+///       started with the light due on there's none, and that period goes unchecked
+/// @note in shadow mode the relay lights nothing, only the schedule runs
+///
+pub struct Light {
+    config: LightConfig,
+    shadow: bool,
+    // when the light was last switched off, dark readings are from after it
+    off_since: DateTime<Utc>,
+    // when it was switched on and the dark reading to beat, until it's been checked
+    pending: Option<(DateTime<Utc>, Option<f64>)>,
+}
+
+impl Light {
+    pub fn new(config: &LightConfig, shadow: bool, now: DateTime<Utc>) -> Light {
+        Light { config: config.clone(), shadow, off_since: now, pending: None }
+    }
+
+    pub fn sensor(&self) -> &str {
+        &self.config.sensor
+    }
+
+    ///
+    /// @return since when a reading counts as dark, or for the check once it's switched on
+    ///
+    pub fn readings_since(&self) -> DateTime<Utc> {
+        self.pending.map_or(self.off_since, |(on_since, _)| on_since)
+    }
+
+    ///
+    /// @return whether a check is still to come for this time it's on
+    ///
+    pub fn pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    ///
+    /// @param dark  the sensor's latest reading since the light went off, if it has one
+    ///
+    pub fn switched_on(&mut self, at: DateTime<Utc>, dark: Option<f64>) {
+        self.pending = (!self.shadow).then_some((at, dark));
+    }
+
+    pub fn switched_off(&mut self, at: DateTime<Utc>) {
+        self.off_since = at;
+        self.pending = None;
+    }
+
+    ///
+    /// @param lux  the sensor's latest illuminance since the light was switched on
+    ///
+    /// @return why the light looks to have failed, at most once each time it's switched on
+    ///
+    pub fn check(&mut self, lux: Option<f64>, at: DateTime<Utc>) -> Option<String> {
+        let (on_since, dark) = self.pending?;
+        let Some(dark) = dark else {
+            self.pending = None;
+            return None;
+        };
+        let lux = lux?;
+        if lux >= dark + self.config.min_rise_lux {
+            self.pending = None;
+            return None;
+        }
+        if at - on_since < Duration::minutes(self.config.check_mins as i64) {
+            return None;
+        }
+        self.pending = None;
+        Some(format!("Grow light on for {} min but {} reads {:.0} lx against {:.0} lx in the dark, its bulb or driver may have failed",
+            (at - on_since).num_minutes(), self.config.sensor, lux, dark))
+    }
+}
+
+///
+/// @brief the newest illuminance `sensor` reported after `since`, from plant_data
+///
+pub async fn latest_lux(client: &Client, sensor: &str, since: DateTime<Utc>) -> Result<Option<f64>, tokio_postgres::Error> {
+    let row = client.query_opt("SELECT value FROM plant_data
+        WHERE sensor = $1 AND metric = 'illuminance' AND timestamp > $2 AND mine(device_id) ORDER BY timestamp DESC LIMIT 1",
+        &[&sensor, &since]).await?;
+    Ok(row.map(|row| row.get(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PinSpec;
+
+    #[test]
+    pub fn test_check() {
+        let config = LightConfig {
+            relay: PinSpec::native(26),
+            on: NaiveTime::from_hms_opt(20, 0, 0).unwrap(),
+            off: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            sensor: "juniper".to_string(),
+            min_rise_lux: 500.0,
+            check_mins: 30,
+        };
+        let hm = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        assert!(scheduled(&config, hm(23, 0)) && scheduled(&config, hm(5, 59)));
+        assert!(!scheduled(&config, hm(6, 0)) && !scheduled(&config, hm(12, 0)));
+
+        let t0 = Utc::now();
+        let at = |mins| t0 + Duration::minutes(mins);
+        let mut light = Light::new(&config, false, t0);
+
+        // lit up, the check passes as soon as a reading shows it
+        light.switched_on(at(0), Some(40.0));
+        assert_eq!(light.check(None, at(5)), None);
+        assert_eq!(light.check(Some(2400.0), at(10)), None);
+        assert!(!light.pending());
+
+        // a dead bulb, given check_mins to warm up before it's flagged, once
+        light.switched_off(at(60));
+        light.switched_on(at(120), Some(40.0));
+        assert_eq!(light.check(Some(60.0), at(126)), None);
+        assert_eq!(light.check(Some(60.0), at(150)).unwrap(),
+            "Grow light on for 30 min but juniper reads 60 lx against 40 lx in the dark, its bulb or driver may have failed");
+        assert_eq!(light.check(Some(60.0), at(160)), None);
+
+        // no dark reading since it went off, nothing to compare with
+        light.switched_off(at(200));
+        light.switched_on(at(201), None);
+        assert_eq!(light.check(Some(60.0), at(240)), None);
+        assert!(!light.pending());
+    }
+}
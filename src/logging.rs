@@ -0,0 +1,110 @@
+use crate::blackbox;
+use crate::config::{LogOutput, LoggingConfig};
+use crate::events::{Bus, Event};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use systemd::journal;
+use tokio::sync::broadcast::error::RecvError;
+
+//
+// @brief  set from [logging] once the config is loaded, everything before that goes to
+//         the journal
+//
+static STDOUT: AtomicBool = AtomicBool::new(false);
+
+pub fn init(config: &LoggingConfig) {
+    STDOUT.store(config.output == LogOutput::Stdout, Ordering::Relaxed);
+}
+
+///
+/// @brief logs `message` to the journal with extra structured fields, e.g.
+///        `send(6, "...", &[("SERVICE", &"climate"), ("RH", &72.3)])`, so that
+///        `journalctl -o json` can be parsed without picking the message apart
+///
+/// @note field names must be upper case letters, digits and underscores; everything sent
+///       also goes to the blackbox when one is configured
+///
+pub fn send(priority: u8, message: &str, fields: &[(&str, &dyn Display)]) {
+    if STDOUT.load(Ordering::Relaxed) {
+        println!("{}", line(priority, message, fields));
+    } else {
+        let entries = entries(priority, message, fields);
+        let entries: Vec<&str> = entries.iter().map(String::as_str).collect();
+        journal::send(&entries);
+    }
+    blackbox::record(priority, message, fields);
+}
+
+///
+/// @brief logs a plain `message` at `priority`, to the journal or stdout as configured
+///
+pub fn print(priority: u8, message: &str) {
+    if STDOUT.load(Ordering::Relaxed) {
+        println!("{}", line(priority, message, &[]));
+    } else {
+        journal::print(priority as u32, message);
+    }
+}
+
+fn entries(priority: u8, message: &str, fields: &[(&str, &dyn Display)]) -> Vec<String> {
+    let mut entries = vec![format!("MESSAGE={}", message), format!("PRIORITY={}", priority)];
+    entries.extend(fields.iter().map(|(name, value)| format!("{}={}", name, value)));
+    entries
+}
+
+///
+/// @brief logs actuator switches from the event bus with SERVICE and EVENT fields, e.g.
+///        EVENT=humidifier_on
+///
+/// @note readings, door changes and alerts are logged where they happen, with more context
+///
+pub fn spawn_journal(bus: &Bus) {
+    let mut rx = bus.subscribe();
+
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(Event::Actuator { name, on, .. }) => {
+                    let state = if on { "on" } else { "off" };
+                    send(6, &format!("{} switched {}", name, state),
+                        &[("SERVICE", &name), ("EVENT", &format!("{}_{}", name, state))]);
+                },
+                Ok(Event::Reading { .. }) | Ok(Event::Door { .. }) | Ok(Event::Alert { .. }) | Ok(Event::Summary { .. }) => {},
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+///
+/// @brief a record for stdout, where a container runtime collects it: the syslog level,
+///        the message and the fields as KEY=value, quoted when they have spaces in them
+///
+fn line(priority: u8, message: &str, fields: &[(&str, &dyn Display)]) -> String {
+    const LEVELS: [&str; 8] = ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+    let mut line = format!("{:<7} {}", LEVELS[priority.min(7) as usize], message);
+    for (name, value) in fields {
+        let value = value.to_string();
+        if value.contains(char::is_whitespace) || value.is_empty() {
+            line.push_str(&format!(" {}={:?}", name, value));
+        } else {
+            line.push_str(&format!(" {}={}", name, value));
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_entries() {
+        assert_eq!(entries(6, "Inserted", &[("SERVICE", &"climate"), ("RH", &72.3), ("TEMP", &21.4)]), vec![
+            "MESSAGE=Inserted", "PRIORITY=6", "SERVICE=climate", "RH=72.3", "TEMP=21.4",
+        ]);
+        assert_eq!(line(4, "Pump skipped", &[("SERVICE", &"pump"), ("REASON", &"pot still wet")]),
+            "warning Pump skipped SERVICE=pump REASON=\"pot still wet\"");
+    }
+}
@@ -1,33 +1,136 @@
 mod sht20;
+mod reading;
+mod config;
+mod actuator;
+mod pins;
+mod expander;
+mod ads1115;
+mod hal;
+mod db;
+mod cli;
+mod install;
+mod secrets;
+mod seed;
+mod ipc;
+mod commands;
+mod metrics;
+mod irrigation;
+mod http;
+mod retention;
+mod export;
+mod events;
+mod recent;
+mod trend;
+mod sampling;
+mod door;
+mod audit;
+mod auth;
+mod ratelimit;
+mod logging;
+mod error;
+mod alerts;
+mod lifecycle;
+mod fallback;
+mod feedback;
+mod warmup;
+mod safety;
+mod ina219;
+mod power;
+mod selftest;
+mod usage;
+mod maintenance;
+mod version;
+mod diagnostics;
+mod experiment;
+mod tuner;
+mod analyze;
+mod stability;
+mod wetting;
+mod profile;
+mod mixing;
+mod condensation;
+mod dormancy;
+mod backup;
+mod calendar;
+mod sx127x;
+mod garden;
+mod watchdog;
+mod storage;
+mod compact;
+mod grafana;
+mod annotations;
+mod wind;
+mod hx711;
+mod wetness;
+mod misting;
+mod heater;
+mod light;
+mod soil;
+mod lease;
+mod fleet;
+mod blackbox;
+mod output;
+#[cfg(feature = "dbus")]
+mod dbus;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "mdns")]
+mod mdns;
+#[cfg(feature = "cloud")]
+mod telemetry;
+#[cfg(feature = "cloud")]
+mod webhooks;
+#[cfg(feature = "cloud")]
+mod notify;
+#[cfg(feature = "ble")]
+mod miflora;
+#[cfg(feature = "mqtt")]
+mod remote;
+#[cfg(feature = "homekit")]
+mod homekit;
+#[cfg(feature = "archive")]
+mod archive;
+#[cfg(feature = "update")]
+mod update;
+#[cfg(feature = "chaos")]
+mod chaos;
+#[cfg(feature = "tui")]
+mod tui;
 use sht20::SHT20;
-use rppal::gpio::{Gpio, OutputPin};
-use chrono::{DateTime, Duration, Utc};
-use std::error::Error;
+use reading::Reading;
+use ads1115::ADS1115;
+use config::Config;
+use actuator::Actuator;
+use chrono::{DateTime, Duration, Local, Utc};
+use error::BonsaiError;
 use std::time::{Duration as StdDuration};
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::future::Future;
-use std::pin::Pin;
-use tokio::time::{interval_at, sleep, Instant, Duration as TokioDuration};
-use tokio::sync::Mutex;
-use tokio_postgres::{Client, NoTls};
+use tokio::time::{interval_at, sleep, sleep_until, Instant, Interval, Duration as TokioDuration};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio_postgres::Client;
 use systemd::journal;
+use clap::Parser;
+use cli::{BlackboxCommand, Cli, Command, DbCommand, FleetCommand, RelayState, SystemdCommand};
 
 //
-// @brief  configuration parameters
+// @brief  timing parameters
 //
-// @note see pinout at link below:
-// https://www.etechnophiles.com/wp-content/uploads/2020/12/R-PI-pinout.jpg?ezimgfmt=ng:webp/ngcb40
-// 
-// @note the control board still has one additional relay for future expansion (recommend gpio 23)
+// @note relay pin mapping now lives in the config file, see config.rs
 //
-const  FAN_PIN:               u8           = 22; 
 const  FAN_PERIODIC_MINS:     i64          = 3;
-const  FAN_DURATION_SECS:     u64          = 30;
-const  HUMIDIFIER_PIN:        u8           = 24; 
-const  CLIMATE_PERIODIC_MINS: i64          = 5;
-const  PUMP_PIN:              u8           = 27;
+const  FAN_DURATION_SECS:     f64          = 30.0;
+const  CLIMATE_PERIODIC_MINS: i64          = 5;     // default, see [sampling] climate_secs
 const  PUMP_PERIODIC_HRS:     i64          = 24;
-const  PUMP_DURATION_SECS:    u64          = 60;
+const  PUMP_DURATION_SECS:    f64          = 60.0;
+const  DOOR_POLL_MILLIS:      u64          = 250;
+const  MAINTENANCE_CHECK_HRS: u64          = 24;
+const  TUNE_PERIODIC_HRS:     u64          = 24;
+const  DORMANCY_CHECK_MINS:   u64          = 60;
+const  STABILITY_CHECK_MINS:  u64          = 60;
+const  RH_LO_THRESH:          f64          = 70.0;  // percent, default, see [humidifier] rh_low
+const  RH_HI_THRESH:          f64          = 80.0;  // percent, default, see [humidifier] rh_high
 
 ///
 /// @brief The main routine, for mains
@@ -38,27 +141,399 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // get that journal up 
     journal::JournalLog::init().unwrap();
 
+    let cli = Cli::parse();
+    let json = cli.json;
+    if cli.version {
+        if json {
+            output::json(&version::BuildInfo::new(&Config::path()));
+        } else if cli.verbose {
+            version::BuildInfo::new(&Config::path()).print();
+        } else {
+            println!("bonsai-bot {}", env!("CARGO_PKG_VERSION"));
+        }
+        return Ok(());
+    }
+    if json && !cli.command.as_ref().is_some_and(Command::has_json) {
+        return Err("--json isn't supported by this subcommand".into());
+    }
+    let result = match cli.command {
+        None => run_daemon(false, false).await,
+        Some(Command::Run { shadow, skip_self_test }) => run_daemon(shadow, skip_self_test).await,
+        Some(Command::Install(args)) => install::run(args, json).await,
+        Some(Command::Status) => commands::run(ipc::Request::Status, json).await,
+        Some(Command::Pump { seconds }) => commands::run(ipc::Request::RunPump { seconds }, json).await,
+        Some(Command::Metrics) => commands::run(ipc::Request::Metrics, json).await,
+        Some(Command::Fan { seconds }) => commands::run(ipc::Request::RunFan { seconds }, json).await,
+        Some(Command::Maintenance) => commands::run(ipc::Request::Maintenance, json).await,
+        Some(Command::Serviced { name }) => commands::run(ipc::Request::Serviced { name }, json).await,
+        Some(Command::Dormancy { mode }) => commands::run(ipc::Request::Dormancy { mode }, json).await,
+        Some(Command::HeaterClear) => commands::run(ipc::Request::HeaterClear, json).await,
+        Some(Command::Ack { kind }) => commands::run(ipc::Request::Ack { kind }, json).await,
+        Some(Command::Profile { name: None }) => profile::print(json),
+        Some(Command::Profile { name: Some(name) }) => commands::run(ipc::Request::Profile { name }, json).await,
+        Some(Command::Relay { node, relay, state }) =>
+            commands::run(ipc::Request::Relay { node, relay, on: state == RelayState::On }, json).await,
+        Some(Command::Token { name, scope }) => auth::print_new(name, scope, json),
+        Some(Command::Tune) => tuner::run(json).await,
+        Some(Command::Analyze(args)) => analyze::run(args, json).await,
+        Some(Command::Stability { weeks }) => stability::print(weeks, json).await,
+        #[cfg(feature = "tui")]
+        Some(Command::Tui) => tui::run().await,
+        #[cfg(not(feature = "tui"))]
+        Some(Command::Tui) => Err("this build lacks the tui feature, rebuild with --features tui".into()),
+        Some(Command::GrafanaDashboard) => grafana::print(),
+        Some(Command::Calibrate { channel }) => soil::run(channel, json).await,
+        Some(Command::Journal { note, at }) => annotations::add_journal_entry(note, at, json).await,
+        Some(Command::CheckConfig { config }) => diagnostics::run(config, json),
+        Some(Command::Db(cmd)) => run_db_command(cmd, json).await,
+        Some(Command::Fleet(FleetCommand::Status)) => fleet::print_status(json).await,
+        Some(Command::Update) => update_now(json),
+        Some(Command::Healthcheck) => commands::healthcheck(json).await,
+        Some(Command::Inject { fault }) => commands::run(ipc::Request::Inject { fault }, json).await,
+        Some(Command::Blackbox(BlackboxCommand::Dump { hours })) => match Config::load() {
+            Ok(config) => match config.blackbox {
+                Some(config) => blackbox::dump(&config, hours, json),
+                None => Err("The config has no [blackbox] section".into()),
+            },
+            Err(e) => Err(e.into()),
+        },
+        Some(Command::Systemd(SystemdCommand::Gen(args))) => install::print_unit(args, json),
+        Some(Command::Systemd(SystemdCommand::Credential { name })) => secrets::store(&name, json),
+    };
+    // scripts get the error on stdout like everything else, and still a failing exit status
+    if let (true, Err(e)) = (json, &result) {
+        output::json(&serde_json::json!({ "error": e.to_string() }));
+        std::process::exit(1);
+    }
+    result
+}
+
+///
+/// @brief Runs a `db` subcommand against BONSAIBOT_DATABASE_URL
+///
+async fn run_db_command(cmd: DbCommand, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut client, connection) = db::establish_connection().await?;
+    let connection = tokio::spawn(connection);
+
+    match cmd {
+        DbCommand::Init => {
+            db::migrate(&mut client).await?;
+            output::message(json, "Schema is up to date");
+        },
+        DbCommand::Seed { days, force } => {
+            db::migrate(&mut client).await?;
+            fleet::ensure(&client).await?;
+            let rows = seed::run(&mut client, days, force).await?;
+            output::message(json, &format!("Seeded {} rows covering {} days", rows, days));
+        },
+        DbCommand::Export(args) => {
+            export::run(&client, &args, json).await?;
+        },
+        DbCommand::Backup => {
+            let backup = Config::load()?.backup.ok_or("The config has no [backup] section")?;
+            let url = secrets::database_url()?;
+            let path = backup::run_once(&backup, &url).await?;
+            output::message(json, &format!("Backed up to {}", path.display()));
+        },
+        #[cfg(feature = "archive")]
+        DbCommand::Archive => {
+            let config = Config::load()?.archive.ok_or("The config has no [archive] section")?;
+            let rows = archive::run_once(&config, None).await?;
+            output::message(json, &format!("Archived {} rows to {}", rows, config.dir));
+        },
+        #[cfg(feature = "archive")]
+        DbCommand::RestoreArchive { files } => {
+            let mut restored = Vec::new();
+            for file in files {
+                let (table, rows) = archive::restore(&client, &file).await?;
+                if !json {
+                    println!("{} rows -> {}", rows, table);
+                }
+                restored.push(serde_json::json!({ "file": file, "table": table, "rows": rows }));
+            }
+            if json {
+                output::json(&restored);
+            }
+        },
+        #[cfg(not(feature = "archive"))]
+        DbCommand::Archive | DbCommand::RestoreArchive { .. } => {
+            return Err("this build lacks the archive feature, rebuild with --features archive".into());
+        },
+    }
+
+    drop(client);
+    let _ = connection.await;
+    Ok(())
+}
+
+///
+/// @brief The climate control daemon
+///
+/// @note with `shadow` set every output is replaced by one which only logs what it would do,
+///       sensors and the database are used as normal
+///
+async fn run_daemon(shadow: bool, skip_self_test: bool) -> Result<(), Box<dyn std::error::Error>> {
+
+    // load pin mapping and friends
+    let mut config = Config::load()?;
+    logging::init(&config.logging);
+
+    // the audit log gets the config as written, credential references rather than secrets
+    let config_text = toml::to_string(&config)?;
+    if let Err(e) = secrets::resolve(&mut config) {
+        logging::print(3, &format!("Cannot load credentials: {}", e));
+        return Err(e.into());
+    }
+
+    // before anything that can go wrong, so the box has it
+    if let Some(c) = &config.blackbox {
+        if let Err(e) = blackbox::open(c) {
+            logging::print(4, &format!("Could not open the blackbox in {}: {}", c.dir, e));
+        }
+    }
+
+    // catch wiring/config mistakes before touching any hardware
+    if let Err(e) = pins::validate(&config) {
+        logging::print(3, &format!("Invalid pin configuration: {}", e));
+        return Err(e.into());
+    }
+
+    // make sure the pins make sense on the board we actually booted on
+    if config.platform.backend == config::Backend::Rppal {
+        match rppal::system::DeviceInfo::new() {
+            Ok(info) => {
+                logging::print(6, &format!("Detected {} ({})", info.model(), info.soc()));
+                match pins::check_model(&config, info.model()) {
+                    Ok(warnings) => for w in warnings {
+                        logging::print(4, &format!("Pin warning: {}", w));
+                    },
+                    Err(e) => {
+                        logging::print(3, &format!("Invalid pin configuration: {}", e));
+                        return Err(e.into());
+                    }
+                }
+            },
+            Err(e) => {
+                logging::print(4, &format!("Could not detect Raspberry Pi model: {}", e));
+            },
+        }
+    }
+
     // create our GPIO'y-bois
-    let gpio = Gpio::new()?;
+    let platform = match hal::open(&config.platform) {
+        Ok(p) => p,
+        Err(e) => {
+            logging::print(3, &format!("Cannot access GPIO (is the user in the gpio group?): {}", e));
+            return Err(e.into());
+        }
+    };
+
+    // bench runs only, every device and line opened from here on can be made to fail
+    #[cfg(feature = "chaos")]
+    let platform = match &config.chaos {
+        Some(c) => {
+            logging::print(4, "Fault injection is on");
+            chaos::enable(c.clone());
+            chaos::wrap(platform)
+        },
+        None => platform,
+    };
+    #[cfg(not(feature = "chaos"))]
+    if config.chaos.is_some() {
+        logging::print(4, "Fault injection configured but this build lacks the chaos feature");
+    }
+
+    // arm the hardware watchdog early, a hang from here on reboots the Pi; shadow mode
+    // leaves it to the real daemon
+    let watchdog = match config.watchdog.as_ref().filter(|_| !shadow).map(watchdog::Watchdog::open) {
+        Some(Ok(w)) => Some(w),
+        Some(Err(e)) => {
+            logging::print(3, &format!("Cannot open the hardware watchdog: {}", e));
+            return Err(e.into());
+        },
+        None => None,
+    };
+
+    // bring up any I2C relay expanders, in shadow mode there's nothing to switch on them
+    let no_expanders = Default::default();
+    let expanders = match expander::open_all(platform.as_ref(), if shadow { &no_expanders } else { &config.expanders }) {
+        Ok(e) => e,
+        Err(e) => {
+            logging::print(3, &format!("Cannot open GPIO expander: {}", e));
+            return Err(e.into());
+        }
+    };
 
     // initialize gpios and peripherals
-    let sht20             = Arc::new(Mutex::new(SHT20::new()?));
-    let mut humd_gpio     = gpio.get(HUMIDIFIER_PIN)?.into_output(); 
-    let mut pump_gpio     = gpio.get(PUMP_PIN)?.into_output(); 
-    let mut fan_gpio      = gpio.get(FAN_PIN)?.into_output();
+    let sht20             = Arc::new(Mutex::new(SHT20::new(platform.as_ref())?));
+    let ambient           = match &config.ambient {
+        Some(c) => Some(Arc::new(Mutex::new(SHT20::with_bus(platform.as_ref(), c.bus)?))),
+        None => None,
+    };
+    let mut adc           = match &config.adc {
+        Some(c) => Some(ADS1115::new(platform.as_ref(), c.bus, c.address)?),
+        None => None,
+    };
+    let (mut humd_gpio, mut pump_gpio, mut fan_gpio, mut zones) = if shadow {
+        logging::print(5, "Shadow mode: running the control logic without driving any outputs");
+        (actuator::Shadow::boxed("humidifier"), actuator::Shadow::boxed("pump"), actuator::Shadow::boxed("fan"), irrigation::shadow_zones(&config))
+    } else {
+        (pins::claim(platform.as_ref(), &expanders, "humidifier", &config.pins.humidifier).map_err(log_pin_error)?,
+         pins::claim_pump(platform.as_ref(), &expanders, &config).map_err(log_pin_error)?,
+         pins::claim(platform.as_ref(), &expanders, "fan", &config.pins.fan).map_err(log_pin_error)?,
+         irrigation::open_zones(platform.as_ref(), &expanders, &config).map_err(log_pin_error)?)
+    };
+    let mut current       = power::open_all(platform.as_ref(), &config.current)?;
+    let mut door          = match &config.door {
+        Some(c) => Some(door::Door::new(platform.as_ref(), c)?),
+        None => None,
+    };
+    let mut mister_gpio   = match &config.misting {
+        Some(_) if shadow => Some(actuator::Shadow::boxed("mister")),
+        Some(c) => Some(pins::claim(platform.as_ref(), &expanders, "mister", &c.valve).map_err(log_pin_error)?),
+        None => None,
+    };
+    let mut heater_gpio   = match &config.heater {
+        Some(_) if shadow => Some(actuator::Shadow::boxed("heater")),
+        Some(c) => Some(pins::claim(platform.as_ref(), &expanders, "heater", &c.relay).map_err(log_pin_error)?),
+        None => None,
+    };
+    let mut light_gpio    = match &config.light {
+        Some(_) if shadow => Some(actuator::Shadow::boxed("light")),
+        Some(c) => Some(pins::claim(platform.as_ref(), &expanders, "light", &c.relay).map_err(log_pin_error)?),
+        None => None,
+    };
+    let mut anemometer    = match &config.wind {
+        Some(c) => Some(wind::Anemometer::open(platform.as_ref(), c)?),
+        None => None,
+    };
+    let tank_cell         = match &config.tank {
+        Some(c) => Some(Arc::new(std::sync::Mutex::new(hx711::LoadCell::open(platform.as_ref(), &c.cell, "tank")?))),
+        None => None,
+    };
+    let pot_cell          = match &config.pot {
+        Some(c) => Some(Arc::new(std::sync::Mutex::new(hx711::LoadCell::open(platform.as_ref(), &c.cell, "pot")?))),
+        None => None,
+    };
+    let mut stagger       = actuator::Stagger::new(TokioDuration::from_millis(config.actuation.stagger_ms));
+    let mut cooldown      = ratelimit::Cooldown::new(StdDuration::from_secs(config.actuation.manual_cooldown_secs));
 
     // connect to database
-    let (mut postgres_client, connection) = establish_connection().await?;
+    let (mut postgres_client, connection) = db::establish_connection().await?;
     tokio::spawn(async move {
         if let Err(e) = connection.await {
-            journal::print(3, &format!("Connection error: {}", e));
+            logging::print(3, &format!("Connection error: {}", e));
             panic!("Panic! Could not connect to DB");
         }
     });
 
+    // bring the schema up to date before anything reads or writes it
+    if let Err(e) = db::migrate(&mut postgres_client).await {
+        logging::print(3, &format!("Database migration failed: {}", e));
+        return Err(e.into());
+    }
+
+    // two daemons scheduling off the same rows water twice or not at all
+    let instance_id = db::instance_id()?;
+    let holder = lease::holder();
+    let instance_lease = lease::instance_lease(&instance_id);
+    if !lease::acquire(&postgres_client, &instance_lease, &holder, lease::INSTANCE_LEASE_SECS).await? {
+        let other = lease::held_by(&postgres_client, &instance_lease).await?.unwrap_or_default();
+        // after a crash systemd restarts us well within the lease, which is still our own
+        if lease::orphaned(&other, &holder, lease::pid_alive)
+            && lease::take_over(&postgres_client, &instance_lease, &other, &holder, lease::INSTANCE_LEASE_SECS).await? {
+            logging::print(5, &format!("Took over the {} lease from {}, which is no longer running", instance_lease, other));
+        } else {
+            logging::print(3, &format!("Instance {} is already running ({}), set BONSAIBOT_INSTANCE_ID to tell them apart", instance_id, other));
+            return Err(BonsaiError::Config(format!("instance {} is already running", instance_id)).into());
+        }
+    }
+    let zone_names: Vec<String> = config.zones.iter().map(|zone| zone.name.clone()).collect();
+    if let Err(e) = fleet::register(&postgres_client, &instance_id, &zone_names).await {
+        logging::print(3, &format!("Cannot register device {}: {}", instance_id, e));
+        return Err(e.into());
+    }
+
+    // readings and events are stored by a task of their own, a slow database can't hold up
+    // the control loop
+    let writer = match storage::spawn(storage::QUEUE_CAPACITY, config.compact.clone()).await {
+        Ok(writer) => writer,
+        Err(e) => {
+            logging::print(3, &format!("Cannot start the storage writer: {}", e));
+            return Err(e.into());
+        },
+    };
+
+    // which build and config this is, for GET /version and the journal
+    let build = version::BuildInfo::new(&Config::path());
+    logging::print(6, &format!("bonsai-bot {} ({}) starting, features: [{}]", build.version, build.git_commit, build.features.join(", ")));
+
+    // note config edits in the audit log so they line up with whatever happened next
+    match audit::record_config(&postgres_client, &Config::path(), &config_text).await {
+        Ok(true) => { logging::print(6, "Config changed since the last start, recorded in audit log"); },
+        Ok(false) => {},
+        Err(e) => { logging::print(3, &format!("Could not record config in audit log: {}", e)); },
+    }
+
+    // restarts piling up mean something keeps killing us, even if every run looks healthy
+    let start = match lifecycle::record_start(&postgres_client).await {
+        Ok(start) => start,
+        Err(e) => {
+            logging::print(3, &format!("Could not record start in system_events: {}", e));
+            lifecycle::Start { at: Utc::now(), ..Default::default() }
+        },
+    };
+    if let Some(since) = start.unclean_since {
+        logging::send(4, &format!("Previous run, started {}, ended without a clean stop", since.format("%Y-%m-%d %H:%M:%S %Z")),
+            &[("SERVICE", &"daemon"), ("EVENT", &"unclean_stop")]);
+    }
+
+    // a heater latched off after a runaway stays off, the self-test included
+    let mut heater = match &config.heater {
+        Some(c) => Some(heater::Heater::new(c, match heater::load_latch(&postgres_client).await {
+            Ok(latched) => latched,
+            Err(e) => {
+                logging::print(3, &format!("Could not read the heater latch: {}", e));
+                Some(format!("latch unreadable at startup: {}", e))
+            },
+        }, shadow)),
+        None => None,
+    };
+
+    // catch wiring broken during maintenance before the control loop relies on it
+    match &config.self_test {
+        Some(_) if skip_self_test => logging::print(5, "Self-test skipped"),
+        Some(self_test) => {
+            let mut relays: Vec<(&'static str, &mut dyn Actuator)> =
+                vec![("humidifier", humd_gpio.as_mut()), ("fan", fan_gpio.as_mut()), ("pump", pump_gpio.as_mut())];
+            relays.extend(mister_gpio.as_mut().map(|m| ("mister", m.as_mut() as &mut dyn Actuator)));
+            if heater.as_ref().is_none_or(|h| h.latched().is_none()) {
+                relays.extend(heater_gpio.as_mut().map(|h| ("heater", h.as_mut() as &mut dyn Actuator)));
+            }
+            relays.extend(light_gpio.as_mut().map(|l| ("light", l.as_mut() as &mut dyn Actuator)));
+            // shadow outputs draw nothing, their current would only fail
+            let sensors = if shadow { &mut [][..] } else { &mut current[..] };
+            let checks = selftest::run(self_test, relays, &mut zones, sensors, sht20.clone(), &postgres_client).await;
+            if let Err(e) = selftest::record(&postgres_client, &checks).await {
+                logging::print(3, &format!("Could not record the self-test in system_events: {}", e));
+            }
+        },
+        None => {},
+    }
+
+    // start the service clock on newly configured consumables
+    if let Err(e) = maintenance::ensure_tracked(&postgres_client, &config.consumables).await {
+        logging::print(3, &format!("Cannot track consumables: {}", e));
+        return Err(e.into());
+    }
+
     // get updated timing for the next pump sequence
     let pump_schedule_dt: DateTime<Utc> = match get_next_pump_schedule(&mut postgres_client).await {
         Ok(t) => t,
+        Err(BonsaiError::Schedule(e)) => {
+            logging::print(4, &format!("Pump schedule looks wrong ({}), starting a fresh {} h cycle", e, PUMP_PERIODIC_HRS));
+            Utc::now() + Duration::hours(PUMP_PERIODIC_HRS)
+        },
         Err(e) => panic!("No pump scheduled: {}", e),
     };
 
@@ -73,188 +548,1484 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     // setup service tick intervals
+    let tick_budget = StdDuration::from_secs(config.sampling.tick_budget_secs.max(1));
     let now = Instant::now();
-    let mut climate_interval = interval_at(now, TokioDuration::from_secs(60 * CLIMATE_PERIODIC_MINS as u64));
+    let mut climate_interval = interval_at(now, TokioDuration::from_secs(config.sampling.climate_secs.max(1)));
+    let mut analog_schedule = sampling::Schedule::new(config.adc.iter()
+        .flat_map(|adc| &adc.channels)
+        .map(|c| TokioDuration::from_secs(c.interval_secs.unwrap_or(config.sampling.climate_secs)))
+        .collect());
     let mut fan_interval = interval_at(now, TokioDuration::from_secs(60 * FAN_PERIODIC_MINS as u64));
     let mut pump_interval = interval_at(now + duration_until_pump,
                         TokioDuration::from_secs(60 * 60 * PUMP_PERIODIC_HRS as u64));
+    let mut retention_interval = interval_at(now, TokioDuration::from_secs(60 * config.retention.interval_mins));
+    let mut door_interval = interval_at(now, TokioDuration::from_millis(DOOR_POLL_MILLIS));
+    let mut wind_interval = interval_at(now, TokioDuration::from_secs(config.wind.as_ref().map_or(60, |w| w.log_secs.max(1))));
+    let mut wind_gauge = wind::Gauge::default();
+    let mut tank_interval = interval_at(now, TokioDuration::from_secs(config.tank.as_ref().map_or(300, |t| t.every_secs.max(1))));
+    let mut tank_band = alerts::Band::default();
+    let mut pot_interval = interval_at(now, TokioDuration::from_secs(config.pot.as_ref().map_or(300, |p| p.every_secs.max(1))));
+    let mut pot_weighed: Option<(DateTime<Utc>, f64)> = None;
+    let mut leaf = config.leaf_wetness.as_ref().map(wetness::LeafWetness::new);
+    let mut mister = config.misting.as_ref().map(misting::Mister::new);
+    let mut humd_cycle = actuator::MinCycle::new(TokioDuration::from_secs(config.humidifier.min_on_secs),
+        TokioDuration::from_secs(config.humidifier.min_off_secs));
+    let mut mist_interval = interval_at(now, TokioDuration::from_secs(60 * config.misting.as_ref().map_or(20, |m| m.every_mins.max(1))));
+    let mut maintenance_interval = interval_at(now, TokioDuration::from_secs(60 * 60 * MAINTENANCE_CHECK_HRS));
+    let mut tune_interval = interval_at(now, TokioDuration::from_secs(60 * 60 * TUNE_PERIODIC_HRS));
+    let mut dormancy_interval = interval_at(now, TokioDuration::from_secs(60 * DORMANCY_CHECK_MINS));
+    let mut stability_interval = interval_at(now, TokioDuration::from_secs(60 * STABILITY_CHECK_MINS));
+    let mut light_interval = interval_at(now, TokioDuration::from_secs(light::CHECK_EVERY_SECS));
+    let mut light = config.light.as_ref().map(|c| light::Light::new(c, shadow, Utc::now()));
 
     // Convert the pump schedule to Mountain Time (UTC-7) and format for logging
     let mountain_time = pump_schedule_dt.with_timezone(&chrono::FixedOffset::west_opt(7 * 3600).unwrap());
-    journal::print(6, &format!("Next pump sequence scheduled at Localtime: {}", mountain_time.format("%Y-%m-%d %H:%M:%S %Z")));
+    logging::print(6, &format!("Next pump sequence scheduled at Localtime: {}", mountain_time.format("%Y-%m-%d %H:%M:%S %Z")));
+
+    // listen for CLI requests so they don't have to fight us for the pins
+    let (control_tx, mut control_rx) = mpsc::channel(8);
+    if let Err(e) = ipc::spawn_server(&config.ipc.socket, control_tx.clone()).await {
+        logging::print(3, &format!("Cannot open control socket {}: {}", config.ipc.socket, e));
+        return Err(e.into());
+    }
+    let mut status = ipc::StatusReport {
+        next_pump: Some(pump_schedule_dt.max(now_utc)),
+        started_at: Some(start.at),
+        restarts_last_day: start.restarts_last_day,
+        shadow,
+        profile: config.profile.clone(),
+        ..Default::default()
+    };
+
+    // soil probes calibrated with `bonsai-bot calibrate`
+    let calibrations = match soil::load(&postgres_client).await {
+        Ok(calibrations) => calibrations,
+        Err(e) => {
+            logging::print(3, &format!("Could not read probe calibrations: {}", e));
+            HashMap::new()
+        },
+    };
+
+    // winter rest, as the last run left it
+    let mut dormancy_state = match dormancy::load(&postgres_client).await {
+        Ok(state) => state,
+        Err(e) => {
+            logging::print(3, &format!("Could not read dormancy state: {}", e));
+            dormancy::State::default()
+        },
+    };
+    status.dormant = dormancy_state.dormant;
+
+    // a runaway latch holds across restarts, not being able to tell counts as latched
+    status.heater_latched = heater.as_ref().and_then(|h| h.latched()).map(String::from);
+    let mut last_watered = pump_schedule_dt - Duration::hours(PUMP_PERIODIC_HRS);
+    let auto_dormancy = config.dormancy.enter_chill_hours.is_some() || config.dormancy.exit_chill_hours.is_some();
+
+    // thresholds for today, which alternate day by day while an experiment runs
+    let mut params = experiment::params(&config, Local::now().naive_local());
+    if dormancy_state.dormant {
+        (params.rh_low, params.rh_high) = (config.dormancy.rh_low, config.dormancy.rh_high);
+    }
+    let mut tuned: Option<(f64, f64)> = None;
+    status.variant = params.variant.map(String::from);
+
+    // pick up where the last run left off instead of forgetting which way the humidifier was
+    // heading through the hysteresis band
+    let max_age = Duration::seconds(3 * config.sampling.climate_secs.max(1) as i64);
+    // compact storage may not have stored a reading since the last keyframe
+    let stored_age = config.compact.as_ref().map_or(max_age, |c| max_age.max(Duration::minutes(c.keyframe_mins as i64)));
+    match lifecycle::last_humidifier(&postgres_client, stored_age).await {
+        Ok((rh, was_on)) => if lifecycle::resume_humidifier(rh, was_on, params.rh_low, params.rh_high) {
+            logging::send(6, &format!("Resuming humidifier at RH {:3.2}", rh.unwrap_or_default()),
+                &[("SERVICE", &"climate"), ("EVENT", &"humidifier_resume")]);
+            stagger.switch_on(humd_gpio.as_mut()).await;
+            status.humidifier_on = true;
+        },
+        Err(e) => { logging::print(3, &format!("Could not read last humidifier state: {}", e)); },
+    }
+    let (status_tx, status_rx) = watch::channel(status.clone());
+
+    // readings and actuator changes, with the last few hours kept in memory
+    let bus = events::bus();
+    let recent = recent::spawn_recorder(&bus);
+    lease::spawn_keeper(instance_lease.clone(), holder.clone(), lease::INSTANCE_LEASE_SECS, bus.clone());
+    logging::spawn_journal(&bus);
+    // outputs that don't switch would only ever look dead or stuck
+    if !current.is_empty() && !shadow {
+        power::spawn_monitor(current, status_tx.subscribe(), bus.clone());
+    }
+
+    // remote graphs for those who don't want to run Grafana
+    #[cfg(feature = "cloud")]
+    if !config.telemetry.is_empty() {
+        telemetry::spawn(config.telemetry.clone(), &bus);
+    }
+    #[cfg(not(feature = "cloud"))]
+    if !config.telemetry.is_empty() {
+        logging::print(4, "Telemetry configured but this build lacks the cloud feature");
+    }
+
+    // glue for n8n, IFTTT and whatever else listens for a POST
+    #[cfg(feature = "cloud")]
+    if !config.webhooks.is_empty() {
+        webhooks::spawn(config.webhooks.clone(), &bus);
+    }
+    #[cfg(not(feature = "cloud"))]
+    if !config.webhooks.is_empty() {
+        logging::print(4, "Webhooks configured but this build lacks the cloud feature");
+    }
+
+    // alerts routed to phones and inboxes by severity
+    #[cfg(feature = "cloud")]
+    let acks = config.notify.clone().map(|notify| notify::spawn(notify, instance_id.clone(), &bus));
+    #[cfg(not(feature = "cloud"))]
+    if config.notify.is_some() {
+        logging::print(4, "Notifications configured but this build lacks the cloud feature");
+    }
+
+    // soil moisture from pots the wiring doesn't reach
+    #[cfg(feature = "ble")]
+    if !config.plant_sensors.is_empty() {
+        if let Err(e) = miflora::spawn(config.plant_sensors.clone()).await {
+            logging::print(3, &format!("Cannot start the Bluetooth scan for plant sensors: {}", e));
+        }
+    }
+    #[cfg(not(feature = "ble"))]
+    if !config.plant_sensors.is_empty() {
+        logging::print(4, "Plant sensors configured but this build lacks the ble feature");
+    }
+
+    // a second enclosure across the room, on ESPHome nodes
+    #[cfg(feature = "mqtt")]
+    let remote = match &config.mqtt {
+        Some(mqtt) => match remote::spawn(mqtt.clone(), bus.clone(), shadow).await {
+            Ok(remote) => Some(remote),
+            Err(e) => {
+                logging::print(3, &format!("Cannot start the MQTT client for remote nodes: {}", e));
+                None
+            },
+        },
+        None => None,
+    };
+    #[cfg(not(feature = "mqtt"))]
+    if config.mqtt.is_some() {
+        logging::print(4, "Remote nodes configured but this build lacks the mqtt feature");
+    }
+
+    // battery nodes out in the yard
+    if let Some(lora) = &config.lora {
+        let settings = sx127x::Settings {
+            frequency_hz: lora.frequency_mhz * 1e6,
+            spreading_factor: lora.spreading_factor,
+            bandwidth_khz: lora.bandwidth_khz,
+            sync_word: lora.sync_word,
+        };
+        let started = match sx127x::SX127x::new(platform.as_ref(), lora.spi_bus, lora.chip_select, lora.reset.as_ref().map(|r| r.pin), &settings) {
+            Ok(radio) => garden::spawn(radio, lora.clone()).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = started {
+            logging::print(3, &format!("Cannot start the LoRa receiver for garden nodes: {}", e));
+        }
+    }
+
+    // an SD card won't last forever, years of history should outlive it
+    if let Some(backup) = &config.backup {
+        match secrets::database_url() {
+            Ok(url) => backup::spawn(backup.clone(), url, bus.clone()),
+            Err(e) => alerts::raise(&bus, "backup_failed", &format!("Database backups are configured but can't run: {}", e)),
+        }
+    }
+    #[cfg(feature = "archive")]
+    if let Some(archive) = &config.archive {
+        archive::spawn(archive.clone(), holder.clone(), bus.clone());
+    }
+    #[cfg(not(feature = "archive"))]
+    if config.archive.is_some() {
+        logging::print(4, "Archiving configured but this build lacks the archive feature");
+    }
+
+    // reflashing an SD card for every fix gets old; switching profiles restarts us too
+    let (restart_tx, mut restart_rx) = mpsc::channel(1);
+    #[cfg(feature = "update")]
+    if let Some(update) = &config.update {
+        update::spawn(update.clone(), restart_tx.clone(), bus.clone());
+    }
+    #[cfg(not(feature = "update"))]
+    if config.update.is_some() {
+        logging::print(4, "Updates configured but this build lacks the update feature");
+    }
+    if start.restarts_last_hour > config.alerts.max_restarts_per_hour as i64 {
+        alerts::raise(&bus, "crash_loop", &format!("Restarted {} times in the last hour, check `journalctl -u bonsai-bot`",
+            start.restarts_last_hour));
+    }
+
+    // publish on the system bus for desktop tools
+    #[cfg(feature = "dbus")]
+    if config.ipc.dbus {
+        if let Err(e) = dbus::serve(status_rx.clone(), control_tx.clone()).await {
+            logging::print(3, &format!("Cannot register on D-Bus: {}", e));
+        }
+    }
+    #[cfg(not(feature = "dbus"))]
+    if config.ipc.dbus {
+        logging::print(4, "D-Bus enabled in config but this build lacks the dbus feature");
+    }
+
+    // the Home app, for family members who'd never open a terminal
+    #[cfg(feature = "homekit")]
+    if let Some(homekit) = &config.homekit {
+        if let Err(e) = homekit::serve(homekit, status_rx.clone(), control_tx.clone()).await {
+            logging::print(3, &format!("Cannot start the HomeKit bridge: {}", e));
+        }
+    }
+    #[cfg(not(feature = "homekit"))]
+    if config.homekit.is_some() {
+        logging::print(4, "HomeKit configured but this build lacks the homekit feature");
+    }
+    drop(status_rx);
+
+    // history queries for dashboards and phones
+    if let Some(http) = &config.http {
+        if let Err(e) = http::serve(http, config.actuation.max_manual_secs, recent.clone(), control_tx.clone(), build.clone()).await {
+            logging::print(3, &format!("Cannot start HTTP API on {}: {}", http.listen, e));
+        }
+    }
+
+    // let phones and Home Assistant find the API without knowing our address
+    #[cfg(feature = "mdns")]
+    let _mdns = match config.http.as_ref().filter(|http| http.mdns) {
+        Some(http) => match mdns::advertise(http) {
+            Ok(daemon) => {
+                logging::print(6, &format!("Advertising the HTTP API as {}", mdns::SERVICE_TYPE));
+                Some(daemon)
+            },
+            Err(e) => {
+                logging::print(3, &format!("Cannot advertise over mDNS: {}", e));
+                None
+            },
+        },
+        None => None,
+    };
+    #[cfg(not(feature = "mdns"))]
+    if config.http.as_ref().is_some_and(|http| http.mdns) {
+        logging::print(4, "mDNS enabled in config but this build lacks the mdns feature");
+    }
+
+    // `kill -USR1` asks for a reading right now, handy while fiddling with the enclosure
+    let mut sigusr1 = signal(SignalKind::user_defined1())?;
+
+    // systemd stops us with SIGTERM, a terminal with SIGINT; either way switch off and log the stop
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+
+    // per-service tick durations and schedule drift
+    let mut metrics = metrics::Metrics::default();
+
+    // runtime and water counters, carried on from what's already in usage_monthly
+    let mut usage_tracker = usage::Tracker::default();
+    if status.humidifier_on {
+        usage_tracker.switched("humidifier", true, start.at);
+    }
+    match usage::totals(&postgres_client).await {
+        Ok(totals) => for total in totals {
+            metrics.add_usage(&total.actuator, 3600.0 * total.hours, total.liters);
+        },
+        Err(e) => { logging::print(3, &format!("Could not load usage totals: {}", e)); },
+    }
+
+    // sample faster for a while after the pump runs or the humidifier switches
+    let mut burst = sampling::Burst::new(&config.sampling);
+
+    // keep humidifying on a timer if the sensor dies
+    let mut fallback = fallback::Fallback::new(&config.humidifier);
+    let mut feedback = feedback::Feedback::new(&config.watering_feedback);
+    let mut until_wet = config.until_wet.as_ref().zip(wetting::burst_ml(&config)).map(|(c, ml)| wetting::UntilWet::new(c, ml));
+    let mut warmup = warmup::WarmUp::new(config.humidifier.warmup_readings);
+
+    // dry the enclosure out if RH stays pinned near 100 %
+    let mut saturation = safety::Saturation::new(&config.humidifier, config.humidifier.rh_high);
+
+    // keep the glass from fogging: fan on and targets down while it's near the dew point
+    let mut condensation = config.condensation.as_ref().map(condensation::Condensation::new);
+    let mut analog_latest = HashMap::new();
+
+    // alert once each time the enclosure leaves its temperature band
+    let mut temp_band = alerts::Band::default();
+    let mut temp_rates = alerts::Rates::new(&config.alerts.temp_rate);
+
+    // stir the air a little after the humidifier starts so the sensor isn't reading the plume
+    let mut mix_kick = mixing::MixKick::new(&config.humidifier);
+    let mut bus_rx = bus.subscribe();
+
+    // shadow waterings aren't stored, nothing went in
+    let pump_writer = Some(&writer).filter(|_| !shadow);
+
+    // a closed connection is replaced off the loop, the control keeps running meanwhile
+    let (reconnect_tx, mut reconnect_rx) = mpsc::channel(1);
+    let mut reconnecting = false;
+
+    // timed runs end on deadlines of their own so readings, and the heater's checks on them,
+    // keep coming in while they run
+    let mut fan_off_at: Option<Instant> = None;
+    let mut manual_fan = false;
+    let mut mist_off_at: Option<Instant> = None;
+    let mut manual_pump: Option<(irrigation::Sequence, feedback::Snapshot, DateTime<Utc>)> = None;
+
+    // READY=1 only once everything above came up, a Type=notify unit counts failing before
+    // here as a failed start
+    watchdog::notify_ready();
 
     loop {
         tokio::select! {
-            _ = climate_interval.tick() => {
-                match climate_service(&mut postgres_client, sht20.clone(), &mut humd_gpio).await {
-                    Ok(_) => {},
+            Some(client) = reconnect_rx.recv() => {
+                logging::send(5, "Reconnected to the database", &[("SERVICE", &"database"), ("EVENT", &"reconnected")]);
+                postgres_client = client;
+                reconnecting = false;
+            }
+            scheduled = next_climate_tick(&mut climate_interval, &mut burst, &mut sigusr1) => {
+                let started = Instant::now();
+                let mut today = experiment::params(&config, Local::now().naive_local());
+                if let (None, None, Some((rh_low, rh_high))) = (today.variant, today.setpoint, tuned) {
+                    (today.rh_low, today.rh_high) = (rh_low, rh_high);
+                }
+                if dormancy_state.dormant {
+                    (today.rh_low, today.rh_high) = (config.dormancy.rh_low, config.dormancy.rh_high);
+                }
+                if condensation.as_ref().is_some_and(|c| c.is_at_risk()) {
+                    let reduction = config.condensation.as_ref().map_or(0.0, |c| c.rh_reduction);
+                    (today.rh_low, today.rh_high) = (today.rh_low - reduction, today.rh_high - reduction);
+                }
+                if today.variant != params.variant {
+                    logging::send(6, &format!("Experiment variant now {}, RH {:.0}-{:.0} %", today.variant.unwrap_or("none"), today.rh_low, today.rh_high),
+                        &[("SERVICE", &"climate"), ("EVENT", &"experiment_variant"), ("VARIANT", &today.variant.unwrap_or_default())]);
+                    status.variant = today.variant.map(String::from);
+                }
+                if today.setpoint != params.setpoint {
+                    if let Some(from) = today.setpoint {
+                        logging::send(6, &format!("Humidifier schedule from {} now, RH {:.0}-{:.0} %", from.format("%H:%M"), today.rh_low, today.rh_high),
+                            &[("SERVICE", &"climate"), ("EVENT", &"setpoint"), ("RH_LOW", &today.rh_low), ("RH_HIGH", &today.rh_high)]);
+                    }
+                }
+                params = today;
+                let rh_trend = if params.predictive {
+                    recent.read().unwrap().humidity(config.humidifier.trend_readings)
+                } else {
+                    Vec::new()
+                };
+                let horizon = Duration::seconds(climate_interval.period().as_secs() as i64);
+                let humidifier_paused = door.as_ref().is_some_and(|d| d.is_open()) || saturation.is_tripped();
+                let reading = within(&mut metrics, "climate", tick_budget,
+                    climate_service(&writer, sht20.clone(), &mut warmup, humd_gpio.as_mut(), &mut stagger, &mut humd_cycle, &params, &rh_trend, horizon, humidifier_paused)).await;
+                match reading {
+                    Ok(None) => {},
+                    Ok(Some(reading)) => {
+                        let (temp, rh) = reading.value;
+                        if fallback.success() {
+                            logging::send(6, "Climate sensor is back, controlling the humidifier on RH again",
+                                &[("SERVICE", &"climate"), ("EVENT", &"fallback_end")]);
+                        }
+                        match saturation.update(rh, reading.taken_at) {
+                            Some(true) => {
+                                humd_gpio.set_off();
+                                stagger.switch_on(fan_gpio.as_mut()).await;
+                                alerts::raise(&bus, "rh_saturated", &format!("RH above {:.0} % for {} min, forcing the humidifier off and the fan on until it drops below {:.0} %",
+                                    config.humidifier.emergency_rh, config.humidifier.emergency_after_mins, config.humidifier.rh_high));
+                            },
+                            Some(false) => {
+                                if !condensation.as_ref().is_some_and(|c| c.holds_fan()) {
+                                    fan_gpio.set_off();
+                                }
+                                logging::send(6, &format!("RH back down to {:3.2}, ending emergency ventilation", rh),
+                                    &[("SERVICE", &"climate"), ("EVENT", &"rh_saturated_end")]);
+                            },
+                            None => {},
+                        }
+                        let (temp_low, temp_high) = match dormancy_state.dormant {
+                            true => (config.dormancy.temp_low, config.dormancy.temp_high),
+                            false => (config.alerts.temp_low, config.alerts.temp_high),
+                        };
+                        match temp_band.check(temp, temp_low, temp_high) {
+                            Some(true) => {
+                                let (side, limit) = match temp_low.filter(|low| temp < *low) {
+                                    Some(low) => ("below", low),
+                                    None => ("above", temp_high.unwrap_or_default()),
+                                };
+                                alerts::raise(&bus, "temperature", &format!("Enclosure at {:3.2} C, {} the {} C limit", temp, side, limit));
+                            },
+                            Some(false) => logging::send(6, &format!("Enclosure back in its temperature band at {:3.2} C", temp),
+                                &[("SERVICE", &"climate"), ("EVENT", &"temperature_ok")]),
+                            None => {},
+                        }
+                        if temp_rates.window_mins() > 0 {
+                            // the bus may not have delivered this reading to the buffer yet
+                            let since = reading.taken_at - Duration::minutes(temp_rates.window_mins() as i64);
+                            let mut readings = recent.read().unwrap().temperature(since);
+                            readings.retain(|(at, _)| *at < reading.taken_at);
+                            readings.push((reading.taken_at, temp));
+                            for message in temp_rates.check(&readings, reading.taken_at) {
+                                alerts::raise(&bus, "temperature_rate", &message);
+                            }
+                        }
+                        if let (Some(heater), Some(relay)) = (heater.as_mut(), heater_gpio.as_mut()) {
+                            match heater.update(temp, reading.taken_at, status.heater_on) {
+                                heater::Heat::On if !status.heater_on => {
+                                    stagger.switch_on(relay.as_mut()).await;
+                                    status.heater_on = true;
+                                },
+                                heater::Heat::Off if status.heater_on => {
+                                    actuator::switch_off(relay.as_mut()).await;
+                                    status.heater_on = false;
+                                },
+                                heater::Heat::Runaway(reason) => {
+                                    actuator::switch_off(relay.as_mut()).await;
+                                    status.heater_on = false;
+                                    status.heater_latched = Some(reason.clone());
+                                    alerts::raise(&bus, "heater_runaway", &format!("Heater latched off, {}; check it, then `bonsai-bot heater-clear`", reason));
+                                    // a shadow run mustn't latch the real heater off on the next start
+                                    if !shadow {
+                                        if let Err(e) = heater::record_trip(&postgres_client, &reason).await {
+                                            logging::print(3, &format!("Could not record the heater latch, it won't hold across a restart: {}", e));
+                                        }
+                                    }
+                                },
+                                _ => {},
+                            }
+                        }
+                        let room_temp = match &ambient {
+                            Some(ambient) => match within(&mut metrics, "ambient", tick_budget, ambient_service(&writer, ambient.clone(), temp, rh)).await {
+                                Ok(room_temp) => Some(room_temp),
+                                Err(e) => {
+                                    logging::send(3, &format!("Ambient service error: {}", e), &[("SERVICE", &"ambient"), ("EVENT", &"error")]);
+                                    None
+                                },
+                            },
+                            None => None,
+                        };
+                        if let (Some(watch), Some(c)) = (condensation.as_mut(), &config.condensation) {
+                            let probe_volts = c.probe.as_ref().and_then(|p| analog_latest.get(&p.channel).map(|&(_, volts)| volts));
+                            if let Some(glass) = condensation::glass_temperature(c, probe_volts, room_temp, temp) {
+                                let dew_point = condensation::dew_point(temp, rh);
+                                match watch.update(dew_point, glass) {
+                                    Some(true) => {
+                                        logging::send(4, &format!("Glass at {:3.2} C is near the {:3.2} C dew point, lowering RH targets by {:.0} %",
+                                            glass, dew_point, c.rh_reduction),
+                                            &[("SERVICE", &"climate"), ("EVENT", &"condensation_risk"),
+                                              ("GLASS_TEMP", &format!("{:.2}", glass)), ("DEW_POINT", &format!("{:.2}", dew_point))]);
+                                        if c.run_fan {
+                                            stagger.switch_on(fan_gpio.as_mut()).await;
+                                        }
+                                    },
+                                    Some(false) => {
+                                        logging::send(6, &format!("Glass at {:3.2} C is clear of the {:3.2} C dew point again", glass, dew_point),
+                                            &[("SERVICE", &"climate"), ("EVENT", &"condensation_clear")]);
+                                        if c.run_fan && !saturation.is_tripped() {
+                                            fan_gpio.set_off();
+                                        }
+                                    },
+                                    None => {},
+                                }
+                            }
+                        }
+                        status.fan_on = fan_gpio.is_on();
+                        status.temperature = Some(temp);
+                        status.humidity = Some(rh);
+                        status.measured_at = Some(reading.taken_at);
+                        status.humidifier_on = humd_gpio.is_on();
+                        events::publish(&status_tx, &bus, &status);
+                    },
                     Err(e) => {
-                        journal::print(3, &format!("Climate service error: {}", e));
-                        if let Some(db_error) = e.downcast_ref::<tokio_postgres::error::Error>() {
-                            if db_error.is_closed() {
-                                try_reconnect(&mut postgres_client).await?;
-                            } else {
-                                journal::print(3, &format!("Unhandled error: {}", db_error));
+                        logging::send(3, &format!("Climate service error: {}", e), &[("SERVICE", &"climate"), ("EVENT", &"error")]);
+                        warmup.failure();
+                        // nothing to tell whether the heater is doing its job, don't leave it on blind
+                        if let (true, Some(relay)) = (status.heater_on, heater_gpio.as_mut()) {
+                            actuator::switch_off(relay.as_mut()).await;
+                            // a relay that didn't open shows on the next reading that does come
+                            if let (Some(heater), Some(temp)) = (heater.as_mut(), status.temperature) {
+                                heater.switched_off(Utc::now(), temp);
                             }
+                            status.heater_on = false;
+                            events::publish(&status_tx, &bus, &status);
+                        }
+                        if matches!(e, BonsaiError::Sensor(_) | BonsaiError::Timeout(..)) && fallback.failure() {
+                            alerts::raise(&bus, "sensor_failed", &format!("Climate sensor failed {} times in a row, running the humidifier {:.0} % of the time",
+                                config.humidifier.fallback_after_failures, 100.0 * config.humidifier.fallback_duty));
                         }
-                        ()
                     },
                 }
+                #[cfg(feature = "chaos")]
+                chaos::tick(&postgres_client).await;
+                if postgres_client.is_closed() && !reconnecting {
+                    reconnecting = true;
+                    db::spawn_reconnect(reconnect_tx.clone());
+                }
+                metrics.record_tick("climate", scheduled, started, climate_interval.period());
+            }
+            due = analog_schedule.due() => {
+                if let (Some(adc), Some(adc_config)) = (adc.as_mut(), config.adc.as_ref()) {
+                    let channels: Vec<_> = due.iter().map(|&i| &adc_config.channels[i]).collect();
+                    if let Err(e) = within(&mut metrics, "analog", tick_budget, analog_service(&writer, adc, &channels, &calibrations, &mut analog_latest)).await {
+                        logging::send(3, &format!("Analog service error: {}", e), &[("SERVICE", &"analog"), ("EVENT", &"error")]);
+                    }
+                    if let (Some(leaf), Some(c)) = (leaf.as_mut(), &config.leaf_wetness) {
+                        if let Some(volts) = channels.iter().any(|ch| ch.name == c.channel).then(|| analog_latest.get(&c.channel)).flatten().map(|(_, volts)| volts) {
+                            let risk = leaf.update(Utc::now(), wetness::wetness(c, *volts), status.temperature);
+                            writer.send(storage::Write::LeafWetness { at: Utc::now(), wetness: risk.wetness, fungal_risk: risk.score });
+                            logging::print(7, &format!("Leaf wetness {:.0} %, wet {:.1} h, fungal risk {:.0} %", risk.wetness, risk.wet_hours, risk.score));
+                            if risk.alert {
+                                alerts::raise(&bus, "fungal_risk", &format!("Leaves have been wet for {:.1} h, long enough at these temperatures for fungal infection",
+                                    risk.wet_hours));
+                            }
+                        }
+                    }
+                }
             }
-            _ = fan_interval.tick() => {
-                match fan_service(&mut fan_gpio).await {
+            scheduled = fan_interval.tick() => {
+                if saturation.is_tripped() || condensation.as_ref().is_some_and(|c| c.holds_fan()) {
+                    // already running flat out
+                    continue;
+                }
+                let started = Instant::now();
+                status.fan_on = true;
+                events::publish(&status_tx, &bus, &status);
+                match fan_service(fan_gpio.as_mut(), &mut stagger).await {
                     Ok(_) => {},
                     Err(e) => {
-                        journal::print(3, &format!("Fan service error: {}", e));
-                        ()
+                        logging::send(3, &format!("Fan service error: {}", e), &[("SERVICE", &"fan"), ("EVENT", &"error")]);
                     }
                 }
+                status.fan_on = false;
+                events::publish(&status_tx, &bus, &status);
+                metrics.record_tick("fan", Some(scheduled), started, fan_interval.period());
             },
-            _ = pump_interval.tick() => {
-                match pump_service(&mut postgres_client, &mut pump_gpio).await {
-                    Ok(_) => {},
+            // a manual run in progress holds these off until it's done
+            scheduled = pump_interval.tick(), if manual_pump.is_none() => {
+                let started = Instant::now();
+                status.next_pump = Some(Utc::now() + Duration::hours(PUMP_PERIODIC_HRS));
+                let dormant_until = last_watered + Duration::days(config.dormancy.water_every_days as i64);
+                if dormancy_state.dormant && Utc::now() < dormant_until {
+                    logging::send(6, &format!("Dormant, skipping watering until {}", dormant_until.with_timezone(&Local).format("%Y-%m-%d")),
+                        &[("SERVICE", &"pump"), ("EVENT", &"pump_skipped_dormant")]);
+                    events::publish(&status_tx, &bus, &status);
+                    continue;
+                }
+                // a pot on a load cell is watered by weight, the timer only stands in when it can't be weighed
+                let weighed = pot_weighed.filter(|(at, _)| Utc::now() - *at <= Duration::hours(1));
+                if let (Some(pot), Some((_, grams))) = (&config.pot, weighed) {
+                    if grams >= pot.dry_grams {
+                        logging::send(6, &format!("Pot still {:.0} g, above its dry weight of {:.0} g, skipping watering", grams, pot.dry_grams),
+                            &[("SERVICE", &"pump"), ("EVENT", &"pump_skipped_wet"), ("GRAMS", &format!("{:.0}", grams))]);
+                        events::publish(&status_tx, &bus, &status);
+                        continue;
+                    }
+                }
+                // with [until_wet] the probe decides how much goes in, the timer only stands in without a reading
+                let before = watering_snapshot(pot_weighed, &analog_latest, &calibrations);
+                let step = until_wet.as_ref().map(|wet| (wet.step(before.moisture_of(wet.channel())), wet.burst_secs()));
+                match step {
+                    Some((wetting::Step::Wet, _)) => {
+                        logging::send(6, "Soil already at its target moisture, skipping watering", &[("SERVICE", &"pump"), ("EVENT", &"pump_skipped_wet")]);
+                        events::publish(&status_tx, &bus, &status);
+                        continue;
+                    },
+                    Some((wetting::Step::NoReading, _)) => logging::send(4, "No calibrated moisture reading to water until wet by, watering on the timer",
+                        &[("SERVICE", &"pump"), ("EVENT", &"until_wet_unavailable")]),
+                    _ => {},
+                }
+                let burst = step.filter(|(step, _)| *step == wetting::Step::Burst).map(|(_, secs)| secs);
+                last_watered = Utc::now();
+                let boost = match &config.wind {
+                    Some(wind) if wind_gauge.drying(wind, status.temperature) => {
+                        logging::send(6, &format!("Hot and windy, watering {} times as long", wind.boost),
+                            &[("SERVICE", &"pump"), ("EVENT", &"pump_boosted"), ("WIND_KMH", &format!("{:.1}", wind_gauge.mean().unwrap_or_default()))]);
+                        wind.boost
+                    },
+                    _ => 1.0,
+                };
+                status.pump_on = true;
+                events::publish(&status_tx, &bus, &status);
+                match pump_service(pump_writer, pump_gpio.as_mut(), &mut zones, &mut stagger, burst, boost, None).await {
+                    Ok(started_at) => match until_wet.as_mut().filter(|_| burst.is_some()) {
+                        Some(wet) => wet.burst(before, started_at),
+                        // a shadow run put no water in, there's nothing for the check to see
+                        None if !shadow => feedback.watered(before),
+                        None => {},
+                    },
                     Err(e) => {
-                        journal::print(3, &format!("Pump service error: {}", e));
-                        ()
+                        logging::send(3, &format!("Pump service error: {}", e), &[("SERVICE", &"pump"), ("EVENT", &"error")]);
                     }
                 }
+                status.pump_on = false;
+                events::publish(&status_tx, &bus, &status);
+                metrics.record_tick("pump", Some(scheduled), started, pump_interval.period());
+            }
+            scheduled = retention_interval.tick() => {
+                let started = Instant::now();
+                // the rollups take a while, the storage task runs them between writes
+                writer.send(storage::Write::Retention {
+                    holder: holder.clone(),
+                    ttl_secs: 2 * 60 * config.retention.interval_mins,
+                    raw_days: config.retention.raw_days,
+                });
+                metrics.record_tick("retention", Some(scheduled), started, retention_interval.period());
+            }
+            _ = async { until_wet.as_ref().expect("enabled").due().await }, if until_wet.is_some() && manual_pump.is_none() => {
+                let Some(wet) = until_wet.as_mut() else {
+                    continue;
+                };
+                // the analog schedule may not get round to the probe for a while, read it now
+                let probe = config.adc.iter().flat_map(|adc| &adc.channels).find(|c| c.name == wet.channel());
+                if let (Some(adc), Some(probe)) = (adc.as_mut(), probe) {
+                    if let Err(e) = analog_service(&writer, adc, &[probe], &calibrations, &mut analog_latest).await {
+                        logging::send(3, &format!("Analog service error: {}", e), &[("SERVICE", &"analog"), ("EVENT", &"error")]);
+                    }
+                }
+                // a reading from before the last burst says nothing about how wet it left the soil
+                let soaked = analog_latest.get(wet.channel()).is_some_and(|(at, _)| wet.soaked(*at));
+                let moisture = watering_snapshot(pot_weighed, &analog_latest, &calibrations).moisture_of(wet.channel()).filter(|_| soaked);
+                let step = wet.step(moisture);
+                if step == wetting::Step::Burst {
+                    status.pump_on = true;
+                    events::publish(&status_tx, &bus, &status);
+                    let result = pump_service(pump_writer, pump_gpio.as_mut(), &mut zones, &mut stagger, Some(wet.burst_secs()), 1.0,
+                        wet.started_at()).await;
+                    status.pump_on = false;
+                    events::publish(&status_tx, &bus, &status);
+                    match result {
+                        Ok(started_at) => {
+                            wet.burst(feedback::Snapshot::default(), started_at);
+                            continue;
+                        },
+                        Err(e) => logging::send(3, &format!("Pump service error: {}", e), &[("SERVICE", &"pump"), ("EVENT", &"error")]),
+                    }
+                }
+                let (bursts, ml) = (wet.bursts(), wet.volume_ml());
+                match step {
+                    wetting::Step::Wet => logging::send(6, &format!("Soil at {:.0} % after {} burst(s), about {:.0} ml", moisture.unwrap_or_default(), bursts, ml),
+                        &[("SERVICE", &"pump"), ("EVENT", &"until_wet_done"), ("BURSTS", &bursts), ("VOLUME_ML", &format!("{:.0}", ml))]),
+                    wetting::Step::Capped => logging::send(4, &format!("Soil still at {:.0} % after {} burst(s), stopping at the {:.0} ml cap",
+                        moisture.unwrap_or_default(), bursts, ml), &[("SERVICE", &"pump"), ("EVENT", &"until_wet_capped"), ("BURSTS", &bursts)]),
+                    wetting::Step::NoReading => logging::send(4, &format!("Lost the moisture reading after {} burst(s), stopping", bursts),
+                        &[("SERVICE", &"pump"), ("EVENT", &"until_wet_unavailable"), ("BURSTS", &bursts)]),
+                    wetting::Step::Burst => {},
+                }
+                if let Some(before) = wet.finish().filter(|_| !shadow) {
+                    feedback.watered(before);
+                }
+            }
+            before = feedback.due() => {
+                let deltas = feedback::deltas(&before, &watering_snapshot(pot_weighed, &analog_latest, &calibrations));
+                for (source, delta) in &deltas {
+                    logging::send(6, &format!("Watering moved {} by {:+.1}", source, delta),
+                        &[("SERVICE", &"pump"), ("EVENT", &"watering_effect"), ("SOURCE", source), ("DELTA", &format!("{:.1}", delta))]);
+                }
+                if feedback::ineffective(&deltas, &config.watering_feedback) {
+                    alerts::raise(&bus, "watering_ineffective", &format!("Watering changed nothing after {} min ({}), check the drip line for a clog",
+                        config.watering_feedback.settle_mins, feedback::describe(&deltas)));
+                }
+            }
+            on = fallback.tick() => {
+                // the duty cycle runs blind, the door and saturation guards still hold, as in climate_service
+                let paused = door.as_ref().is_some_and(|d| d.is_open()) || saturation.is_tripped();
+                if on && !paused {
+                    stagger.switch_on(humd_gpio.as_mut()).await;
+                } else {
+                    humd_gpio.set_off();
+                }
+                status.humidifier_on = humd_gpio.is_on();
+                events::publish(&status_tx, &bus, &status);
+            }
+            _ = maintenance_interval.tick(), if !config.consumables.is_empty() => {
+                match within(&mut metrics, "maintenance", tick_budget, maintenance::status(&postgres_client, &config.consumables)).await {
+                    Ok(consumables) => for c in consumables.iter().filter(|c| c.due) {
+                        alerts::raise(&bus, "maintenance_due", &format!("The {} is due for service, last serviced {}", c.name, c.serviced_at.format("%Y-%m-%d")));
+                    },
+                    Err(e) => { logging::print(3, &format!("Maintenance check error: {}", e)); },
+                }
+            }
+            _ = mix_kick.tick() => {
+                if fan_gpio.is_on() {
+                    // the periodic, manual or emergency run is mixing already
+                    continue;
+                }
+                logging::send(6, &format!("Running the fan {} s to mix the humidified air", config.humidifier.mix_fan_secs),
+                    &[("SERVICE", &"fan"), ("EVENT", &"mix_fan"), ("SECONDS", &config.humidifier.mix_fan_secs)]);
+                status.fan_on = true;
+                events::publish(&status_tx, &bus, &status);
+                stagger.switch_on(fan_gpio.as_mut()).await;
+                fan_off_at = Some(Instant::now() + actuator::run_time(config.humidifier.mix_fan_secs));
+            }
+            _ = async { sleep_until(fan_off_at.expect("running")).await }, if fan_off_at.is_some() => {
+                fan_off_at = None;
+                // the saturation and condensation guards keep it running past the end of a run
+                if !saturation.is_tripped() && !condensation.as_ref().is_some_and(|c| c.holds_fan()) {
+                    fan_gpio.set_off();
+                }
+                if std::mem::take(&mut manual_fan) {
+                    cooldown.finished("fan", std::time::Instant::now());
+                }
+                status.fan_on = fan_gpio.is_on();
+                events::publish(&status_tx, &bus, &status);
+            }
+            _ = async { sleep_until(mist_off_at.expect("pulsing")).await }, if mist_off_at.is_some() => {
+                mist_off_at = None;
+                if let Some(nozzle) = mister_gpio.as_mut() {
+                    actuator::switch_off(nozzle.as_mut()).await;
+                }
+                status.mister_on = false;
+                events::publish(&status_tx, &bus, &status);
+            }
+            _ = async { sleep_until(manual_pump.as_ref().expect("running").0.due()).await }, if manual_pump.is_some() => {
+                let Some((run, _, _)) = manual_pump.as_mut() else {
+                    continue;
+                };
+                let Some(deliveries) = run.advance(pump_gpio.as_mut(), &mut zones, &mut stagger).await else {
+                    // on to the next zone
+                    continue;
+                };
+                if let Some((_, before, started_at)) = manual_pump.take().filter(|_| !shadow) {
+                    // recorded like a scheduled run, so the schedule and the usage count it
+                    let ended_at = Utc::now();
+                    let seconds = (ended_at - started_at).num_milliseconds() as f64 / 1000.0;
+                    writer.send(storage::Write::PumpEnd { started_at, ended_at, seconds, deliveries });
+                    feedback.watered(before);
+                }
+                cooldown.finished("pump", std::time::Instant::now());
+                status.pump_on = false;
+                events::publish(&status_tx, &bus, &status);
+            }
+            _ = dormancy_interval.tick(), if auto_dormancy => {
+                let query = async { Ok(dormancy::chill_hours(&postgres_client, config.dormancy.chill_below_c).await?) };
+                let chill_hours = match within(&mut metrics, "dormancy", tick_budget, query).await {
+                    Ok(hours) => hours,
+                    Err(e) => {
+                        logging::print(3, &format!("Chill hour query error: {}", e));
+                        continue;
+                    },
+                };
+                if let Some(dormant) = dormancy::decide(&config.dormancy, dormancy_state, chill_hours) {
+                    let reason = format!("{:.1} chill hours in the last {} days", chill_hours, dormancy::CHILL_WINDOW_DAYS);
+                    let (event, detail) = dormancy::switch(&mut dormancy_state, dormant, &reason);
+                    writer.send(storage::Write::SystemEvent { event, detail });
+                    status.dormant = dormancy_state.dormant;
+                    events::publish(&status_tx, &bus, &status);
+                }
+            }
+            _ = tune_interval.tick(), if config.tuner.is_some() => {
+                let tuner = config.tuner.as_ref().expect("checked in the guard");
+                if params.variant.is_some() || params.setpoint.is_some() || condensation.as_ref().is_some_and(|c| c.is_at_risk()) {
+                    // tuning mid-experiment would spoil the comparison, a schedule has no one
+                    // band to tune, and the lowered condensation targets aren't it either
+                    continue;
+                }
+                let query = async { Ok(tuner::measure(&postgres_client, tuner.days).await?) };
+                match within(&mut metrics, "tuner", tick_budget, query).await {
+                    Ok(rates) => if let Some((rh_low, rh_high)) = tuner::suggest(&rates, params.rh_low, params.rh_high, tuner) {
+                        let verb = if tuner.apply { "Tuned" } else { "Tuner suggests" };
+                        logging::send(6, &format!("{} humidifier band {:.1}-{:.1} % (was {:.1}-{:.1} %), RH rises {:.2} and decays {:.2} %/min, {:.1} cycles/hour",
+                            verb, rh_low, rh_high, params.rh_low, params.rh_high, rates.rise_per_min, rates.decay_per_min, rates.cycles_per_hour),
+                            &[("SERVICE", &"climate"), ("EVENT", &if tuner.apply { "tune_applied" } else { "tune_suggestion" }),
+                              ("RH_LOW", &rh_low), ("RH_HIGH", &rh_high)]);
+                        if tuner.apply {
+                            tuned = Some((rh_low, rh_high));
+                            (params.rh_low, params.rh_high) = (rh_low, rh_high);
+                        }
+                    },
+                    Err(e) => { logging::print(3, &format!("Tuner error: {}", e)); },
+                }
+            }
+            _ = stability_interval.tick() => {
+                let (rh_low, rh_high) = (config.humidifier.rh_low, config.humidifier.rh_high);
+                match within(&mut metrics, "stability", tick_budget, async { Ok(stability::catch_up(&postgres_client, rh_low, rh_high).await?) }).await {
+                    Ok(Some(report)) => {
+                        let message = report.summary();
+                        logging::send(6, &message, &[("SERVICE", &"climate"), ("EVENT", &"weekly_stability"),
+                            ("IN_BAND_PCT", &report.in_band_pct), ("DUTY_PCT", &report.duty_pct)]);
+                        let _ = bus.send(events::Event::Summary { at: Utc::now(), kind: "weekly_stability", message });
+                    },
+                    Ok(None) => {},
+                    Err(e) => { logging::print(3, &format!("Stability report error: {}", e)); },
+                }
+            }
+            _ = wind_interval.tick(), if anemometer.is_some() => {
+                let (Some(anemometer), Some(wind)) = (anemometer.as_mut(), config.wind.as_ref()) else {
+                    continue;
+                };
+                let reading = anemometer.speed();
+                writer.send(storage::Write::Wind { at: reading.taken_at, speed_kmh: reading.value });
+                wind_gauge.record(reading.taken_at, reading.value);
+                status.wind_kmh = Some(reading.value);
+                events::publish(&status_tx, &bus, &status);
+                logging::print(7, &format!("Wind {:.1} km/h", reading.value));
+
+                // pots drying out between scheduled runs get an extra one
+                let since_watered = Utc::now() - last_watered;
+                if !dormancy_state.dormant && since_watered >= Duration::hours(wind.water_every_hours as i64)
+                    && wind_gauge.drying(wind, status.temperature) {
+                    logging::send(6, &format!("Hot and windy, watering early after {} h", since_watered.num_hours()),
+                        &[("SERVICE", &"pump"), ("EVENT", &"pump_early_wind")]);
+                    pump_interval.reset_immediately();
+                }
+            }
+            _ = tank_interval.tick(), if tank_cell.is_some() => {
+                let (Some(cell), Some(tank)) = (tank_cell.as_ref(), config.tank.as_ref()) else {
+                    continue;
+                };
+                match within(&mut metrics, "tank", tick_budget, weigh_service(&writer, cell.clone(), "tank")).await {
+                    Ok(grams) => {
+                        status.tank_grams = Some(grams);
+                        events::publish(&status_tx, &bus, &status);
+                        match tank_band.check(grams, Some(tank.low_grams), None) {
+                            Some(true) => alerts::raise(&bus, "tank_low", &format!("Humidifier tank down to {:.0} g of water, refill it", grams)),
+                            Some(false) => logging::send(6, &format!("Humidifier tank refilled, {:.0} g", grams),
+                                &[("SERVICE", &"tank"), ("EVENT", &"tank_refilled")]),
+                            None => {},
+                        }
+                    },
+                    Err(e) => logging::send(3, &format!("Tank load cell error: {}", e), &[("SERVICE", &"tank"), ("EVENT", &"error")]),
+                }
+            }
+            _ = pot_interval.tick(), if pot_cell.is_some() => {
+                let (Some(cell), Some(pot)) = (pot_cell.as_ref(), config.pot.as_ref()) else {
+                    continue;
+                };
+                match within(&mut metrics, "pot", tick_budget, weigh_service(&writer, cell.clone(), "pot")).await {
+                    Ok(grams) => {
+                        pot_weighed = Some((Utc::now(), grams));
+                        status.pot_grams = Some(grams);
+                        events::publish(&status_tx, &bus, &status);
+
+                        // the water needs time to soak in and drain before the weight means anything
+                        let since_watered = Utc::now() - last_watered;
+                        if grams < pot.dry_grams && since_watered >= Duration::hours(pot.min_gap_hours as i64) {
+                            logging::send(6, &format!("Pot down to {:.0} g, below its dry weight of {:.0} g, watering", grams, pot.dry_grams),
+                                &[("SERVICE", &"pump"), ("EVENT", &"pump_early_dry"), ("GRAMS", &format!("{:.0}", grams))]);
+                            pump_interval.reset_immediately();
+                        }
+                    },
+                    Err(e) => logging::send(3, &format!("Pot load cell error: {}", e), &[("SERVICE", &"pot"), ("EVENT", &"error")]),
+                }
+            }
+            _ = mist_interval.tick(), if mister.is_some() && mist_off_at.is_none() => {
+                let (Some(mister), Some(nozzle)) = (mister.as_mut(), mister_gpio.as_mut()) else {
+                    continue;
+                };
+                // a reading older than a few climate ticks is no reason to mist
+                let fresh = status.measured_at.is_some_and(|at| Utc::now() - at <= max_age);
+                let paused = door.as_ref().is_some_and(|d| d.is_open()) || saturation.is_tripped();
+                match mister.check(Utc::now(), status.humidity.filter(|_| fresh), params.rh_low) {
+                    misting::Mist::Pulse if !paused => {
+                        mister.fired(Utc::now());
+                        status.mister_on = true;
+                        events::publish(&status_tx, &bus, &status);
+                        stagger.switch_on(nozzle.as_mut()).await;
+                        mist_off_at = Some(Instant::now() + actuator::run_time(mister.pulse_secs()));
+                    },
+                    limit @ (misting::Mist::HourlyLimit | misting::Mist::DailyLimit) => {
+                        logging::send(4, &format!("RH {:3.2} is low but misting is at its {} limit", status.humidity.unwrap_or_default(),
+                            if limit == misting::Mist::HourlyLimit { "hourly" } else { "daily" }),
+                            &[("SERVICE", &"misting"), ("EVENT", &"mist_limited")]);
+                    },
+                    _ => {},
+                }
+            }
+            _ = light_interval.tick(), if light.is_some() => {
+                let (Some(light), Some(relay), Some(schedule)) = (light.as_mut(), light_gpio.as_mut(), config.light.as_ref()) else {
+                    continue;
+                };
+                let due = light::scheduled(schedule, Local::now().time());
+                if due == status.light_on && !light.pending() {
+                    continue;
+                }
+                // the newest dark reading before it's switched on, or the lit one after
+                let lux = match light::latest_lux(&postgres_client, light.sensor(), light.readings_since()).await {
+                    Ok(lux) => lux,
+                    Err(e) => {
+                        logging::print(3, &format!("Could not read the grow light's lux: {}", e));
+                        None
+                    },
+                };
+                if due && !status.light_on {
+                    stagger.switch_on(relay.as_mut()).await;
+                    light.switched_on(Utc::now(), lux);
+                    status.light_on = true;
+                    events::publish(&status_tx, &bus, &status);
+                } else if !due && status.light_on {
+                    actuator::switch_off(relay.as_mut()).await;
+                    light.switched_off(Utc::now());
+                    status.light_on = false;
+                    events::publish(&status_tx, &bus, &status);
+                } else if let Some(reason) = light.check(lux, Utc::now()) {
+                    alerts::raise(&bus, "light_failure", &reason);
+                }
+            }
+            _ = door_interval.tick(), if door.is_some() => {
+                match door.as_mut().and_then(|d| d.poll()) {
+                    Some(door::DoorChange::Opened) => {
+                        logging::send(6, "Enclosure door opened, pausing the humidifier", &[("SERVICE", &"door"), ("EVENT", &"door_open")]);
+                        humd_gpio.set_off();
+                        status.humidifier_on = false;
+                        status.door_open = true;
+                        events::publish(&status_tx, &bus, &status);
+                    },
+                    Some(door::DoorChange::Closed { opened_at, closed_at }) => {
+                        let open_secs = (closed_at - opened_at).num_seconds();
+                        logging::send(6, &format!("Enclosure door closed after {} s", open_secs),
+                            &[("SERVICE", &"door"), ("EVENT", &"door_closed"), ("OPEN_SECS", &open_secs)]);
+                        status.door_open = false;
+                        events::publish(&status_tx, &bus, &status);
+                        writer.send(storage::Write::Door { opened_at, closed_at });
+                    },
+                    Some(door::DoorChange::LeftOpen { since }) => {
+                        logging::send(4, &format!("Enclosure door has been open since {}", since.format("%H:%M:%S %Z")),
+                            &[("SERVICE", &"door"), ("EVENT", &"door_left_open"), ("OPEN_SINCE", &since.to_rfc3339())]);
+                    },
+                    None => {},
+                }
+            }
+            Ok(event) = bus_rx.recv() => {
+                if let events::Event::Alert { at, kind, message } = &event {
+                    writer.send(storage::Write::Alert { at: *at, kind, message: message.clone() });
+                }
+                if let events::Event::Actuator { at, name, on } = event {
+                    if matches!(name, "humidifier" | "pump") {
+                        burst.trigger(&format!("{} switched {}", name, if on { "on" } else { "off" }));
+                    }
+                    if name == "humidifier" && on {
+                        mix_kick.humidifier_on();
+                    }
+                    // a shadow run switched nothing: its rows would resume the humidifier on the next
+                    // real start and count runtime towards consumables that didn't run
+                    if shadow {
+                        continue;
+                    }
+                    writer.send(storage::Write::Actuator { at, actuator: name, on, variant: params.variant });
+                    if let Some(seconds) = usage_tracker.switched(name, on, at) {
+                        let volume_ml = match (name, config.pump.flow_ml_per_min) {
+                            ("pump", Some(flow)) => flow * seconds / 60.0,
+                            _ => 0.0,
+                        };
+                        metrics.add_usage(name, seconds, volume_ml / 1000.0);
+                        writer.send(storage::Write::Usage { at, actuator: name, seconds, volume_ml });
+                    }
+                }
+            }
+            reason = next_shutdown(&mut sigterm, &mut sigint, &mut restart_rx) => {
+                logging::send(6, &format!("{} received after {}, switching everything off", reason,
+                    lifecycle::format_uptime(Utc::now() - start.at)), &[("SERVICE", &"daemon"), ("EVENT", &"stop")]);
+                watchdog::notify_stopping();
+                humd_gpio.set_off();
+                pump_gpio.set_off();
+                // a manual run may be part way through the zones
+                for zone in zones.iter_mut() {
+                    zone.valve().set_off();
+                }
+                fan_gpio.set_off();
+                if let Some(mister) = mister_gpio.as_mut() {
+                    mister.set_off();
+                }
+                if let Some(heater) = heater_gpio.as_mut() {
+                    heater.set_off();
+                }
+                if let Some(light) = light_gpio.as_mut() {
+                    light.set_off();
+                }
+                if let Some(watchdog) = &watchdog {
+                    watchdog.disarm();
+                }
+                if let Err(e) = lifecycle::record_stop(&postgres_client, reason).await {
+                    logging::print(3, &format!("Could not record stop in system_events: {}", e));
+                }
+                if let Err(e) = lease::release(&postgres_client, &instance_lease, &holder).await {
+                    logging::print(3, &format!("Could not release the {} lease: {}", instance_lease, e));
+                }
+                if matches!(reason, "update" | "profile") {
+                    std::process::exit(install::RESTART_EXIT_STATUS);
+                }
+                return Ok(());
+            }
+            Some((request, origin, reply)) = control_rx.recv() => {
+                let action = match request {
+                    ipc::Request::RunPump { .. } => Some("pump"),
+                    ipc::Request::RunFan { .. } => Some("fan"),
+                    _ => None,
+                };
+                if let ipc::Request::RunPump { seconds } | ipc::Request::RunFan { seconds } = request {
+                    if let Err(message) = ipc::check_run(seconds, config.actuation.max_manual_secs) {
+                        let _ = reply.send(ipc::Response::Error { message });
+                        continue;
+                    }
+                }
+                if let Some(Err(left)) = action.map(|a| cooldown.check(a, std::time::Instant::now())) {
+                    let message = format!("The {} ran manually moments ago, try again in {} s", action.unwrap_or_default(), left.as_secs() + 1);
+                    logging::send(4, &format!("Refused {:?} from {}: {}", request, origin.source.as_str(), message),
+                        &[("SERVICE", &"control"), ("EVENT", &"refused"), ("SOURCE", &origin.source.as_str())]);
+                    let _ = reply.send(ipc::Response::Error { message });
+                    continue;
+                }
+                if audit::describe(&request).is_some() {
+                    writer.send(storage::Write::Audit { origin: origin.clone(), request: request.clone() });
+                }
+                let response = match request {
+                    ipc::Request::Status => {
+                        ipc::Response::Status(status.clone())
+                    },
+                    ipc::Request::Metrics => {
+                        metrics.set_writes_dropped(writer.dropped());
+                        metrics.set_writes_compacted(writer.compacted());
+                        ipc::Response::Metrics { text: metrics.render_prometheus() }
+                    },
+                    ipc::Request::Maintenance => {
+                        match maintenance::status(&postgres_client, &config.consumables).await {
+                            Ok(consumables) => ipc::Response::Maintenance { consumables },
+                            Err(e) => ipc::Response::Error { message: e.to_string() },
+                        }
+                    },
+                    ipc::Request::Serviced { name } => {
+                        match maintenance::known(&config.consumables, &name) {
+                            Ok(()) => {
+                                let message = format!("Marked the {} as serviced", name);
+                                writer.send(storage::Write::Serviced { name, origin: origin.clone() });
+                                ipc::Response::Ok { message }
+                            },
+                            Err(e) => ipc::Response::Error { message: e.to_string() },
+                        }
+                    },
+                    ipc::Request::Dormancy { mode } => {
+                        let ((event, detail), message) = dormancy::apply(&mut dormancy_state, mode, &origin.describe());
+                        writer.send(storage::Write::SystemEvent { event, detail });
+                        status.dormant = dormancy_state.dormant;
+                        events::publish(&status_tx, &bus, &status);
+                        ipc::Response::Ok { message }
+                    },
+                    ipc::Request::Recent { minutes } => {
+                        let events = recent.read().unwrap().last(minutes);
+                        ipc::Response::Recent { events: events.iter().filter_map(|e| serde_json::to_value(e).ok()).collect() }
+                    },
+                    ipc::Request::HeaterClear => match heater.as_mut() {
+                        Some(heater) => match heater::record_clear(&postgres_client, &origin.describe()).await {
+                            Ok(()) => {
+                                heater.clear();
+                                status.heater_latched = None;
+                                events::publish(&status_tx, &bus, &status);
+                                ipc::Response::Ok { message: "Heater cleared, it runs again from the next reading".to_string() }
+                            },
+                            Err(e) => ipc::Response::Error { message: e.to_string() },
+                        },
+                        None => ipc::Response::Error { message: "No [heater] section in the config".to_string() },
+                    },
+                    ipc::Request::Relay { node, relay, on } => {
+                        #[cfg(feature = "mqtt")]
+                        let result = match &remote {
+                            Some(remote) => remote.switch(&node, &relay, on).await,
+                            None => Err("No [mqtt] section in the config".to_string()),
+                        };
+                        #[cfg(not(feature = "mqtt"))]
+                        let result: Result<String, String> = Err(format!("Cannot switch {} {} {}, this build lacks the mqtt feature",
+                            node, relay, if on { "on" } else { "off" }));
+                        match result {
+                            Ok(message) => ipc::Response::Ok { message },
+                            Err(message) => ipc::Response::Error { message },
+                        }
+                    },
+                    ipc::Request::Ack { kind } => {
+                        #[cfg(feature = "cloud")]
+                        let response = match &acks {
+                            Some(acks) => notify::acknowledge(acks, kind).await,
+                            None => ipc::Response::Error { message: "No [notify] section in the config".to_string() },
+                        };
+                        #[cfg(not(feature = "cloud"))]
+                        let response = ipc::Response::Error {
+                            message: format!("Cannot acknowledge {}, this build lacks the cloud feature", kind.as_deref().unwrap_or("alerts")),
+                        };
+                        response
+                    },
+                    ipc::Request::Profile { name } => match profile::select(&name) {
+                        Ok(message) => {
+                            logging::send(5, &message, &[("SERVICE", &"control"), ("EVENT", &"profile"), ("PROFILE", &name)]);
+                            let _ = restart_tx.try_send("profile");
+                            ipc::Response::Ok { message: format!("{}, restarting", message) }
+                        },
+                        Err(e) => ipc::Response::Error { message: e.to_string() },
+                    },
+                    ipc::Request::Inject { fault } => {
+                        #[cfg(feature = "chaos")]
+                        let result = chaos::arm(fault);
+                        #[cfg(not(feature = "chaos"))]
+                        let result: Result<String, String> = Err(format!("Cannot inject {:?}, this build lacks the chaos feature", fault));
+                        match result {
+                            Ok(message) => ipc::Response::Ok { message },
+                            Err(message) => ipc::Response::Error { message },
+                        }
+                    },
+                    ipc::Request::RunPump { .. } if manual_pump.is_some() => {
+                        ipc::Response::Error { message: "The pump is already running".to_string() }
+                    },
+                    ipc::Request::RunPump { seconds } => {
+                        logging::send(6, &format!("Manual pump run for {} s requested", seconds),
+                            &[("SERVICE", &"control"), ("EVENT", &"manual_pump"), ("SOURCE", &origin.source.as_str()), ("SECONDS", &seconds)]);
+                        status.pump_on = true;
+                        events::publish(&status_tx, &bus, &status);
+                        let before = watering_snapshot(pot_weighed, &analog_latest, &calibrations);
+                        let started_at = Utc::now();
+                        if let Some(writer) = pump_writer {
+                            writer.send(storage::Write::PumpStart { at: started_at });
+                        }
+                        let run = irrigation::Sequence::start(pump_gpio.as_mut(), &mut zones, &mut stagger, Some(seconds), 1.0).await;
+                        manual_pump = Some((run, before, started_at));
+                        ipc::Response::Ok { message: format!("Running pump for {} s", seconds) }
+                    },
+                    ipc::Request::RunFan { seconds } => {
+                        logging::send(6, &format!("Manual fan run for {} s requested", seconds),
+                            &[("SERVICE", &"control"), ("EVENT", &"manual_fan"), ("SOURCE", &origin.source.as_str()), ("SECONDS", &seconds)]);
+                        status.fan_on = true;
+                        events::publish(&status_tx, &bus, &status);
+                        stagger.switch_on(fan_gpio.as_mut()).await;
+                        let until = Instant::now() + actuator::run_time(seconds);
+                        fan_off_at = Some(fan_off_at.map_or(until, |at| at.max(until)));
+                        manual_fan = true;
+                        ipc::Response::Ok { message: format!("Running fan for {} s", seconds) }
+                    },
+                };
+                let _ = reply.send(response);
             }
         }
     }
 }
 
+
+///
+/// @brief Waits for SIGTERM or SIGINT, or for the updater to have installed a new binary or
+///        another profile to have been selected
 ///
-/// @brief Establishes client connection to the postgres DB
+/// @return the name of the signal received, or "update" or "profile"
 ///
-async fn establish_connection() -> Result<(Client, Pin<Box<dyn Future<Output = Result<(), tokio_postgres::Error>> + Send>>), Box<dyn std::error::Error>> {
-    let database_url = std::env::var("BONSAIBOT_DATABASE_URL")?;
-    let (client, connection) = tokio_postgres::connect(&database_url, NoTls).await?;
-    Ok((client, Box::pin(connection)))
+async fn next_shutdown(sigterm: &mut Signal, sigint: &mut Signal, restart: &mut mpsc::Receiver<&'static str>) -> &'static str {
+    tokio::select! {
+        _ = sigterm.recv() => "SIGTERM",
+        _ = sigint.recv() => "SIGINT",
+        Some(reason) = restart.recv() => reason,
+    }
 }
 
-/// 
-/// @brief Tries to reconnect to the postgres client
 ///
-async fn try_reconnect(postgres_client: &mut tokio_postgres::Client) -> Result<(), Box<dyn std::error::Error>> {
-    if postgres_client.is_closed() {
-        let (new_client, new_connection) = establish_connection().await?;
-        *postgres_client = new_client;
+/// @brief `bonsai-bot update`: installs a newer release now, as configured in [update]
+///
+fn update_now(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "update")]
+    {
+        let config = Config::load()?.update.ok_or("The config has no [update] section")?;
+        let installed = update::check(&config)?;
+        if json {
+            output::json(&serde_json::json!({ "version": env!("CARGO_PKG_VERSION"), "installed": installed }));
+            return Ok(());
+        }
+        match installed {
+            Some(version) => println!("Installed bonsai-bot {}, restart the daemon to run it", version),
+            None => println!("bonsai-bot {} is the latest release", env!("CARGO_PKG_VERSION")),
+        }
+        Ok(())
+    }
+    #[cfg(not(feature = "update"))]
+    {
+        let _ = json;
+        Err("this build lacks the update feature, rebuild with --features update".into())
+    }
+}
 
-        tokio::spawn(async move {
-            if let Err(e) = new_connection.await {
-                journal::print(3, &format!("Reconnection error: {}", e));
-            }
-        });
+///
+/// @brief Waits for the next scheduled climate tick, a burst reading, or for SIGUSR1 asking
+///        for one right now
+///
+/// @return the instant the tick was scheduled for, None for SIGUSR1 readings
+///
+async fn next_climate_tick(interval: &mut Interval, burst: &mut sampling::Burst, sigusr1: &mut Signal) -> Option<Instant> {
+    tokio::select! {
+        scheduled = interval.tick() => Some(scheduled),
+        scheduled = burst.tick() => Some(scheduled),
+        _ = sigusr1.recv() => {
+            logging::print(6, "SIGUSR1 received, taking an immediate climate reading");
+            None
+        },
+    }
+}
+
+///
+/// @brief runs one service tick, abandoning it once it has taken longer than `budget`
+///
+/// @note a timeout can only fire while the tick is waiting, a blocking call that never
+///       returns is the hardware watchdog's to deal with
+/// @note the pump runs outside of it on purpose: its run time is set by the watering, not
+///       the budget, and dropping it halfway would leave the pump relay on; storage writes
+///       only queue for the writer task and the reconnect runs on a task of its own, so
+///       neither can hold up a tick
+///
+async fn within<T>(metrics: &mut metrics::Metrics, service: &'static str, budget: StdDuration,
+        tick: impl std::future::Future<Output = error::Result<T>>) -> error::Result<T> {
+    match tokio::time::timeout(budget, tick).await {
+        Ok(result) => result,
+        Err(_) => {
+            metrics.record_timeout(service, budget);
+            Err(BonsaiError::Timeout(service, budget.as_secs()))
+        },
     }
-    Ok(())
 }
 
+///
+/// @brief Logs a failed relay claim; the error already names the role
+///
+fn log_pin_error(e: pins::PinError) -> pins::PinError {
+    logging::print(3, &format!("Startup pin check failed: {}", e));
+    e
+}
 
 ///
-/// @brief turns on humidifier if RH < RH_LO_THRESH and off if RH > RH_HI_THRESH
-///        and logs temperature and humidity to the database
-///    
+/// @brief turns on humidifier if RH < `params.rh_low` and off if RH > `params.rh_high`
+///        and logs temperature and humidity, tagged with the experiment variant, to the database
+///
+/// @note with earlier readings in `rh_trend`, the humidifier also starts early when the
+///       trend says RH will be below `params.rh_low` within `horizon` (the next regular tick);
+///       it is never switched on while `humidifier_paused`, nor switched sooner than `cycle`
+///       allows
+///
+/// @return the (temperature, humidity) reading, None while `warmup` discards them
+///
+#[allow(clippy::too_many_arguments)]
 async fn climate_service(
-    client: &mut Client, 
+    writer: &storage::Writer,
     sht20: Arc<Mutex<SHT20>>, 
-    humd: &mut OutputPin
-) -> Result<(), Box<dyn Error>> {
-
-    const RH_LO_THRESH: f64 = 70.0;  // percent
-    const RH_HI_THRESH: f64 = 80.0;  // percent
-
-
-    let temp = match SHT20::get_temperature_celsius(sht20.clone()).await {
-        Ok(t) => t as f64,
-        Err(e) => {
-            journal::print(3, &format!("No temp measurement avail"));
-            return Err(Box::new(e));
-        },
-    };
+    warmup: &mut warmup::WarmUp,
+    humd: &mut dyn Actuator,
+    stagger: &mut actuator::Stagger,
+    cycle: &mut actuator::MinCycle,
+    params: &experiment::Params,
+    rh_trend: &[(DateTime<Utc>, f64)],
+    horizon: Duration,
+    humidifier_paused: bool
+) -> error::Result<Option<Reading<(f64, f64)>>> {
 
-    let rh = match SHT20::get_humidity_percent(sht20).await {
-        Ok(t) => t as f64,
+    let reading = match SHT20::read_climate(sht20).await {
+        Ok(reading) => reading.map(|(t, rh)| (t as f64, rh as f64)),
         Err(e) => {
-            journal::print(3, &format!("No humidity measurement avail"));
-            return Err(Box::new(e));
+            logging::print(3, "No climate measurement avail");
+            return Err(e.into());
         },
     };
+    let (temp, rh) = reading.value;
+    if warmup.discard() {
+        logging::send(6, &format!("Discarding warm-up reading {:3.2}, {:3.2}", temp, rh),
+            &[("SERVICE", &"climate"), ("EVENT", &"warmup_reading"), ("TEMP", &format!("{:.2}", temp)), ("RH", &format!("{:.2}", rh))]);
+        return Ok(None);
+    }
 
-    rh = if rh > 100.0 { 100.0 } else { rh };
-
-    // Insert data into the database
-    let stmt = match client.prepare("INSERT INTO climate_data (timestamp, temperature, humidity, is_pump_start) VALUES ($1, $2, $3, FALSE)").await {
-        Ok(t) => t,
-        Err(e) => {
-            journal::print(3, &format!("Database prepare error {:?}", e));
-            return Err(Box::new(e));
-        }
-    };
-
-    let utctime = Utc::now();
+    // Queue it for the database
+    let utctime = reading.taken_at;
+    writer.send(storage::Write::Climate { at: utctime, temperature: temp, humidity: rh, variant: params.variant });
 
-    let _ = match client.execute(&stmt, &[&utctime, &(temp.clone()), &(rh.clone())]).await {
-        Ok(t) => t,
-        Err(e) => {
-            journal::print(3, &format!("Database execute error {:?}", e));
-            return Err(Box::new(e));
-        }
-    };
+    logging::send(6, &format!("Queued {:3.2}, {:3.2} for the database", temp, rh),
+        &[("SERVICE", &"climate"), ("EVENT", &"reading"), ("TEMP", &format!("{:.2}", temp)), ("RH", &format!("{:.2}", rh)),
+          ("RH_LOW", &params.rh_low), ("RH_HIGH", &params.rh_high)]);
 
-    journal::print(6, &format!("Inserted {:3.2}, {:3.2} into database", temp, rh));
+    // something else may have switched it since the last reading
+    let now = Instant::now();
+    cycle.observe(humd.is_on(), now);
+    let held = !cycle.allows(!humd.is_on(), now);
 
     // humidifier is on and humidity is less than threshold
-    if rh < RH_LO_THRESH {
+    if humidifier_paused {
+        // enclosure door is open, humidifying the room is pointless
+    } else if rh < params.rh_low && held {
+        // held on is already what low RH asks for
+        if !humd.is_on() {
+            logging::send(7, &format!("RH {:3.2} is low, holding the humidifier off for its minimum off time, {} s left",
+                rh, cycle.remaining(now).as_secs()), &[("SERVICE", &"climate"), ("EVENT", &"humidifier_held"), ("REASON", &"min_off")]);
+        }
+    } else if rh < params.rh_low {
         // turn on humidifier
-        humd.set_high();
+        stagger.switch_on(humd).await;
+    } else if !humd.is_on() && !held && !rh_trend.is_empty() {
+        // turn it on a little early if RH is falling fast enough to dip below by next tick
+        let mut readings = rh_trend.to_vec();
+        readings.push((utctime, rh));
+        let next_tick = utctime + horizon;
+        if let Some(projected) = trend::project(&readings, next_tick).filter(|p| *p < params.rh_low) {
+            logging::send(6, &format!("RH {:3.2} trending to {:3.2} by next reading, pre-starting humidifier", rh, projected),
+                &[("SERVICE", &"climate"), ("EVENT", &"humidifier_prestart"), ("RH_PROJECTED", &format!("{:.2}", projected))]);
+            stagger.switch_on(humd).await;
+        }
     }
-    if rh > RH_HI_THRESH {
+    if rh > params.rh_high && held && humd.is_on() {
+        logging::send(7, &format!("RH {:3.2} is high, holding the humidifier on for its minimum on time, {} s left",
+            rh, cycle.remaining(now).as_secs()), &[("SERVICE", &"climate"), ("EVENT", &"humidifier_held"), ("REASON", &"min_on")]);
+    } else if rh > params.rh_high {
         // turn off humidifier
-        humd.set_low();
+        humd.set_off();
     }
-    
+    cycle.observe(humd.is_on(), Instant::now());
+
+    Ok(Some(reading))
+}
+
+///
+/// @brief what a pump run should change: the pot's weight and the moisture of every
+///        calibrated probe, each if it was measured in the last hour
+///
+fn watering_snapshot(pot: Option<(DateTime<Utc>, f64)>, analog_latest: &HashMap<String, (DateTime<Utc>, f64)>,
+        calibrations: &HashMap<String, soil::Calibration>) -> feedback::Snapshot {
+    let mut moisture: Vec<(String, f64)> = calibrations.iter()
+        .filter_map(|(channel, c)| analog_latest.get(channel)
+            .filter(|(at, _)| Utc::now() - *at <= Duration::hours(1))
+            .map(|(_, volts)| (channel.clone(), c.moisture(*volts))))
+        .collect();
+    moisture.sort_by(|a, b| a.0.cmp(&b.0));
+    feedback::Snapshot {
+        pot_grams: pot.filter(|(at, _)| Utc::now() - *at <= Duration::hours(1)).map(|(_, grams)| grams),
+        moisture,
+    }
+}
+
+///
+/// @brief weighs a load cell and stores the result under `name`
+///
+/// @return the weight in grams
+///
+async fn weigh_service(writer: &storage::Writer, cell: Arc<std::sync::Mutex<hx711::LoadCell>>, name: &'static str) -> error::Result<f64> {
+    let reading = hx711::LoadCell::weigh(cell).await?;
+    let (grams, counts) = reading.value;
+    writer.send(storage::Write::Weight { at: reading.taken_at, cell: name, grams });
+    logging::send(7, &format!("{} weighs {:.0} g", name, grams),
+        &[("SERVICE", &name), ("EVENT", &"reading"), ("GRAMS", &format!("{:.0}", grams)), ("COUNTS", &counts)]);
+    Ok(grams)
+}
+
+///
+/// @brief reads the room sensor and logs it alongside the enclosure-minus-room deltas, which
+///        show whether the enclosure seal and humidifier are doing their job
+///
+/// @return the room temperature
+///
+async fn ambient_service(
+    writer: &storage::Writer,
+    sht20: Arc<Mutex<SHT20>>,
+    enclosure_temp: f64,
+    enclosure_rh: f64
+) -> error::Result<f64> {
+    let reading = SHT20::read_climate(sht20).await?;
+    let (temp, rh) = (reading.value.0 as f64, reading.value.1 as f64);
+    let (temp_delta, rh_delta) = (enclosure_temp - temp, enclosure_rh - rh);
+
+    writer.send(storage::Write::Ambient { at: reading.taken_at, temperature: temp, humidity: rh, temperature_delta: temp_delta, humidity_delta: rh_delta });
+    logging::send(6, &format!("Ambient {:3.2} C, {:3.2} %, enclosure delta {:+3.2} C, {:+3.2} %", temp, rh, temp_delta, rh_delta), &[
+        ("SERVICE", &"ambient"), ("EVENT", &"reading"),
+        ("TEMP", &format!("{:.2}", temp)), ("RH", &format!("{:.2}", rh)),
+        ("TEMP_DELTA", &format!("{:.2}", temp_delta)), ("RH_DELTA", &format!("{:.2}", rh_delta)),
+    ]);
+
+    Ok(temp)
+}
+
+///
+/// @brief samples the given ADC channels and logs the voltages to the database, keeping the
+///        latest voltage per channel name in `latest`
+///
+async fn analog_service(
+    writer: &storage::Writer,
+    adc: &mut ADS1115,
+    channels: &[&config::AdcChannelSpec],
+    calibrations: &HashMap<String, soil::Calibration>,
+    latest: &mut HashMap<String, (DateTime<Utc>, f64)>
+) -> error::Result<()> {
+
+    for channel in channels {
+        let (volts, taken_at) = match adc.read_voltage(channel).await {
+            Ok(reading) => (reading.value as f64, reading.taken_at),
+            Err(e) => {
+                logging::print(3, &format!("No measurement avail on ADC channel {}: {}", channel.name, e));
+                continue;
+            }
+        };
+        latest.insert(channel.name.clone(), (taken_at, volts));
+        let moisture = calibrations.get(&channel.name).map(|c| c.moisture(volts));
+        writer.send(storage::Write::Analog { at: taken_at, channel: channel.name.clone(), voltage: volts, moisture });
+        let shown = moisture.map(|m| format!(" ({:.0} % moisture)", m)).unwrap_or_default();
+        logging::send(6, &format!("Queued {} = {:1.4} V{} for the database", channel.name, volts, shown),
+            &[("SERVICE", &"analog"), ("EVENT", &"reading"), ("CHANNEL", &channel.name), ("VOLTS", &format!("{:.4}", volts))]);
+    }
+
     Ok(())
 }
 
 ///
-/// @brief runs the pump for a brief period of time and writes timestamp to log file 
+/// @brief runs the pump for a brief period of time (or through every irrigation zone),
+///        `boost` times as long on hot windy days or for `seconds` a zone if given, and
+///        queues timestamp and per-zone deliveries for the database
+///
+/// @note the start row is queued before the pump switches on, so a run that fails midway
+///       still counts as a watering for the schedule; its end time, duration and the
+///       deliveries are filled in together afterwards
+/// @note a run continuing the watering started at `session`, a later until-wet burst, adds
+///       to that watering's row instead of starting one, so the schedule counts from its first
+///
+/// @note `writer` is None in shadow mode, no water went in for the schedule or the usage to count
 ///
-async fn pump_service(client: &mut Client, pump: &mut OutputPin) -> Result<(), Box<dyn std::error::Error>> {
+/// @return when the watering started
+///
+async fn pump_service(
+    writer: Option<&storage::Writer>,
+    pump: &mut dyn Actuator,
+    zones: &mut [irrigation::Zone],
+    stagger: &mut actuator::Stagger,
+    seconds: Option<f64>,
+    boost: f64,
+    session: Option<DateTime<Utc>>
+) -> error::Result<DateTime<Utc>> {
 
-    let start_time = Utc::now();
-    let stmt = match client.prepare("INSERT INTO climate_data (timestamp, temperature, humidity, is_pump_start) VALUES ($1, NULL, NULL, TRUE);").await {
-        Ok(t) => t,
-        Err(e) => {
-            journal::print(3, &format!("Database prepare error {:?}", e));
-            return Err(Box::new(e));
-        }
-    };
+    let start_time = session.unwrap_or_else(Utc::now);
+    if let (Some(writer), None) = (writer, session) {
+        writer.send(storage::Write::PumpStart { at: start_time });
+    }
+    let run_start = Utc::now();
 
+    logging::send(6, &format!("Starting pump sequence at {}", Utc::now().with_timezone(&chrono::FixedOffset::west_opt(7*3600).expect("FixedOffset::west_opt fail")).format("%Y-%m-%d %H:%M:%S %Z")),
+        &[("SERVICE", &"pump"), ("EVENT", &"pump_sequence_start")]);
     
-    journal::print(6, &format!("Starting pump sequence at {}", Utc::now().with_timezone(&chrono::FixedOffset::west_opt(7*3600).expect("FixedOffset::west_opt fail")).format("%Y-%m-%d %H:%M:%S %Z")));
-    
-    run_pump_interval(pump, PUMP_DURATION_SECS).await?;
+    let deliveries = if zones.is_empty() {
+        run_pump_interval(pump, zones, stagger, seconds.unwrap_or(PUMP_DURATION_SECS * boost)).await?;
+        Vec::new()
+    } else {
+        irrigation::water(pump, zones, stagger, seconds, boost).await
+    };
 
-    journal::print(6, &format!("Ending pump sequence at {}", Utc::now().with_timezone(&chrono::FixedOffset::west_opt(7*3600).expect("FixedOffset::west_opt fail")).format("%Y-%m-%d %H:%M:%S %Z")));
+    logging::send(6, &format!("Ending pump sequence at {}", Utc::now().with_timezone(&chrono::FixedOffset::west_opt(7*3600).expect("FixedOffset::west_opt fail")).format("%Y-%m-%d %H:%M:%S %Z")),
+        &[("SERVICE", &"pump"), ("EVENT", &"pump_sequence_end")]);
 
-    let _ = match client.execute(&stmt, &[&start_time]).await {
-        Ok(t) => t,
-        Err(e) => {
-            journal::print(3, &format!("Database execute error {:?}", e));
-            return Err(Box::new(e));
-        }
-    };
+    let ended_at = Utc::now();
+    let seconds = (ended_at - run_start).num_milliseconds() as f64 / 1000.0;
+    if let Some(writer) = writer {
+        writer.send(storage::Write::PumpEnd { started_at: start_time, ended_at, seconds, deliveries });
+    }
 
-    Ok(())
+    Ok(start_time)
 }
 
 ///
-/// @brief Runs the pump for a specified duration in seconds by asserting the GPIO
+/// @brief Runs the pump for a specified duration in seconds by asserting the GPIO; with
+///        irrigation zones configured, each zone gets watered for that long in turn
 ///
-async fn run_pump_interval(pump: &mut OutputPin, seconds: u64) -> Result<(), Box<dyn std::error::Error>> {
-    pump.set_high();
-    sleep(TokioDuration::from_secs(seconds)).await;
-    pump.set_low();
+async fn run_pump_interval(
+    pump: &mut dyn Actuator,
+    zones: &mut [irrigation::Zone],
+    stagger: &mut actuator::Stagger,
+    seconds: f64
+) -> error::Result<()> {
+    if !zones.is_empty() {
+        irrigation::water(pump, zones, stagger, Some(seconds), 1.0).await;
+        return Ok(());
+    }
+
+    stagger.switch_on(pump).await;
+    sleep(actuator::run_time(seconds)).await;
+    actuator::switch_off(pump).await;
 
     Ok(())
 }
@@ -262,13 +2033,19 @@ async fn run_pump_interval(pump: &mut OutputPin, seconds: u64) -> Result<(), Box
 ///
 /// @brief gets the next pump service time based on pump log file timestamps 
 ///
-async fn get_next_pump_schedule(client: &mut Client) -> Result<DateTime<Utc>, Box<dyn std::error::Error>> {
-    let stmt = "SELECT MAX(timestamp) FROM climate_data WHERE is_pump_start = TRUE;";
+/// @return BonsaiError::Schedule if the last run is further in the future than a whole
+///         period, which means the clock was wrong when it was logged (or is now)
+///
+async fn get_next_pump_schedule(client: &mut Client) -> error::Result<DateTime<Utc>> {
+    let stmt = "SELECT MAX(timestamp) FROM climate_data WHERE is_pump_start = TRUE AND mine(device_id);";
     let rows = client.query(stmt, &[]).await?;
 
-    if let Some(row) = rows.get(0) {
-        let last_pump_time: DateTime<Utc> = row.get(0);
-        Ok(last_pump_time + Duration::hours(PUMP_PERIODIC_HRS))
+    if let Some(last_pump_time) = rows.first().and_then(|row| row.get::<_, Option<DateTime<Utc>>>(0)) {
+        let next = last_pump_time + Duration::hours(PUMP_PERIODIC_HRS);
+        if next > Utc::now() + Duration::hours(PUMP_PERIODIC_HRS) {
+            return Err(BonsaiError::Schedule(format!("last pump run logged at {}, in the future", last_pump_time)));
+        }
+        Ok(next)
     } else {
         // If no entry found, default to current time + pump interval
         Ok(Utc::now() + Duration::hours(PUMP_PERIODIC_HRS))
@@ -278,10 +2055,10 @@ async fn get_next_pump_schedule(client: &mut Client) -> Result<DateTime<Utc>, Bo
 ///
 /// @brief runs the fans for a brief period of time
 ///
-async fn fan_service(fan: &mut OutputPin) -> Result<(), Box<dyn std::error::Error>> {
-    fan.set_high();
-    sleep(TokioDuration::from_secs(FAN_DURATION_SECS)).await;
-    fan.set_low();
+async fn fan_service(fan: &mut dyn Actuator, stagger: &mut actuator::Stagger) -> error::Result<()> {
+    stagger.switch_on(fan).await;
+    sleep(actuator::run_time(FAN_DURATION_SECS)).await;
+    fan.set_off();
 
     Ok(())
 }
@@ -289,11 +2066,13 @@ async fn fan_service(fan: &mut OutputPin) -> Result<(), Box<dyn std::error::Erro
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
 
     #[tokio::test]
     pub async fn test_pump() {
-        let gpio = Gpio::new().expect("Cannot get access to GPIO");
-        let mut pump_gpio = gpio.get(PUMP_PIN).expect("GPIO cannot be taken").into_output(); 
-        run_pump_interval(&mut pump_gpio, 10).await.expect("Pump did not run"); 
+        let platform = hal::RppalPlatform::new().expect("Cannot get access to GPIO");
+        let mut pump_gpio = pins::claim(&platform, &HashMap::new(), "pump", &Config::default().pins.pump).expect("GPIO cannot be taken");
+        let mut stagger = actuator::Stagger::new(TokioDuration::from_millis(250));
+        run_pump_interval(pump_gpio.as_mut(), &mut [], &mut stagger, 10.0).await.expect("Pump did not run"); 
     }
 }
@@ -0,0 +1,126 @@
+use crate::audit::Origin;
+use crate::config::ConsumableSpec;
+use crate::error::{self, BonsaiError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Client;
+
+///
+/// @brief how far a consumable is through its service interval
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsumableStatus {
+    pub name: String,
+    pub serviced_at: DateTime<Utc>,
+    pub days: f64,
+    pub every_days: Option<u32>,
+    pub runtime_hours: Option<f64>,
+    pub every_runtime_hours: Option<f64>,
+    pub due: bool,
+}
+
+///
+/// @brief due once either interval has passed
+///
+pub fn is_due(spec: &ConsumableSpec, days: f64, runtime_hours: Option<f64>) -> bool {
+    let calendar = spec.every_days.is_some_and(|every| days >= every as f64);
+    let runtime = matches!((spec.every_runtime_hours, runtime_hours), (Some(every), Some(hours)) if hours >= every);
+    calendar || runtime
+}
+
+///
+/// @brief starts the clock on consumables which have never been serviced
+///
+pub async fn ensure_tracked(client: &Client, specs: &[ConsumableSpec]) -> error::Result<()> {
+    for spec in specs {
+        if spec.every_runtime_hours.is_some() && spec.actuator.is_none() {
+            return Err(BonsaiError::Config(format!("consumable {} has every_runtime_hours but no actuator", spec.name)));
+        }
+        client.execute("INSERT INTO maintenance_log (consumable, source, note)
+            SELECT $1, 'config', 'tracking started'
+            WHERE NOT EXISTS (SELECT 1 FROM maintenance_log WHERE consumable = $1 AND mine(device_id))", &[&spec.name]).await?;
+    }
+    Ok(())
+}
+
+///
+/// @brief where every configured consumable stands
+///
+pub async fn status(client: &Client, specs: &[ConsumableSpec]) -> error::Result<Vec<ConsumableStatus>> {
+    let now = Utc::now();
+    let mut consumables = Vec::with_capacity(specs.len());
+
+    for spec in specs {
+        let serviced_at: DateTime<Utc> = client.query_one("SELECT COALESCE(MAX(serviced_at), now()) FROM maintenance_log WHERE consumable = $1 AND mine(device_id)",
+            &[&spec.name]).await?.get(0);
+        let runtime_hours = match &spec.actuator {
+            Some(actuator) => Some(client.query_one("SELECT COALESCE(SUM(EXTRACT(EPOCH FROM next - timestamp)), 0)::float8 / 3600.0 FROM (
+                    SELECT timestamp, is_on, LEAD(timestamp) OVER (ORDER BY timestamp) AS next
+                    FROM actuator_events WHERE actuator = $1 AND timestamp >= $2 AND mine(device_id)
+                ) runs WHERE is_on AND next IS NOT NULL", &[actuator, &serviced_at]).await?.get(0)),
+            None => None,
+        };
+        let days = (now - serviced_at).num_minutes() as f64 / (24.0 * 60.0);
+
+        consumables.push(ConsumableStatus {
+            name: spec.name.clone(),
+            serviced_at,
+            days,
+            every_days: spec.every_days,
+            runtime_hours,
+            every_runtime_hours: spec.every_runtime_hours,
+            due: is_due(spec, days, runtime_hours),
+        });
+    }
+
+    Ok(consumables)
+}
+
+///
+/// @brief resets a consumable's intervals, recording who serviced it
+///
+pub async fn mark_serviced(client: &Client, specs: &[ConsumableSpec], name: &str, origin: &Origin) -> error::Result<()> {
+    known(specs, name)?;
+    record_serviced(client, name, origin).await?;
+    Ok(())
+}
+
+///
+/// @brief refuses a consumable the config doesn't track
+///
+pub fn known(specs: &[ConsumableSpec], name: &str) -> error::Result<()> {
+    match specs.iter().any(|spec| spec.name == name) {
+        true => Ok(()),
+        false => Err(BonsaiError::Config(format!("no consumable named {}", name))),
+    }
+}
+
+///
+/// @brief the maintenance_log row for a service, `known` has checked the name
+///
+pub async fn record_serviced(client: &Client, name: &str, origin: &Origin) -> Result<(), tokio_postgres::Error> {
+    client.execute("INSERT INTO maintenance_log (consumable, source, identity) VALUES ($1, $2, $3)",
+        &[&name, &origin.source.as_str(), &origin.identity]).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_is_due() {
+        let wick = ConsumableSpec {
+            name: "humidifier wick".to_string(),
+            every_days: Some(30),
+            every_runtime_hours: Some(200.0),
+            actuator: Some("humidifier".to_string()),
+        };
+        assert!(!is_due(&wick, 12.0, Some(80.0)));
+        assert!(is_due(&wick, 31.0, Some(80.0)));
+        assert!(is_due(&wick, 12.0, Some(210.0)));
+
+        let filter = ConsumableSpec { name: "fan filter".to_string(), every_days: None, every_runtime_hours: None, actuator: None };
+        assert!(!is_due(&filter, 1000.0, None));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::config::HttpConfig;
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::error::Error;
+
+//
+// @brief  DNS-SD service type companion apps browse for
+//
+pub const SERVICE_TYPE: &str = "_bonsaibot._tcp.local.";
+
+///
+/// @brief announces the HTTP API on the LAN as `<hostname>._bonsaibot._tcp`, answering on
+///        every interface with whatever addresses it currently has
+///
+/// @note the returned daemon keeps answering queries until it is dropped
+///
+pub fn advertise(http: &HttpConfig) -> Result<ServiceDaemon, Box<dyn Error>> {
+    let port = port(&http.listen).ok_or_else(|| format!("No port in http.listen {}", http.listen))?;
+    let host = std::fs::read_to_string("/etc/hostname")
+        .map(|h| h.trim().to_string())
+        .ok()
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "bonsai-bot".to_string());
+
+    let properties = [
+        ("version", env!("CARGO_PKG_VERSION")),
+        ("scheme", if http.tls.is_some() { "https" } else { "http" }),
+        ("auth", if http.tokens.is_empty() { "none" } else { "token" }),
+    ];
+    let info = ServiceInfo::new(SERVICE_TYPE, &host, &format!("{}.local.", host), (), port, &properties[..])?
+        .enable_addr_auto();
+
+    let daemon = ServiceDaemon::new()?;
+    daemon.register(info)?;
+    Ok(daemon)
+}
+
+///
+/// @brief the port of a `host:port` listen address
+///
+fn port(listen: &str) -> Option<u16> {
+    listen.rsplit_once(':')?.1.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_port() {
+        assert_eq!(port("0.0.0.0:8080"), Some(8080));
+        assert_eq!(port("[::]:8443"), Some(8443));
+        assert_eq!(port("localhost"), None);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::logging;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use tokio::time::{Duration, Instant};
+
+//
+// @brief  a tick starting later than this behind schedule gets logged
+//
+const DRIFT_WARN: Duration = Duration::from_secs(5);
+
+///
+/// @brief timing of one periodic service
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServiceTiming {
+    pub ticks: u64,
+    pub overruns: u64,
+    pub timeouts: u64,
+    pub last_duration: Duration,
+    pub max_duration: Duration,
+    pub last_drift: Duration,
+    pub max_drift: Duration,
+}
+
+//
+// @brief  metric name, type, help text and how to read it off a ServiceTiming
+//
+type Family = (&'static str, &'static str, &'static str, fn(&ServiceTiming) -> f64);
+
+///
+/// @brief in-process service metrics, rendered in the Prometheus text format
+///
+#[derive(Debug, Default)]
+pub struct Metrics {
+    services: BTreeMap<&'static str, ServiceTiming>,
+    // seconds switched on and liters delivered per actuator, since records began
+    usage: BTreeMap<String, (f64, f64)>,
+    // readings and events the storage writer dropped while the database fell behind
+    writes_dropped: u64,
+    // readings compact storage left out as within their deadband
+    writes_compacted: u64,
+}
+
+impl Metrics {
+
+    ///
+    /// @brief records a finished tick; `scheduled` is the instant the interval meant to fire
+    ///        (None for ad hoc runs, which have no schedule to drift from)
+    ///
+    pub fn record_tick(&mut self, service: &'static str, scheduled: Option<Instant>, started: Instant, period: Duration) {
+        let duration = started.elapsed();
+        let drift = scheduled.map(|s| started.saturating_duration_since(s)).unwrap_or_default();
+        let timing = self.services.entry(service).or_default();
+
+        timing.ticks += 1;
+        timing.last_duration = duration;
+        timing.max_duration = timing.max_duration.max(duration);
+        timing.last_drift = drift;
+        timing.max_drift = timing.max_drift.max(drift);
+
+        if duration > period {
+            timing.overruns += 1;
+            logging::send(4, &format!("{} tick took {:.1} s, longer than its {} s interval",
+                service, duration.as_secs_f64(), period.as_secs()),
+                &[("SERVICE", &service), ("EVENT", &"tick_overrun"), ("DURATION_SECS", &format!("{:.1}", duration.as_secs_f64()))]);
+        }
+        if drift > DRIFT_WARN {
+            logging::send(4, &format!("{} tick started {:.1} s behind schedule", service, drift.as_secs_f64()),
+                &[("SERVICE", &service), ("EVENT", &"tick_late"), ("DRIFT_SECS", &format!("{:.1}", drift.as_secs_f64()))]);
+        }
+    }
+
+    ///
+    /// @brief records a tick abandoned for running past its budget
+    ///
+    pub fn record_timeout(&mut self, service: &'static str, budget: Duration) {
+        self.services.entry(service).or_default().timeouts += 1;
+        logging::send(3, &format!("{} tick abandoned after its {} s budget", service, budget.as_secs()),
+            &[("SERVICE", &service), ("EVENT", &"tick_timeout"), ("BUDGET_SECS", &budget.as_secs())]);
+    }
+
+    ///
+    /// @brief adds to an actuator's runtime and water totals; the daemon seeds these from
+    ///        usage_monthly at startup so they survive restarts
+    ///
+    pub fn add_usage(&mut self, actuator: &str, seconds: f64, liters: f64) {
+        let usage = self.usage.entry(actuator.to_string()).or_default();
+        usage.0 += seconds;
+        usage.1 += liters;
+    }
+
+    pub fn set_writes_dropped(&mut self, dropped: u64) {
+        self.writes_dropped = dropped;
+    }
+
+    pub fn set_writes_compacted(&mut self, compacted: u64) {
+        self.writes_compacted = compacted;
+    }
+
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        let families: [Family; 7] = [
+            ("bonsaibot_service_ticks_total", "counter", "Service ticks run", |t| t.ticks as f64),
+            ("bonsaibot_service_overruns_total", "counter", "Ticks which took longer than the service interval", |t| t.overruns as f64),
+            ("bonsaibot_service_timeouts_total", "counter", "Ticks abandoned for running past their budget", |t| t.timeouts as f64),
+            ("bonsaibot_service_duration_seconds", "gauge", "Duration of the last tick", |t| t.last_duration.as_secs_f64()),
+            ("bonsaibot_service_duration_max_seconds", "gauge", "Longest tick since start", |t| t.max_duration.as_secs_f64()),
+            ("bonsaibot_service_drift_seconds", "gauge", "How late the last tick started", |t| t.last_drift.as_secs_f64()),
+            ("bonsaibot_service_drift_max_seconds", "gauge", "Worst tick start delay since start", |t| t.max_drift.as_secs_f64()),
+        ];
+
+        for (name, kind, help, value) in families {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (service, timing) in &self.services {
+                let _ = writeln!(out, "{}{{service=\"{}\"}} {}", name, service, value(timing));
+            }
+        }
+
+        let _ = writeln!(out, "# HELP bonsaibot_actuator_seconds_total Time switched on since records began");
+        let _ = writeln!(out, "# TYPE bonsaibot_actuator_seconds_total counter");
+        for (actuator, (seconds, _)) in &self.usage {
+            let _ = writeln!(out, "bonsaibot_actuator_seconds_total{{actuator=\"{}\"}} {}", actuator, seconds);
+        }
+        let _ = writeln!(out, "# HELP bonsaibot_water_liters_total Water pumped since records began");
+        let _ = writeln!(out, "# TYPE bonsaibot_water_liters_total counter");
+        let _ = writeln!(out, "bonsaibot_water_liters_total {}", self.usage.values().map(|(_, liters)| liters).sum::<f64>());
+        let _ = writeln!(out, "# HELP bonsaibot_storage_dropped_total Readings and events dropped because the database fell behind");
+        let _ = writeln!(out, "# TYPE bonsaibot_storage_dropped_total counter");
+        let _ = writeln!(out, "bonsaibot_storage_dropped_total {}", self.writes_dropped);
+        let _ = writeln!(out, "# HELP bonsaibot_storage_compacted_total Readings compact storage left out as within their deadband");
+        let _ = writeln!(out, "# TYPE bonsaibot_storage_compacted_total counter");
+        let _ = writeln!(out, "bonsaibot_storage_compacted_total {}", self.writes_compacted);
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_record_tick() {
+        let mut metrics = Metrics::default();
+        let started = Instant::now() - Duration::from_secs(2);
+        metrics.record_tick("fan", Some(started - Duration::from_millis(300)), started, Duration::from_secs(1));
+
+        let fan = &metrics.services["fan"];
+        assert_eq!(fan.ticks, 1);
+        assert_eq!(fan.overruns, 1);
+        assert_eq!(fan.last_drift, Duration::from_millis(300));
+        assert!(metrics.render_prometheus().contains("bonsaibot_service_overruns_total{service=\"fan\"} 1\n"));
+
+        metrics.record_timeout("climate", Duration::from_secs(30));
+        assert_eq!(metrics.services["climate"].timeouts, 1);
+        assert!(metrics.render_prometheus().contains("bonsaibot_service_timeouts_total{service=\"climate\"} 1\n"));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::config::PlantSensorSpec;
+use crate::db;
+use crate::logging;
+use btleplug::api::{bleuuid::uuid_from_u16, Central, CentralEvent, Manager as _, Peripheral as _, ScanFilter};
+use btleplug::platform::Manager;
+use chrono::Utc;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+//
+// @brief  MiBeacon service data UUID Xiaomi sensors advertise under
+//
+const MIBEACON_UUID: u16 = 0xfe95;
+
+//
+// @brief  a Mi Flora advertises every few seconds, one value at a time; store each value at
+//         most this often
+//
+const STORE_EVERY_SECS: u64 = 600;
+
+//
+// @brief  MiBeacon frame control flags
+//
+const FLAG_ENCRYPTED: u16 = 0x0008;
+const FLAG_MAC: u16 = 0x0010;
+const FLAG_CAPABILITY: u16 = 0x0020;
+const FLAG_OBJECT: u16 = 0x0040;
+
+///
+/// @brief one value decoded from a MiBeacon advertisement
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Measurement {
+    Temperature(f64),
+    Moisture(f64),
+    Illuminance(f64),
+    Conductivity(f64),
+    Battery(f64),
+}
+
+impl Measurement {
+    pub fn metric(&self) -> &'static str {
+        match self {
+            Measurement::Temperature(_) => "temperature",
+            Measurement::Moisture(_) => "moisture",
+            Measurement::Illuminance(_) => "illuminance",
+            Measurement::Conductivity(_) => "conductivity",
+            Measurement::Battery(_) => "battery",
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        match *self {
+            Measurement::Temperature(v) | Measurement::Moisture(v) | Measurement::Illuminance(v)
+                | Measurement::Conductivity(v) | Measurement::Battery(v) => v,
+        }
+    }
+}
+
+///
+/// @brief decodes the object in an unencrypted MiBeacon service data payload
+///
+/// @return None for frames without an object, encrypted ones, and objects a plant sensor
+///         doesn't send
+///
+pub fn parse(data: &[u8]) -> Option<Measurement> {
+    let control = u16::from_le_bytes([*data.first()?, *data.get(1)?]);
+    if control & FLAG_OBJECT == 0 || control & FLAG_ENCRYPTED != 0 {
+        return None;
+    }
+    // frame control, product id, frame counter, then the optional MAC and capability
+    let mut i = 5;
+    if control & FLAG_MAC != 0 {
+        i += 6;
+    }
+    if control & FLAG_CAPABILITY != 0 {
+        i += 1;
+    }
+    let object = u16::from_le_bytes([*data.get(i)?, *data.get(i + 1)?]);
+    let len = *data.get(i + 2)? as usize;
+    let value = data.get(i + 3..i + 3 + len)?;
+
+    match (object, value) {
+        (0x1004, [lo, hi]) => Some(Measurement::Temperature(i16::from_le_bytes([*lo, *hi]) as f64 / 10.0)),
+        (0x1007, [a, b, c]) => Some(Measurement::Illuminance(u32::from_le_bytes([*a, *b, *c, 0]) as f64)),
+        (0x1008, [moisture]) => Some(Measurement::Moisture(*moisture as f64)),
+        (0x1009, [lo, hi]) => Some(Measurement::Conductivity(u16::from_le_bytes([*lo, *hi]) as f64)),
+        (0x100a, [battery]) => Some(Measurement::Battery(*battery as f64)),
+        _ => None,
+    }
+}
+
+///
+/// @brief scans for the configured sensors' advertisements and stores what they report in
+///        plant_data, tagged with the sensor's zone
+///
+pub async fn spawn(sensors: Vec<PlantSensorSpec>) -> Result<(), Box<dyn Error>> {
+    let manager = Manager::new().await?;
+    let adapter = manager.adapters().await?.into_iter().next().ok_or("No Bluetooth adapter found")?;
+    let mut events = adapter.events().await?;
+    adapter.start_scan(ScanFilter::default()).await?;
+    let (client, connection) = db::establish_connection().await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            logging::print(3, &format!("Plant sensor connection error: {}", e));
+        }
+    });
+
+    tokio::spawn(async move {
+        let mibeacon = uuid_from_u16(MIBEACON_UUID);
+        let mut stored: HashMap<(String, &'static str), Instant> = HashMap::new();
+
+        while let Some(event) = events.next().await {
+            let CentralEvent::ServiceDataAdvertisement { id, service_data } = event else {
+                continue;
+            };
+            let Some(measurement) = service_data.get(&mibeacon).and_then(|data| parse(data)) else {
+                continue;
+            };
+            let Ok(address) = adapter.peripheral(&id).await.map(|p| p.address().to_string()) else {
+                continue;
+            };
+            let Some(sensor) = sensors.iter().find(|s| s.address.eq_ignore_ascii_case(&address)) else {
+                continue;
+            };
+
+            let key = (sensor.name.clone(), measurement.metric());
+            if stored.get(&key).is_some_and(|at| at.elapsed() < Duration::from_secs(STORE_EVERY_SECS)) {
+                continue;
+            }
+            stored.insert(key, Instant::now());
+
+            if let Err(e) = client.execute("INSERT INTO plant_data (timestamp, sensor, zone, metric, value) VALUES ($1, $2, $3, $4, $5)",
+                    &[&Utc::now(), &sensor.name, &sensor.zone, &measurement.metric(), &measurement.value()]).await {
+                logging::print(3, &format!("Database execute error {:?}", e));
+            }
+            logging::send(6, &format!("{} {} = {}", sensor.name, measurement.metric(), measurement.value()),
+                &[("SERVICE", &"plant"), ("EVENT", &"reading"), ("SENSOR", &sensor.name),
+                  ("METRIC", &measurement.metric()), ("VALUE", &measurement.value())]);
+        }
+        logging::print(4, "Bluetooth scan ended, plant sensors are no longer read");
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_parse() {
+        // frame control 0x2071: object, capability and MAC present; product 0x0098 (Mi Flora)
+        let header = [0x71, 0x20, 0x98, 0x00, 0x42, 0x34, 0x12, 0x6a, 0x8d, 0x7c, 0xc4, 0x0d];
+        let frame = |object: &[u8]| [&header[..], object].concat();
+
+        assert_eq!(parse(&frame(&[0x04, 0x10, 0x02, 0xd2, 0x00])), Some(Measurement::Temperature(21.0)));
+        assert_eq!(parse(&frame(&[0x08, 0x10, 0x01, 0x2a])), Some(Measurement::Moisture(42.0)));
+        assert_eq!(parse(&frame(&[0x07, 0x10, 0x03, 0x10, 0x27, 0x00])), Some(Measurement::Illuminance(10000.0)));
+        assert_eq!(parse(&frame(&[0x09, 0x10, 0x02, 0x5e, 0x01])), Some(Measurement::Conductivity(350.0)));
+        assert_eq!(parse(&frame(&[0x09, 0x10, 0x02, 0x5e])), None);
+        assert_eq!(parse(&[0x30, 0x20, 0x98, 0x00, 0x42]), None);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::config::MistingConfig;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+
+///
+/// @brief what a misting tick decided
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mist {
+    Pulse,
+    Humid,
+    NoReading,
+    HourlyLimit,
+    DailyLimit,
+}
+
+///
+/// @brief decides when a misting nozzle fires; unlike the humidifier it is never left on,
+///        each pulse is a fixed `pulse_secs` and the pulses are capped per hour and day
+///
+pub struct Mister {
+    pulse_secs: f64,
+    below_rh: Option<f64>,
+    max_pulses_per_hour: usize,
+    max_secs_per_day: f64,
+    fired: VecDeque<DateTime<Utc>>,
+}
+
+impl Mister {
+    pub fn new(config: &MistingConfig) -> Mister {
+        Mister {
+            pulse_secs: config.pulse_secs,
+            below_rh: config.below_rh,
+            max_pulses_per_hour: config.max_pulses_per_hour as usize,
+            max_secs_per_day: config.max_secs_per_day,
+            fired: VecDeque::new(),
+        }
+    }
+
+    pub fn pulse_secs(&self) -> f64 {
+        self.pulse_secs
+    }
+
+    ///
+    /// @brief whether to fire now, given the latest RH (None when there is no recent
+    ///        reading) and the humidifier's `rh_low` for when no `below_rh` is configured
+    ///
+    pub fn check(&mut self, now: DateTime<Utc>, rh: Option<f64>, rh_low: f64) -> Mist {
+        while self.fired.front().is_some_and(|at| now - *at >= Duration::days(1)) {
+            self.fired.pop_front();
+        }
+        let Some(rh) = rh else {
+            // misting blind is how pots get flooded
+            return Mist::NoReading;
+        };
+        if rh >= self.below_rh.unwrap_or(rh_low) {
+            return Mist::Humid;
+        }
+        if self.fired.iter().filter(|at| now - **at < Duration::hours(1)).count() >= self.max_pulses_per_hour {
+            return Mist::HourlyLimit;
+        }
+        if (self.fired.len() + 1) as f64 * self.pulse_secs > self.max_secs_per_day {
+            return Mist::DailyLimit;
+        }
+        Mist::Pulse
+    }
+
+    pub fn fired(&mut self, at: DateTime<Utc>) {
+        self.fired.push_back(at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    pub fn test_mister() {
+        let config = Config::parse(r#"
+            [misting]
+            valve               = { pin = 23 }
+            pulse_secs          = 3
+            max_pulses_per_hour = 2
+            max_secs_per_day    = 9
+        "#).expect("Config did not parse").misting.unwrap();
+        let mut mister = Mister::new(&config);
+        let t0 = Utc::now();
+        let at = |mins| t0 + Duration::minutes(mins);
+
+        assert_eq!(mister.check(at(0), None, 50.0), Mist::NoReading);
+        assert_eq!(mister.check(at(0), Some(55.0), 50.0), Mist::Humid);
+        assert_eq!(mister.check(at(0), Some(45.0), 50.0), Mist::Pulse);
+        mister.fired(at(0));
+        mister.fired(at(20));
+        assert_eq!(mister.check(at(40), Some(45.0), 50.0), Mist::HourlyLimit);
+        assert_eq!(mister.check(at(60), Some(45.0), 50.0), Mist::Pulse);
+        mister.fired(at(60));
+        assert_eq!(mister.check(at(140), Some(45.0), 50.0), Mist::DailyLimit);
+        assert_eq!(mister.check(at(24 * 60 + 20), Some(45.0), 50.0), Mist::Pulse);
+    }
+}
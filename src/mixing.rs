@@ -0,0 +1,57 @@
+use crate::config::HumidifierConfig;
+use tokio::time::{sleep_until, Duration, Instant};
+
+///
+/// @brief schedules a fan run a while after the humidifier switches on, to stir the mist
+///        through the enclosure before the sensor next the mister reads it as saturated
+///
+pub struct MixKick {
+    after: Option<Duration>,
+    at: Option<Instant>,
+}
+
+impl MixKick {
+    pub fn new(config: &HumidifierConfig) -> MixKick {
+        MixKick {
+            after: config.mix_fan_after_secs.map(Duration::from_secs),
+            at: None,
+        }
+    }
+
+    ///
+    /// @brief the humidifier just switched on; a kick already pending keeps its time
+    ///
+    pub fn humidifier_on(&mut self) {
+        if let Some(after) = self.after {
+            self.at.get_or_insert(Instant::now() + after);
+        }
+    }
+
+    ///
+    /// @brief waits for the pending kick; never completes while none is pending
+    ///
+    pub async fn tick(&mut self) {
+        let Some(at) = self.at else {
+            return std::future::pending().await;
+        };
+        sleep_until(at).await;
+        self.at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_mix_kick() {
+        let mut kick = MixKick::new(&HumidifierConfig::default());
+        kick.humidifier_on();
+        assert!(tokio::time::timeout(Duration::from_millis(50), kick.tick()).await.is_err());
+
+        let mut kick = MixKick { after: Some(Duration::from_millis(20)), at: None };
+        kick.humidifier_on();
+        assert!(tokio::time::timeout(Duration::from_millis(100), kick.tick()).await.is_ok());
+        assert!(tokio::time::timeout(Duration::from_millis(50), kick.tick()).await.is_err());
+    }
+}
@@ -0,0 +1,540 @@
+use crate::config::{NotifierSpec, NotifyConfig, Severity, WebhookSpec};
+use crate::events::{Bus, Event};
+use crate::ipc::Response;
+use crate::logging;
+use crate::webhooks;
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, oneshot};
+
+const TIMEOUT_SECS: u64 = 10;
+
+//
+// @brief  how often pending alerts are held against the escalations
+//
+const ESCALATE_EVERY_SECS: u64 = 30;
+
+//
+// @brief  how long a Telegram poll for /ack waits for a message before asking again
+//
+const POLL_SECS: u64 = 50;
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+///
+/// @brief an alert as one route renders it for a notifier
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub kind: &'static str,
+    pub severity: Severity,
+    pub title: String,
+    pub body: String,
+    pub event: Event,
+}
+
+///
+/// @brief somewhere alerts can be sent
+///
+/// @note `send` blocks, the router runs each on the blocking pool so a slow backend doesn't
+///       hold up the others
+///
+pub trait Notifier: Send + Sync {
+    fn send(&self, message: &Message) -> Result<(), String>;
+}
+
+struct Ntfy {
+    agent: ureq::Agent,
+    url: String,
+    token: Option<String>,
+}
+
+impl Notifier for Ntfy {
+    fn send(&self, message: &Message) -> Result<(), String> {
+        let priority = match message.severity {
+            Severity::Critical => "urgent",
+            Severity::Warning => "high",
+            Severity::Info => "default",
+        };
+        let mut request = self.agent.post(&self.url)
+            .set("Title", &message.title)
+            .set("Priority", priority)
+            .set("Tags", message.kind);
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        request.send_string(&message.body).map(|_| ()).map_err(|e| format!("{}: {}", self.url, e))
+    }
+}
+
+struct Telegram {
+    agent: ureq::Agent,
+    bot_token: String,
+    chat_id: String,
+}
+
+// ureq's errors quote the URL, which carries the bot token
+fn telegram_error(e: ureq::Error) -> String {
+    match e {
+        ureq::Error::Status(code, _) => format!("Telegram answered {}", code),
+        ureq::Error::Transport(e) => format!("Telegram unreachable, {}", e.kind()),
+    }
+}
+
+fn telegram_send(agent: &ureq::Agent, bot_token: &str, chat_id: &str, text: &str) -> Result<(), String> {
+    agent.post(&format!("https://api.telegram.org/bot{}/sendMessage", bot_token))
+        .send_json(serde_json::json!({ "chat_id": chat_id, "text": text }))
+        .map(|_| ())
+        .map_err(telegram_error)
+}
+
+impl Notifier for Telegram {
+    fn send(&self, message: &Message) -> Result<(), String> {
+        telegram_send(&self.agent, &self.bot_token, &self.chat_id, &format!("{}\n{}", message.title, message.body))
+    }
+}
+
+struct Email {
+    to: String,
+    from: String,
+    command: Vec<String>,
+}
+
+impl Notifier for Email {
+    fn send(&self, message: &Message) -> Result<(), String> {
+        let program = self.command.first().ok_or("notify email command is empty")?;
+        let mut child = Command::new(program).args(&self.command[1..])
+            .stdin(Stdio::piped())
+            .spawn().map_err(|e| format!("{}: {}", program, e))?;
+        let mail = format!("To: {}\nFrom: {}\nSubject: {}\n\n{}\n", self.to, self.from, message.title, message.body);
+        let written = child.stdin.take().unwrap().write_all(mail.as_bytes());
+        let status = child.wait().map_err(|e| format!("{}: {}", program, e))?;
+        written.map_err(|e| format!("{}: {}", program, e))?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(format!("{} exited with {}", program, status)),
+        }
+    }
+}
+
+struct Webhook {
+    agent: ureq::Agent,
+    hook: WebhookSpec,
+}
+
+impl Notifier for Webhook {
+    fn send(&self, message: &Message) -> Result<(), String> {
+        let body = serde_json::to_vec(&message.event).map_err(|e| e.to_string())?;
+        webhooks::deliver(&self.agent, &self.hook, "alert", &body)
+    }
+}
+
+fn build(spec: &NotifierSpec, agent: &ureq::Agent) -> Arc<dyn Notifier> {
+    match spec.clone() {
+        NotifierSpec::Ntfy { url, token, .. } => Arc::new(Ntfy { agent: agent.clone(), url, token }),
+        NotifierSpec::Telegram { bot_token, chat_id, .. } => Arc::new(Telegram { agent: agent.clone(), bot_token, chat_id }),
+        NotifierSpec::Email { to, from, command, .. } => Arc::new(Email { to, from, command }),
+        NotifierSpec::Webhook { url, secret, retries, .. } => Arc::new(Webhook {
+            agent: agent.clone(),
+            hook: WebhookSpec { url, events: vec!["alert".to_string()], secret, retries },
+        }),
+    }
+}
+
+///
+/// @brief how urgent an alert or summary kind is unless [notify.severity] says otherwise:
+///        critical when plants or hardware are at risk right now, info when it can wait for
+///        the weekend
+///
+pub fn default_severity(kind: &str) -> Severity {
+    match kind {
+        "heater_runaway" | "stuck_on" | "temperature" | "temperature_rate" | "sensor_failed" | "crash_loop" => Severity::Critical,
+        "maintenance_due" | "update_failed" | "weekly_stability" => Severity::Info,
+        _ => Severity::Warning,
+    }
+}
+
+///
+/// @brief fills the {field}s of `template`; unknown ones are left as they are
+///
+pub fn render(template: &str, fields: &[(&str, &str)]) -> String {
+    fields.iter().fold(template.to_string(), |text, (field, value)| text.replace(&format!("{{{}}}", field), value))
+}
+
+///
+/// @brief an alert waiting to be acknowledged
+///
+struct Pending {
+    at: DateTime<Utc>,
+    severity: Severity,
+    title: String,
+    event: Event,
+    // the escalations already sent, by index
+    escalated: Vec<usize>,
+}
+
+///
+/// @brief decides which notifiers an alert goes to and what they say, and which to try
+///        next while nobody acknowledges it
+///
+pub struct Router {
+    config: NotifyConfig,
+    device: String,
+    sent: HashMap<&'static str, DateTime<Utc>>,
+    pending: BTreeMap<&'static str, Pending>,
+}
+
+impl Router {
+    pub fn new(config: &NotifyConfig, device: &str) -> Router {
+        Router { config: config.clone(), device: device.to_string(), sent: HashMap::new(), pending: BTreeMap::new() }
+    }
+
+    pub fn severity(&self, kind: &str) -> Severity {
+        self.config.severity.get(kind).copied().unwrap_or_else(|| default_severity(kind))
+    }
+
+    ///
+    /// @return the notifiers `event` goes to, each once, with its message; nothing for events
+    ///         other than alerts and summaries and for a kind already sent within dedup_mins
+    ///
+    /// @note an alert an escalation applies to is pending from here, until acknowledged, even
+    ///       when dedup_mins holds back the message itself; summaries are only routed
+    ///
+    pub fn route(&mut self, event: &Event) -> Vec<(String, Message)> {
+        let (at, kind, message, alert) = match event {
+            Event::Alert { at, kind, message } => (at, kind, message, true),
+            Event::Summary { at, kind, message } => (at, kind, message, false),
+            _ => return Vec::new(),
+        };
+        let severity = self.severity(kind);
+        let title = format!("{} {}: {}", self.device, severity.name(), kind);
+        // a kind raised again while pending keeps escalating from the first time
+        if alert && self.config.escalations.iter().any(|e| severity >= e.severity) && !self.pending.contains_key(kind) {
+            self.pending.insert(kind, Pending { at: *at, severity, title: title.clone(), event: event.clone(), escalated: Vec::new() });
+        }
+        if self.sent.get(kind).is_some_and(|last| *at - *last < Duration::minutes(self.config.dedup_mins as i64)) {
+            return Vec::new();
+        }
+
+        let at_text = at.to_rfc3339();
+        let fields = [("kind", *kind), ("severity", severity.name()), ("message", message.as_str()),
+            ("device", self.device.as_str()), ("at", at_text.as_str())];
+        let mut sends: Vec<(String, Message)> = Vec::new();
+        for route in &self.config.routes {
+            if severity < route.severity || !(route.kinds.is_empty() || route.kinds.iter().any(|k| k == kind)) {
+                continue;
+            }
+            let body = render(&route.template, &fields);
+            for name in &route.to {
+                if !sends.iter().any(|(sent, _)| sent == name) {
+                    sends.push((name.clone(), Message { kind, severity, title: title.clone(), body: body.clone(), event: event.clone() }));
+                }
+            }
+        }
+        if !sends.is_empty() {
+            self.sent.insert(kind, *at);
+        }
+        sends
+    }
+
+    ///
+    /// @return the escalations due by `now` for the alerts still pending, each sent once
+    ///
+    pub fn escalate(&mut self, now: DateTime<Utc>) -> Vec<(String, Message)> {
+        let mut sends = Vec::new();
+        for (kind, pending) in &mut self.pending {
+            for (i, escalation) in self.config.escalations.iter().enumerate() {
+                if pending.severity < escalation.severity || pending.escalated.contains(&i)
+                        || now - pending.at < Duration::minutes(escalation.after_mins as i64) {
+                    continue;
+                }
+                pending.escalated.push(i);
+                let Event::Alert { message, .. } = &pending.event else {
+                    continue;
+                };
+                let body = format!("{}\nNot acknowledged for {} min, reply /ack {} or run `bonsai-bot ack {}`",
+                    message, (now - pending.at).num_minutes(), kind, kind);
+                for name in &escalation.to {
+                    sends.push((name.clone(), Message {
+                        kind,
+                        severity: pending.severity,
+                        title: format!("{} (unacknowledged)", pending.title),
+                        body: body.clone(),
+                        event: pending.event.clone(),
+                    }));
+                }
+            }
+        }
+        sends
+    }
+
+    ///
+    /// @return the kinds acknowledged: `kind` if it was pending, every pending one for None
+    ///
+    pub fn ack(&mut self, kind: Option<&str>) -> Vec<&'static str> {
+        let kinds: Vec<&'static str> = self.pending.keys().copied().filter(|k| kind.is_none_or(|kind| kind == *k)).collect();
+        for kind in &kinds {
+            self.pending.remove(kind);
+        }
+        kinds
+    }
+}
+
+///
+/// @brief acknowledges the pending alert of `kind`, or every one, so it stops escalating;
+///        `done` gets the kinds that were pending
+///
+pub struct Ack {
+    pub kind: Option<String>,
+    pub done: oneshot::Sender<Vec<&'static str>>,
+}
+
+///
+/// @brief the daemon's answer to `bonsai-bot ack` and `POST /alerts/ack`
+///
+pub async fn acknowledge(acks: &mpsc::Sender<Ack>, kind: Option<String>) -> Response {
+    let (done, acked) = oneshot::channel();
+    if acks.send(Ack { kind: kind.clone(), done }).await.is_err() {
+        return Response::Error { message: "The notifier has stopped".to_string() };
+    }
+    match (acked.await, kind) {
+        (Ok(kinds), _) if !kinds.is_empty() => Response::Ok { message: format!("Acknowledged {}", kinds.join(", ")) },
+        (Ok(_), Some(kind)) => Response::Error { message: format!("No {} alert is waiting to be acknowledged", kind) },
+        (Ok(_), None) => Response::Ok { message: "No alerts are waiting to be acknowledged".to_string() },
+        (Err(_), _) => Response::Error { message: "The notifier has stopped".to_string() },
+    }
+}
+
+///
+/// @return what a Telegram message acknowledges: Some(None) for a bare `/ack`, the kind
+///         for `/ack <kind>`, None for anything else
+///
+pub fn ack_command(text: &str) -> Option<Option<String>> {
+    let mut words = text.split_whitespace();
+    let command = words.next()?;
+    // in a group the command comes as /ack@the_bots_name
+    if command.split('@').next() != Some("/ack") {
+        return None;
+    }
+    Some(words.next().map(|kind| kind.to_string()))
+}
+
+#[derive(Deserialize)]
+struct Updates {
+    result: Vec<Update>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<TelegramMessage>,
+}
+
+#[derive(Deserialize)]
+struct TelegramMessage {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+///
+/// @brief long-polls the bot for `/ack` from its chat and answers with what it acknowledged
+///
+/// @note messages from any other chat are ignored, anyone can message a bot
+/// @note the first poll only skips past what the bot already holds: pending alerts don't
+///       outlive the daemon, and an /ack from before it started would acknowledge a new one
+///
+fn poll_acks(bot_token: String, chat_id: String, acks: mpsc::Sender<Ack>) {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs(POLL_SECS + TIMEOUT_SECS))
+        .build();
+    tokio::spawn(async move {
+        // -1 asks for just the newest update, whose id the next poll starts after
+        let mut offset = -1;
+        loop {
+            let timeout = if offset < 0 { 0 } else { POLL_SECS };
+            let polled = {
+                let (agent, url) = (agent.clone(), format!("https://api.telegram.org/bot{}/getUpdates?timeout={}&offset={}", bot_token, timeout, offset));
+                tokio::task::spawn_blocking(move || agent.get(&url).call().map_err(telegram_error)?
+                    .into_json::<Updates>().map_err(|e| e.to_string()))
+                    .await.unwrap_or_else(|e| Err(e.to_string()))
+            };
+            let updates = match polled {
+                Ok(updates) => updates,
+                Err(e) => {
+                    logging::print(4, &format!("Telegram /ack poll failed, {}", e));
+                    tokio::time::sleep(std::time::Duration::from_secs(POLL_SECS)).await;
+                    continue;
+                },
+            };
+            if offset < 0 {
+                offset = updates.result.iter().map(|update| update.update_id + 1).max().unwrap_or(0);
+                continue;
+            }
+            for update in updates.result {
+                offset = offset.max(update.update_id + 1);
+                let Some(message) = update.message.filter(|m| m.chat.id.to_string() == chat_id) else {
+                    continue;
+                };
+                let Some(kind) = message.text.as_deref().and_then(ack_command) else {
+                    continue;
+                };
+                let (done, acked) = oneshot::channel();
+                if acks.send(Ack { kind, done }).await.is_err() {
+                    return;
+                }
+                let reply = match acked.await {
+                    Ok(kinds) if !kinds.is_empty() => format!("Acknowledged {}", kinds.join(", ")),
+                    _ => "Nothing was waiting to be acknowledged".to_string(),
+                };
+                let (agent, bot_token, chat_id) = (agent.clone(), bot_token.clone(), chat_id.clone());
+                let _ = tokio::task::spawn_blocking(move || telegram_send(&agent, &bot_token, &chat_id, &reply)).await;
+            }
+        }
+    });
+}
+
+///
+/// @brief sends every alert and summary on the bus where [notify] routes it, and the escalations of
+///        those nobody acknowledges
+///
+/// @return where acknowledgements go
+///
+/// @note like webhooks, a failed send is only logged; an alert about it would go the same
+///       way
+///
+pub fn spawn(config: NotifyConfig, device: String, bus: &Bus) -> mpsc::Sender<Ack> {
+    let mut rx = bus.subscribe();
+    let (acks, mut ack_rx) = mpsc::channel::<Ack>(8);
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs(TIMEOUT_SECS))
+        .build();
+    let notifiers: BTreeMap<String, Arc<dyn Notifier>> = config.notifiers.iter()
+        .map(|spec| (spec.name().to_string(), build(spec, &agent)))
+        .collect();
+    if !config.escalations.is_empty() {
+        for spec in &config.notifiers {
+            if let NotifierSpec::Telegram { bot_token, chat_id, .. } = spec {
+                poll_acks(bot_token.clone(), chat_id.clone(), acks.clone());
+            }
+        }
+    }
+    let mut router = Router::new(&config, &device);
+
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(ESCALATE_EVERY_SECS));
+        loop {
+            let sends = tokio::select! {
+                event = rx.recv() => match event {
+                    Ok(event) => router.route(&event),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                Some(ack) = ack_rx.recv() => {
+                    let kinds = router.ack(ack.kind.as_deref());
+                    if !kinds.is_empty() {
+                        logging::send(5, &format!("Acknowledged {}", kinds.join(", ")),
+                            &[("SERVICE", &"notify"), ("EVENT", &"ack"), ("ALERT", &kinds.join(","))]);
+                    }
+                    let _ = ack.done.send(kinds);
+                    continue;
+                },
+                _ = tick.tick() => router.escalate(Utc::now()),
+            };
+            for (name, message) in sends {
+                let Some(notifier) = notifiers.get(&name).cloned() else {
+                    continue;
+                };
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = notifier.send(&message) {
+                        logging::print(4, &format!("Alert {} not sent to {}, {}", message.kind, name, e));
+                    }
+                });
+            }
+        }
+    });
+    acks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EscalationSpec, RouteSpec};
+
+    #[test]
+    pub fn test_route() {
+        let route = |severity, to: &[&str], kinds: &[&str], template: &str| RouteSpec {
+            severity,
+            to: to.iter().map(|s| s.to_string()).collect(),
+            kinds: kinds.iter().map(|s| s.to_string()).collect(),
+            template: template.to_string(),
+        };
+        let config = NotifyConfig {
+            dedup_mins: 30,
+            severity: BTreeMap::from([("tank_low".to_string(), Severity::Critical)]),
+            notifiers: Vec::new(),
+            routes: vec![
+                route(Severity::Warning, &["mail"], &[], "{device} at {at}: {message}"),
+                route(Severity::Critical, &["phone", "mail"], &[], "{message}"),
+                route(Severity::Info, &["chat"], &["maintenance_due"], "[{severity}] {kind}"),
+            ],
+            escalations: vec![EscalationSpec { severity: Severity::Critical, after_mins: 15, to: vec!["chat".to_string()] }],
+        };
+        let mut router = Router::new(&config, "shelf");
+        assert_eq!(router.severity("tank_low"), Severity::Critical);
+        assert_eq!(router.severity("backup_failed"), Severity::Warning);
+
+        let t0 = DateTime::parse_from_rfc3339("2024-05-01T10:00:00Z").unwrap().with_timezone(&Utc);
+        let alert = |mins, kind, message: &str| Event::Alert { at: t0 + Duration::minutes(mins), kind, message: message.to_string() };
+        let sent = router.route(&alert(0, "heater_runaway", "Heater latched off"));
+        assert_eq!(sent.iter().map(|(name, m)| (name.as_str(), m.body.as_str())).collect::<Vec<_>>(),
+            vec![("mail", "shelf at 2024-05-01T10:00:00+00:00: Heater latched off"), ("phone", "Heater latched off")]);
+        assert_eq!(sent[0].1.title, "shelf critical: heater_runaway");
+        assert!(router.route(&alert(20, "heater_runaway", "Heater latched off")).is_empty());
+        assert_eq!(router.route(&alert(31, "heater_runaway", "Heater latched off")).len(), 2);
+
+        assert_eq!(router.route(&alert(0, "backup_failed", "disk full")).iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(), vec!["mail"]);
+        assert_eq!(router.route(&alert(0, "maintenance_due", "")).iter().map(|(n, m)| (n.as_str(), m.body.as_str())).collect::<Vec<_>>(),
+            vec![("chat", "[info] maintenance_due")]);
+        assert!(router.route(&Event::Door { at: t0, open: true }).is_empty());
+
+        // nobody acknowledged the runaway, raised again at 31 min it still counts from 0
+        assert!(router.escalate(t0 + Duration::minutes(10)).is_empty());
+        let escalated = router.escalate(t0 + Duration::minutes(16));
+        assert_eq!(escalated.iter().map(|(name, m)| (name.as_str(), m.title.as_str(), m.body.as_str())).collect::<Vec<_>>(),
+            vec![("chat", "shelf critical: heater_runaway (unacknowledged)",
+                "Heater latched off\nNot acknowledged for 16 min, reply /ack heater_runaway or run `bonsai-bot ack heater_runaway`")]);
+        assert!(router.escalate(t0 + Duration::minutes(40)).is_empty());
+        router.route(&alert(50, "tank_low", "Tank below 200 g"));
+        assert_eq!(router.ack(Some("tank_low")), vec!["tank_low"]);
+        assert!(router.escalate(t0 + Duration::minutes(90)).is_empty());
+        assert_eq!(router.ack(None), vec!["heater_runaway"]);
+        assert!(router.ack(None).is_empty());
+
+        // raised again within dedup_mins of the last message, after an ack: not sent, but pending again
+        assert!(router.route(&alert(75, "tank_low", "Tank below 200 g")).is_empty());
+        assert_eq!(router.escalate(t0 + Duration::minutes(91)).len(), 1);
+
+        assert_eq!(ack_command("/ack"), Some(None));
+        assert_eq!(ack_command("/ack@bonsai_bot tank_low"), Some(Some("tank_low".to_string())));
+        assert_eq!(ack_command("/acknowledge"), None);
+        assert_eq!(ack_command("thanks"), None);
+    }
+}
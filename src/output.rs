@@ -0,0 +1,23 @@
+use serde::Serialize;
+use serde_json::json;
+
+///
+/// @brief prints `value` as one line of JSON, what a subcommand prints under `--json`
+///
+pub fn json(value: &impl Serialize) {
+    match serde_json::to_string(value) {
+        Ok(line) => println!("{}", line),
+        Err(e) => println!("{}", json!({ "error": e.to_string() })),
+    }
+}
+
+///
+/// @brief prints a one-line result, as `{"message": ...}` under `--json`
+///
+pub fn message(as_json: bool, message: &str) {
+    if as_json {
+        json(&json!({ "message": message }));
+    } else {
+        println!("{}", message);
+    }
+}
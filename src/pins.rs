@@ -0,0 +1,281 @@
+use crate::actuator::{Actuator, GpioRelay, PwmMotor};
+use crate::config::{Backend, Config, PinSpec, PumpConfig, PumpDrive};
+use crate::expander::{Expander, ExpanderRelay, SharedExpander};
+use crate::hal::{HalError, Platform};
+use rppal::system::Model;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::{error, fmt};
+
+//
+// @brief  BCM gpios the daemon refuses to drive
+//
+// @note 0/1 are the HAT ID EEPROM, 2/3 are I2C1 (the SHT20 lives there), 14/15 are the UART
+//
+const RESERVED_PINS: [(u8, &str); 6] = [
+    (0,  "ID_SD (HAT EEPROM)"),
+    (1,  "ID_SC (HAT EEPROM)"),
+    (2,  "SDA1 (I2C)"),
+    (3,  "SCL1 (I2C)"),
+    (14, "TXD0 (UART)"),
+    (15, "RXD0 (UART)"),
+];
+const MAX_HEADER_PIN: u8 = 27;
+
+//
+// @brief  header pins whose alternate function is commonly enabled in /boot/config.txt
+//
+const ALT_FUNCTION_PINS: [(u8, &str); 11] = [
+    (4,  "1-Wire (w1-gpio overlay)"),
+    (7,  "SPI0 CE1"),
+    (8,  "SPI0 CE0"),
+    (9,  "SPI0 MISO"),
+    (10, "SPI0 MOSI"),
+    (11, "SPI0 SCLK"),
+    (12, "PWM0"),
+    (13, "PWM1"),
+    (18, "PCM CLK / PWM0"),
+    (19, "PCM FS / PWM1"),
+    (21, "PCM DOUT"),
+];
+
+//
+// @brief  gpios broken out on the 26-pin header of the original boards
+//
+const REV1_HEADER: [u8; 17] = [0, 1, 4, 7, 8, 9, 10, 11, 14, 15, 17, 18, 21, 22, 23, 24, 25];
+const REV2_HEADER: [u8; 17] = [2, 3, 4, 7, 8, 9, 10, 11, 14, 15, 17, 18, 22, 23, 24, 25, 27];
+
+#[derive(Debug)]
+pub enum PinError {
+    OutOfRange { role: &'static str, pin: u8 },
+    Reserved { role: &'static str, pin: u8, function: &'static str },
+    Duplicate { role: &'static str, other: &'static str, pin: u8 },
+    Unavailable { role: &'static str, pin: u8, source: HalError },
+    UnknownExpander { role: &'static str, name: String },
+    NoSuchChannel { role: &'static str, name: String, pin: u8 },
+    ExpanderUnavailable { role: &'static str, name: String, source: HalError },
+    NotOnHeader { role: &'static str, pin: u8, model: Model },
+    PwmOnExpander { role: &'static str, name: String },
+    InputOnExpander { role: &'static str, name: String },
+    NativeOnly { role: &'static str, name: String },
+}
+
+impl fmt::Display for PinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PinError::OutOfRange { role, pin } =>
+                write!(f, "{} pin gpio{} is not on the 40-pin header (0..={})", role, pin, MAX_HEADER_PIN),
+            PinError::Reserved { role, pin, function } =>
+                write!(f, "{} pin gpio{} is reserved for {}", role, pin, function),
+            PinError::Duplicate { role, other, pin } =>
+                write!(f, "{} and {} are both mapped to gpio{}", role, other, pin),
+            PinError::Unavailable { role, pin, source } =>
+                write!(f, "{} pin gpio{} could not be claimed: {}", role, pin, source),
+            PinError::UnknownExpander { role, name } =>
+                write!(f, "{} pin refers to expander '{}' which is not configured", role, name),
+            PinError::NoSuchChannel { role, name, pin } =>
+                write!(f, "{} pin {} does not exist on expander '{}'", role, pin, name),
+            PinError::ExpanderUnavailable { role, name, source } =>
+                write!(f, "{} pin could not be configured on expander '{}': {}", role, name, source),
+            PinError::NotOnHeader { role, pin, model } =>
+                write!(f, "{} pin gpio{} is not broken out on the {} header", role, pin, model),
+            PinError::PwmOnExpander { role, name } =>
+                write!(f, "{} is set to pwm drive but wired to expander '{}', which cannot do pwm", role, name),
+            PinError::InputOnExpander { role, name } =>
+                write!(f, "{} switch is on expander '{}', inputs must be native gpios", role, name),
+            PinError::NativeOnly { role, name } =>
+                write!(f, "{} is on expander '{}' but must be a native gpio", role, name),
+        }
+    }
+}
+
+impl error::Error for PinError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PinError::Unavailable { source, .. } => Some(source),
+            PinError::ExpanderUnavailable { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+///
+/// @brief checks the configured pins exist (on the header or their expander), are not reserved,
+///        and are distinct
+///
+pub fn validate(config: &Config) -> Result<(), PinError> {
+    let roles = config.gpios();
+
+    for (i, (role, spec)) in roles.iter().enumerate() {
+        match &spec.expander {
+            // cdev line offsets are board specific, there is no header to check against
+            None if config.platform.backend == Backend::Cdev => {},
+            None => {
+                if spec.pin > MAX_HEADER_PIN {
+                    return Err(PinError::OutOfRange { role, pin: spec.pin });
+                }
+                if let Some((_, function)) = RESERVED_PINS.iter().find(|(p, _)| *p == spec.pin) {
+                    return Err(PinError::Reserved { role, pin: spec.pin, function });
+                }
+            },
+            Some(name) => {
+                let expander = config.expanders.get(name)
+                    .ok_or_else(|| PinError::UnknownExpander { role, name: name.clone() })?;
+                if spec.pin >= Expander::channels(expander.kind) {
+                    return Err(PinError::NoSuchChannel { role, name: name.clone(), pin: spec.pin });
+                }
+            },
+        }
+        if let Some((other, _)) = roles[..i].iter().find(|(_, s)| s.pin == spec.pin && s.expander == spec.expander) {
+            return Err(PinError::Duplicate { role, other, pin: spec.pin });
+        }
+    }
+
+    if let (PumpDrive::Pwm, Some(name)) = (config.pump.drive, &config.pins.pump.expander) {
+        return Err(PinError::PwmOnExpander { role: "pump", name: name.clone() });
+    }
+    if let Some(name) = config.door.as_ref().and_then(|door| door.switch.expander.as_ref()) {
+        return Err(PinError::InputOnExpander { role: "door", name: name.clone() });
+    }
+    if let Some(name) = config.wind.as_ref().and_then(|wind| wind.sensor.expander.as_ref()) {
+        return Err(PinError::InputOnExpander { role: "anemometer", name: name.clone() });
+    }
+    if let Some(name) = config.lora.as_ref().and_then(|lora| lora.reset.as_ref()).and_then(|reset| reset.expander.as_ref()) {
+        return Err(PinError::NativeOnly { role: "lora_reset", name: name.clone() });
+    }
+    // bit-banged, an expander is far too slow to clock it
+    let load_cells = ["tank_data", "tank_clock", "pot_data", "pot_clock"];
+    if let Some((role, spec)) = config.gpios().into_iter().find(|(role, spec)| load_cells.contains(role) && spec.expander.is_some()) {
+        return Err(PinError::NativeOnly { role, name: spec.expander.clone().unwrap_or_default() });
+    }
+
+    Ok(())
+}
+
+///
+/// @brief checks the header pins against the detected Pi model, returning a warning for
+///        every pin which doubles as a commonly enabled alternate function
+///
+/// @note 40-pin boards and compute modules break out every gpio validate() accepts
+///
+pub fn check_model(config: &Config, model: Model) -> Result<Vec<String>, PinError> {
+    let header: Option<&[u8]> = match model {
+        Model::RaspberryPiBRev1 => Some(&REV1_HEADER),
+        Model::RaspberryPiA | Model::RaspberryPiBRev2 => Some(&REV2_HEADER),
+        _ => None,
+    };
+
+    let mut warnings = Vec::new();
+    for (role, spec) in config.gpios() {
+        if spec.expander.is_some() {
+            continue;
+        }
+        if let Some(header) = header {
+            if !header.contains(&spec.pin) {
+                return Err(PinError::NotOnHeader { role, pin: spec.pin, model });
+            }
+        }
+        if model == Model::RaspberryPiBRev1 && (spec.pin == 0 || spec.pin == 1) {
+            warnings.push(format!("{} pin gpio{} is I2C0 on the {}", role, spec.pin, model));
+        }
+        if let Some((_, function)) = ALT_FUNCTION_PINS.iter().find(|(p, _)| *p == spec.pin) {
+            warnings.push(format!("{} pin gpio{} is also {}, make sure it is disabled", role, spec.pin, function));
+        }
+    }
+
+    Ok(warnings)
+}
+
+///
+/// @brief claims a relay output, naming the role in the error if it can't be driven
+///
+pub fn claim(
+    platform: &dyn Platform,
+    expanders: &HashMap<String, SharedExpander>,
+    role: &'static str,
+    spec: &PinSpec
+) -> Result<Box<dyn Actuator>, PinError> {
+    match &spec.expander {
+        None => {
+            let relay = GpioRelay::new(platform, spec)
+                .map_err(|source| PinError::Unavailable { role, pin: spec.pin, source })?;
+            Ok(Box::new(relay))
+        },
+        Some(name) => {
+            let expander = expanders.get(name)
+                .ok_or_else(|| PinError::UnknownExpander { role, name: name.clone() })?;
+            let relay = ExpanderRelay::new(expander.clone(), spec.pin, spec.active_low)
+                .map_err(|source| PinError::ExpanderUnavailable { role, name: name.clone(), source })?;
+            Ok(Box::new(relay))
+        },
+    }
+}
+
+///
+/// @brief claims the pump output, as a relay or a soft starting pwm motor per `[pump] drive`
+///
+pub fn claim_pump(
+    platform: &dyn Platform,
+    expanders: &HashMap<String, SharedExpander>,
+    config: &Config
+) -> Result<Box<dyn Actuator>, PinError> {
+    match config.pump.drive {
+        PumpDrive::Relay => claim(platform, expanders, "pump", &config.pins.pump),
+        PumpDrive::Pwm => claim_pwm(platform, "pump", &config.pins.pump, &config.pump),
+    }
+}
+
+fn claim_pwm(
+    platform: &dyn Platform,
+    role: &'static str,
+    spec: &PinSpec,
+    pump: &PumpConfig
+) -> Result<Box<dyn Actuator>, PinError> {
+    if let Some(name) = &spec.expander {
+        return Err(PinError::PwmOnExpander { role, name: name.clone() });
+    }
+    let motor = PwmMotor::new(platform, spec, pump.pwm_frequency, Duration::from_millis(pump.ramp_ms))
+        .map_err(|source| PinError::Unavailable { role, pin: spec.pin, source })?;
+    Ok(Box::new(motor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_validate() {
+        let mut config = Config::default();
+        validate(&config).expect("Default pins should be valid");
+
+        config.pins.fan.pin = config.pins.pump.pin;
+        assert!(matches!(validate(&config), Err(PinError::Duplicate { pin: 27, .. })));
+
+        config.pins.fan.pin = 3;
+        assert!(matches!(validate(&config), Err(PinError::Reserved { role: "fan", .. })));
+
+        // channel 3 on an expander is not header gpio3
+        config.pins.fan.expander = Some("missing".to_string());
+        assert!(matches!(validate(&config), Err(PinError::UnknownExpander { .. })));
+
+        config = Config::default();
+        config.pump.drive = PumpDrive::Pwm;
+        config.pins.pump = PinSpec { pin: 0, active_low: false, expander: Some("board".to_string()) };
+        config.expanders.insert("board".to_string(), crate::config::ExpanderSpec {
+            kind: crate::config::ExpanderKind::Pcf8574, address: 0x20, bus: 1,
+        });
+        assert!(matches!(validate(&config), Err(PinError::PwmOnExpander { role: "pump", .. })));
+    }
+
+    #[test]
+    pub fn test_check_model() {
+        let mut config = Config::default();
+        assert!(check_model(&config, Model::RaspberryPi3B).unwrap().is_empty());
+
+        // gpio27 replaced gpio21 on the rev2 header
+        assert!(matches!(check_model(&config, Model::RaspberryPiBRev1), Err(PinError::NotOnHeader { pin: 27, .. })));
+
+        config.pins.fan.pin = 10;
+        assert_eq!(check_model(&config, Model::RaspberryPi4B).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,384 @@
+use crate::alerts;
+use crate::config::CurrentSensorSpec;
+use crate::db;
+use crate::error::{self, BonsaiError};
+use crate::events::Bus;
+use crate::hal::Platform;
+use crate::ina219::{InaError, INA219, PowerReading};
+use crate::ipc::StatusReport;
+use crate::logging;
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use tokio::sync::watch;
+use tokio::time::{interval, sleep, Duration, MissedTickBehavior};
+use tokio_postgres::Client;
+
+//
+// @brief  how long after a switch the load gets to settle (stagger, pwm ramp, inrush) before
+//         its current is checked, and how often it is checked otherwise
+//
+const SETTLE_MILLIS: u64 = 3000;
+const CHECK_SECS: u64    = 60;
+
+//
+// @brief  how often the pump's current is sampled while it runs, how many samples make a run
+//         worth judging and how many normal runs make a baseline to judge it by
+//
+const PROFILE_MILLIS: u64       = 250;
+const PROFILE_MIN_SAMPLES: usize = 8;
+const BASELINE_MIN_RUNS: usize  = 3;
+
+///
+/// @brief how an actuator's current disagrees with what it was told to do
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    // switched on but drawing nothing: dead pump, dry humidifier, broken wire
+    NoCurrent,
+    // switched off but still drawing: welded relay contacts, wrong polarity
+    StuckOn,
+}
+
+impl Fault {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Fault::NoCurrent => "no_current",
+            Fault::StuckOn => "stuck_on",
+        }
+    }
+}
+
+///
+/// @brief checks a reading against the switch state
+///
+pub fn verify(spec: &CurrentSensorSpec, commanded_on: bool, amps: f64) -> Option<Fault> {
+    match commanded_on {
+        true if amps < spec.on_min_amps => Some(Fault::NoCurrent),
+        false if amps > spec.off_max_amps => Some(Fault::StuckOn),
+        _ => None,
+    }
+}
+
+///
+/// @brief the current a pump run settled at, and how much it wandered around that
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Profile {
+    pub mean_amps: f64,
+    pub ripple_amps: f64,
+}
+
+impl Profile {
+    ///
+    /// @return the profile of a run's samples, None when the run was too short to tell
+    ///
+    pub fn of(amps: &[f64]) -> Option<Profile> {
+        if amps.len() < PROFILE_MIN_SAMPLES {
+            return None;
+        }
+        let n = amps.len() as f64;
+        let mean = amps.iter().sum::<f64>() / n;
+        let ripple = (amps.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / n).sqrt();
+        Some(Profile { mean_amps: mean, ripple_amps: ripple })
+    }
+
+    ///
+    /// @return the typical run, the median mean and ripple of `runs`, None with too few of them
+    ///
+    pub fn baseline(runs: &[Profile]) -> Option<Profile> {
+        if runs.len() < BASELINE_MIN_RUNS {
+            return None;
+        }
+        let median = |pick: fn(&Profile) -> f64| {
+            let mut values: Vec<f64> = runs.iter().map(pick).collect();
+            values.sort_by(f64::total_cmp);
+            values[values.len() / 2]
+        };
+        Some(Profile { mean_amps: median(|p| p.mean_amps), ripple_amps: median(|p| p.ripple_amps) })
+    }
+}
+
+///
+/// @brief how a pump run's current differs from the baseline
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    // more than usual: a blockage, kinked tubing, a stiff impeller
+    High,
+    // less than usual: cavitating on air, an empty tank, a slipping impeller
+    Low,
+    // about the usual, but wandering: air in the line, a failing impeller or bearing
+    Erratic,
+}
+
+impl Anomaly {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Anomaly::High => "high",
+            Anomaly::Low => "low",
+            Anomaly::Erratic => "erratic",
+        }
+    }
+
+    fn cause(&self) -> &'static str {
+        match self {
+            Anomaly::High => "a blockage or a stiff impeller",
+            Anomaly::Low => "cavitation, an empty tank or a slipping impeller",
+            Anomaly::Erratic => "air in the line or a failing impeller",
+        }
+    }
+}
+
+///
+/// @brief checks a run against the baseline, allowing `max_deviation` of the usual mean either
+///        way on the mean and on top of the usual ripple
+///
+pub fn compare(baseline: &Profile, run: &Profile, max_deviation: f64) -> Option<Anomaly> {
+    let allowed = max_deviation * baseline.mean_amps;
+    if run.mean_amps > baseline.mean_amps + allowed {
+        Some(Anomaly::High)
+    } else if run.mean_amps < baseline.mean_amps - allowed {
+        Some(Anomaly::Low)
+    } else if run.ripple_amps > baseline.ripple_amps + allowed {
+        Some(Anomaly::Erratic)
+    } else {
+        None
+    }
+}
+
+///
+/// @brief a pump run being profiled
+///
+struct Run {
+    started: DateTime<Utc>,
+    amps: Vec<f64>,
+}
+
+pub struct CurrentSensor {
+    spec: CurrentSensorSpec,
+    ina: INA219,
+    fault: Option<Fault>,
+    run: Option<Run>,
+    // the last baseline_runs normal runs, oldest first
+    history: VecDeque<Profile>,
+}
+
+impl CurrentSensor {
+    pub fn actuator(&self) -> &str {
+        &self.spec.actuator
+    }
+
+    ///
+    /// @brief reads the current now, checking it against `on`
+    ///
+    pub fn check(&mut self, on: bool) -> Result<(f64, Option<Fault>), InaError> {
+        let amps = self.ina.read()?.current_amps;
+        Ok((amps, verify(&self.spec, on, amps)))
+    }
+
+    fn profiles(&self) -> bool {
+        self.spec.actuator == "pump" && self.spec.baseline_runs > 0
+    }
+}
+
+///
+/// @brief opens an INA219 for every `[[current]]` entry
+///
+pub fn open_all(platform: &dyn Platform, specs: &[CurrentSensorSpec]) -> error::Result<Vec<CurrentSensor>> {
+    specs.iter().map(|spec| {
+        if commanded(&StatusReport::default(), &spec.actuator).is_none() {
+            return Err(BonsaiError::Config(format!("current sensor on unknown actuator {}", spec.actuator)));
+        }
+        let ina = INA219::new(platform, spec.bus, spec.address, spec.shunt_ohms)?;
+        Ok(CurrentSensor { spec: spec.clone(), ina, fault: None, run: None, history: VecDeque::new() })
+    }).collect()
+}
+
+fn commanded(status: &StatusReport, actuator: &str) -> Option<bool> {
+    match actuator {
+        "humidifier" => Some(status.humidifier_on),
+        "pump" => Some(status.pump_on),
+        "fan" => Some(status.fan_on),
+        _ => None,
+    }
+}
+
+///
+/// @brief checks every sensor shortly after each status change and every minute, logging the
+///        readings to power_data and raising an alert when a fault starts
+///
+/// @note while the pump runs, from the first check after it switched on, its sensor is also
+///       read every PROFILE_MILLIS for the run's profile
+///
+pub fn spawn_monitor(mut sensors: Vec<CurrentSensor>, mut status_rx: watch::Receiver<StatusReport>, bus: Bus) {
+    tokio::spawn(async move {
+        let client = match db::establish_connection().await {
+            Ok((client, connection)) => {
+                tokio::spawn(connection);
+                Some(client)
+            },
+            Err(e) => {
+                logging::print(3, &format!("Power readings will not be stored: {}", e));
+                None
+            },
+        };
+        if let Some(client) = &client {
+            for sensor in sensors.iter_mut().filter(|s| s.profiles()) {
+                match load_history(client, sensor.spec.baseline_runs).await {
+                    Ok(history) => sensor.history = history,
+                    Err(e) => { logging::print(3, &format!("Cannot load past pump current profiles: {}", e)); },
+                }
+            }
+        }
+        let mut check = interval(Duration::from_secs(CHECK_SECS));
+        let mut sample = interval(Duration::from_millis(PROFILE_MILLIS));
+        sample.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            let profiling = sensors.iter().any(|s| s.run.is_some());
+            tokio::select! {
+                changed = status_rx.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    sleep(Duration::from_millis(SETTLE_MILLIS)).await;
+                },
+                _ = check.tick() => {},
+                _ = sample.tick(), if profiling => {
+                    for sensor in sensors.iter_mut() {
+                        if let (Some(run), Ok(reading)) = (sensor.run.as_mut(), sensor.ina.read()) {
+                            run.amps.push(reading.current_amps);
+                        }
+                    }
+                    continue;
+                },
+            }
+            let status = status_rx.borrow_and_update().clone();
+            for sensor in sensors.iter_mut() {
+                let on = commanded(&status, &sensor.spec.actuator).unwrap_or_default();
+                match sensor.ina.read() {
+                    Ok(reading) => check_reading(sensor, on, reading, client.as_ref(), &bus).await,
+                    Err(e) => { logging::print(3, &format!("Cannot read {} current: {}", sensor.spec.actuator, e)); },
+                }
+                if !sensor.profiles() {
+                    continue;
+                }
+                match (on, sensor.run.take()) {
+                    (true, None) => sensor.run = Some(Run { started: Utc::now(), amps: Vec::new() }),
+                    (true, Some(run)) => sensor.run = Some(run),
+                    (false, Some(run)) => finish_run(sensor, run, client.as_ref(), &bus).await,
+                    (false, None) => {},
+                }
+            }
+        }
+    });
+}
+
+async fn check_reading(sensor: &mut CurrentSensor, on: bool, reading: PowerReading, client: Option<&Client>, bus: &Bus) {
+    let actuator = sensor.spec.actuator.as_str();
+    if let Some(client) = client {
+        if let Err(e) = client.execute("INSERT INTO power_data (timestamp, actuator, commanded_on, bus_volts, current_amps) VALUES ($1, $2, $3, $4, $5)",
+                &[&Utc::now(), &actuator, &on, &reading.bus_volts, &reading.current_amps]).await {
+            logging::print(3, &format!("Database execute error {:?}", e));
+        }
+    }
+
+    let fault = verify(&sensor.spec, on, reading.current_amps);
+    if fault == sensor.fault {
+        return;
+    }
+    match fault {
+        Some(fault) => {
+            let message = match fault {
+                Fault::NoCurrent => format!("The {} is switched on but only draws {:.3} A", actuator, reading.current_amps),
+                Fault::StuckOn => format!("The {} is switched off but still draws {:.3} A", actuator, reading.current_amps),
+            };
+            alerts::raise(bus, fault.kind(), &message);
+        },
+        None => logging::send(6, &format!("The {} draws {:.3} A ({:.1} W) as expected again", actuator, reading.current_amps, reading.watts()),
+            &[("SERVICE", &"power"), ("EVENT", &"fault_cleared"), ("ACTUATOR", &actuator)]),
+    }
+    sensor.fault = fault;
+}
+
+///
+/// @brief the profiles of the last `runs` pump runs that were normal, oldest first
+///
+async fn load_history(client: &Client, runs: usize) -> Result<VecDeque<Profile>, tokio_postgres::Error> {
+    let rows = client.query("SELECT pump_current_amps FROM climate_data
+        WHERE is_pump_start AND pump_current_amps IS NOT NULL AND pump_current_anomaly IS NULL AND mine(device_id)
+        ORDER BY timestamp DESC LIMIT $1", &[&(runs as i64)]).await?;
+    Ok(rows.iter().rev().filter_map(|row| Profile::of(&row.get::<_, Vec<f64>>(0))).collect())
+}
+
+///
+/// @brief judges a finished pump run against the baseline and stores its samples with the
+///        scheduled run's row in climate_data; a manual run has none and is only judged
+///
+/// @note an anomalous run stays out of the baseline, so a pump going bad can't become normal
+///
+async fn finish_run(sensor: &mut CurrentSensor, run: Run, client: Option<&Client>, bus: &Bus) {
+    let Some(profile) = Profile::of(&run.amps) else {
+        return;
+    };
+    let baseline = Profile::baseline(sensor.history.make_contiguous());
+    let anomaly = baseline.and_then(|baseline| compare(&baseline, &profile, sensor.spec.max_deviation));
+    logging::send(6, &format!("The pump drew {:.3} A, ripple {:.3} A", profile.mean_amps, profile.ripple_amps),
+        &[("SERVICE", &"power"), ("EVENT", &"pump_profile"), ("MEAN_AMPS", &profile.mean_amps), ("RIPPLE_AMPS", &profile.ripple_amps),
+          ("ANOMALY", &anomaly.map_or("none", |a| a.name()))]);
+    match (anomaly, baseline) {
+        (Some(anomaly), Some(baseline)) => alerts::raise(bus, "pump_current", &format!(
+            "The pump drew {:.3} A, ripple {:.3} A, where it usually draws {:.3} A, ripple {:.3} A: {}?",
+            profile.mean_amps, profile.ripple_amps, baseline.mean_amps, baseline.ripple_amps, anomaly.cause())),
+        _ => {
+            sensor.history.push_back(profile);
+            if sensor.history.len() > sensor.spec.baseline_runs {
+                sensor.history.pop_front();
+            }
+        },
+    }
+
+    if let Some(client) = client {
+        // pump_service adds the row as the run starts
+        if let Err(e) = client.execute("UPDATE climate_data SET pump_current_amps = $1, pump_current_anomaly = $2
+                WHERE is_pump_start AND mine(device_id) AND timestamp BETWEEN $3 - interval '1 minute' AND $3 + interval '1 minute'",
+                &[&run.amps, &anomaly.map(|a| a.name()), &run.started]).await {
+            logging::print(3, &format!("Database execute error {:?}", e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_verify() {
+        let spec = CurrentSensorSpec {
+            actuator: "pump".to_string(), address: 0x40, bus: 1, shunt_ohms: 0.1, on_min_amps: 0.05, off_max_amps: 0.02,
+            baseline_runs: 10, max_deviation: 0.25,
+        };
+        assert_eq!(verify(&spec, true, 0.4), None);
+        assert_eq!(verify(&spec, true, 0.001), Some(Fault::NoCurrent));
+        assert_eq!(verify(&spec, false, 0.001), None);
+        assert_eq!(verify(&spec, false, 0.4), Some(Fault::StuckOn));
+    }
+
+    #[test]
+    pub fn test_profile() {
+        let steady = [0.40, 0.41, 0.39, 0.40, 0.41, 0.39, 0.40, 0.40];
+        assert_eq!(Profile::of(&steady[..4]), None);
+        let normal = Profile::of(&steady).unwrap();
+        assert!((normal.mean_amps - 0.40).abs() < 1e-9 && normal.ripple_amps < 0.01);
+
+        let runs = [normal, Profile { mean_amps: 0.42, ripple_amps: 0.01 }];
+        assert_eq!(Profile::baseline(&runs), None);
+        let baseline = Profile::baseline(&[runs[0], runs[1], Profile { mean_amps: 0.38, ripple_amps: 0.02 }]).unwrap();
+        assert_eq!(baseline, Profile { mean_amps: 0.40, ripple_amps: 0.01 });
+
+        assert_eq!(compare(&baseline, &Profile { mean_amps: 0.45, ripple_amps: 0.02 }, 0.25), None);
+        assert_eq!(compare(&baseline, &Profile { mean_amps: 0.55, ripple_amps: 0.01 }, 0.25), Some(Anomaly::High));
+        assert_eq!(compare(&baseline, &Profile { mean_amps: 0.25, ripple_amps: 0.01 }, 0.25), Some(Anomaly::Low));
+        assert_eq!(compare(&baseline, &Profile { mean_amps: 0.40, ripple_amps: 0.15 }, 0.25), Some(Anomaly::Erratic));
+    }
+}
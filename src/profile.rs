@@ -0,0 +1,110 @@
+use crate::config::{Config, DEFAULT_PROFILE, PROFILE_PATH};
+use crate::diagnostics;
+use crate::output;
+use serde::Serialize;
+use std::error::Error;
+
+///
+/// @brief a `[profiles.NAME]` table as `bonsai-bot profile` lists it
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Listed {
+    pub name: String,
+    pub inherits: Option<String>,
+    pub active: bool,
+}
+
+///
+/// @brief makes `name` the profile the daemon starts with, once the config it makes checks out
+///
+/// @return what was switched to, for the caller to report
+///
+pub fn select(name: &str) -> Result<String, Box<dyn Error>> {
+    if let Ok(profile) = std::env::var("BONSAIBOT_PROFILE") {
+        return Err(format!("BONSAIBOT_PROFILE={} is set, it overrides switching profiles", profile).into());
+    }
+    check(&Config::path(), name)?;
+    std::fs::write(PROFILE_PATH, format!("{}\n", name))
+        .map_err(|e| format!("Could not record the profile in {}: {}", PROFILE_PATH, e))?;
+    Ok(format!("Switched to profile {}", name))
+}
+
+///
+/// @brief refuses profile `name` of the config at `path` unless it parses and checks out
+///
+fn check(path: &str, name: &str) -> Result<(), Box<dyn Error>> {
+    let config = Config::from_file_as(path, name)?;
+    let problems = diagnostics::check(&config);
+    if !problems.is_empty() {
+        let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+        return Err(format!("Profile {} doesn't check out: {}", name, problems.join("; ")).into());
+    }
+    Ok(())
+}
+
+///
+/// @return the profiles of `config`, `default` first unless the file has its own
+///
+fn list(config: &Config, active: &str) -> Vec<Listed> {
+    let mut listed = vec![];
+    if !config.profiles.contains_key(DEFAULT_PROFILE) {
+        listed.push(Listed { name: DEFAULT_PROFILE.to_string(), inherits: None, active: active == DEFAULT_PROFILE });
+    }
+    listed.extend(config.profiles.iter().map(|(name, table)| Listed {
+        name: name.clone(),
+        inherits: table.get("inherits").and_then(|parent| parent.as_str()).map(str::to_string),
+        active: name == active,
+    }));
+    listed
+}
+
+///
+/// @brief `bonsai-bot profile`: the config's profiles, marking the one the daemon runs with
+///
+pub fn print(json: bool) -> Result<(), Box<dyn Error>> {
+    let config = Config::from_file_as(&Config::path(), DEFAULT_PROFILE)?;
+    let listed = list(&config, &Config::active_profile());
+
+    if json {
+        output::json(&listed);
+        return Ok(());
+    }
+    for profile in &listed {
+        match &profile.inherits {
+            Some(parent) => println!("{} {} (inherits {})", if profile.active { "*" } else { " " }, profile.name, parent),
+            None => println!("{} {}", if profile.active { "*" } else { " " }, profile.name),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_profile() {
+        let path = std::env::temp_dir().join(format!("bonsai-bot-profile-{}.toml", std::process::id()));
+        let contents = r#"
+            [profiles.winter.humidifier]
+            rh_low = 50.0
+            rh_high = 60.0
+
+            [profiles.broken]
+            inherits = "winter"
+            humidifier = { rh_high = 40.0 }
+        "#;
+        std::fs::write(&path, contents).unwrap();
+        let path = path.to_string_lossy();
+        assert!(check(&path, "winter").is_ok());
+        assert!(check(&path, "broken").unwrap_err().to_string().starts_with("Profile broken doesn't check out: humidifier.rh_high"));
+        assert!(check(&path, "summer").is_err());
+        std::fs::remove_file(&*path).unwrap();
+
+        let config = Config::parse_as(contents, DEFAULT_PROFILE).unwrap();
+        let listed = list(&config, "winter");
+        let names: Vec<(&str, bool)> = listed.iter().map(|p| (p.name.as_str(), p.active)).collect();
+        assert_eq!(names, vec![("default", false), ("broken", false), ("winter", true)]);
+        assert_eq!(listed[1].inherits.as_deref(), Some("winter"));
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+///
+/// @brief at most `limit` requests per client in any sliding `window`
+///
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    clients: HashMap<String, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration) -> RateLimiter {
+        RateLimiter { limit, window, clients: HashMap::new() }
+    }
+
+    ///
+    /// @brief counts a request from `client` if it is within its allowance
+    ///
+    /// @return how long until the client may try again when it is over the limit
+    ///
+    pub fn check(&mut self, client: &str, now: Instant) -> Result<(), Duration> {
+        let window = self.window;
+        let times = self.clients.entry(client.to_string()).or_default();
+        while times.front().is_some_and(|t| now.duration_since(*t) >= window) {
+            times.pop_front();
+        }
+        if times.len() >= self.limit {
+            return Err(times.front().map_or(window, |t| window - now.duration_since(*t)));
+        }
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+///
+/// @brief refuses an action again until `cooldown` after it last finished, so a double tap
+///        or a stuck automation can't queue up back-to-back runs
+///
+pub struct Cooldown {
+    cooldown: Duration,
+    finished: HashMap<&'static str, Instant>,
+}
+
+impl Cooldown {
+    pub fn new(cooldown: Duration) -> Cooldown {
+        Cooldown { cooldown, finished: HashMap::new() }
+    }
+
+    ///
+    /// @return how much of the cooldown is left when `action` may not run yet
+    ///
+    pub fn check(&self, action: &'static str, now: Instant) -> Result<(), Duration> {
+        match self.finished.get(action) {
+            Some(t) if now.duration_since(*t) < self.cooldown => Err(self.cooldown - now.duration_since(*t)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn finished(&mut self, action: &'static str, now: Instant) {
+        self.finished.insert(action, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_rate_limit() {
+        let t0 = Instant::now();
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.check("phone", t0).is_ok());
+        assert!(limiter.check("phone", t0 + Duration::from_secs(10)).is_ok());
+        assert_eq!(limiter.check("phone", t0 + Duration::from_secs(20)), Err(Duration::from_secs(40)));
+        assert!(limiter.check("automation", t0 + Duration::from_secs(20)).is_ok());
+        assert!(limiter.check("phone", t0 + Duration::from_secs(60)).is_ok());
+
+        let mut cooldown = Cooldown::new(Duration::from_secs(30));
+        assert!(cooldown.check("pump", t0).is_ok());
+        cooldown.finished("pump", t0);
+        assert_eq!(cooldown.check("pump", t0 + Duration::from_secs(5)), Err(Duration::from_secs(25)));
+        assert!(cooldown.check("fan", t0 + Duration::from_secs(5)).is_ok());
+        assert!(cooldown.check("pump", t0 + Duration::from_secs(30)).is_ok());
+    }
+}
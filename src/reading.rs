@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+
+///
+/// @brief a value as the sensor layer hands it out, stamped with when it was measured and
+///        which sensor measured it
+///
+/// @note consumers store and publish `taken_at` rather than the time they got around to it
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading<T> {
+    pub value: T,
+    pub taken_at: DateTime<Utc>,
+    pub sensor_id: String,
+}
+
+impl<T> Reading<T> {
+
+    ///
+    /// @brief a value measured just now
+    ///
+    pub fn now(value: T, sensor_id: &str) -> Reading<T> {
+        Reading { value, taken_at: Utc::now(), sensor_id: sensor_id.to_string() }
+    }
+
+    ///
+    /// @brief converts the value, keeping when and where it came from
+    ///
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Reading<U> {
+        Reading { value: f(self.value), taken_at: self.taken_at, sensor_id: self.sensor_id }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_map() {
+        let reading = Reading::now((21.5f32, 64.25f32), "sht20@i2c-1");
+        let converted = reading.clone().map(|(t, rh)| (t as f64, rh as f64));
+        assert_eq!(converted.value, (21.5, 64.25));
+        assert_eq!((converted.taken_at, converted.sensor_id), (reading.taken_at, reading.sensor_id));
+    }
+}
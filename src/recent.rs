@@ -0,0 +1,126 @@
+use crate::events::{Bus, Event};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast::error::RecvError;
+
+//
+// @brief  events kept in memory, several hours' worth at the default service periods
+//
+pub const RECENT_CAPACITY: usize = 1024;
+
+///
+/// @brief ring buffer of the latest readings and actuator events, oldest first
+///
+#[derive(Debug)]
+pub struct Recent {
+    events: VecDeque<Event>,
+    capacity: usize,
+}
+
+pub type SharedRecent = Arc<RwLock<Recent>>;
+
+impl Recent {
+    pub fn new(capacity: usize) -> Recent {
+        Recent { events: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn push(&mut self, event: Event) {
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    ///
+    /// @brief every buffered event at or after `since`
+    ///
+    pub fn since(&self, since: DateTime<Utc>) -> Vec<Event> {
+        self.events.iter().filter(|e| e.at() >= since).cloned().collect()
+    }
+
+    ///
+    /// @brief every buffered event from the last `minutes`, as asked for by a client
+    ///
+    /// @note a span too long to subtract from now returns the whole buffer, negative
+    ///       spans none of it
+    ///
+    pub fn last(&self, minutes: i64) -> Vec<Event> {
+        match Duration::try_minutes(minutes.max(0)).and_then(|span| Utc::now().checked_sub_signed(span)) {
+            Some(since) => self.since(since),
+            None => self.events.iter().cloned().collect(),
+        }
+    }
+
+    ///
+    /// @brief the temperature readings at or after `since`, oldest first
+    ///
+    pub fn temperature(&self, since: DateTime<Utc>) -> Vec<(DateTime<Utc>, f64)> {
+        self.events.iter()
+            .filter_map(|e| match e {
+                Event::Reading { at, temperature, .. } if *at >= since => Some((*at, *temperature)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    ///
+    /// @brief the last `n` humidity readings, oldest first
+    ///
+    pub fn humidity(&self, n: usize) -> Vec<(DateTime<Utc>, f64)> {
+        let mut readings: Vec<_> = self.events.iter().rev()
+            .filter_map(|e| match e {
+                Event::Reading { at, humidity, .. } => Some((*at, *humidity)),
+                _ => None,
+            })
+            .take(n)
+            .collect();
+        readings.reverse();
+        readings
+    }
+}
+
+///
+/// @brief fills a shared ring buffer from the event bus for as long as the bus lives
+///
+pub fn spawn_recorder(bus: &Bus) -> SharedRecent {
+    let recent = Arc::new(RwLock::new(Recent::new(RECENT_CAPACITY)));
+    let mut rx = bus.subscribe();
+    let shared = recent.clone();
+
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => shared.write().unwrap().push(event),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    recent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_recent() {
+        let now = Utc::now();
+        let mut recent = Recent::new(3);
+        for minutes in [90, 45, 30, 10] {
+            recent.push(Event::Actuator { at: now - Duration::minutes(minutes), name: "fan", on: true });
+        }
+
+        // the 90 minute old event was evicted to make room
+        assert_eq!(recent.since(now - Duration::hours(2)).len(), 3);
+        assert_eq!(recent.since(now - Duration::hours(1)).len(), 3);
+        assert_eq!(recent.since(now - Duration::minutes(40)).len(), 2);
+
+        // spans past what chrono can represent don't panic, they return everything
+        assert_eq!(recent.last(i64::MAX).len(), 3);
+        assert_eq!(recent.last(-5).len(), 0);
+        assert_eq!(recent.last(20).len(), 1);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::alerts;
+use crate::config::{MqttConfig, RemoteHumidifierSpec, RemoteNodeSpec};
+use crate::db;
+use crate::events::Bus;
+use crate::logging;
+use chrono::Utc;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::collections::HashMap;
+use std::time::Duration;
+
+//
+// @brief  how long the broker waits without hearing from us before dropping the session
+//
+const KEEP_ALIVE_SECS: u64 = 30;
+
+//
+// @brief  pause before reconnecting after the broker goes away
+//
+const RECONNECT_SECS: u64 = 5;
+
+///
+/// @brief what one message from a node's ESPHome topics means
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Update<'a> {
+    Reading { node: &'a str, metric: &'a str, value: f64 },
+    Relay { node: &'a str, relay: &'a str, on: bool },
+    Online { node: &'a str, online: bool },
+}
+
+///
+/// @brief ESPHome publishes under `<topic>/sensor/<id>/state`, takes switch commands on
+///        `<topic>/switch/<id>/command` and keeps `<topic>/status` at online or offline
+///
+pub fn subscriptions(node: &RemoteNodeSpec) -> Vec<String> {
+    let topic = node.topic();
+    vec![
+        format!("{}/sensor/{}/state", topic, node.temperature),
+        format!("{}/sensor/{}/state", topic, node.humidity),
+        format!("{}/switch/+/state", topic),
+        format!("{}/status", topic),
+    ]
+}
+
+pub fn command_topic(node: &RemoteNodeSpec, relay: &str) -> String {
+    format!("{}/switch/{}/command", node.topic(), relay)
+}
+
+///
+/// @brief what a node's humidifier relay should be switched to at `rh`
+///
+/// @return None inside the band, where it stays as it is
+///
+pub fn humidifier_command(band: &RemoteHumidifierSpec, rh: f64) -> Option<bool> {
+    if rh < band.rh_low {
+        Some(true)
+    } else if rh > band.rh_high {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+///
+/// @brief makes sense of a message on one of the subscribed topics
+///
+/// @return None for topics no configured node owns and payloads that don't parse
+///
+pub fn parse<'a>(nodes: &'a [RemoteNodeSpec], topic: &str, payload: &str) -> Option<Update<'a>> {
+    let payload = payload.trim();
+    nodes.iter().find_map(|node| {
+        let rest = topic.strip_prefix(node.topic())?.strip_prefix('/')?;
+        let node_name = node.name.as_str();
+        if rest == "status" {
+            return Some(Update::Online { node: node_name, online: payload == "online" });
+        }
+        let (kind, id) = rest.strip_suffix("/state")?.split_once('/')?;
+        match kind {
+            "sensor" => {
+                let metric = if id == node.temperature {
+                    "temperature"
+                } else if id == node.humidity {
+                    "humidity"
+                } else {
+                    return None;
+                };
+                // ESPHome publishes "nan" when the sensor didn't answer
+                let value = payload.parse::<f64>().ok().filter(|v| v.is_finite())?;
+                Some(Update::Reading { node: node_name, metric, value })
+            },
+            "switch" => {
+                let relay = node.relays.iter().find(|r| *r == id)?;
+                Some(Update::Relay { node: node_name, relay, on: payload == "ON" })
+            },
+            _ => None,
+        }
+    })
+}
+
+///
+/// @brief the daemon's side of the broker connection, for switching relays on remote nodes
+///
+#[derive(Clone)]
+pub struct Remote {
+    client: AsyncClient,
+    nodes: Vec<RemoteNodeSpec>,
+}
+
+impl Remote {
+    ///
+    /// @brief asks `node` to switch `relay`; the node reports the new state on its own topic
+    ///
+    pub async fn switch(&self, node: &str, relay: &str, on: bool) -> Result<String, String> {
+        let spec = self.nodes.iter().find(|n| n.name == node)
+            .ok_or_else(|| format!("No remote node is named {:?}", node))?;
+        if !spec.relays.iter().any(|r| r == relay) {
+            return Err(format!("{} has no relay {:?}, expected one of {:?}", node, relay, spec.relays));
+        }
+        let payload = if on { "ON" } else { "OFF" };
+        self.client.publish(command_topic(spec, relay), QoS::AtLeastOnce, false, payload).await
+            .map_err(|e| format!("Could not reach the MQTT broker: {}", e))?;
+        Ok(format!("Sent {} to {} {}", payload, node, relay))
+    }
+}
+
+///
+/// @brief connects to the broker and stores every reading the nodes publish in remote_data;
+///        a node going offline raises a `remote_offline` alert
+///
+/// @note nodes with a `humidifier` have its relay switched off their humidity readings,
+///       in `shadow` mode that is only logged
+///
+pub async fn spawn(config: MqttConfig, bus: Bus, shadow: bool) -> Result<Remote, Box<dyn std::error::Error>> {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(KEEP_ALIVE_SECS));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+
+    let (db_client, connection) = db::establish_connection().await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            logging::print(3, &format!("Remote node connection error: {}", e));
+        }
+    });
+
+    let remote = Remote { client: client.clone(), nodes: config.nodes.clone() };
+    tokio::spawn(async move {
+        let nodes = config.nodes;
+        // as last reported by the nodes or last commanded, whichever came later
+        let mut relays: HashMap<(String, String), bool> = HashMap::new();
+        loop {
+            let publish = match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    // a new session has no subscriptions; try_ because this task drives the queue
+                    for topic in nodes.iter().flat_map(subscriptions) {
+                        if let Err(e) = client.try_subscribe(&topic, QoS::AtLeastOnce) {
+                            logging::print(3, &format!("Cannot subscribe to {}: {}", topic, e));
+                        }
+                    }
+                    continue;
+                },
+                Ok(Event::Incoming(Packet::Publish(publish))) => publish,
+                Ok(_) => continue,
+                Err(e) => {
+                    logging::print(4, &format!("MQTT broker {}:{} unreachable: {}", config.host, config.port, e));
+                    tokio::time::sleep(Duration::from_secs(RECONNECT_SECS)).await;
+                    continue;
+                },
+            };
+            let payload = String::from_utf8_lossy(&publish.payload);
+            match parse(&nodes, &publish.topic, &payload) {
+                Some(Update::Reading { node, metric, value }) => {
+                    if let Err(e) = db_client.execute("INSERT INTO remote_data (timestamp, node, metric, value) VALUES ($1, $2, $3, $4)",
+                            &[&Utc::now(), &node, &metric, &value]).await {
+                        logging::print(3, &format!("Database execute error {:?}", e));
+                    }
+                    logging::send(6, &format!("{} {} = {}", node, metric, value),
+                        &[("SERVICE", &"remote"), ("EVENT", &"reading"), ("NODE", &node), ("METRIC", &metric), ("VALUE", &value)]);
+
+                    let Some(spec) = nodes.iter().find(|n| n.name == node).filter(|_| metric == "humidity") else {
+                        continue;
+                    };
+                    let Some(band) = &spec.humidifier else {
+                        continue;
+                    };
+                    let key = (spec.name.clone(), band.relay.clone());
+                    let Some(on) = humidifier_command(band, value).filter(|on| relays.get(&key) != Some(on)) else {
+                        continue;
+                    };
+                    let payload = if on { "ON" } else { "OFF" };
+                    if shadow {
+                        logging::send(6, &format!("Shadow: would switch {} {} {} at {:.1} %", node, band.relay, payload, value),
+                            &[("SERVICE", &"remote"), ("EVENT", &"shadow"), ("NODE", &node), ("RELAY", &band.relay), ("ON", &on)]);
+                    } else if let Err(e) = client.try_publish(command_topic(spec, &band.relay), QoS::AtLeastOnce, false, payload) {
+                        logging::print(3, &format!("Cannot switch {} {}: {}", node, band.relay, e));
+                        continue;
+                    } else {
+                        logging::send(6, &format!("Switching {} {} {} at {:.1} %", node, band.relay, payload, value),
+                            &[("SERVICE", &"remote"), ("EVENT", &"humidifier"), ("NODE", &node), ("RELAY", &band.relay), ("ON", &on)]);
+                    }
+                    relays.insert(key, on);
+                },
+                Some(Update::Relay { node, relay, on }) => {
+                    relays.insert((node.to_string(), relay.to_string()), on);
+                    logging::send(6, &format!("{} {} switched {}", node, relay, if on { "on" } else { "off" }),
+                        &[("SERVICE", &"remote"), ("EVENT", &"relay"), ("NODE", &node), ("RELAY", &relay), ("ON", &on)]);
+                },
+                Some(Update::Online { node, online: false }) => {
+                    alerts::raise(&bus, "remote_offline", &format!("Remote node {} went offline", node));
+                },
+                Some(Update::Online { .. }) | None => {},
+            }
+        }
+    });
+    Ok(remote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_parse() {
+        let nodes = vec![RemoteNodeSpec {
+            name: "shelf".to_string(),
+            topic: Some("esp-shelf".to_string()),
+            temperature: "enclosure_temperature".to_string(),
+            humidity: "enclosure_humidity".to_string(),
+            relays: vec!["humidifier".to_string()],
+            humidifier: Some(RemoteHumidifierSpec { relay: "humidifier".to_string(), rh_low: 70.0, rh_high: 80.0 }),
+        }];
+        assert_eq!(parse(&nodes, "esp-shelf/sensor/enclosure_humidity/state", "71.3"),
+            Some(Update::Reading { node: "shelf", metric: "humidity", value: 71.3 }));
+        assert_eq!(parse(&nodes, "esp-shelf/switch/humidifier/state", "ON"),
+            Some(Update::Relay { node: "shelf", relay: "humidifier", on: true }));
+        assert_eq!(parse(&nodes, "esp-shelf/status", "offline"), Some(Update::Online { node: "shelf", online: false }));
+        assert_eq!(parse(&nodes, "esp-shelf/sensor/wifi_signal/state", "-61"), None);
+        assert_eq!(parse(&nodes, "esp-shelf/sensor/enclosure_humidity/state", "nan"), None);
+        assert_eq!(parse(&nodes, "esp-shelf2/status", "online"), None);
+        assert_eq!(command_topic(&nodes[0], "humidifier"), "esp-shelf/switch/humidifier/command");
+
+        let band = nodes[0].humidifier.as_ref().unwrap();
+        assert_eq!(humidifier_command(band, 68.5), Some(true));
+        assert_eq!(humidifier_command(band, 75.0), None);
+        assert_eq!(humidifier_command(band, 81.0), Some(false));
+    }
+}
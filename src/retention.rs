@@ -0,0 +1,78 @@
+use chrono::{Duration, Utc};
+use crate::logging;
+use tokio_postgres::Client;
+
+//
+// @brief  recomputes every hour from the newest rolled up bucket onwards, the newest one
+//         included since it was likely still filling up last time; each device gets its own
+//
+const ROLLUP_HOURLY: &str = "
+INSERT INTO climate_hourly (device_id, bucket, temperature_min, temperature_avg, temperature_max,
+                            humidity_min, humidity_avg, humidity_max, samples)
+SELECT device_id, date_trunc('hour', timestamp) AS hour,
+       MIN(temperature), AVG(temperature), MAX(temperature),
+       MIN(humidity), AVG(humidity), MAX(humidity), COUNT(*)
+FROM climate_data
+WHERE NOT is_pump_start
+  AND timestamp >= (SELECT COALESCE(MAX(bucket), '-infinity') FROM climate_hourly)
+GROUP BY device_id, hour
+ON CONFLICT (device_id, bucket) DO UPDATE SET
+    temperature_min = EXCLUDED.temperature_min,
+    temperature_avg = EXCLUDED.temperature_avg,
+    temperature_max = EXCLUDED.temperature_max,
+    humidity_min    = EXCLUDED.humidity_min,
+    humidity_avg    = EXCLUDED.humidity_avg,
+    humidity_max    = EXCLUDED.humidity_max,
+    samples         = EXCLUDED.samples";
+
+//
+// @brief  days are built from the hourly table so they survive raw data being pruned
+//
+const ROLLUP_DAILY: &str = "
+INSERT INTO climate_daily (device_id, bucket, temperature_min, temperature_avg, temperature_max,
+                           humidity_min, humidity_avg, humidity_max, samples)
+SELECT device_id, date_trunc('day', bucket) AS day,
+       MIN(temperature_min), SUM(temperature_avg * samples) / SUM(samples), MAX(temperature_max),
+       MIN(humidity_min), SUM(humidity_avg * samples) / SUM(samples), MAX(humidity_max),
+       SUM(samples)::BIGINT
+FROM climate_hourly
+WHERE bucket >= (SELECT COALESCE(MAX(bucket), '-infinity') FROM climate_daily)
+GROUP BY device_id, day
+ON CONFLICT (device_id, bucket) DO UPDATE SET
+    temperature_min = EXCLUDED.temperature_min,
+    temperature_avg = EXCLUDED.temperature_avg,
+    temperature_max = EXCLUDED.temperature_max,
+    humidity_min    = EXCLUDED.humidity_min,
+    humidity_avg    = EXCLUDED.humidity_avg,
+    humidity_max    = EXCLUDED.humidity_max,
+    samples         = EXCLUDED.samples";
+
+///
+/// @brief brings the hourly and daily rollups up to date, then prunes raw readings older
+///        than `raw_days`; raw rows are only deleted once they are covered by a rollup
+///
+/// @note pump start rows are kept, the pump schedule is derived from them
+///
+pub async fn run(client: &mut Client, raw_days: Option<u32>) -> Result<(), tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+    let hours = tx.execute(ROLLUP_HOURLY, &[]).await?;
+    let days = tx.execute(ROLLUP_DAILY, &[]).await?;
+
+    let mut pruned = 0;
+    if let Some(raw_days) = raw_days {
+        let cutoff = Utc::now() - Duration::days(raw_days as i64);
+        pruned += tx.execute("
+            DELETE FROM climate_data
+            WHERE timestamp < $1 AND NOT is_pump_start
+              AND timestamp < (SELECT COALESCE(MAX(bucket), '-infinity') FROM climate_hourly)", &[&cutoff]).await?;
+        pruned += tx.execute("DELETE FROM analog_data WHERE timestamp < $1", &[&cutoff]).await?;
+        pruned += tx.execute("DELETE FROM ambient_data WHERE timestamp < $1", &[&cutoff]).await?;
+        pruned += tx.execute("DELETE FROM wind_data WHERE timestamp < $1", &[&cutoff]).await?;
+        pruned += tx.execute("DELETE FROM leaf_wetness WHERE timestamp < $1", &[&cutoff]).await?;
+        pruned += tx.execute("DELETE FROM weight_data WHERE timestamp < $1", &[&cutoff]).await?;
+    }
+    tx.commit().await?;
+
+    logging::print(6, &format!("Retention: refreshed {} hourly and {} daily rollups, pruned {} raw rows", hours, days, pruned));
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::config::HumidifierConfig;
+use chrono::{DateTime, Duration, Utc};
+
+///
+/// @brief trips when RH stays above `emergency_rh` for `emergency_after_mins`, which means the
+///        humidifier is running regardless of what it's told (stuck relay, active-low board
+///        configured as active-high); clears once RH is back below `clear_below`
+///
+pub struct Saturation {
+    threshold: f64,
+    after: Duration,
+    clear_below: f64,
+    above_since: Option<DateTime<Utc>>,
+    tripped: bool,
+}
+
+impl Saturation {
+    pub fn new(config: &HumidifierConfig, clear_below: f64) -> Saturation {
+        Saturation {
+            threshold: config.emergency_rh,
+            after: Duration::minutes(config.emergency_after_mins as i64),
+            clear_below,
+            above_since: None,
+            tripped: false,
+        }
+    }
+
+    ///
+    /// @brief feeds a reading taken at `at`
+    ///
+    /// @return Some(true) when this reading tripped it, Some(false) when it cleared
+    ///
+    pub fn update(&mut self, rh: f64, at: DateTime<Utc>) -> Option<bool> {
+        if self.tripped {
+            if rh < self.clear_below {
+                self.tripped = false;
+                self.above_since = None;
+                return Some(false);
+            }
+            return None;
+        }
+
+        if rh <= self.threshold {
+            self.above_since = None;
+            return None;
+        }
+        let since = *self.above_since.get_or_insert(at);
+        if at - since >= self.after {
+            self.tripped = true;
+            return Some(true);
+        }
+        None
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_saturation() {
+        let config = HumidifierConfig { emergency_rh: 95.0, emergency_after_mins: 15, ..Default::default() };
+        let mut saturation = Saturation::new(&config, 80.0);
+        let start = Utc::now();
+        let at = |mins| start + Duration::minutes(mins);
+
+        // a brief spike doesn't count
+        assert_eq!(saturation.update(97.0, at(0)), None);
+        assert_eq!(saturation.update(90.0, at(5)), None);
+        assert_eq!(saturation.update(97.0, at(10)), None);
+        assert_eq!(saturation.update(98.0, at(20)), None);
+        assert_eq!(saturation.update(98.0, at(25)), Some(true));
+        assert!(saturation.is_tripped());
+
+        assert_eq!(saturation.update(85.0, at(40)), None);
+        assert_eq!(saturation.update(79.0, at(60)), Some(false));
+        assert!(!saturation.is_tripped());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::config::SamplingConfig;
+use crate::logging;
+use tokio::time::{sleep_until, Duration, Instant};
+
+///
+/// @brief extra climate readings at a faster rate for a while after something interesting
+///        happens (pump run, humidifier switching), on top of the regular interval
+///
+pub struct Burst {
+    period: Duration,
+    window: Duration,
+    until: Option<Instant>,
+    next: Option<Instant>,
+}
+
+impl Burst {
+    pub fn new(config: &SamplingConfig) -> Burst {
+        Burst {
+            period: Duration::from_secs(config.burst_period_secs),
+            window: Duration::from_secs(60 * config.burst_window_mins),
+            until: None,
+            next: None,
+        }
+    }
+
+    ///
+    /// @brief starts a burst, or extends the running one to a full window from now
+    ///
+    pub fn trigger(&mut self, reason: &str) {
+        if self.window.is_zero() || self.period.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        if self.until.is_none() {
+            logging::print(6, &format!("{}, sampling every {} s for {} min", reason,
+                self.period.as_secs(), self.window.as_secs() / 60));
+        }
+        self.until = Some(now + self.window);
+        self.next.get_or_insert(now + self.period);
+    }
+
+    ///
+    /// @brief waits for the next burst reading; never completes while no burst is running
+    ///
+    /// @return the instant the reading was due
+    ///
+    pub async fn tick(&mut self) -> Instant {
+        let (Some(next), Some(until)) = (self.next, self.until) else {
+            return std::future::pending().await;
+        };
+        sleep_until(next).await;
+
+        if next + self.period > until {
+            self.next = None;
+            self.until = None;
+        } else {
+            self.next = Some(next + self.period);
+        }
+        next
+    }
+}
+
+///
+/// @brief independent fixed-rate timers, one per sensor, all due straight away
+///
+pub struct Schedule {
+    periods: Vec<Duration>,
+    next: Vec<Instant>,
+}
+
+impl Schedule {
+    pub fn new(periods: Vec<Duration>) -> Schedule {
+        let now = Instant::now();
+        let periods: Vec<_> = periods.into_iter().map(|p| p.max(Duration::from_secs(1))).collect();
+        Schedule { next: vec![now; periods.len()], periods }
+    }
+
+    ///
+    /// @brief waits for the earliest timer and returns the index of every timer now due;
+    ///        a timer which fell behind skips the readings it missed
+    ///
+    pub async fn due(&mut self) -> Vec<usize> {
+        let Some(&earliest) = self.next.iter().min() else {
+            return std::future::pending().await;
+        };
+        sleep_until(earliest).await;
+
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (i, next) in self.next.iter_mut().enumerate() {
+            if *next <= now {
+                due.push(i);
+                while *next <= now {
+                    *next += self.periods[i];
+                }
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_sampling() {
+        let mut burst = Burst::new(&SamplingConfig { burst_period_secs: 0, burst_window_mins: 1, ..Default::default() });
+        burst.trigger("test");
+        assert!(tokio::time::timeout(Duration::from_millis(50), burst.tick()).await.is_err());
+
+        let mut burst = Burst {
+            period: Duration::from_millis(20),
+            window: Duration::from_millis(50),
+            until: None,
+            next: None,
+        };
+        burst.trigger("test");
+        let mut ticks = 0;
+        while tokio::time::timeout(Duration::from_millis(100), burst.tick()).await.is_ok() {
+            ticks += 1;
+        }
+        assert_eq!(ticks, 2);
+
+        let mut schedule = Schedule::new(vec![Duration::from_secs(1), Duration::from_secs(3)]);
+        assert_eq!(schedule.due().await, vec![0, 1]);
+        assert_eq!(schedule.due().await, vec![0]);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::config::{Config, NotifierSpec, TelemetrySpec};
+use crate::error::{self, BonsaiError};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+//
+// @brief  where `bonsai-bot systemd credential` keeps credentials, encrypted with
+//         systemd-creds for the unit to hand the daemon in $CREDENTIALS_DIRECTORY
+//
+pub const CREDSTORE: &str = "/etc/credstore.encrypted";
+
+//
+// @brief  a config value starting with this names a credential to use instead
+//
+const PREFIX: &str = "credential:";
+
+//
+// @brief  the credential holding the Postgres connection string
+//
+pub const DATABASE_URL: &str = "database-url";
+
+///
+/// @return where `name` is stored encrypted, for LoadCredentialEncrypted=
+///
+pub fn path(name: &str) -> String {
+    format!("{}/bonsai-bot.{}", CREDSTORE, name)
+}
+
+///
+/// @return the credential systemd decrypted for us, None when there's no such credential or
+///         we weren't started with any
+///
+pub fn credential(name: &str) -> error::Result<Option<String>> {
+    credential_in(std::env::var_os("CREDENTIALS_DIRECTORY").as_deref().map(Path::new), name)
+}
+
+fn credential_in(dir: Option<&Path>, name: &str) -> error::Result<Option<String>> {
+    let Some(dir) = dir else {
+        return Ok(None);
+    };
+    match std::fs::read_to_string(dir.join(name)) {
+        Ok(value) => Ok(Some(value.trim_end_matches(['\r', '\n']).to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(BonsaiError::Config(format!("credential {}: {}", name, e))),
+    }
+}
+
+///
+/// @return the database-url credential, or BONSAIBOT_DATABASE_URL without one
+///
+pub fn database_url() -> error::Result<String> {
+    if let Some(url) = credential(DATABASE_URL)? {
+        return Ok(url);
+    }
+    std::env::var("BONSAIBOT_DATABASE_URL")
+        .map_err(|e| BonsaiError::Config(format!("BONSAIBOT_DATABASE_URL: {}, and no {} credential", e, DATABASE_URL)))
+}
+
+///
+/// @brief the config values that can name a credential: API keys, tokens and passwords
+///
+fn secret_values(config: &mut Config) -> Vec<&mut String> {
+    let mut values = Vec::new();
+    for telemetry in config.telemetry.iter_mut() {
+        match telemetry {
+            TelemetrySpec::Thingspeak { api_key, .. } | TelemetrySpec::Adafruit { api_key, .. } => values.push(api_key),
+        }
+    }
+    values.extend(config.mqtt.as_mut().and_then(|mqtt| mqtt.password.as_mut()));
+    values.extend(config.webhooks.iter_mut().filter_map(|webhook| webhook.secret.as_mut()));
+    for notifier in config.notify.iter_mut().flat_map(|notify| notify.notifiers.iter_mut()) {
+        match notifier {
+            NotifierSpec::Ntfy { token, .. } => values.extend(token.as_mut()),
+            NotifierSpec::Telegram { bot_token, .. } => values.push(bot_token),
+            NotifierSpec::Webhook { secret, .. } => values.extend(secret.as_mut()),
+            NotifierSpec::Email { .. } => {},
+        }
+    }
+    values
+}
+
+///
+/// @return the credentials the config names, which the unit has to load
+///
+pub fn references(config: &Config) -> BTreeSet<String> {
+    let mut config = config.clone();
+    secret_values(&mut config).into_iter().filter_map(|value| value.strip_prefix(PREFIX).map(str::to_string)).collect()
+}
+
+///
+/// @brief replaces every `credential:NAME` value in the config with that credential
+///
+pub fn resolve(config: &mut Config) -> error::Result<()> {
+    resolve_in(config, std::env::var_os("CREDENTIALS_DIRECTORY").as_deref().map(Path::new))
+}
+
+fn resolve_in(config: &mut Config, dir: Option<&Path>) -> error::Result<()> {
+    for value in secret_values(config) {
+        if let Some(name) = value.strip_prefix(PREFIX) {
+            *value = credential_in(dir, name)?
+                .ok_or_else(|| BonsaiError::Config(format!("the config names credential {}, which the unit doesn't load", name)))?;
+        }
+    }
+    Ok(())
+}
+
+///
+/// @brief `bonsai-bot systemd credential`: encrypts stdin into the credstore, where the unit
+///        `systemd gen` prints picks it up
+///
+pub fn store(name: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+        return Err(format!("{:?} is not a credential name, use letters, digits, - _ and .", name).into());
+    }
+    let mut value = String::new();
+    std::io::stdin().read_to_string(&mut value)?;
+    encrypt(name, &value)?;
+    crate::output::message(json, &format!("Encrypted to {}, restart the daemon to use it", path(name)));
+    Ok(())
+}
+
+///
+/// @brief encrypts `value` with systemd-creds, for this machine only, into the credstore
+///
+pub fn encrypt(name: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(CREDSTORE)?;
+    let mut child = Command::new("systemd-creds")
+        .args(["encrypt", &format!("--name={}", name), "-", &path(name)])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Cannot run systemd-creds: {}", e))?;
+    child.stdin.take().expect("piped").write_all(value.trim_end_matches(['\r', '\n']).as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("systemd-creds encrypt failed with {}", status).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_resolve() {
+        let mut config = Config::parse(r#"
+            [[webhooks]]
+            url    = "https://example.com/hook"
+            events = ["alert"]
+            secret = "credential:hook-secret"
+
+            [notify]
+            [[notify.notifiers]]
+            name      = "chat"
+            type      = "telegram"
+            bot_token = "credential:telegram-token"
+            chat_id   = "42"
+
+            [[notify.notifiers]]
+            name  = "phone"
+            type  = "ntfy"
+            url   = "https://ntfy.sh/bonsai"
+            token = "tk_plain"
+
+            [[notify.routes]]
+            severity = "critical"
+            to       = ["chat", "phone"]
+        "#).unwrap();
+        assert_eq!(references(&config), BTreeSet::from(["hook-secret".to_string(), "telegram-token".to_string()]));
+
+        let dir = std::env::temp_dir().join(format!("bonsai-bot-credentials-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("hook-secret"), "s3cret\n").unwrap();
+        // the unit loads one of the two
+        assert!(resolve_in(&mut config.clone(), Some(&dir)).unwrap_err().to_string().contains("credential telegram-token"));
+        std::fs::write(dir.join("telegram-token"), "123:abc").unwrap();
+        resolve_in(&mut config, Some(&dir)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(config.webhooks[0].secret.as_deref(), Some("s3cret"));
+        let notify = config.notify.unwrap();
+        assert!(matches!(&notify.notifiers[0], NotifierSpec::Telegram { bot_token, .. } if bot_token == "123:abc"));
+        assert!(matches!(&notify.notifiers[1], NotifierSpec::Ntfy { token: Some(token), .. } if token == "tk_plain"));
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::{DateTime, Duration, Timelike, Utc};
+use rand::Rng;
+use std::error::Error;
+use tokio_postgres::Client;
+
+//
+// @brief  shape of the synthetic enclosure, roughly what the real one does
+//
+const SAMPLE_MINS: i64          = 5;
+const TEMP_MEAN_C: f64          = 21.0;
+const TEMP_SWING_C: f64         = 3.0;     // afternoon peak above the mean
+const RH_LO: f64                = 70.0;
+const RH_HI: f64                = 80.0;
+const RH_RISE_PER_SAMPLE: f64   = 1.6;     // humidifier on
+const RH_DECAY_PER_SAMPLE: f64  = 0.7;     // humidifier off
+const PUMP_HOUR_UTC: u32        = 14;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedRow {
+    pub timestamp: DateTime<Utc>,
+    pub temperature: Option<f64>,
+    pub humidity: Option<f64>,
+    pub is_pump_start: bool,
+}
+
+///
+/// @brief generates climate samples every five minutes with a diurnal temperature swing,
+///        a humidifier sawtooth between the thresholds and one pump start per day
+///
+pub fn synthetic_history(start: DateTime<Utc>, end: DateTime<Utc>, rng: &mut impl Rng) -> Vec<SeedRow> {
+    let mut rows = Vec::new();
+    let mut rh = (RH_LO + RH_HI) / 2.0;
+    let mut humidifier_on = false;
+    let mut t = start;
+
+    while t < end {
+        let hour = t.hour() as f64 + t.minute() as f64 / 60.0;
+        let phase = (hour - 9.0) / 24.0 * std::f64::consts::TAU;
+        let temp = TEMP_MEAN_C + TEMP_SWING_C * phase.sin() + rng.gen_range(-0.2..0.2);
+
+        if humidifier_on {
+            rh += RH_RISE_PER_SAMPLE + rng.gen_range(-0.4..0.4);
+        } else {
+            rh -= RH_DECAY_PER_SAMPLE + rng.gen_range(-0.3..0.3);
+        }
+        if rh < RH_LO { humidifier_on = true; }
+        if rh > RH_HI { humidifier_on = false; }
+        rh = rh.clamp(0.0, 100.0);
+
+        rows.push(SeedRow { timestamp: t, temperature: Some(temp), humidity: Some(rh), is_pump_start: false });
+
+        if t.hour() == PUMP_HOUR_UTC && t.minute() < SAMPLE_MINS as u32 {
+            rows.push(SeedRow { timestamp: t, temperature: None, humidity: None, is_pump_start: true });
+        }
+
+        t += Duration::minutes(SAMPLE_MINS);
+    }
+
+    rows
+}
+
+///
+/// @brief fills climate_data with the last `days` of synthetic history
+///
+/// @return how many rows it wrote
+///
+pub async fn run(client: &mut Client, days: i64, force: bool) -> Result<usize, Box<dyn Error>> {
+    let existing: i64 = client.query_one("SELECT COUNT(*) FROM climate_data", &[]).await?.get(0);
+    if existing > 0 && !force {
+        return Err(format!("climate_data already holds {} rows, pass --force to seed anyway", existing).into());
+    }
+
+    let end = Utc::now();
+    let rows = synthetic_history(end - Duration::days(days), end, &mut rand::thread_rng());
+
+    let tx = client.transaction().await?;
+    let stmt = tx.prepare("INSERT INTO climate_data (timestamp, temperature, humidity, is_pump_start) VALUES ($1, $2, $3, $4)").await?;
+    for row in &rows {
+        tx.execute(&stmt, &[&row.timestamp, &row.temperature, &row.humidity, &row.is_pump_start]).await?;
+    }
+    tx.commit().await?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_synthetic_history() {
+        let end = Utc::now();
+        let rows = synthetic_history(end - Duration::days(2), end, &mut rand::thread_rng());
+
+        let samples: Vec<_> = rows.iter().filter(|r| !r.is_pump_start).collect();
+        assert_eq!(samples.len() as i64, 2 * 24 * 60 / SAMPLE_MINS);
+        assert!(samples.iter().all(|r| (RH_LO - 3.0..=RH_HI + 3.0).contains(&r.humidity.unwrap())));
+        assert!((1..=3).contains(&rows.iter().filter(|r| r.is_pump_start).count()));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::actuator::Actuator;
+use crate::config::SelfTestConfig;
+use crate::irrigation::Zone;
+use crate::logging;
+use crate::power::{CurrentSensor, Fault};
+use crate::sht20::SHT20;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Duration, Instant};
+use tokio_postgres::Client;
+
+///
+/// @brief how one part of the self-test went, `detail` saying what was seen either way
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, result: Result<String, String>) -> Check {
+        let (passed, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        Check { name: name.into(), passed, detail }
+    }
+}
+
+///
+/// @return one line for the journal and system_events, listing only the failures if any
+///
+pub fn summary(checks: &[Check]) -> String {
+    let describe = |checks: Vec<&Check>| checks.iter().map(|c| format!("{} {}", c.name, c.detail)).collect::<Vec<_>>();
+    let failed: Vec<&Check> = checks.iter().filter(|c| !c.passed).collect();
+    match failed.len() {
+        0 => format!("Self-test passed: {}", describe(checks.iter().collect()).join(", ")),
+        n => format!("Self-test failed {} of {} checks: {}", n, checks.len(), describe(failed).join("; ")),
+    }
+}
+
+///
+/// @brief judges our clock by the database's and by the newest reading stored, which a clock
+///        that came up without NTP or an RTC is behind
+///
+pub fn clock(now: DateTime<Utc>, database: DateTime<Utc>, newest: Option<DateTime<Utc>>, max_skew_secs: i64) -> Result<String, String> {
+    let skew = (now - database).num_seconds();
+    if skew.abs() > max_skew_secs {
+        return Err(format!("is {} s off the database's", skew));
+    }
+    if let Some(newest) = newest.filter(|newest| *newest > now) {
+        return Err(format!("is behind the newest stored reading, from {}", newest.format("%Y-%m-%d %H:%M:%S %Z")));
+    }
+    Ok(format!("within {} s of the database", skew.abs()))
+}
+
+///
+/// @brief switches `relay` on for `pulse`, checking its current sensor if it has one
+///
+async fn pulse(relay: &mut dyn Actuator, mut sensor: Option<&mut CurrentSensor>, pulse: Duration) -> Result<String, String> {
+    relay.set_on();
+    sleep(pulse).await;
+    let on = sensor.as_mut().map(|sensor| sensor.check(true));
+    relay.set_off();
+    let Some(sensor) = sensor else {
+        return Ok("pulsed".to_string());
+    };
+    sleep(pulse).await;
+    match (on.expect("read while on"), sensor.check(false)) {
+        (Ok((amps, None)), Ok((_, None))) => Ok(format!("drew {:.3} A", amps)),
+        (Ok((amps, Some(Fault::NoCurrent))), _) => Err(format!("drew only {:.3} A when switched on", amps)),
+        (_, Ok((amps, Some(Fault::StuckOn)))) => Err(format!("still drew {:.3} A when switched off", amps)),
+        (Err(e), _) | (_, Err(e)) => Err(format!("current unreadable: {}", e)),
+        _ => Err("current disagrees with the switch".to_string()),
+    }
+}
+
+///
+/// @brief pulses each relay in turn, reads the SHT20 and checks the database and clock
+///
+/// @note with irrigation zones the pump is pulsed into the first zone, never against closed valves
+///
+pub async fn run(
+    config: &SelfTestConfig,
+    relays: Vec<(&'static str, &mut dyn Actuator)>,
+    zones: &mut [Zone],
+    sensors: &mut [CurrentSensor],
+    sht20: Arc<Mutex<SHT20>>,
+    client: &Client
+) -> Vec<Check> {
+    let wanted = |name: &str| config.relays.as_ref().is_none_or(|relays| relays.iter().any(|r| r == name));
+    let length = Duration::from_millis(config.pulse_ms);
+    let mut checks = Vec::new();
+
+    for (name, relay) in relays.into_iter().filter(|(name, _)| wanted(name)) {
+        let sensor = sensors.iter_mut().find(|s| s.actuator() == name);
+        let result = match zones.first_mut() {
+            Some(zone) if name == "pump" => {
+                zone.valve().set_on();
+                let result = pulse(relay, sensor, length).await;
+                zone.valve().set_off();
+                result
+            },
+            _ => pulse(relay, sensor, length).await,
+        };
+        checks.push(Check::new(name, result));
+    }
+    for zone in zones.iter_mut().filter(|zone| wanted(&zone.name)) {
+        let result = pulse(zone.valve(), None, length).await;
+        checks.push(Check::new(format!("{} valve", zone.name), result));
+    }
+
+    let reading = SHT20::read_climate(sht20).await
+        .map(|r| format!("read {:.1} C, {:.1} % RH", r.value.0, r.value.1))
+        .map_err(|e| format!("unreadable: {}", e));
+    checks.push(Check::new("sensor", reading));
+
+    let asked = Instant::now();
+    match client.query_one("SELECT now(), (SELECT MAX(timestamp) FROM climate_data WHERE mine(device_id))", &[]).await {
+        Ok(row) => {
+            checks.push(Check::new("database", Ok(format!("answered in {} ms", asked.elapsed().as_millis()))));
+            checks.push(Check::new("clock", clock(Utc::now(), row.get(0), row.get(1), config.max_clock_skew_secs)));
+        },
+        Err(e) => {
+            checks.push(Check::new("database", Err(format!("unreachable: {}", e))));
+            checks.push(Check::new("clock", Err("unchecked without the database".to_string())));
+        },
+    }
+    checks
+}
+
+///
+/// @brief logs the outcome and stores it in system_events as self_test_passed or self_test_failed
+///
+pub async fn record(client: &Client, checks: &[Check]) -> Result<(), tokio_postgres::Error> {
+    let passed = checks.iter().all(|c| c.passed);
+    let (priority, event) = if passed { (6, "self_test_passed") } else { (3, "self_test_failed") };
+    let summary = summary(checks);
+    logging::send(priority, &summary, &[("SERVICE", &"daemon"), ("EVENT", &event)]);
+    client.execute("INSERT INTO system_events (event, detail) VALUES ($1, $2)", &[&event, &summary]).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    pub fn test_summary() {
+        let now = Utc::now();
+        assert_eq!(clock(now, now - Duration::seconds(4), Some(now - Duration::minutes(5)), 30), Ok("within 4 s of the database".to_string()));
+        assert_eq!(clock(now, now + Duration::seconds(90), None, 30), Err("is -90 s off the database's".to_string()));
+        assert!(clock(now, now, Some(now + Duration::days(40)), 30).unwrap_err().starts_with("is behind the newest stored reading"));
+
+        let mut checks = vec![
+            Check::new("pump", Ok("drew 0.412 A".to_string())),
+            Check::new("sensor", Ok("read 21.5 C, 72.0 % RH".to_string())),
+        ];
+        assert_eq!(summary(&checks), "Self-test passed: pump drew 0.412 A, sensor read 21.5 C, 72.0 % RH");
+        checks.push(Check::new("fan", Err("drew only 0.001 A when switched on".to_string())));
+        assert_eq!(summary(&checks), "Self-test failed 1 of 3 checks: fan drew only 0.001 A when switched on");
+    }
+}
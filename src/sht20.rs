@@ -1,6 +1,7 @@
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use rppal::i2c::I2c;
+use crate::hal::{HalError, I2cDevice, Platform};
+use crate::reading::Reading;
 use std::{error, fmt};
 
 const I2C_GPIO_BUS: u8              = 1;
@@ -17,22 +18,36 @@ const _SOFT_RESET: u8                = 0b11111110;
 
 const LSB_STATUS_MASK: u16           = 0x03;
 
+//
+// @brief  longest conversion time, 14-bit temperature (datasheet sec. 2.4)
+//
+const MEAS_TIME_MS: u64              = 85;
+
+//
+// @brief  what the sensor is specified to measure (datasheet sec. 2.1); near saturation
+//         humidity reads a little over 100 % and is clipped, further off it is a fault
+//
+const TEMP_RANGE_CELSIUS: (f32, f32) = (-40.0, 125.0);
+const RH_SATURATION_MARGIN: f32      = 5.0;
+
 pub type Result<T> = std::result::Result<T, ShtError>;
 
 #[derive(Debug)]
 pub enum ShtError {
-    MeasInProgress,
     BytesReadMismatch,
-    I2c(rppal::i2c::Error),
+    OutOfRange(Measurement, f32),
+    I2c(HalError),
 }
 
 impl fmt::Display for ShtError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            ShtError::MeasInProgress => 
-                write!(f, "Measurement in progress"),
             ShtError::BytesReadMismatch => 
                 write!(f, "Unexpected number of bytes read"),
+            ShtError::OutOfRange(Measurement::Temperature, value) =>
+                write!(f, "Temperature {:.2} C is outside what the sensor can measure", value),
+            ShtError::OutOfRange(Measurement::Humidity, value) =>
+                write!(f, "Relative humidity {:.2} % is outside 0 % to 100 %", value),
             ShtError::I2c(..) => 
                 write!(f, "I2C error"),
         }
@@ -42,87 +57,72 @@ impl fmt::Display for ShtError {
 impl error::Error for ShtError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
-            ShtError::MeasInProgress => None,
             ShtError::BytesReadMismatch => None,
+            ShtError::OutOfRange(..) => None,
             ShtError::I2c(ref e) => Some(e),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Measurement {
     Temperature,
     Humidity,
 }
 
+///
+/// @brief SHT20 temperature/humidity sensor, shared as `Arc<Mutex<SHT20>>`
+///
+/// @note the sensor converts one measurement at a time; each request holds the lock from
+///       trigger to read, and tokio's mutex is fair, so requests arriving together (the API
+///       and the climate tick, say) queue up in order instead of failing
+///
 pub struct SHT20 {
-    i2c: I2c,
-    measurement_type: Option<Measurement>,
-    in_progress: bool,
+    i2c: Box<dyn I2cDevice>,
+    sensor_id: String,
 }
 
 impl SHT20 {
 
-    pub fn new() -> Result<SHT20> {
-        match I2c::with_bus(I2C_GPIO_BUS) {
-            Ok(mut i2c_device) => 
-                if let Err(e) = i2c_device.set_slave_address(SHT20_ADDR as u16) {
-                    return Err(ShtError::I2c(e));
-                } else {
-                    return Ok(
-                        SHT20 {
-                            i2c: i2c_device,
-                            measurement_type: None,
-                            in_progress: false,
-                        })
-                },
-            Err(e) => {
-                return Err(ShtError::I2c(e));
-            }, 
-        }
+    pub fn new(platform: &dyn Platform) -> Result<SHT20> {
+        Self::with_bus(platform, I2C_GPIO_BUS)
     }
 
-    pub async fn get_temperature_celsius(sht20: Arc<Mutex<Self>>) -> Result<f32> {
-        let mut sht20 = sht20.lock().await;
-        sht20.trigger_temp_measurement()?;
-        std::thread::sleep(std::time::Duration::from_millis(85));
-        return sht20.read_measurement();
+    ///
+    /// @brief an SHT20 on another bus; the address is fixed, so a second sensor needs its own bus
+    ///
+    pub fn with_bus(platform: &dyn Platform, bus: u8) -> Result<SHT20> {
+        let i2c = platform.i2c(bus, SHT20_ADDR as u16).map_err(ShtError::I2c)?;
+        Ok(SHT20 { i2c, sensor_id: format!("sht20@i2c-{}", bus) })
     }
 
-    pub async fn get_humidity_percent(sht20: Arc<Mutex<Self>>) -> Result<f32> {
+    pub async fn get_temperature_celsius(sht20: Arc<Mutex<Self>>) -> Result<Reading<f32>> {
         let mut sht20 = sht20.lock().await;
-        sht20.trigger_humidity_measurement()?;
-        std::thread::sleep(std::time::Duration::from_millis(85));
-        return sht20.read_measurement();
+        let temperature = sht20.measure(TEMP_MEAS_NO_HOLD_MASTER).await?;
+        Ok(Reading::now(temperature, &sht20.sensor_id))
     }
 
-    fn trigger_temp_measurement(&mut self) -> Result<()> {
-       
-        if self.in_progress { 
-            return Err(ShtError::MeasInProgress);
-        }
-
-        match self.i2c.write(&[TEMP_MEAS_NO_HOLD_MASTER]) {
-           Ok(_) => {
-               self.in_progress = true; 
-               Ok(())
-           }
-           Err(e) => Err(ShtError::I2c(e))
-        }
+    ///
+    /// @brief temperature and humidity measured back to back under one lock, so nothing
+    ///        queued in between can separate them
+    ///
+    /// @return (temperature in C, relative humidity in %), taken when the humidity
+    ///         conversion finished
+    ///
+    pub async fn read_climate(sht20: Arc<Mutex<Self>>) -> Result<Reading<(f32, f32)>> {
+        let mut sht20 = sht20.lock().await;
+        let temperature = sht20.measure(TEMP_MEAS_NO_HOLD_MASTER).await?;
+        let humidity = sht20.measure(RH_MEAS_NO_HOLD_MASTER).await?;
+        Ok(Reading::now((temperature, humidity), &sht20.sensor_id))
     }
 
-    fn trigger_humidity_measurement(&mut self) -> Result<()> {
-
-        if self.in_progress { 
-            return Err(ShtError::MeasInProgress);
-        }
-
-        match self.i2c.write(&[RH_MEAS_NO_HOLD_MASTER]) {
-           Ok(_) => {
-               self.in_progress = true; 
-               Ok(())
-           },
-           Err(e) => Err(ShtError::I2c(e))
-        }
+    ///
+    /// @brief triggers `command`, waits out the conversion and reads the result
+    ///
+    async fn measure(&mut self, command: u8) -> Result<f32> {
+        self.i2c.write(&[command]).map_err(ShtError::I2c)?;
+        tokio::time::sleep(std::time::Duration::from_millis(MEAS_TIME_MS)).await;
+        self.read_measurement()
     }
 
     fn read_measurement(&mut self) -> Result<f32> {
@@ -130,42 +130,41 @@ impl SHT20 {
         const EXPECTED_BYTES: usize = 2;
         let mut raw_bytes: [u8; EXPECTED_BYTES] = [0, 0];
 
-        if let Ok(EXPECTED_BYTES) = self.i2c.read(&mut raw_bytes[..]) {
-
-            let data: u16 = (raw_bytes[0] as u16) << 8 | raw_bytes[1] as u16;
-            if data & LSB_STATUS_MASK == 0 {
-                // it is a temperature measurement - use 14-bit representation
-                self.measurement_type = Some(Measurement::Temperature);
-                self.in_progress = false;
-                return Ok(Self::convert_temp(data & !LSB_STATUS_MASK));
-            } else {
-                // it is a relative humidity measurement - use 12-bit representation
-                self.measurement_type = Some(Measurement::Humidity);
-                self.in_progress = false;
-                return Ok(Self::convert_humidity(data & !LSB_STATUS_MASK));
-            }
-
-        } else { 
-            self.in_progress = false;
+        let Ok(EXPECTED_BYTES) = self.i2c.read(&mut raw_bytes[..]) else {
             return Err(ShtError::BytesReadMismatch);
-        } 
+        };
+        let data: u16 = (raw_bytes[0] as u16) << 8 | raw_bytes[1] as u16;
+        if data & LSB_STATUS_MASK == 0 {
+            // it is a temperature measurement - use 14-bit representation
+            Self::check(Measurement::Temperature, Self::convert_temp(data & !LSB_STATUS_MASK))
+        } else {
+            // it is a relative humidity measurement - use 12-bit representation
+            Self::check(Measurement::Humidity, Self::convert_humidity(data & !LSB_STATUS_MASK))
+        }
     }
 
-    #[allow(dead_code)]
-    pub fn get_measurement_type(self) -> Option<Measurement> {
-        return self.measurement_type;
+    ///
+    /// @brief rejects values the sensor can't have measured, clipping humidity that reads
+    ///        just over 100 % at saturation
+    ///
+    fn check(measurement: Measurement, value: f32) -> Result<f32> {
+        match measurement {
+            Measurement::Temperature if (TEMP_RANGE_CELSIUS.0..=TEMP_RANGE_CELSIUS.1).contains(&value) => Ok(value),
+            Measurement::Humidity if (0.0..=100.0 + RH_SATURATION_MARGIN).contains(&value) => Ok(value.min(100.0)),
+            _ => Err(ShtError::OutOfRange(measurement, value)),
+        }
     }
 
     fn convert_humidity(raw_humidity: u16) -> f32 {
         // SHT20 datasheet sec. 6.1:
         // RH [%] = -6 + 125 * S_RH / 2^16
-        return -6.0 + 125.0 * raw_humidity as f32 / 65536.0;
+        -6.0 + 125.0 * raw_humidity as f32 / 65536.0
     }
 
     fn convert_temp(raw_temp: u16) -> f32 {
         // SHT20 datasheet sec. 6.2:
         // T [Celsius] = -46.85 + 175.72 * S_T / 2^16
-        return -46.85 + 175.72 * raw_temp as f32 / 65536.0;
+        -46.85 + 175.72 * raw_temp as f32 / 65536.0
     }
 } 
 
@@ -175,8 +174,19 @@ mod tests {
 
     #[tokio::test]
     pub async fn test_sht20() {
-        let mut s = Arc::new(Mutex::new(SHT20::new().expect("Failed to create SHT20")));
+        let platform = crate::hal::RppalPlatform::new().expect("Cannot get access to GPIO");
+        let s = Arc::new(Mutex::new(SHT20::new(&platform).expect("Failed to create SHT20")));
         SHT20::get_temperature_celsius(s).await.expect("Failed to get temperature");
     }
+
+    #[test]
+    pub fn test_check() {
+        assert_eq!(SHT20::check(Measurement::Humidity, 55.5).ok(), Some(55.5));
+        assert_eq!(SHT20::check(Measurement::Humidity, 102.0).ok(), Some(100.0));
+        assert!(matches!(SHT20::check(Measurement::Humidity, -3.0), Err(ShtError::OutOfRange(Measurement::Humidity, _))));
+        assert!(matches!(SHT20::check(Measurement::Humidity, 118.0), Err(ShtError::OutOfRange(Measurement::Humidity, _))));
+        assert_eq!(SHT20::check(Measurement::Temperature, -12.0).ok(), Some(-12.0));
+        assert!(matches!(SHT20::check(Measurement::Temperature, 128.9), Err(ShtError::OutOfRange(Measurement::Temperature, _))));
+    }
 }
 
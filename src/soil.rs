@@ -0,0 +1,161 @@
+use crate::ads1115::ADS1115;
+use crate::config::Config;
+use crate::db;
+use crate::fleet;
+use crate::hal;
+use crate::output;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use tokio::time::{sleep, Duration};
+use tokio_postgres::Client;
+
+//
+// @brief  readings taken per calibration step, the median is kept
+//
+const SAMPLES: usize = 15;
+const SAMPLE_GAP_MILLIS: u64 = 200;
+
+//
+// @brief  a probe whose air and water readings are closer than this can't tell wet from dry
+//
+const MIN_SPAN_VOLTS: f64 = 0.1;
+
+///
+/// @brief one probe's readings in dry air, in water and in saturated soil
+///
+/// @note capacitive probes read lower the wetter they are and resistive ones higher, the
+///       curve works either way round
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Calibration {
+    pub air_volts: f64,
+    pub water_volts: f64,
+    pub soil_volts: f64,
+}
+
+impl Calibration {
+
+    ///
+    /// @brief moisture in percent of saturation: 0 reading like air, 100 like the saturated
+    ///        soil, clipped to that range
+    ///
+    pub fn moisture(&self, volts: f64) -> f64 {
+        (100.0 * (volts - self.air_volts) / (self.soil_volts - self.air_volts)).clamp(0.0, 100.0)
+    }
+
+    ///
+    /// @return what is wrong with the readings, None if they make a usable curve
+    ///
+    pub fn problem(&self) -> Option<String> {
+        let span = self.water_volts - self.air_volts;
+        if span.abs() < MIN_SPAN_VOLTS {
+            return Some(format!("air ({:.3} V) and water ({:.3} V) read almost the same, is the probe connected to this channel?",
+                self.air_volts, self.water_volts));
+        }
+        let soil = (self.soil_volts - self.air_volts) / span;
+        if !(0.05..=1.05).contains(&soil) {
+            return Some(format!("saturated soil ({:.3} V) should read between air ({:.3} V) and water ({:.3} V)",
+                self.soil_volts, self.air_volts, self.water_volts));
+        }
+        None
+    }
+}
+
+///
+/// @brief the newest calibration of each ADC channel
+///
+pub async fn load(client: &Client) -> Result<HashMap<String, Calibration>, tokio_postgres::Error> {
+    let rows = client.query("SELECT DISTINCT ON (channel) channel, air_volts, water_volts, soil_volts
+        FROM probe_calibrations WHERE mine(device_id) ORDER BY channel, calibrated_at DESC", &[]).await?;
+    Ok(rows.iter().map(|row| (row.get(0), Calibration { air_volts: row.get(1), water_volts: row.get(2), soil_volts: row.get(3) })).collect())
+}
+
+fn median(mut volts: Vec<f64>) -> f64 {
+    volts.sort_by(f64::total_cmp);
+    volts[volts.len() / 2]
+}
+
+// with `--json` the prompts go to stderr, stdout only gets the result
+fn say(json: bool, text: &str) -> io::Result<()> {
+    match json {
+        true => write!(io::stderr(), "{}", text),
+        false => write!(io::stdout(), "{}", text).and_then(|_| io::stdout().flush()),
+    }
+}
+
+async fn step(adc: &mut ADS1115, spec: &crate::config::AdcChannelSpec, prompt: &str, json: bool) -> Result<f64, Box<dyn Error>> {
+    say(json, &format!("{}, then press Enter ", prompt))?;
+    io::stdin().lock().read_line(&mut String::new())?;
+
+    let mut volts = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        volts.push(adc.read_voltage(spec).await?.value as f64);
+        sleep(Duration::from_millis(SAMPLE_GAP_MILLIS)).await;
+    }
+    let volts = median(volts);
+    say(json, &format!("  {:.3} V\n", volts))?;
+    Ok(volts)
+}
+
+///
+/// @brief `bonsai-bot calibrate <channel>`: walks through reading a soil probe in air, water
+///        and saturated soil and stores the result for the daemon to turn its readings into
+///        moisture with
+///
+/// @note still interactive with `json`, the prompts and readings go to stderr and the saved
+///       calibration to stdout
+///
+pub async fn run(channel: String, json: bool) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let adc_config = config.adc.as_ref().ok_or("No [adc] section in the config")?;
+    let spec = adc_config.channels.iter().find(|c| c.name == channel)
+        .ok_or_else(|| format!("No [[adc.channels]] entry is named {:?}", channel))?;
+
+    let platform = hal::open(&config.platform)?;
+    let mut adc = ADS1115::new(platform.as_ref(), adc_config.bus, adc_config.address)?;
+    say(json, &format!("Calibrating {} (ADC channel {}), wipe the probe clean and dry first.\n", spec.name, spec.channel))?;
+
+    let air_volts = step(&mut adc, spec, "Hold the probe in the air", json).await?;
+    let water_volts = step(&mut adc, spec, "Stand it in a glass of water up to the line", json).await?;
+    let soil_volts = step(&mut adc, spec, "Push it into the pot's soil just after a thorough watering has drained", json).await?;
+    let calibration = Calibration { air_volts, water_volts, soil_volts };
+    if let Some(problem) = calibration.problem() {
+        return Err(format!("Not saved, {}", problem).into());
+    }
+
+    let (client, connection) = db::establish_connection().await?;
+    let connection = tokio::spawn(connection);
+    fleet::ensure(&client).await?;
+    client.execute("INSERT INTO probe_calibrations (channel, calibrated_at, air_volts, water_volts, soil_volts) VALUES ($1, now(), $2, $3, $4)",
+        &[&spec.name, &air_volts, &water_volts, &soil_volts]).await?;
+    if json {
+        output::json(&serde_json::json!({ "channel": spec.name, "calibration": calibration }));
+    } else {
+        println!("Saved. {} now reads 0 % in air and 100 % in saturated soil; restart the daemon to use it.", spec.name);
+    }
+
+    drop(client);
+    let _ = connection.await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_calibration() {
+        // capacitive v1.2 probe on 3.3 V: lower is wetter
+        let probe = Calibration { air_volts: 2.8, water_volts: 1.2, soil_volts: 1.4 };
+        assert_eq!(probe.problem(), None);
+        assert!((probe.moisture(2.1) - 50.0).abs() < 1e-9);
+        assert_eq!(probe.moisture(3.0), 0.0);
+        assert_eq!(probe.moisture(1.25), 100.0);
+
+        assert!(Calibration { air_volts: 2.8, water_volts: 2.75, soil_volts: 2.78 }.problem().is_some());
+        assert!(Calibration { air_volts: 2.8, water_volts: 1.2, soil_volts: 2.9 }.problem().is_some());
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), 2.0);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::analyze::{self, Sample};
+use crate::db;
+use crate::output;
+use crate::tuner::MAX_GAP_MINS;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Utc};
+use serde::Serialize;
+use std::error::Error;
+use tokio_postgres::Client;
+
+///
+/// @brief how well the humidifier held the band over a week, Monday to Monday local time
+///
+/// @note `in_band_pct` is the health score; `max_above` and `max_below` are the furthest RH
+///       got outside the band, in RH points
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub week_start: DateTime<Utc>,
+    pub rh_low: f64,
+    pub rh_high: f64,
+    pub in_band_pct: f64,
+    pub duty_pct: f64,
+    pub max_above: f64,
+    pub max_below: f64,
+    pub longest_outside_mins: f64,
+}
+
+impl Report {
+    pub fn summary(&self) -> String {
+        format!("Week of {}: RH within {:.0}-{:.0} % {:.1} % of the time, humidifier on {:.1} %, \
+            at worst {:.1} above and {:.1} below, longest {:.0} min outside",
+            self.week_start.with_timezone(&Local).format("%Y-%m-%d"), self.rh_low, self.rh_high, self.in_band_pct,
+            self.duty_pct, self.max_above, self.max_below, self.longest_outside_mins)
+    }
+}
+
+///
+/// @return the week before the one `now` is in, from Monday midnight to Monday midnight
+///
+pub fn last_week<Tz: TimeZone>(now: DateTime<Tz>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let monday = now.date_naive() - Duration::days(now.weekday().num_days_from_monday() as i64);
+    let midnight = |date: chrono::NaiveDate| {
+        let at = date.and_time(NaiveTime::MIN);
+        // a DST change at midnight, where midnight doesn't exist, counts from an hour later
+        now.timezone().from_local_datetime(&at).earliest()
+            .or_else(|| now.timezone().from_local_datetime(&(at + Duration::hours(1))).earliest())
+            .map(|at| at.with_timezone(&Utc))
+            .unwrap_or_else(|| at.and_utc())
+    };
+    (midnight(monday - Duration::days(7)), midnight(monday))
+}
+
+///
+/// @return the week's report from its readings, None without any to judge it by
+///
+pub fn report(samples: &[Sample], week_start: DateTime<Utc>, rh_low: f64, rh_high: f64) -> Option<Report> {
+    let outcome = analyze::recorded(samples, rh_low, rh_high);
+    let (mut covered, mut outside, mut longest) = (0.0, 0.0, 0.0_f64);
+    let (mut max_above, mut max_below) = (0.0_f64, 0.0_f64);
+    for pair in samples.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let mins = (b.at - a.at).num_seconds() as f64 / 60.0;
+        if mins <= 0.0 || mins > MAX_GAP_MINS as f64 {
+            outside = 0.0;
+            continue;
+        }
+        covered += mins;
+        max_above = max_above.max(a.rh - rh_high);
+        max_below = max_below.max(rh_low - a.rh);
+        outside = if (rh_low..=rh_high).contains(&a.rh) { 0.0 } else { outside + mins };
+        longest = longest.max(outside);
+    }
+    if covered == 0.0 {
+        return None;
+    }
+    Some(Report {
+        week_start,
+        rh_low,
+        rh_high,
+        in_band_pct: outcome.in_band_pct,
+        duty_pct: 100.0 * outcome.runtime_mins / covered,
+        max_above,
+        max_below,
+        longest_outside_mins: longest,
+    })
+}
+
+///
+/// @brief reports on last week if nobody has yet; the daemon calls this every hour, so a
+///        week the daemon was down at the end of still gets its report
+///
+/// @return the report, None when last week already has one or had no readings
+///
+pub async fn catch_up(client: &Client, rh_low: f64, rh_high: f64) -> Result<Option<Report>, tokio_postgres::Error> {
+    let (start, end) = last_week(Local::now());
+    if client.query_opt("SELECT 1 FROM stability_reports WHERE week_start = $1 AND mine(device_id)", &[&start]).await?.is_some() {
+        return Ok(None);
+    }
+    let Some(report) = report(&analyze::load(client, start, end).await?, start, rh_low, rh_high) else {
+        return Ok(None);
+    };
+    let stored = client.execute("
+        INSERT INTO stability_reports (week_start, rh_low, rh_high, in_band_pct, duty_pct, max_above, max_below, longest_outside_mins)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT DO NOTHING",
+        &[&report.week_start, &report.rh_low, &report.rh_high, &report.in_band_pct, &report.duty_pct,
+          &report.max_above, &report.max_below, &report.longest_outside_mins]).await?;
+    Ok(Some(report).filter(|_| stored == 1))
+}
+
+///
+/// @brief `bonsai-bot stability`: the last `weeks` reports, newest first
+///
+pub async fn print(weeks: u32, json: bool) -> Result<(), Box<dyn Error>> {
+    let (client, connection) = db::establish_connection().await?;
+    let connection = tokio::spawn(connection);
+    let rows = client.query("
+        SELECT week_start, rh_low, rh_high, in_band_pct, duty_pct, max_above, max_below, longest_outside_mins
+        FROM stability_reports WHERE mine(device_id) ORDER BY week_start DESC LIMIT $1", &[&(weeks as i64)]).await;
+    drop(client);
+    let _ = connection.await;
+    let reports: Vec<Report> = rows?.iter().map(|row| Report {
+        week_start: row.get(0),
+        rh_low: row.get(1),
+        rh_high: row.get(2),
+        in_band_pct: row.get(3),
+        duty_pct: row.get(4),
+        max_above: row.get(5),
+        max_below: row.get(6),
+        longest_outside_mins: row.get(7),
+    }).collect();
+
+    if json {
+        output::json(&reports);
+        return Ok(());
+    }
+    if reports.is_empty() {
+        println!("No weekly reports yet, the daemon writes one after each week it ran");
+        return Ok(());
+    }
+    println!("{:<10} {:>9} {:>8} {:>6} {:>6} {:>7} {:>12}", "WEEK", "BAND", "IN BAND", "DUTY", "ABOVE", "BELOW", "LONGEST OUT");
+    for r in &reports {
+        println!("{:<10} {:>9} {:>7.1}% {:>5.1}% {:>6.1} {:>7.1} {:>8.0} min", r.week_start.with_timezone(&Local).format("%Y-%m-%d"),
+            format!("{:.0}-{:.0} %", r.rh_low, r.rh_high), r.in_band_pct, r.duty_pct, r.max_above, r.max_below, r.longest_outside_mins);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    #[test]
+    pub fn test_report() {
+        // Wednesday 2024-05-08 15:00 at UTC-6: last week began Monday 2024-04-29 06:00 UTC
+        let now = FixedOffset::west_opt(6 * 3600).unwrap().with_ymd_and_hms(2024, 5, 8, 15, 0, 0).unwrap();
+        let at = |d, h| Utc.with_ymd_and_hms(2024, 5, d, h, 0, 0).unwrap();
+        let (start, end) = last_week(now);
+        assert_eq!((start, end), (Utc.with_ymd_and_hms(2024, 4, 29, 6, 0, 0).unwrap(), at(6, 6)));
+
+        // an hour at 72 %, 20 min dipping to 66 with the humidifier on, then a gap and 10 min at 82
+        let mut samples = Vec::new();
+        let t0 = at(1, 0);
+        for minute in (0..60).step_by(5) {
+            samples.push(Sample { at: t0 + Duration::minutes(minute), rh: 72.0, on: false });
+        }
+        for (i, rh) in [69.0, 66.0, 67.0, 69.5].into_iter().enumerate() {
+            samples.push(Sample { at: t0 + Duration::minutes(60 + 5 * i as i64), rh, on: true });
+        }
+        samples.push(Sample { at: t0 + Duration::minutes(80), rh: 72.0, on: false });
+        for minute in [200, 205, 210] {
+            samples.push(Sample { at: t0 + Duration::minutes(minute), rh: 82.0, on: false });
+        }
+
+        let report = report(&samples, start, 70.0, 80.0).unwrap();
+        assert_eq!(report.in_band_pct, 100.0 * 60.0 / 90.0);
+        assert_eq!(report.duty_pct, 100.0 * 20.0 / 90.0);
+        assert_eq!((report.max_above, report.max_below, report.longest_outside_mins), (2.0, 4.0, 20.0));
+        assert!(report.summary().contains("RH within 70-80 % 66.7 % of the time, humidifier on 22.2 %"));
+        assert_eq!(super::report(&samples[..1], start, 70.0, 80.0), None);
+
+        // nothing but compact storage's 30 min keyframes still makes a week
+        let keyframes: Vec<_> = (0..4).map(|i| Sample { at: t0 + Duration::minutes(MAX_GAP_MINS * i), rh: 72.0, on: false }).collect();
+        assert_eq!(super::report(&keyframes, start, 70.0, 80.0).map(|r| r.in_band_pct), Some(100.0));
+    }
+}
@@ -0,0 +1,279 @@
+use crate::audit::{self, Origin};
+use crate::compact::Compactor;
+use crate::config::CompactConfig;
+use crate::db;
+use crate::error;
+use crate::ipc::Request;
+use crate::irrigation::Delivery;
+use crate::logging;
+use crate::{lease, maintenance, retention, usage};
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+
+//
+// @brief  writes held while the database is slow or away, about a day of readings
+//
+pub const QUEUE_CAPACITY: usize = 4096;
+
+///
+/// @brief a row the control loop wants stored, without waiting for it
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Write {
+    Climate { at: DateTime<Utc>, temperature: f64, humidity: f64, variant: Option<&'static str> },
+    Ambient { at: DateTime<Utc>, temperature: f64, humidity: f64, temperature_delta: f64, humidity_delta: f64 },
+    Analog { at: DateTime<Utc>, channel: String, voltage: f64, moisture: Option<f64> },
+    Door { opened_at: DateTime<Utc>, closed_at: DateTime<Utc> },
+    Actuator { at: DateTime<Utc>, actuator: &'static str, on: bool, variant: Option<&'static str> },
+    Usage { at: DateTime<Utc>, actuator: &'static str, seconds: f64, volume_ml: f64 },
+    Alert { at: DateTime<Utc>, kind: &'static str, message: String },
+    Wind { at: DateTime<Utc>, speed_kmh: f64 },
+    LeafWetness { at: DateTime<Utc>, wetness: f64, fungal_risk: f64 },
+    Weight { at: DateTime<Utc>, cell: &'static str, grams: f64 },
+    PumpStart { at: DateTime<Utc> },
+    PumpEnd { started_at: DateTime<Utc>, ended_at: DateTime<Utc>, seconds: f64, deliveries: Vec<Delivery> },
+    Audit { origin: Origin, request: Request },
+    Serviced { name: String, origin: Origin },
+    SystemEvent { event: &'static str, detail: String },
+    // the rollups and pruning, if `holder` gets the retention lease
+    Retention { holder: String, ttl_secs: u64, raw_days: Option<u32> },
+}
+
+struct Queue {
+    writes: Mutex<VecDeque<Write>>,
+    capacity: usize,
+    dropped: AtomicU64,
+    compactor: Option<Mutex<Compactor>>,
+    compacted: AtomicU64,
+    ready: Notify,
+}
+
+///
+/// @brief hands rows to the storage task; sending never waits on the database
+///
+/// @note once `capacity` writes are waiting the oldest is dropped to make room and
+///       counted, recent readings matter more than a complete backlog
+/// @note with compact storage, readings within their deadband are counted and left out;
+///       dropped ones are counted as dropped, not compacted
+///
+#[derive(Clone)]
+pub struct Writer(Arc<Queue>);
+
+impl Writer {
+
+    fn with_capacity(capacity: usize, compact: Option<CompactConfig>) -> Writer {
+        Writer(Arc::new(Queue {
+            writes: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            dropped: AtomicU64::new(0),
+            compactor: compact.map(|config| Mutex::new(Compactor::new(config))),
+            compacted: AtomicU64::new(0),
+            ready: Notify::new(),
+        }))
+    }
+
+    pub fn send(&self, write: Write) {
+        // held until the write is queued, so the compactor measures against what was
+        let mut compactor = self.0.compactor.as_ref().and_then(|c| c.lock().ok());
+        if compactor.as_ref().is_some_and(|c| !c.keep(&write)) {
+            self.0.compacted.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let Ok(mut writes) = self.0.writes.lock() else {
+            return;
+        };
+        if writes.len() >= self.0.capacity {
+            if let (Some(oldest), Some(compactor)) = (writes.pop_front(), compactor.as_mut()) {
+                compactor.lost(&oldest);
+            }
+            self.0.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(compactor) = compactor.as_mut() {
+            compactor.kept(&write);
+        }
+        writes.push_back(write);
+        self.0.ready.notify_one();
+    }
+
+    ///
+    /// @brief writes dropped to make room since the daemon started
+    ///
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// @brief readings compact storage left out since the daemon started
+    ///
+    pub fn compacted(&self) -> u64 {
+        self.0.compacted.load(Ordering::Relaxed)
+    }
+
+    fn take(&self) -> Option<Write> {
+        self.0.writes.lock().ok()?.pop_front()
+    }
+
+    ///
+    /// @brief puts back a write the database couldn't take, unless newer ones filled its place
+    ///
+    fn retry(&self, write: Write) {
+        let mut compactor = self.0.compactor.as_ref().and_then(|c| c.lock().ok());
+        if let Ok(mut writes) = self.0.writes.lock() {
+            if writes.len() < self.0.capacity {
+                writes.push_front(write);
+                return;
+            }
+        }
+        if let Some(compactor) = compactor.as_mut() {
+            compactor.lost(&write);
+        }
+        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    ///
+    /// @brief notes a write the database refused, compact storage doesn't measure against it
+    ///
+    fn refused(&self, write: &Write) {
+        if let Some(Ok(mut compactor)) = self.0.compactor.as_ref().map(|c| c.lock()) {
+            compactor.lost(write);
+        }
+    }
+}
+
+async fn execute(client: &mut tokio_postgres::Client, write: &Write) -> Result<(), tokio_postgres::Error> {
+    match write {
+        Write::Climate { at, temperature, humidity, variant } =>
+            client.execute("INSERT INTO climate_data (timestamp, temperature, humidity, is_pump_start, variant) VALUES ($1, $2, $3, FALSE, $4)",
+                &[at, temperature, humidity, variant]).await.map(drop),
+        Write::Ambient { at, temperature, humidity, temperature_delta, humidity_delta } =>
+            client.execute("INSERT INTO ambient_data (timestamp, temperature, humidity, temperature_delta, humidity_delta) VALUES ($1, $2, $3, $4, $5)",
+                &[at, temperature, humidity, temperature_delta, humidity_delta]).await.map(drop),
+        Write::Analog { at, channel, voltage, moisture } =>
+            client.execute("INSERT INTO analog_data (timestamp, channel, voltage, moisture) VALUES ($1, $2, $3, $4)",
+                &[at, channel, voltage, moisture]).await.map(drop),
+        Write::Door { opened_at, closed_at } =>
+            client.execute("INSERT INTO door_log (opened_at, closed_at) VALUES ($1, $2)",
+                &[opened_at, closed_at]).await.map(drop),
+        Write::Actuator { at, actuator, on, variant } =>
+            client.execute("INSERT INTO actuator_events (timestamp, actuator, is_on, variant) VALUES ($1, $2, $3, $4)",
+                &[at, actuator, on, variant]).await.map(drop),
+        Write::Usage { at, actuator, seconds, volume_ml } =>
+            usage::record(client, actuator, *at, *seconds, *volume_ml).await,
+        Write::Alert { at, kind, message } =>
+            client.execute("INSERT INTO alert_log (timestamp, kind, message) VALUES ($1, $2, $3)",
+                &[at, kind, message]).await.map(drop),
+        Write::Wind { at, speed_kmh } =>
+            client.execute("INSERT INTO wind_data (timestamp, speed_kmh) VALUES ($1, $2)",
+                &[at, speed_kmh]).await.map(drop),
+        Write::LeafWetness { at, wetness, fungal_risk } =>
+            client.execute("INSERT INTO leaf_wetness (timestamp, wetness, fungal_risk) VALUES ($1, $2, $3)",
+                &[at, wetness, fungal_risk]).await.map(drop),
+        Write::Weight { at, cell, grams } =>
+            client.execute("INSERT INTO weight_data (timestamp, cell, grams) VALUES ($1, $2, $3)",
+                &[at, cell, grams]).await.map(drop),
+        Write::PumpStart { at } =>
+            client.execute("INSERT INTO climate_data (timestamp, temperature, humidity, is_pump_start) VALUES ($1, NULL, NULL, TRUE)",
+                &[at]).await.map(drop),
+        Write::PumpEnd { started_at, ended_at, seconds, deliveries } => {
+            let tx = client.transaction().await?;
+            tx.execute("UPDATE climate_data SET pump_ended_at = $2, pump_seconds = COALESCE(pump_seconds, 0) + $3 WHERE is_pump_start AND timestamp = $1",
+                &[started_at, ended_at, seconds]).await?;
+            let stmt = tx.prepare("INSERT INTO irrigation_data (timestamp, zone, seconds, volume_ml) VALUES ($1, $2, $3, $4)").await?;
+            for delivery in deliveries {
+                tx.execute(&stmt, &[started_at, &delivery.zone, &delivery.seconds, &delivery.volume_ml]).await?;
+            }
+            tx.commit().await
+        },
+        Write::Audit { origin, request } =>
+            audit::record_request(client, origin, request).await,
+        Write::Serviced { name, origin } =>
+            maintenance::record_serviced(client, name, origin).await,
+        Write::SystemEvent { event, detail } =>
+            client.execute("INSERT INTO system_events (event, detail) VALUES ($1, $2)",
+                &[event, detail]).await.map(drop),
+        Write::Retention { holder, ttl_secs, raw_days } => {
+            // the rollups and pruning cover every instance's rows, one of them doing it is enough
+            if lease::acquire(client, "retention", holder, *ttl_secs).await? {
+                retention::run(client, *raw_days).await?;
+            }
+            Ok(())
+        },
+    }
+}
+
+///
+/// @brief starts the storage task on its own database connection
+///
+/// @note a write that fails while the connection is up is logged and dropped; one that
+///       fails because it went away is kept until the task has reconnected
+///
+pub async fn spawn(capacity: usize, compact: Option<CompactConfig>) -> error::Result<Writer> {
+    let (mut client, connection) = db::establish_connection().await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            logging::print(3, &format!("Storage connection error: {}", e));
+        }
+    });
+
+    let writer = Writer::with_capacity(capacity, compact);
+    let queue = writer.clone();
+    tokio::spawn(async move {
+        loop {
+            let Some(write) = queue.take() else {
+                queue.0.ready.notified().await;
+                continue;
+            };
+            if let Err(e) = execute(&mut client, &write).await {
+                if !client.is_closed() {
+                    logging::print(3, &format!("Database execute error {:?}", e));
+                    queue.refused(&write);
+                    continue;
+                }
+                queue.retry(write);
+                sleep(Duration::from_secs(db::RECONNECT_SECS)).await;
+                if let Err(e) = db::try_reconnect(&mut client).await {
+                    logging::send(3, &format!("Storage could not reconnect, {} writes waiting: {}", queue.0.writes.lock().map(|w| w.len()).unwrap_or_default(), e),
+                        &[("SERVICE", &"storage"), ("EVENT", &"reconnect_failed")]);
+                }
+            }
+        }
+    });
+    Ok(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_drop_oldest() {
+        let at = Utc::now();
+        let door = |secs| Write::Door { opened_at: at, closed_at: at + chrono::Duration::seconds(secs) };
+        let writer = Writer::with_capacity(2, None);
+        writer.send(door(1));
+        writer.send(door(2));
+        writer.send(door(3));
+        assert_eq!(writer.dropped(), 1);
+        assert_eq!(writer.take(), Some(door(2)));
+
+        writer.send(door(4));
+        writer.retry(door(2));
+        assert_eq!(writer.dropped(), 2);
+        assert_eq!((writer.take(), writer.take(), writer.take()), (Some(door(3)), Some(door(4)), None));
+
+        // a reading dropped for room is counted as dropped, and the next one is stored instead
+        let climate = |secs| Write::Climate { at: at + chrono::Duration::seconds(secs), temperature: 22.0, humidity: 70.0, variant: None };
+        let writer = Writer::with_capacity(1, Some(CompactConfig::default()));
+        writer.send(climate(0));
+        writer.send(climate(10));
+        assert_eq!((writer.compacted(), writer.dropped()), (1, 0));
+        writer.send(door(5));
+        writer.send(climate(20));
+        assert_eq!((writer.compacted(), writer.dropped()), (1, 2));
+        assert_eq!(writer.take(), Some(climate(20)));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::hal::{HalError, OutputLine, Platform, SpiDevice};
+use std::{error, fmt, thread, time};
+
+const FIFO_REG: u8                   = 0x00;
+const OP_MODE_REG: u8                = 0x01;
+const FRF_MSB_REG: u8                = 0x06;
+const LNA_REG: u8                    = 0x0C;
+const FIFO_ADDR_PTR_REG: u8          = 0x0D;
+const FIFO_RX_BASE_ADDR_REG: u8      = 0x0F;
+const FIFO_RX_CURRENT_ADDR_REG: u8   = 0x10;
+const IRQ_FLAGS_REG: u8              = 0x12;
+const RX_NB_BYTES_REG: u8            = 0x13;
+const PKT_SNR_REG: u8                = 0x19;
+const PKT_RSSI_REG: u8               = 0x1A;
+const MODEM_CONFIG_1_REG: u8         = 0x1D;
+const MODEM_CONFIG_2_REG: u8         = 0x1E;
+const MODEM_CONFIG_3_REG: u8         = 0x26;
+const SYNC_WORD_REG: u8              = 0x39;
+const VERSION_REG: u8                = 0x42;
+
+const WRITE: u8                      = 0x80;
+const LONG_RANGE_MODE: u8            = 0x80;
+const MODE_SLEEP: u8                 = 0x00;
+const MODE_RX_CONTINUOUS: u8         = 0x05;
+const LNA_MAX_GAIN_BOOST: u8         = 0x23;
+const IRQ_RX_DONE: u8                = 0x40;
+const IRQ_PAYLOAD_CRC_ERROR: u8      = 0x20;
+const CODING_RATE_4_5: u8            = 0x02;
+const RX_PAYLOAD_CRC_ON: u8          = 0x04;
+const AGC_AUTO_ON: u8                = 0x04;
+const LOW_DATA_RATE_OPTIMIZE: u8     = 0x08;
+const SX1276_VERSION: u8             = 0x12;
+
+const CRYSTAL_HZ: f64                = 32e6;
+const SPI_CLOCK_HZ: u32              = 1_000_000;
+
+//
+// @brief  the bandwidths ModemConfig1 can select, in its own order
+//
+pub const BANDWIDTHS_KHZ: [f64; 10] = [7.8, 10.4, 15.6, 20.8, 31.25, 41.7, 62.5, 125.0, 250.0, 500.0];
+
+pub type Result<T> = std::result::Result<T, SxError>;
+
+#[derive(Debug)]
+pub enum SxError {
+    Version(u8),
+    Spi(HalError),
+}
+
+impl fmt::Display for SxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SxError::Version(version) =>
+                write!(f, "Not an SX1276/77/78/79, version register reads {:#04x}", version),
+            SxError::Spi(..) =>
+                write!(f, "SPI error"),
+        }
+    }
+}
+
+impl error::Error for SxError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            SxError::Spi(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+///
+/// @brief LoRa modem settings; they must match the transmitting nodes'
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub frequency_hz: f64,
+    pub spreading_factor: u8,
+    pub bandwidth_khz: f64,
+    pub sync_word: u8,
+}
+
+///
+/// @brief one packet that arrived with a good CRC
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub payload: Vec<u8>,
+    pub rssi_dbm: i16,
+    pub snr_db: f64,
+}
+
+///
+/// @brief Semtech SX1276/77/78/79 LoRa transceiver (RFM95W, Ra-02, ...) as a receiver
+///
+/// @note DIO0 is not used, `receive` polls the IRQ flags instead
+///
+pub struct SX127x {
+    spi: Box<dyn SpiDevice>,
+    high_band: bool,
+}
+
+///
+/// @brief the three frequency registers for `frequency_hz`, steps of 32 MHz / 2^19
+///
+pub fn frf(frequency_hz: f64) -> [u8; 3] {
+    let frf = (frequency_hz * (1u32 << 19) as f64 / CRYSTAL_HZ).round() as u32;
+    let [_, msb, mid, lsb] = frf.to_be_bytes();
+    [msb, mid, lsb]
+}
+
+impl SX127x {
+
+    ///
+    /// @brief resets the chip if its reset line is wired, checks it is there and leaves it
+    ///        listening continuously
+    ///
+    pub fn new(platform: &dyn Platform, bus: u8, chip_select: u8, reset_pin: Option<u8>, settings: &Settings) -> Result<SX127x> {
+        if let Some(pin) = reset_pin {
+            let mut reset: Box<dyn OutputLine> = platform.output(pin, true).map_err(SxError::Spi)?;
+            reset.set_low().map_err(SxError::Spi)?;
+            thread::sleep(time::Duration::from_millis(1));
+            reset.set_high().map_err(SxError::Spi)?;
+            thread::sleep(time::Duration::from_millis(10));
+        }
+        let spi = platform.spi(bus, chip_select, SPI_CLOCK_HZ).map_err(SxError::Spi)?;
+        let mut sx = SX127x { spi, high_band: settings.frequency_hz > 779e6 };
+
+        let version = sx.read(VERSION_REG)?;
+        if version != SX1276_VERSION {
+            return Err(SxError::Version(version));
+        }
+
+        // LoRa mode can only be entered from sleep
+        sx.write(OP_MODE_REG, LONG_RANGE_MODE | MODE_SLEEP)?;
+        let [msb, mid, lsb] = frf(settings.frequency_hz);
+        sx.write(FRF_MSB_REG, msb)?;
+        sx.write(FRF_MSB_REG + 1, mid)?;
+        sx.write(FRF_MSB_REG + 2, lsb)?;
+        sx.write(FIFO_RX_BASE_ADDR_REG, 0)?;
+        sx.write(LNA_REG, LNA_MAX_GAIN_BOOST)?;
+
+        let bandwidth = BANDWIDTHS_KHZ.iter().position(|&bw| bw == settings.bandwidth_khz).unwrap_or(7) as u8;
+        sx.write(MODEM_CONFIG_1_REG, bandwidth << 4 | CODING_RATE_4_5)?;
+        sx.write(MODEM_CONFIG_2_REG, settings.spreading_factor << 4 | RX_PAYLOAD_CRC_ON)?;
+        // symbols longer than 16 ms need low data rate optimization
+        let symbol_ms = (1u32 << settings.spreading_factor) as f64 / settings.bandwidth_khz;
+        sx.write(MODEM_CONFIG_3_REG, AGC_AUTO_ON | if symbol_ms > 16.0 { LOW_DATA_RATE_OPTIMIZE } else { 0 })?;
+        sx.write(SYNC_WORD_REG, settings.sync_word)?;
+
+        sx.write(OP_MODE_REG, LONG_RANGE_MODE | MODE_RX_CONTINUOUS)?;
+        Ok(sx)
+    }
+
+    ///
+    /// @brief takes the packet received since the last call, if any
+    ///
+    /// @return None when nothing arrived or the packet failed its CRC
+    ///
+    pub fn receive(&mut self) -> Result<Option<Packet>> {
+        let flags = self.read(IRQ_FLAGS_REG)?;
+        if flags & IRQ_RX_DONE == 0 {
+            return Ok(None);
+        }
+        self.write(IRQ_FLAGS_REG, flags)?;
+        if flags & IRQ_PAYLOAD_CRC_ERROR != 0 {
+            return Ok(None);
+        }
+
+        let len = self.read(RX_NB_BYTES_REG)? as usize;
+        let start = self.read(FIFO_RX_CURRENT_ADDR_REG)?;
+        self.write(FIFO_ADDR_PTR_REG, start)?;
+        let mut buf = vec![0u8; len + 1];
+        let mut out = vec![0u8; len + 1];
+        out[0] = FIFO_REG;
+        self.spi.transfer(&out, &mut buf).map_err(SxError::Spi)?;
+
+        let snr_db = self.read(PKT_SNR_REG)? as i8 as f64 / 4.0;
+        let rssi = self.read(PKT_RSSI_REG)? as i16;
+        let rssi_dbm = rssi - if self.high_band { 157 } else { 164 };
+        Ok(Some(Packet { payload: buf.split_off(1), rssi_dbm, snr_db }))
+    }
+
+    fn read(&mut self, reg: u8) -> Result<u8> {
+        let mut buf = [0u8; 2];
+        self.spi.transfer(&[reg & !WRITE, 0], &mut buf).map_err(SxError::Spi)?;
+        Ok(buf[1])
+    }
+
+    fn write(&mut self, reg: u8, value: u8) -> Result<()> {
+        let mut buf = [0u8; 2];
+        self.spi.transfer(&[reg | WRITE, value], &mut buf).map_err(SxError::Spi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_frf() {
+        // the datasheet's defaults: 434 MHz is 0x6C8000, and Europe's and America's LoRa bands
+        assert_eq!(frf(434e6), [0x6c, 0x80, 0x00]);
+        assert_eq!(frf(868e6), [0xd9, 0x00, 0x00]);
+        assert_eq!(frf(915e6), [0xe4, 0xc0, 0x00]);
+    }
+}
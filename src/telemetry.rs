@@ -0,0 +1,115 @@
+use crate::config::TelemetrySpec;
+use crate::events::{Bus, Event};
+use serde_json::{json, Value};
+use crate::logging;
+use tokio::sync::broadcast::error::RecvError;
+
+//
+// @brief  hosted platforms are on the far side of a home internet connection
+//
+const TIMEOUT_SECS: u64 = 10;
+
+///
+/// @brief one HTTP POST to a hosted platform
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Push {
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Value,
+}
+
+///
+/// @brief the requests that put one reading on `spec`'s platform: a single ThingSpeak channel
+///        update, or one data point per Adafruit IO feed
+///
+pub fn pushes(spec: &TelemetrySpec, temperature: f64, humidity: f64) -> Vec<Push> {
+    match spec {
+        TelemetrySpec::Thingspeak { api_key, temperature_field, humidity_field } => vec![Push {
+            url: "https://api.thingspeak.com/update.json".to_string(),
+            headers: Vec::new(),
+            body: json!({
+                "api_key": api_key,
+                format!("field{}", temperature_field): format!("{:.2}", temperature),
+                format!("field{}", humidity_field): format!("{:.2}", humidity),
+            }),
+        }],
+        TelemetrySpec::Adafruit { username, api_key, temperature_feed, humidity_feed } => [
+            (temperature_feed, temperature),
+            (humidity_feed, humidity),
+        ].into_iter().map(|(feed, value)| Push {
+            url: format!("https://io.adafruit.com/api/v2/{}/feeds/{}/data", username, feed),
+            headers: vec![("X-AIO-Key", api_key.clone())],
+            body: json!({ "value": format!("{:.2}", value) }),
+        }).collect(),
+    }
+}
+
+fn send(agent: &ureq::Agent, push: &Push) -> Result<(), Box<ureq::Error>> {
+    let mut request = agent.post(&push.url);
+    for (name, value) in &push.headers {
+        request = request.set(name, value);
+    }
+    request.send_json(&push.body)?;
+    Ok(())
+}
+
+///
+/// @brief pushes every reading on the bus to the configured platforms
+///
+/// @note a reading that can't be delivered is dropped with a journal warning; the database
+///       still has it
+///
+pub fn spawn(specs: Vec<TelemetrySpec>, bus: &Bus) {
+    let mut rx = bus.subscribe();
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs(TIMEOUT_SECS))
+        .build();
+
+    tokio::spawn(async move {
+        loop {
+            let (temperature, humidity) = match rx.recv().await {
+                Ok(Event::Reading { temperature, humidity, .. }) => (temperature, humidity),
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            let pushes: Vec<Push> = specs.iter().flat_map(|spec| pushes(spec, temperature, humidity)).collect();
+            let agent = agent.clone();
+            let failed = tokio::task::spawn_blocking(move || {
+                pushes.iter()
+                    .filter_map(|push| send(&agent, push).err().map(|e| format!("{}: {}", push.url, e)))
+                    .collect::<Vec<_>>()
+            }).await.unwrap_or_default();
+            for failure in failed {
+                logging::print(4, &format!("Telemetry push failed, {}", failure));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_pushes() {
+        let thingspeak = TelemetrySpec::Thingspeak { api_key: "KEY".to_string(), temperature_field: 1, humidity_field: 3 };
+        assert_eq!(pushes(&thingspeak, 21.4, 68.25), vec![Push {
+            url: "https://api.thingspeak.com/update.json".to_string(),
+            headers: vec![],
+            body: json!({ "api_key": "KEY", "field1": "21.40", "field3": "68.25" }),
+        }]);
+
+        let adafruit = TelemetrySpec::Adafruit {
+            username: "jo".to_string(),
+            api_key: "aio_KEY".to_string(),
+            temperature_feed: "bonsai-temp".to_string(),
+            humidity_feed: "bonsai-rh".to_string(),
+        };
+        let urls: Vec<_> = pushes(&adafruit, 21.4, 68.25).into_iter().map(|p| p.url).collect();
+        assert_eq!(urls, vec![
+            "https://io.adafruit.com/api/v2/jo/feeds/bonsai-temp/data",
+            "https://io.adafruit.com/api/v2/jo/feeds/bonsai-rh/data",
+        ]);
+    }
+}
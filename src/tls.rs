@@ -0,0 +1,82 @@
+use crate::config::TlsConfig;
+use axum_server::tls_rustls::RustlsConfig;
+use std::error::Error;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use crate::logging;
+
+///
+/// @brief loads the certificate and key, generating a self-signed pair first if allowed
+///        and neither file exists yet
+///
+pub async fn load(config: &TlsConfig) -> Result<RustlsConfig, Box<dyn Error>> {
+    // the ring provider is the only one built in, a second install is harmless
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    if config.self_signed && !Path::new(&config.cert).exists() && !Path::new(&config.key).exists() {
+        let names = subject_names();
+        generate(&config.cert, &config.key, names.clone())?;
+        logging::print(6, &format!("Generated a self-signed certificate for {} in {}", names.join(", "), config.cert));
+    }
+
+    RustlsConfig::from_pem_file(&config.cert, &config.key).await
+        .map_err(|e| format!("Could not load TLS certificate {} / key {}: {}", config.cert, config.key, e).into())
+}
+
+///
+/// @brief the names phones and browsers will reach us by: the hostname, its mDNS name, localhost
+///
+fn subject_names() -> Vec<String> {
+    let mut names = Vec::new();
+    if let Ok(host) = std::fs::read_to_string("/etc/hostname") {
+        let host = host.trim();
+        if !host.is_empty() {
+            names.push(host.to_string());
+            names.push(format!("{}.local", host));
+        }
+    }
+    names.push("localhost".to_string());
+    names
+}
+
+///
+/// @brief writes a new self-signed certificate and its key, the key readable by us only
+///
+fn generate(cert_path: &str, key_path: &str, names: Vec<String>) -> Result<(), Box<dyn Error>> {
+    let certified = rcgen::generate_simple_self_signed(names)?;
+
+    for path in [cert_path, key_path] {
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    std::fs::write(cert_path, certified.cert.pem())?;
+    std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(key_path)?
+        .write_all(certified.key_pair.serialize_pem().as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_self_signed() {
+        let dir = std::env::temp_dir().join(format!("bonsai-bot-tls-{}", std::process::id()));
+        let config = TlsConfig {
+            cert: dir.join("cert.pem").to_string_lossy().into_owned(),
+            key: dir.join("key.pem").to_string_lossy().into_owned(),
+            self_signed: true,
+        };
+
+        load(&config).await.expect("Could not generate and load a self-signed certificate");
+        let cert = std::fs::read_to_string(&config.cert).unwrap();
+        assert!(cert.starts_with("-----BEGIN CERTIFICATE-----"));
+
+        // a second start reuses the same certificate
+        load(&config).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&config.cert).unwrap(), cert);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
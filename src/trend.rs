@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+
+///
+/// @brief fits a least-squares line through the readings and extrapolates it to `at`
+///
+/// @return None with fewer than three readings, or when they were all taken at once
+///
+pub fn project(readings: &[(DateTime<Utc>, f64)], at: DateTime<Utc>) -> Option<f64> {
+    if readings.len() < 3 {
+        return None;
+    }
+
+    // minutes relative to the first reading keeps the numbers small
+    let origin = readings[0].0;
+    let minutes = |t: DateTime<Utc>| (t - origin).num_milliseconds() as f64 / 60_000.0;
+    let n = readings.len() as f64;
+    let mean_x = readings.iter().map(|(t, _)| minutes(*t)).sum::<f64>() / n;
+    let mean_y = readings.iter().map(|(_, v)| v).sum::<f64>() / n;
+
+    let (mut cov, mut var) = (0.0, 0.0);
+    for (t, v) in readings {
+        let dx = minutes(*t) - mean_x;
+        cov += dx * (v - mean_y);
+        var += dx * dx;
+    }
+    if var == 0.0 {
+        return None;
+    }
+
+    Some(mean_y + cov / var * (minutes(at) - mean_x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    pub fn test_project() {
+        let t0 = Utc::now();
+        let falling: Vec<_> = (0..4).map(|i| (t0 + Duration::minutes(5 * i), 76.0 - 1.5 * i as f64)).collect();
+
+        let projected = project(&falling, t0 + Duration::minutes(20)).unwrap();
+        assert!((projected - 70.0).abs() < 1e-9);
+        assert_eq!(project(&falling[..2], t0), None);
+        assert_eq!(project(&[(t0, 70.0), (t0, 71.0), (t0, 72.0)], t0), None);
+    }
+}
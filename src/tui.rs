@@ -0,0 +1,232 @@
+use crate::config::Config;
+use crate::ipc::{self, Request, Response, StatusReport};
+use crate::lifecycle;
+use chrono::{DateTime, Local, Utc};
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+use serde::Deserialize;
+use std::error::Error;
+use std::time::Duration;
+
+//
+// @brief  how often the daemon is asked again, and how long a key press can take to count
+//
+const REFRESH_SECS: u64 = 2;
+
+//
+// @brief  history shown in the sparklines and the log, taken from the daemon's memory
+//
+const HISTORY_MINS: i64 = 120;
+
+///
+/// @brief an event as the daemon hands it out, see events::Event
+///
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Logged {
+    Reading { at: DateTime<Utc>, temperature: f64, humidity: f64 },
+    Actuator { at: DateTime<Utc>, name: String, on: bool },
+    Door { at: DateTime<Utc>, open: bool },
+    Alert { at: DateTime<Utc>, kind: String, message: String },
+    Summary { at: DateTime<Utc>, message: String },
+}
+
+///
+/// @brief what the screen shows, refreshed from the control socket
+///
+#[derive(Default)]
+struct View {
+    status: Option<StatusReport>,
+    events: Vec<Logged>,
+    error: Option<String>,
+}
+
+impl View {
+    async fn refresh(&mut self, socket: &str) {
+        let (status, recent) = (ipc::request(socket, &Request::Status).await, ipc::request(socket, &Request::Recent { minutes: HISTORY_MINS }).await);
+        match (status, recent) {
+            (Ok(Response::Status(status)), Ok(Response::Recent { events })) => {
+                self.status = Some(status);
+                self.events = events.into_iter().filter_map(|e| serde_json::from_value(e).ok()).collect();
+                self.error = None;
+            },
+            (Err(e), _) | (_, Err(e)) => self.error = Some(format!("{}: {}", socket, e)),
+            (Ok(Response::Error { message }), _) | (_, Ok(Response::Error { message })) => self.error = Some(message),
+            _ => self.error = Some("unexpected response from the daemon".to_string()),
+        }
+    }
+
+    ///
+    /// @brief one series of the readings, scaled to tenths for the sparkline
+    ///
+    fn series(&self, pick: fn(f64, f64) -> f64) -> Vec<u64> {
+        self.events.iter().filter_map(|e| match e {
+            Logged::Reading { temperature, humidity, .. } => Some((pick(*temperature, *humidity) * 10.0).max(0.0) as u64),
+            _ => None,
+        }).collect()
+    }
+}
+
+///
+/// @brief `bonsai-bot tui`: a live view of the running daemon until `q`
+///
+pub async fn run() -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let mut terminal = ratatui::init();
+    let result = monitor(&mut terminal, &config.ipc.socket).await;
+    ratatui::restore();
+    result
+}
+
+async fn monitor(terminal: &mut DefaultTerminal, socket: &str) -> Result<(), Box<dyn Error>> {
+    let mut view = View::default();
+    loop {
+        view.refresh(socket).await;
+        terminal.draw(|frame| draw(frame, &view))?;
+        let quit = tokio::task::spawn_blocking(|| -> std::io::Result<bool> {
+            if !event::poll(Duration::from_secs(REFRESH_SECS))? {
+                return Ok(false);
+            }
+            Ok(match event::read()? {
+                TermEvent::Key(key) if key.kind == KeyEventKind::Press =>
+                    matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                        || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)),
+                _ => false,
+            })
+        }).await??;
+        if quit {
+            return Ok(());
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, view: &View) {
+    let [top, sparks, log, help] = Layout::vertical([Constraint::Length(9), Constraint::Length(8), Constraint::Min(3), Constraint::Length(1)])
+        .areas(frame.area());
+    let [readings, actuators] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(top);
+    draw_readings(frame, readings, view);
+    draw_actuators(frame, actuators, view);
+
+    let [temperature, humidity] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(sparks);
+    for (area, title, pick, color) in [
+        (temperature, "Temperature", (|t, _| t) as fn(f64, f64) -> f64, Color::Red),
+        (humidity, "Humidity", |_, rh| rh, Color::Cyan),
+    ] {
+        let data = view.series(pick);
+        // the lowest reading sits on the floor, or a 2 C swing would be a flat line at 20 C
+        let floor = data.iter().min().copied().unwrap_or(0).saturating_sub(1);
+        let data: Vec<u64> = data.iter().map(|v| v - floor).collect();
+        frame.render_widget(Sparkline::default()
+            .block(Block::bordered().title(format!("{} ({} min)", title, HISTORY_MINS)))
+            .data(&data[data.len().saturating_sub(area.width.saturating_sub(2) as usize)..])
+            .style(Style::default().fg(color)), area);
+    }
+
+    let lines: Vec<Line> = view.events.iter().rev().filter_map(|e| {
+        let (at, text, color) = match e {
+            Logged::Reading { .. } => return None,
+            Logged::Actuator { at, name, on } => (at, format!("{} {}", name, if *on { "on" } else { "off" }), Color::Reset),
+            Logged::Door { at, open } => (at, format!("door {}", if *open { "opened" } else { "closed" }), Color::Reset),
+            Logged::Alert { at, kind, message } => (at, format!("{}: {}", kind, message), Color::Yellow),
+            Logged::Summary { at, message } => (at, message.clone(), Color::Cyan),
+        };
+        Some(Line::from(vec![Span::raw(format!("{}  ", at.with_timezone(&Local).format("%H:%M:%S"))), Span::styled(text, Style::default().fg(color))]))
+    }).take(log.height.saturating_sub(2) as usize).collect();
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Events, newest first")), log);
+    frame.render_widget(Line::from(format!(" q quit, refreshes every {} s", REFRESH_SECS)).dim(), help);
+}
+
+fn draw_readings(frame: &mut Frame, area: Rect, view: &View) {
+    let mut lines = Vec::new();
+    if let Some(error) = &view.error {
+        lines.push(Line::from(format!("Daemon unreachable, {}", error)).red());
+    }
+    if let Some(status) = &view.status {
+        let now = Utc::now();
+        match (status.temperature, status.humidity, status.measured_at) {
+            (Some(t), Some(rh), Some(at)) => {
+                lines.push(Line::from(format!("{:.1} C   {:.1} % RH", t, rh)).bold());
+                lines.push(Line::from(format!("read {} ago", lifecycle::format_uptime(now - at))));
+            },
+            _ => lines.push(Line::from("no reading yet")),
+        }
+        let mut flags = Vec::new();
+        if status.shadow {
+            flags.push("shadow mode".to_string());
+        }
+        if status.dormant {
+            flags.push("dormant".to_string());
+        }
+        if status.door_open {
+            flags.push("door open".to_string());
+        }
+        if let Some(variant) = &status.variant {
+            flags.push(format!("variant {}", variant));
+        }
+        if let Some(profile) = &status.profile {
+            flags.push(format!("profile {}", profile));
+        }
+        if !flags.is_empty() {
+            lines.push(Line::from(flags.join(", ")).yellow());
+        }
+        if let Some(next) = status.next_pump {
+            lines.push(Line::from(format!("next pump run {}", next.with_timezone(&Local).format("%a %H:%M"))));
+        }
+        for (name, grams) in [("tank", status.tank_grams), ("pot", status.pot_grams)] {
+            if let Some(grams) = grams {
+                lines.push(Line::from(format!("{} {:.0} g", name, grams)));
+            }
+        }
+        if let Some(started) = status.started_at {
+            lines.push(Line::from(format!("up {}", lifecycle::format_uptime(now - started))).dim());
+        }
+    }
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Enclosure")), area);
+}
+
+fn draw_actuators(frame: &mut Frame, area: Rect, view: &View) {
+    let Some(status) = &view.status else {
+        frame.render_widget(Block::bordered().title("Actuators"), area);
+        return;
+    };
+    let state = |name: &str, on: bool| Line::from(vec![
+        Span::raw(format!("{:<12}", name)),
+        if on { Span::styled("ON", Style::default().fg(Color::Green).bold()) } else { Span::raw("off").dim() },
+    ]);
+    let mut lines = vec![
+        state("humidifier", status.humidifier_on),
+        state("pump", status.pump_on),
+        state("fan", status.fan_on),
+        state("mister", status.mister_on),
+        state("heater", status.heater_on),
+        state("light", status.light_on),
+    ];
+    if let Some(reason) = &status.heater_latched {
+        lines.push(Line::from(format!("heater LATCHED: {}", reason)).red());
+    }
+    if let Some(kmh) = status.wind_kmh {
+        lines.push(Line::from(format!("wind {:.1} km/h", kmh)));
+    }
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Actuators")), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_view() {
+        let events: Vec<serde_json::Value> = serde_json::from_str(r#"[
+            {"event": "reading", "at": "2024-05-01T10:00:00Z", "temperature": 21.0, "humidity": 70.5},
+            {"event": "actuator", "at": "2024-05-01T10:00:01Z", "name": "humidifier", "on": true},
+            {"event": "reading", "at": "2024-05-01T10:01:00Z", "temperature": 21.3, "humidity": 72.0}]"#).unwrap();
+        let view = View { events: events.into_iter().filter_map(|e| serde_json::from_value(e).ok()).collect(), ..Default::default() };
+        assert_eq!(view.events.len(), 3);
+        assert_eq!(view.series(|t, _| t), vec![210, 213]);
+        assert_eq!(view.series(|_, rh| rh), vec![705, 720]);
+    }
+}
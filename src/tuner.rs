@@ -0,0 +1,140 @@
+use crate::config::{Config, TunerConfig};
+use crate::{db, output};
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use std::error::Error;
+use tokio_postgres::Client;
+
+//
+// @brief  gaps between readings longer than this (daemon down, sensor dead) aren't rates
+//
+pub const MAX_GAP_MINS: i64 = 30;
+
+///
+/// @brief how the enclosure behaves, measured from history: RH gained per minute while the
+///        humidifier is on, RH lost per minute while it's off, and how often it switches on
+///
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct Rates {
+    pub rise_per_min: f64,
+    pub decay_per_min: f64,
+    pub cycles_per_hour: f64,
+}
+
+///
+/// @brief averages the RH slope between consecutive readings over the last `days`, split by
+///        whichever way the humidifier was switched at the first of the two
+///
+pub async fn measure(client: &Client, days: u32) -> Result<Rates, tokio_postgres::Error> {
+    let since = Utc::now() - Duration::days(days as i64);
+    let rows = client.query("
+        WITH readings AS (
+            SELECT timestamp, humidity,
+                   LEAD(timestamp) OVER (ORDER BY timestamp) AS next_at,
+                   LEAD(humidity) OVER (ORDER BY timestamp) AS next_rh
+            FROM climate_data
+            WHERE NOT is_pump_start AND humidity IS NOT NULL AND timestamp >= $1 AND mine(device_id)
+        )
+        SELECT COALESCE((SELECT e.is_on FROM actuator_events e
+                         WHERE e.actuator = 'humidifier' AND e.timestamp <= r.timestamp AND mine(e.device_id)
+                         ORDER BY e.timestamp DESC LIMIT 1), FALSE) AS is_on,
+               SUM(next_rh - humidity) / NULLIF(SUM(EXTRACT(EPOCH FROM next_at - timestamp)::float8 / 60), 0) AS rate
+        FROM readings r
+        WHERE next_at IS NOT NULL AND next_at - timestamp < $2::bigint * interval '1 minute'
+        GROUP BY 1", &[&since, &MAX_GAP_MINS]).await?;
+
+    let mut rates = Rates::default();
+    for row in rows {
+        let rate: Option<f64> = row.get(1);
+        match row.get::<_, bool>(0) {
+            true => rates.rise_per_min = rate.unwrap_or_default(),
+            false => rates.decay_per_min = -rate.unwrap_or_default(),
+        }
+    }
+    let cycles: i64 = client.query_one("SELECT COUNT(*) FROM actuator_events WHERE actuator = 'humidifier' AND is_on AND timestamp >= $1 AND mine(device_id)",
+        &[&since]).await?.get(0);
+    rates.cycles_per_hour = cycles as f64 / (24.0 * days as f64);
+    Ok(rates)
+}
+
+///
+/// @brief the narrowest band around the current one's midpoint that keeps the humidifier to
+///        `max_cycles_per_hour`, given how fast RH rises and decays, within `min_band`..`max_band`
+///
+/// @return None when the rates make no sense (no humidifier runs, RH not falling) or the band
+///         is already within half a percent of the suggestion
+///
+pub fn suggest(rates: &Rates, rh_low: f64, rh_high: f64, config: &TunerConfig) -> Option<(f64, f64)> {
+    if !(rates.rise_per_min > 0.0 && rates.decay_per_min > 0.0 && config.max_cycles_per_hour > 0.0) {
+        return None;
+    }
+    // one cycle takes width / rise to fill plus width / decay to drain
+    let period_mins = 60.0 / config.max_cycles_per_hour;
+    let width = period_mins * rates.rise_per_min * rates.decay_per_min / (rates.rise_per_min + rates.decay_per_min);
+    let width = width.clamp(config.min_band, config.max_band);
+
+    let middle = (rh_low + rh_high) / 2.0;
+    let half = |rh: f64| (2.0 * rh).round() / 2.0;
+    let (low, high) = (half(middle - width / 2.0).max(0.0), half(middle + width / 2.0).min(100.0));
+    if (low - rh_low).abs() < 0.5 && (high - rh_high).abs() < 0.5 {
+        return None;
+    }
+    Some((low, high))
+}
+
+///
+/// @brief `bonsai-bot tune`: prints the measured rates and the band the tuner would pick
+///
+pub async fn run(json: bool) -> Result<(), Box<dyn Error>> {
+    let config = Config::load()?;
+    let tuner = config.tuner.clone().unwrap_or_default();
+    let (client, connection) = db::establish_connection().await?;
+    let connection = tokio::spawn(connection);
+
+    let rates = measure(&client, tuner.days).await?;
+    let (rh_low, rh_high) = (config.humidifier.rh_low, config.humidifier.rh_high);
+    let suggested = suggest(&rates, rh_low, rh_high, &tuner);
+    if json {
+        output::json(&serde_json::json!({
+            "days": tuner.days,
+            "rates": rates,
+            "band": { "rh_low": rh_low, "rh_high": rh_high },
+            "suggested": suggested.map(|(low, high)| serde_json::json!({ "rh_low": low, "rh_high": high })),
+        }));
+    } else {
+        println!("Over the last {} days:", tuner.days);
+        println!("  RH rises  {:.2} %/min with the humidifier on", rates.rise_per_min);
+        println!("  RH decays {:.2} %/min with it off", rates.decay_per_min);
+        println!("  {:.1} humidifier cycles per hour", rates.cycles_per_hour);
+        match suggested {
+            Some((low, high)) => println!("Suggested band {:.1}-{:.1} %, currently {:.1}-{:.1} %", low, high, rh_low, rh_high),
+            None => println!("No change suggested to the {:.1}-{:.1} % band", rh_low, rh_high),
+        }
+    }
+
+    drop(client);
+    let _ = connection.await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_suggest() {
+        let config = TunerConfig::default();
+        // 1 %/min up, 0.25 %/min down: a 10 % band takes 10 + 40 = 50 min, 4 cycles/hour needs only 3 %,
+        // so min_band it is
+        let rates = Rates { rise_per_min: 1.0, decay_per_min: 0.25, cycles_per_hour: 6.0 };
+        assert_eq!(suggest(&rates, 70.0, 80.0, &config), Some((73.0, 77.0)));
+
+        // quick both ways and one cycle an hour: wants 60 %, gets max_band
+        let config = TunerConfig { max_cycles_per_hour: 1.0, ..config };
+        let rates = Rates { rise_per_min: 2.0, decay_per_min: 2.0, cycles_per_hour: 3.0 };
+        assert_eq!(suggest(&rates, 70.0, 80.0, &config), Some((67.0, 83.0)));
+
+        assert_eq!(suggest(&rates, 67.0, 83.0, &config), None);
+        assert_eq!(suggest(&Rates::default(), 70.0, 80.0, &config), None);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::alerts;
+use crate::config::UpdateConfig;
+use crate::events::Bus;
+use crate::logging;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::Deserialize;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
+
+const TIMEOUT_SECS: u64 = 60;
+
+//
+// @brief  a download bigger than this isn't a bonsai-bot binary
+//
+const MAX_BINARY_BYTES: u64 = 64 * 1024 * 1024;
+
+///
+/// @brief the parts of a GitHub release (GET /repos/{owner}/{repo}/releases/latest) the
+///        updater reads; a self-hosted `release_url` serves the same shape
+///
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+///
+/// @return whether `tag` (e.g. "v0.1.2") is a later version than `current`; tags that
+///         aren't plain dotted numbers, release candidates included, never are
+///
+pub fn newer(tag: &str, current: &str) -> bool {
+    let parse = |v: &str| v.trim_start_matches('v').split('.').map(|part| part.parse::<u64>().ok()).collect::<Option<Vec<u64>>>();
+    match (parse(tag), parse(current)) {
+        (Some(tag), Some(current)) => tag > current,
+        _ => false,
+    }
+}
+
+///
+/// @brief the binary for this machine's architecture and its detached signature, named
+///        `bonsai-bot-<arch>` and `bonsai-bot-<arch>.sig` in the release
+///
+pub fn assets_for<'a>(release: &'a Release, arch: &str) -> Option<(&'a Asset, &'a Asset)> {
+    let name = format!("bonsai-bot-{}", arch);
+    let binary = release.assets.iter().find(|a| a.name == name)?;
+    let signature = release.assets.iter().find(|a| a.name == format!("{}.sig", name))?;
+    Some((binary, signature))
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+///
+/// @brief checks `binary` against its hex ed25519 `signature` and the hex `public_key`
+///
+pub fn verify(public_key: &str, binary: &[u8], signature: &str) -> Result<(), String> {
+    let key = unhex(public_key).filter(|k| k.len() == 32).ok_or("update.public_key isn't 32 bytes of hex")?;
+    let signature = unhex(signature).ok_or("the signature isn't hex")?;
+    UnparsedPublicKey::new(&ED25519, key).verify(binary, &signature).map_err(|_| "bad signature".to_string())
+}
+
+fn download(agent: &ureq::Agent, url: &str) -> Result<Vec<u8>, String> {
+    let response = agent.get(url).call().map_err(|e| format!("{}: {}", url, e))?;
+    let mut body = Vec::new();
+    response.into_reader().take(MAX_BINARY_BYTES + 1).read_to_end(&mut body).map_err(|e| format!("{}: {}", url, e))?;
+    if body.len() as u64 > MAX_BINARY_BYTES {
+        return Err(format!("{} is over {} MiB", url, MAX_BINARY_BYTES / 1024 / 1024));
+    }
+    Ok(body)
+}
+
+///
+/// @brief looks for a newer release and, if there is one, puts its binary in place of ours;
+///        the running daemon carries on with the old one until it restarts
+///
+/// @return the version installed, None when already up to date
+///
+pub fn check(config: &UpdateConfig) -> Result<Option<String>, String> {
+    let agent = ureq::AgentBuilder::new().timeout(std::time::Duration::from_secs(TIMEOUT_SECS)).build();
+    let release: Release = agent.get(&config.release_url)
+        .set("Accept", "application/vnd.github+json")
+        .set("User-Agent", concat!("bonsai-bot/", env!("CARGO_PKG_VERSION")))
+        .call().map_err(|e| format!("{}: {}", config.release_url, e))?
+        .into_json().map_err(|e| format!("{}: {}", config.release_url, e))?;
+    if !newer(&release.tag_name, env!("CARGO_PKG_VERSION")) {
+        return Ok(None);
+    }
+
+    let arch = std::env::consts::ARCH;
+    let (binary, signature) = assets_for(&release, arch)
+        .ok_or_else(|| format!("release {} has no signed binary for {}", release.tag_name, arch))?;
+    let bytes = download(&agent, &binary.browser_download_url)?;
+    let signature = String::from_utf8(download(&agent, &signature.browser_download_url)?).map_err(|_| "the signature isn't hex".to_string())?;
+    verify(&config.public_key, &bytes, &signature).map_err(|e| format!("{} not installed, {}", binary.name, e))?;
+
+    let path = match &config.install_path {
+        Some(path) => PathBuf::from(path),
+        None => std::env::current_exe().map_err(|e| e.to_string())?,
+    };
+    // renaming over the binary swaps it whole, the running process keeps the old inode
+    let staged = path.with_extension("new");
+    let installed = std::fs::write(&staged, &bytes)
+        .and_then(|_| std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755)))
+        .and_then(|_| std::fs::rename(&staged, &path));
+    if let Err(e) = installed {
+        let _ = std::fs::remove_file(&staged);
+        return Err(format!("cannot replace {}: {}", path.display(), e));
+    }
+    Ok(Some(release.tag_name))
+}
+
+///
+/// @brief checks every `every_hours`; with `restart` set, a new binary asks the daemon to
+///        stop through `restart` so systemd starts it again
+///
+/// @note failures raise an `update_failed` alert
+///
+pub fn spawn(config: UpdateConfig, restart: mpsc::Sender<&'static str>, bus: Bus) {
+    tokio::spawn(async move {
+        loop {
+            let checked = {
+                let config = config.clone();
+                tokio::task::spawn_blocking(move || check(&config)).await.unwrap_or_else(|e| Err(e.to_string()))
+            };
+            match checked {
+                Ok(Some(version)) => {
+                    logging::send(5, &format!("Installed bonsai-bot {}, it runs from the next restart", version),
+                        &[("SERVICE", &"update"), ("EVENT", &"installed"), ("VERSION", &version)]);
+                    if config.restart {
+                        let _ = restart.send("update").await;
+                        return;
+                    }
+                },
+                Ok(None) => {},
+                Err(e) => alerts::raise(&bus, "update_failed", &format!("Update check failed: {}", e)),
+            }
+            sleep(Duration::from_secs(config.every_hours * 60 * 60)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    #[test]
+    pub fn test_update() {
+        assert!(newer("v0.1.2", "0.1.1"));
+        assert!(newer("0.2.0", "0.1.10"));
+        assert!(!newer("v0.1.1", "0.1.1"));
+        assert!(!newer("v0.2.0-rc1", "0.1.1"));
+
+        let release: Release = serde_json::from_str(r#"{"tag_name": "v0.1.2", "assets": [
+            {"name": "bonsai-bot-aarch64", "browser_download_url": "https://example.com/a"},
+            {"name": "bonsai-bot-aarch64.sig", "browser_download_url": "https://example.com/a.sig"},
+            {"name": "bonsai-bot-arm", "browser_download_url": "https://example.com/b"}]}"#).unwrap();
+        assert_eq!(assets_for(&release, "aarch64").map(|(b, s)| (b.name.as_str(), s.name.as_str())),
+            Some(("bonsai-bot-aarch64", "bonsai-bot-aarch64.sig")));
+        assert_eq!(assets_for(&release, "arm"), None);
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        let (key, signature) = (hex(pair.public_key().as_ref()), hex(pair.sign(b"binary").as_ref()));
+        assert_eq!(verify(&key, b"binary", &format!("{}\n", signature)), Ok(()));
+        assert!(verify(&key, b"tampered", &signature).is_err());
+        assert!(verify("abcd", b"binary", &signature).is_err());
+    }
+}
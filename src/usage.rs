@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio_postgres::Client;
+
+///
+/// @brief when each actuator last switched on, to work out how long it ran once it switches off
+///
+#[derive(Debug, Default)]
+pub struct Tracker {
+    since: HashMap<&'static str, DateTime<Utc>>,
+}
+
+impl Tracker {
+
+    ///
+    /// @return how many seconds the actuator ran for, when this switched it off
+    ///
+    pub fn switched(&mut self, name: &'static str, on: bool, at: DateTime<Utc>) -> Option<f64> {
+        if on {
+            self.since.entry(name).or_insert(at);
+            return None;
+        }
+        let since = self.since.remove(name)?;
+        Some((at - since).num_milliseconds().max(0) as f64 / 1000.0)
+    }
+}
+
+///
+/// @brief runtime and water use of one actuator, over a month or all time
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Usage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub month: Option<String>,
+    pub actuator: String,
+    pub hours: f64,
+    pub liters: f64,
+}
+
+///
+/// @brief adds a finished run to the month it ended in
+///
+pub async fn record(client: &Client, actuator: &str, at: DateTime<Utc>, seconds: f64, volume_ml: f64) -> Result<(), tokio_postgres::Error> {
+    client.execute("INSERT INTO usage_monthly (month, actuator, seconds, volume_ml)
+        VALUES (date_trunc('month', $1::timestamptz AT TIME ZONE 'UTC')::date, $2, $3, $4)
+        ON CONFLICT (device_id, month, actuator) DO UPDATE SET
+            seconds = usage_monthly.seconds + EXCLUDED.seconds,
+            volume_ml = usage_monthly.volume_ml + EXCLUDED.volume_ml",
+        &[&at, &actuator, &seconds, &volume_ml]).await?;
+    Ok(())
+}
+
+///
+/// @brief usage per month, newest first
+///
+pub async fn monthly(client: &Client) -> Result<Vec<Usage>, tokio_postgres::Error> {
+    let rows = client.query("SELECT to_char(month, 'YYYY-MM'), actuator, SUM(seconds) / 3600.0, SUM(volume_ml) / 1000.0
+        FROM usage_monthly WHERE mine(device_id) GROUP BY month, actuator ORDER BY month DESC, actuator", &[]).await?;
+    Ok(rows.iter().map(|row| Usage { month: Some(row.get(0)), actuator: row.get(1), hours: row.get(2), liters: row.get(3) }).collect())
+}
+
+///
+/// @brief usage since records began
+///
+pub async fn totals(client: &Client) -> Result<Vec<Usage>, tokio_postgres::Error> {
+    let rows = client.query("SELECT actuator, SUM(seconds) / 3600.0, SUM(volume_ml) / 1000.0
+        FROM usage_monthly WHERE mine(device_id) GROUP BY actuator ORDER BY actuator", &[]).await?;
+    Ok(rows.iter().map(|row| Usage { month: None, actuator: row.get(0), hours: row.get(1), liters: row.get(2) }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    pub fn test_tracker() {
+        let start = Utc::now();
+        let mut tracker = Tracker::default();
+        assert_eq!(tracker.switched("pump", false, start), None);
+        assert_eq!(tracker.switched("pump", true, start), None);
+        assert_eq!(tracker.switched("fan", true, start + Duration::seconds(5)), None);
+        assert_eq!(tracker.switched("pump", false, start + Duration::milliseconds(60500)), Some(60.5));
+        assert_eq!(tracker.switched("pump", false, start + Duration::seconds(90)), None);
+        assert_eq!(tracker.switched("fan", false, start + Duration::seconds(35)), Some(30.0));
+    }
+}
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+///
+/// @brief exactly which build is running, for bug reports and `GET /version`
+///
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub built_at: Option<DateTime<Utc>>,
+    pub features: Vec<&'static str>,
+    // sha256 of the config file as loaded, None when running on defaults
+    pub config_sha256: Option<String>,
+}
+
+impl BuildInfo {
+    pub fn new(config_path: &str) -> BuildInfo {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("BONSAIBOT_GIT_COMMIT"),
+            built_at: env!("BONSAIBOT_BUILD_EPOCH").parse().ok().and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            features: features(),
+            config_sha256: std::fs::read(config_path).ok().map(|bytes| hex_sha256(&bytes)),
+        }
+    }
+
+    ///
+    /// @brief `bonsai-bot --version --verbose`
+    ///
+    pub fn print(&self) {
+        println!("bonsai-bot {}", self.version);
+        println!("commit:   {}", self.git_commit);
+        match self.built_at {
+            Some(at) => println!("built:    {}", at.format("%Y-%m-%d %H:%M:%S %Z")),
+            None => println!("built:    unknown"),
+        }
+        println!("features: {}", if self.features.is_empty() { "none".to_string() } else { self.features.join(", ") });
+        println!("config:   {}", self.config_sha256.as_deref().unwrap_or("none (defaults)"));
+    }
+}
+
+///
+/// @brief optional cargo features this binary was built with
+///
+pub fn features() -> Vec<&'static str> {
+    [
+        ("cloud", cfg!(feature = "cloud")),
+        ("ble", cfg!(feature = "ble")),
+        ("mqtt", cfg!(feature = "mqtt")),
+        ("homekit", cfg!(feature = "homekit")),
+        ("archive", cfg!(feature = "archive")),
+        ("update", cfg!(feature = "update")),
+        ("chaos", cfg!(feature = "chaos")),
+        ("dbus", cfg!(feature = "dbus")),
+        ("mdns", cfg!(feature = "mdns")),
+        ("parquet", cfg!(feature = "parquet")),
+        ("tls", cfg!(feature = "tls")),
+        ("tui", cfg!(feature = "tui")),
+    ].into_iter().filter(|(_, on)| *on).map(|(name, _)| name).collect()
+}
+
+fn hex_sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_build_info() {
+        let info = BuildInfo::new("/nonexistent/config.toml");
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_commit.is_empty());
+        assert!(info.built_at.is_some());
+        assert_eq!(info.config_sha256, None);
+        assert_eq!(hex_sha256(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
@@ -0,0 +1,62 @@
+///
+/// @brief drops the first readings after boot, and again after the sensor stops answering:
+///        the first SHT20 measurement after power-up reads well off, enough to start the
+///        humidifier for nothing
+///
+/// @note discarded readings are logged but neither stored nor acted on
+///
+pub struct WarmUp {
+    readings: u32,
+    left: u32,
+}
+
+impl WarmUp {
+    pub fn new(readings: u32) -> WarmUp {
+        WarmUp { readings, left: readings }
+    }
+
+    ///
+    /// @brief counts a reading
+    ///
+    /// @return true if it's one of the first `warmup_readings` and shouldn't be used
+    ///
+    pub fn discard(&mut self) -> bool {
+        if self.left == 0 {
+            return false;
+        }
+        self.left -= 1;
+        true
+    }
+
+    ///
+    /// @brief a reading failed, and a sensor that dropped off the bus may have power-cycled
+    ///
+    pub fn failure(&mut self) {
+        self.left = self.readings;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_warmup() {
+        let mut warmup = WarmUp::new(2);
+        assert!(warmup.discard());
+        assert!(warmup.discard());
+        assert!(!warmup.discard());
+
+        warmup.failure();
+        assert!(warmup.discard());
+        warmup.failure();
+        assert!(warmup.discard());
+        assert!(warmup.discard());
+        assert!(!warmup.discard());
+
+        let mut off = WarmUp::new(0);
+        assert!(!off.discard());
+        off.failure();
+        assert!(!off.discard());
+    }
+}
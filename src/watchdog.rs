@@ -0,0 +1,149 @@
+use crate::config::WatchdogConfig;
+use crate::logging;
+use chrono::Utc;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use systemd::daemon;
+
+//
+// @brief  how often the device is fed; the bcm2835 driver reboots after 15 s without
+//         a write unless booted with a different bcm2835_wdt.heartbeat
+//
+const FEED_SECS: u64 = 5;
+
+//
+// @brief  written before closing the device, tells the driver the close is deliberate
+//
+const MAGIC_CLOSE: &[u8] = b"V";
+
+///
+/// @brief whether a heartbeat at `last` still counts as alive at `now`
+///
+pub fn alive(last: i64, now: i64, stall_secs: u64) -> bool {
+    now - last <= stall_secs as i64
+}
+
+///
+/// @brief holds the hardware watchdog open; the Pi reboots once the runtime stops
+///        heartbeating for `stall_secs` and the device goes unfed
+///
+/// @note systemd can only restart a process that exits, this also catches the daemon (or
+///       the whole of userspace) hanging without exiting
+///
+pub struct Watchdog {
+    device: Arc<Mutex<File>>,
+    disarmed: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+
+    ///
+    /// @brief opens the device, which arms it, and starts feeding it
+    ///
+    pub fn open(config: &WatchdogConfig) -> io::Result<Watchdog> {
+        let device = Arc::new(Mutex::new(OpenOptions::new().write(true).open(&config.device)?));
+        let disarmed = Arc::new(AtomicBool::new(false));
+        let last_beat = Arc::new(AtomicI64::new(Utc::now().timestamp()));
+
+        // the heartbeat only runs while the runtime still schedules tasks
+        let beat = last_beat.clone();
+        tokio::spawn(async move {
+            let mut every = tokio::time::interval(tokio::time::Duration::from_secs(1));
+            loop {
+                every.tick().await;
+                beat.store(Utc::now().timestamp(), Ordering::Relaxed);
+            }
+        });
+
+        // and the feeding on a thread of its own, so a wedged runtime can't feed it
+        let (feed_device, feed_disarmed, stall_secs) = (device.clone(), disarmed.clone(), config.stall_secs);
+        std::thread::spawn(move || {
+            let mut stalled = false;
+            loop {
+                std::thread::sleep(Duration::from_secs(FEED_SECS));
+                let Ok(mut device) = feed_device.lock() else {
+                    return;
+                };
+                if feed_disarmed.load(Ordering::Relaxed) {
+                    return;
+                }
+                let last = last_beat.load(Ordering::Relaxed);
+                if !alive(last, Utc::now().timestamp(), stall_secs) {
+                    if !stalled {
+                        logging::send(2, &format!("No heartbeat for {} s, leaving the hardware watchdog to reboot", Utc::now().timestamp() - last),
+                            &[("SERVICE", &"watchdog"), ("EVENT", &"stall")]);
+                        stalled = true;
+                    }
+                    continue;
+                }
+                if let Err(e) = device.write_all(b"\0") {
+                    logging::send(3, &format!("Could not feed the hardware watchdog: {}", e),
+                        &[("SERVICE", &"watchdog"), ("EVENT", &"error")]);
+                }
+            }
+        });
+
+        Ok(Watchdog { device, disarmed })
+    }
+
+    ///
+    /// @brief stops feeding and disarms the device so a clean shutdown doesn't reboot the Pi
+    ///
+    /// @note a daemon that dies without getting here leaves it armed, the Pi then reboots
+    ///       unless systemd restarts the daemon before the device times out
+    ///
+    pub fn disarm(&self) {
+        let Ok(mut device) = self.device.lock() else {
+            return;
+        };
+        self.disarmed.store(true, Ordering::Relaxed);
+        if let Err(e) = device.write_all(MAGIC_CLOSE) {
+            logging::send(3, &format!("Could not disarm the hardware watchdog: {}", e),
+                &[("SERVICE", &"watchdog"), ("EVENT", &"error")]);
+        }
+    }
+}
+
+///
+/// @brief tells systemd the daemon is up and, under a unit with WatchdogSec=, pings it at
+///        half that from a runtime task so a wedged runtime gets the daemon restarted
+///
+/// @note does nothing outside a `Type=notify` unit
+///
+pub fn notify_ready() {
+    let _ = daemon::notify(false, [(daemon::STATE_READY, "1")].into_iter());
+    let usec = match daemon::watchdog_enabled(false) {
+        Ok(usec) if usec > 0 => usec,
+        _ => return,
+    };
+    tokio::spawn(async move {
+        let mut every = tokio::time::interval(tokio::time::Duration::from_micros(usec / 2));
+        loop {
+            every.tick().await;
+            let _ = daemon::notify(false, [(daemon::STATE_WATCHDOG, "1")].into_iter());
+        }
+    });
+}
+
+///
+/// @brief tells systemd the daemon is shutting down, so the time spent switching things off
+///        isn't taken for a hang
+///
+pub fn notify_stopping() {
+    let _ = daemon::notify(false, [(daemon::STATE_STOPPING, "1")].into_iter());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_alive() {
+        assert!(alive(1_000, 1_000, 60));
+        assert!(alive(1_000, 1_060, 60));
+        assert!(!alive(1_000, 1_061, 60));
+    }
+}
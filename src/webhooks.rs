@@ -0,0 +1,139 @@
+use crate::config::WebhookSpec;
+use crate::events::{Bus, Event};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::logging;
+use tokio::sync::broadcast::error::RecvError;
+
+//
+// @brief  the first retry waits this long, each one after twice as long as the last
+//
+const RETRY_BASE_SECS: u64 = 2;
+
+//
+// @brief  retries never wait longer than this, however many `retries` a hook asks for
+//
+const RETRY_MAX_SECS: u64 = 300;
+
+const TIMEOUT_SECS: u64 = 10;
+
+///
+/// @brief the webhook event names `event` counts as; a pump switching off is also
+///        `pump_complete`
+///
+pub fn kinds(event: &Event) -> Vec<&'static str> {
+    match event {
+        Event::Reading { .. } => vec!["reading"],
+        Event::Actuator { name: "pump", on: false, .. } => vec!["actuator", "pump_complete"],
+        Event::Actuator { .. } => vec!["actuator"],
+        Event::Door { .. } => vec!["door"],
+        Event::Alert { .. } => vec!["alert"],
+        Event::Summary { .. } => vec!["summary"],
+    }
+}
+
+///
+/// @brief `X-Bonsai-Signature` for `body`: hex HMAC-SHA256 keyed with the hook's secret
+///
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    let digest: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", digest)
+}
+
+///
+/// @brief how long to wait before retry number `attempt`, counting from 0
+///
+pub fn backoff(attempt: u32) -> std::time::Duration {
+    let secs = 1u64.checked_shl(attempt).map_or(RETRY_MAX_SECS, |factor| RETRY_BASE_SECS.saturating_mul(factor));
+    std::time::Duration::from_secs(secs.min(RETRY_MAX_SECS))
+}
+
+///
+/// @brief POSTs `body` to the hook, retrying all but a refusal `retries` times
+///
+pub fn deliver(agent: &ureq::Agent, hook: &WebhookSpec, kind: &str, body: &[u8]) -> Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        let mut request = agent.post(&hook.url)
+            .set("Content-Type", "application/json")
+            .set("X-Bonsai-Event", kind);
+        if let Some(secret) = &hook.secret {
+            request = request.set("X-Bonsai-Signature", &signature(secret, body));
+        }
+        match request.send_bytes(body) {
+            Ok(_) => return Ok(()),
+            // the receiver understood and refused, sending it again won't help
+            Err(ureq::Error::Status(code, _)) if (400..500).contains(&code) && code != 429 =>
+                return Err(format!("{} answered {}", hook.url, code)),
+            Err(e) if attempt >= hook.retries => return Err(format!("{}: {}", hook.url, e)),
+            Err(_) => {
+                std::thread::sleep(backoff(attempt));
+                attempt += 1;
+            },
+        }
+    }
+}
+
+///
+/// @brief POSTs every bus event a hook asked for to its URL, the event as JSON
+///
+/// @note a failed delivery is only logged; raising an alert would feed the alert hooks
+///       the very failure they can't deliver
+///
+pub fn spawn(hooks: Vec<WebhookSpec>, bus: &Bus) {
+    let mut rx = bus.subscribe();
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs(TIMEOUT_SECS))
+        .build();
+
+    tokio::spawn(async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            let kinds = kinds(&event);
+            let Ok(body) = serde_json::to_vec(&event) else {
+                continue;
+            };
+            for hook in &hooks {
+                let Some(kind) = kinds.iter().find(|kind| hook.events.iter().any(|e| e == *kind)) else {
+                    continue;
+                };
+                let (agent, hook, kind, body) = (agent.clone(), hook.clone(), *kind, body.clone());
+                // each delivery retries on its own, a slow receiver doesn't hold up the others
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = deliver(&agent, &hook, kind, &body) {
+                        logging::print(4, &format!("Webhook {} not delivered, {}", kind, e));
+                    }
+                });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    pub fn test_webhooks() {
+        let at = Utc::now();
+        assert_eq!(kinds(&Event::Actuator { at, name: "pump", on: false }), vec!["actuator", "pump_complete"]);
+        assert_eq!(kinds(&Event::Actuator { at, name: "pump", on: true }), vec!["actuator"]);
+        assert_eq!(kinds(&Event::Alert { at, kind: "door", message: String::new() }), vec!["alert"]);
+
+        // RFC 4231 test case 2
+        assert_eq!(signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+
+        assert_eq!(backoff(0).as_secs(), 2);
+        assert_eq!(backoff(3).as_secs(), 16);
+        assert_eq!(backoff(10).as_secs(), RETRY_MAX_SECS);
+        assert_eq!(backoff(200).as_secs(), RETRY_MAX_SECS);
+    }
+}
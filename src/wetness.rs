@@ -0,0 +1,162 @@
+use crate::config::LeafWetnessConfig;
+use chrono::{DateTime, Duration, Utc};
+
+//
+// @brief  the generic temperature response of the Magarey et al. (2005) infection model:
+//         no infection below T_MIN or above T_MAX C, fastest at T_OPT
+//
+const T_MIN: f64 = 5.0;
+const T_OPT: f64 = 20.0;
+const T_MAX: f64 = 30.0;
+
+//
+// @brief  wetness needed at the worst temperatures is capped here, beyond it the model
+//         predicts no infection
+//
+const MAX_WET_HOURS: f64 = 48.0;
+
+//
+// @brief  a dry spell shorter than this doesn't end a wet period, spores on the leaf
+//         survive it
+//
+const DRY_RESET_HOURS: i64 = 4;
+
+//
+// @brief  a gap between readings longer than this (daemon down, channel failing) isn't
+//         counted as wet or dry
+//
+const MAX_GAP_MINS: i64 = 60;
+
+///
+/// @brief the grid's reading as a percentage between `dry_volts` and `wet_volts`
+///
+pub fn wetness(config: &LeafWetnessConfig, volts: f64) -> f64 {
+    (100.0 * (volts - config.dry_volts) / (config.wet_volts - config.dry_volts)).clamp(0.0, 100.0)
+}
+
+///
+/// @brief how fast infection progresses at `temp_c`, 1 at the optimum and 0 outside
+///        T_MIN..T_MAX
+///
+pub fn temperature_response(temp_c: f64) -> f64 {
+    if temp_c <= T_MIN || temp_c >= T_MAX {
+        return 0.0;
+    }
+    let rise = (temp_c - T_MIN) / (T_OPT - T_MIN);
+    let fall = (T_MAX - temp_c) / (T_MAX - T_OPT);
+    fall * rise.powf((T_OPT - T_MIN) / (T_MAX - T_OPT))
+}
+
+///
+/// @brief what the latest reading says about the leaves
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Risk {
+    pub wetness: f64,
+    pub wet_hours: f64,
+    pub score: f64,
+    pub alert: bool,
+}
+
+///
+/// @brief tracks wet periods and scores the fungal infection risk of the current one as the
+///        percentage of the wetness needed at its mean temperature, 100 % meaning infection
+///        conditions were met
+///
+pub struct LeafWetness {
+    wet_percent: f64,
+    min_wet_hours: f64,
+    last: Option<DateTime<Utc>>,
+    dry_since: Option<DateTime<Utc>>,
+    wet_secs: f64,
+    temp_secs: f64,
+    temp_sum: f64,
+    alerted: bool,
+}
+
+impl LeafWetness {
+    pub fn new(config: &LeafWetnessConfig) -> LeafWetness {
+        LeafWetness {
+            wet_percent: config.wet_percent,
+            min_wet_hours: config.min_wet_hours,
+            last: None,
+            dry_since: None,
+            wet_secs: 0.0,
+            temp_secs: 0.0,
+            temp_sum: 0.0,
+            alerted: false,
+        }
+    }
+
+    ///
+    /// @brief feeds a wetness reading and the enclosure temperature at the time
+    ///
+    pub fn update(&mut self, at: DateTime<Utc>, wetness: f64, temp_c: Option<f64>) -> Risk {
+        let elapsed = self.last.map(|last| at - last).filter(|gap| *gap <= Duration::minutes(MAX_GAP_MINS));
+        let secs = elapsed.map_or(0.0, |gap| gap.num_milliseconds() as f64 / 1000.0);
+        self.last = Some(at);
+
+        if wetness >= self.wet_percent {
+            self.dry_since = None;
+            self.wet_secs += secs;
+            if let Some(t) = temp_c {
+                self.temp_secs += secs;
+                self.temp_sum += t * secs;
+            }
+        } else if at - *self.dry_since.get_or_insert(at) >= Duration::hours(DRY_RESET_HOURS) {
+            (self.wet_secs, self.temp_secs, self.temp_sum, self.alerted) = (0.0, 0.0, 0.0, false);
+        }
+
+        let wet_hours = self.wet_secs / 3600.0;
+        let response = if self.temp_secs > 0.0 { temperature_response(self.temp_sum / self.temp_secs) } else { 0.0 };
+        let needed = if response > 0.0 { (self.min_wet_hours / response).min(MAX_WET_HOURS) } else { MAX_WET_HOURS };
+        let score = if response > 0.0 { (100.0 * wet_hours / needed).min(100.0) } else { 0.0 };
+
+        let alert = score >= 100.0 && !self.alerted;
+        self.alerted |= alert;
+        Risk { wetness, wet_hours, score, alert }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    pub fn test_leaf_wetness() {
+        let config = Config::parse(r#"
+            [leaf_wetness]
+            channel   = "leaf"
+            dry_volts = 0.5
+            wet_volts = 2.5
+        "#).expect("Config did not parse").leaf_wetness.unwrap();
+        assert_eq!(wetness(&config, 1.5), 50.0);
+        assert_eq!(wetness(&config, 3.0), 100.0);
+        assert_eq!(temperature_response(T_OPT), 1.0);
+        assert_eq!(temperature_response(T_MAX), 0.0);
+        // off the optimum it's ((30 - T) / 10) * ((T - 5) / 15)^1.5
+        assert!((temperature_response(10.0) - 0.3849).abs() < 1e-4);
+        assert!((temperature_response(25.0) - 0.7698).abs() < 1e-4);
+
+        // wet at the optimum temperature, infection needs min_wet_hours
+        let mut leaf = LeafWetness::new(&config);
+        let t0 = Utc::now();
+        let at = |mins| t0 + Duration::minutes(mins);
+        assert_eq!(leaf.update(at(0), 80.0, Some(T_OPT)).score, 0.0);
+        let half = leaf.update(at(30), 80.0, Some(T_OPT));
+        assert!((half.score - 100.0 * 0.5 / config.min_wet_hours).abs() < 1e-9);
+        let mut risk = half;
+        for step in 2..=(config.min_wet_hours as i64 * 2) {
+            risk = leaf.update(at(30 * step), 80.0, Some(T_OPT));
+        }
+        assert!(risk.alert && risk.score == 100.0);
+        assert!(!leaf.update(at(30 * 13), 80.0, Some(T_OPT)).alert);
+
+        // a short dry spell keeps the wet period going, a long one ends it
+        let end = 30 * 13;
+        assert_eq!(leaf.update(at(end + 60), 10.0, Some(T_OPT)).wet_hours, risk.wet_hours + 0.5);
+        assert!(leaf.update(at(end + 120), 10.0, Some(T_OPT)).wet_hours > 0.0);
+        assert_eq!(leaf.update(at(end + 60 * 5), 10.0, Some(T_OPT)).wet_hours, 0.0);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::config::{Config, UntilWetConfig};
+use crate::feedback::Snapshot;
+use chrono::{DateTime, Utc};
+use tokio::time::{sleep_until, Duration, Instant};
+
+///
+/// @brief what to do next in a repeat-until-wet watering, from the probe's latest moisture
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Burst,
+    Wet,
+    Capped,
+    NoReading,
+}
+
+///
+/// @return how much one burst delivers: the pump's flow, or every zone's, for `burst_secs`;
+///         None when a flow isn't known
+///
+pub fn burst_ml(config: &Config) -> Option<f64> {
+    let burst_secs = config.until_wet.as_ref()?.burst_secs;
+    let flow = match config.zones.is_empty() {
+        true => config.pump.flow_ml_per_min?,
+        false => config.zones.iter().map(|zone| zone.flow_ml_per_min()).sum::<Option<f64>>()?,
+    };
+    Some(flow * burst_secs / 60.0)
+}
+
+///
+/// @brief the watering under way, started by the first burst
+///
+struct Session {
+    started_at: DateTime<Utc>,
+    // when the last burst finished, the soak starts
+    soaking_since: DateTime<Utc>,
+    check_at: Instant,
+    bursts: u32,
+    before: Snapshot,
+}
+
+///
+/// @brief waters in bursts, re-reading the soil probe a soak after each, until the pot is
+///        wet enough or as much as it may get has gone in
+///
+pub struct UntilWet {
+    config: UntilWetConfig,
+    burst_ml: f64,
+    max_bursts: u32,
+    session: Option<Session>,
+}
+
+impl UntilWet {
+    pub fn new(config: &UntilWetConfig, burst_ml: f64) -> UntilWet {
+        UntilWet {
+            config: config.clone(),
+            burst_ml,
+            // check-config refuses a cap below one burst, the first always goes in
+            max_bursts: ((config.max_volume_ml / burst_ml).floor() as u32).max(1),
+            session: None,
+        }
+    }
+
+    pub fn channel(&self) -> &str {
+        &self.config.channel
+    }
+
+    pub fn burst_secs(&self) -> f64 {
+        self.config.burst_secs
+    }
+
+    pub fn bursts(&self) -> u32 {
+        self.session.as_ref().map_or(0, |session| session.bursts)
+    }
+
+    pub fn volume_ml(&self) -> f64 {
+        self.bursts() as f64 * self.burst_ml
+    }
+
+    ///
+    /// @return when the watering under way started, the time its pump start row is stamped with
+    ///
+    pub fn started_at(&self) -> Option<DateTime<Utc>> {
+        self.session.as_ref().map(|session| session.started_at)
+    }
+
+    ///
+    /// @return whether a reading taken at `taken_at` was after the last burst soaked in
+    ///
+    pub fn soaked(&self, taken_at: DateTime<Utc>) -> bool {
+        self.session.as_ref().is_none_or(|session| taken_at >= session.soaking_since)
+    }
+
+    ///
+    /// @brief what the probe reading `moisture` calls for, given the bursts so far
+    ///
+    pub fn step(&self, moisture: Option<f64>) -> Step {
+        match moisture {
+            None => Step::NoReading,
+            Some(moisture) if moisture >= self.config.target_moisture => Step::Wet,
+            Some(_) if self.bursts() >= self.max_bursts => Step::Capped,
+            Some(_) => Step::Burst,
+        }
+    }
+
+    ///
+    /// @brief a burst just went in; the first one's `before` and `started_at` are kept for
+    ///        the watering feedback and the pump start row
+    ///
+    pub fn burst(&mut self, before: Snapshot, started_at: DateTime<Utc>) {
+        let check_at = Instant::now() + Duration::from_secs(60 * self.config.soak_mins);
+        let soaking_since = Utc::now();
+        match self.session.as_mut() {
+            Some(session) => {
+                session.soaking_since = soaking_since;
+                session.check_at = check_at;
+                session.bursts += 1;
+            },
+            None => self.session = Some(Session { started_at, soaking_since, check_at, bursts: 1, before }),
+        }
+    }
+
+    ///
+    /// @brief ends the watering
+    ///
+    /// @return the measurements from before its first burst
+    ///
+    pub fn finish(&mut self) -> Option<Snapshot> {
+        self.session.take().map(|session| session.before)
+    }
+
+    ///
+    /// @brief waits until the soil has soaked up the last burst; never completes between waterings
+    ///
+    pub async fn due(&self) {
+        match &self.session {
+            Some(session) => sleep_until(session.check_at).await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_until_wet() {
+        let config = Config::parse(r#"
+            [pump]
+            flow_ml_per_min = 240
+
+            [until_wet]
+            channel         = "bonsai"
+            target_moisture = 45
+            max_volume_ml   = 100
+            burst_secs      = 7.5
+        "#).unwrap();
+        let burst_ml = burst_ml(&config).unwrap();
+        assert_eq!(burst_ml, 30.0);
+
+        let mut wet = UntilWet::new(config.until_wet.as_ref().unwrap(), burst_ml);
+        assert_eq!(wet.step(None), Step::NoReading);
+        assert_eq!(wet.step(Some(50.0)), Step::Wet);
+        // 100 ml is three bursts of 30, never a fourth
+        let started_at = Utc::now();
+        for _ in 0..3 {
+            assert_eq!(wet.step(Some(30.0)), Step::Burst);
+            wet.burst(Snapshot::default(), Utc::now());
+        }
+        // the first burst started the watering, and only readings after the last count
+        assert_eq!(wet.started_at().map(|at| (at - started_at).num_seconds()), Some(0));
+        assert!(!wet.soaked(started_at - chrono::Duration::minutes(1)));
+        assert!(wet.soaked(Utc::now()));
+        assert_eq!((wet.step(Some(40.0)), wet.volume_ml()), (Step::Capped, 90.0));
+        assert_eq!(wet.step(Some(46.0)), Step::Wet);
+        assert_eq!(wet.finish(), Some(Snapshot::default()));
+        assert_eq!(wet.bursts(), 0);
+        assert!(wet.soaked(started_at - chrono::Duration::minutes(1)));
+    }
+}
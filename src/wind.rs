@@ -0,0 +1,163 @@
+use crate::config::WindConfig;
+use crate::hal::{self, Platform};
+use crate::reading::Reading;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use crate::logging;
+
+//
+// @brief  how often the reed switch is sampled; a cup anemometer at 100 km/h closes it
+//         about 40 times a second
+//
+const POLL_MILLIS: u64 = 2;
+
+//
+// @brief  a rising edge within this long of the last one is contact bounce
+//
+const DEBOUNCE_MILLIS: u64 = 6;
+
+//
+// @brief  how far back the mean wind speed behind watering decisions looks
+//
+const WINDOW_MINS: i64 = 30;
+
+///
+/// @brief counts debounced rising edges in a stream of samples
+///
+#[derive(Debug, Default)]
+struct Edges {
+    high: bool,
+    last_ms: Option<u64>,
+}
+
+impl Edges {
+    fn sample(&mut self, high: bool, now_ms: u64) -> bool {
+        let rising = high && !self.high;
+        self.high = high;
+        if !rising || self.last_ms.is_some_and(|last| now_ms - last < DEBOUNCE_MILLIS) {
+            return false;
+        }
+        self.last_ms = Some(now_ms);
+        true
+    }
+}
+
+///
+/// @brief a cup anemometer's reed switch, its pulses counted on a thread of its own
+///
+/// @note the hal has no edge interrupts, so the line is polled every POLL_MILLIS
+///
+pub struct Anemometer {
+    pulses: Arc<AtomicU64>,
+    kmh_per_hz: f64,
+    since: (Instant, u64),
+    sensor_id: String,
+}
+
+impl Anemometer {
+    pub fn open(platform: &dyn Platform, config: &WindConfig) -> hal::Result<Anemometer> {
+        let mut line = platform.input(config.sensor.pin, config.pull_up)?;
+        let pulses = Arc::new(AtomicU64::new(0));
+
+        let counted = pulses.clone();
+        std::thread::spawn(move || {
+            let started = Instant::now();
+            let mut edges = Edges::default();
+            loop {
+                match line.is_high() {
+                    Ok(high) => if edges.sample(high, started.elapsed().as_millis() as u64) {
+                        counted.fetch_add(1, Ordering::Relaxed);
+                    },
+                    Err(e) => {
+                        logging::print(3, &format!("Anemometer read error: {}", e));
+                        std::thread::sleep(std::time::Duration::from_secs(1));
+                    },
+                }
+                std::thread::sleep(std::time::Duration::from_millis(POLL_MILLIS));
+            }
+        });
+
+        Ok(Anemometer {
+            pulses,
+            kmh_per_hz: config.kmh_per_hz,
+            since: (Instant::now(), 0),
+            sensor_id: format!("anemometer@gpio{}", config.sensor.pin),
+        })
+    }
+
+    ///
+    /// @brief mean wind speed in km/h since the last call
+    ///
+    pub fn speed(&mut self) -> Reading<f64> {
+        let (now, pulses) = (Instant::now(), self.pulses.load(Ordering::Relaxed));
+        let (then, before) = std::mem::replace(&mut self.since, (now, pulses));
+        let secs = (now - then).as_secs_f64();
+        let hz = if secs > 0.0 { (pulses - before) as f64 / secs } else { 0.0 };
+        Reading::now(hz * self.kmh_per_hz, &self.sensor_id)
+    }
+}
+
+///
+/// @brief the last WINDOW_MINS of wind speeds, smoothing gusts and lulls out of the
+///        watering decisions
+///
+#[derive(Debug, Default)]
+pub struct Gauge {
+    speeds: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl Gauge {
+    pub fn record(&mut self, at: DateTime<Utc>, kmh: f64) {
+        self.speeds.push_back((at, kmh));
+        while self.speeds.front().is_some_and(|(t, _)| at - *t > Duration::minutes(WINDOW_MINS)) {
+            self.speeds.pop_front();
+        }
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        if self.speeds.is_empty() {
+            return None;
+        }
+        Some(self.speeds.iter().map(|(_, kmh)| kmh).sum::<f64>() / self.speeds.len() as f64)
+    }
+
+    ///
+    /// @brief whether it is hot and windy enough for pots to dry out faster than the
+    ///        schedule waters them
+    ///
+    pub fn drying(&self, config: &WindConfig, temperature: Option<f64>) -> bool {
+        match (temperature, self.mean()) {
+            (Some(t), Some(kmh)) => t >= config.hot_c && kmh >= config.windy_kmh,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    pub fn test_wind() {
+        let mut edges = Edges::default();
+        let counted: Vec<bool> = [(true, 0), (false, 2), (true, 4), (false, 10), (true, 12), (true, 14)]
+            .iter().map(|&(high, ms)| edges.sample(high, ms)).collect();
+        assert_eq!(counted, vec![true, false, false, false, true, false]);
+
+        let config = Config::parse("[wind]\nsensor = { pin = 17 }").expect("Config did not parse").wind.unwrap();
+        let t0 = Utc::now();
+        let mut gauge = Gauge::default();
+        assert!(!gauge.drying(&config, Some(35.0)));
+        gauge.record(t0, 40.0);
+        gauge.record(t0 + Duration::minutes(31), 10.0);
+        gauge.record(t0 + Duration::minutes(32), 20.0);
+        assert_eq!(gauge.mean(), Some(15.0));
+        assert!(gauge.drying(&config, Some(30.0)));
+        assert!(!gauge.drying(&config, Some(25.0)));
+        assert!(!gauge.drying(&config, None));
+    }
+}